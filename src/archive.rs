@@ -0,0 +1,264 @@
+//! Packaging objects into a static library, i.e. an `ar` archive with a symbol index, which the linker searches
+//! for the members defining the symbols it has left undefined
+
+use failure::Error;
+use goblin::{self, Object};
+use goblin::elf::section_header::SHN_UNDEF;
+use goblin::elf::sym::{STB_GLOBAL, STB_GNU_UNIQUE, STB_WEAK};
+use scroll::{Pwrite, BE, LE};
+use target_lexicon::BinaryFormat;
+
+use std::io::Write;
+
+use Artifact;
+
+const MAGIC: &[u8] = b"!<arch>\n";
+const SIZEOF_HEADER: u64 = 60;
+/// The longest name which fits in a GNU member header, with its terminating `/`
+const MAX_GNU_NAME: usize = 15;
+
+/// The variant of the `ar` format an archive is written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// The GNU (System V) variant, used for ELF: the symbol index is the member `/`, and names longer than a
+    /// header holds are in the member `//`
+    Gnu,
+    /// The BSD variant, used for Mach-O: the symbol index is the member `__.SYMDEF`, and each name follows its
+    /// header, padded so that the member's contents are 8 byte aligned, as `ld64` expects
+    Bsd,
+}
+
+impl ArchiveFormat {
+    /// The variant the linkers of `format` read, if faerie can write one
+    pub fn for_binary_format(format: BinaryFormat) -> Option<Self> {
+        match format {
+            BinaryFormat::Elf => Some(ArchiveFormat::Gnu),
+            BinaryFormat::Macho => Some(ArchiveFormat::Bsd),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Member {
+    name: String,
+    data: Vec<u8>,
+    symbols: Vec<String>,
+}
+
+/// A static library of objects, which are either emitted from artifacts or added as they are.
+///
+/// Every member is written with a zero timestamp, owner and group, so an archive of the same objects is always
+/// the same bytes.
+///
+/// **NB**: COFF `.lib` archives are not supported, since faerie does not emit COFF objects
+#[derive(Debug, Clone)]
+pub struct Archive {
+    /// The variant of `ar` this archive is written in
+    pub format: ArchiveFormat,
+    members: Vec<Member>,
+}
+
+impl Archive {
+    /// Create an empty archive written in `format`
+    pub fn new(format: ArchiveFormat) -> Self {
+        Archive { format, members: Vec::new() }
+    }
+    /// Emit `artifact` in the binary format of its target, and add it as the member named after the artifact.
+    /// Its [exported symbols](../struct.Artifact.html#method.exported_symbols) are indexed.
+    pub fn add_artifact(&mut self, artifact: &Artifact) -> Result<(), Error> {
+        let format = artifact.target.binary_format;
+        let data = artifact.emit()?;
+        self.members.push(Member { name: artifact.name.clone(), data, symbols: artifact.exported_symbols(format) });
+        Ok(())
+    }
+    /// Add the ELF or Mach-O object `data` as the member `name`; the externally visible symbols it defines are
+    /// read from its symbol table and indexed
+    pub fn add_object<T: Into<String>>(&mut self, name: T, data: Vec<u8>) -> Result<(), Error> {
+        let name = name.into();
+        let symbols = defined_symbols(&data).map_err(|e| format_err!("cannot read the symbols of the object {}: {}", name, e))?;
+        self.members.push(Member { name, data, symbols });
+        Ok(())
+    }
+    /// The names of the members, in the order they were added
+    pub fn members<'a>(&'a self) -> Box<dyn Iterator<Item = &'a str> + 'a> {
+        Box::new(self.members.iter().map(|member| member.name.as_str()))
+    }
+    /// Write the archive to `sink`, which need not be seekable, e.g. a pipe
+    pub fn write<W: Write>(&self, mut sink: W) -> Result<(), Error> {
+        match self.format {
+            ArchiveFormat::Gnu => self.write_gnu(&mut sink),
+            ArchiveFormat::Bsd => self.write_bsd(&mut sink),
+        }
+    }
+    /// Write the archive to a blob of bytes
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::new();
+        self.write(&mut bytes)?;
+        Ok(bytes)
+    }
+    fn symbols(&self) -> usize {
+        self.members.iter().map(|member| member.symbols.len()).sum()
+    }
+    fn write_gnu<W: Write>(&self, sink: &mut W) -> Result<(), Error> {
+        // names which don't fit in the header, or have a `/` which would end them, are in the long names member
+        let mut long_names = Vec::new();
+        let mut names = Vec::with_capacity(self.members.len());
+        for member in &self.members {
+            if member.name.len() <= MAX_GNU_NAME && !member.name.contains('/') {
+                names.push(format!("{}/", member.name));
+            } else {
+                names.push(format!("/{}", long_names.len()));
+                long_names.extend_from_slice(member.name.as_bytes());
+                long_names.extend_from_slice(b"/\n");
+            }
+        }
+        // the padding of the long names is part of them
+        if long_names.len() % 2 == 1 {
+            long_names.push(b'\n');
+        }
+        let symbols = self.symbols();
+        let index_size = if symbols == 0 {
+            0
+        } else {
+            let strings = self.members.iter().flat_map(|member| &member.symbols).map(|symbol| symbol.len() as u64 + 1).sum::<u64>();
+            4 + 4 * symbols as u64 + strings
+        };
+        let mut offset = MAGIC.len() as u64;
+        if index_size > 0 {
+            offset += SIZEOF_HEADER + padded(index_size, 2);
+        }
+        if !long_names.is_empty() {
+            offset += SIZEOF_HEADER + long_names.len() as u64;
+        }
+        let mut offsets = Vec::with_capacity(self.members.len());
+        for member in &self.members {
+            if offset > u64::from(u32::MAX) {
+                return Err(format_err!("the member {} is too far into the archive to be indexed", member.name));
+            }
+            offsets.push(offset as u32);
+            offset += SIZEOF_HEADER + padded(member.data.len() as u64, 2);
+        }
+
+        sink.write_all(MAGIC)?;
+        if index_size > 0 {
+            let mut index = vec![0; index_size as usize];
+            let mut at = index.pwrite_with(symbols as u32, 0, BE)?;
+            for (member, &offset) in self.members.iter().zip(&offsets) {
+                for _ in &member.symbols {
+                    at += index.pwrite_with(offset, at, BE)?;
+                }
+            }
+            for symbol in self.members.iter().flat_map(|member| &member.symbols) {
+                index[at..at + symbol.len()].copy_from_slice(symbol.as_bytes());
+                at += symbol.len() + 1;
+            }
+            write_member(sink, "/", Some(0), &index, b'\n')?;
+        }
+        if !long_names.is_empty() {
+            write_member(sink, "//", None, &long_names, b'\n')?;
+        }
+        for (member, name) in self.members.iter().zip(&names) {
+            write_member(sink, name, Some(0o644), &member.data, b'\n')?;
+        }
+        Ok(())
+    }
+    fn write_bsd<W: Write>(&self, sink: &mut W) -> Result<(), Error> {
+        const SYMDEF: &str = "__.SYMDEF";
+        let mut strings = Vec::new();
+        let mut ranlibs = Vec::with_capacity(self.symbols());
+        let mut offset = MAGIC.len() as u64;
+        let index_size = 4 + 8 * self.symbols() as u64 + 4 + padded(self.members.iter().flat_map(|member| &member.symbols).map(|symbol| symbol.len() as u64 + 1).sum(), 8);
+        offset += bsd_member_size(SYMDEF, offset, index_size);
+        for member in &self.members {
+            if offset > u64::from(u32::MAX) {
+                return Err(format_err!("the member {} is too far into the archive to be indexed", member.name));
+            }
+            for symbol in &member.symbols {
+                ranlibs.push((strings.len() as u32, offset as u32));
+                strings.extend_from_slice(symbol.as_bytes());
+                strings.push(0);
+            }
+            offset += bsd_member_size(&member.name, offset, member.data.len() as u64);
+        }
+        strings.resize(padded(strings.len() as u64, 8) as usize, 0);
+
+        let mut index = vec![0; index_size as usize];
+        let mut at = index.pwrite_with(8 * ranlibs.len() as u32, 0, LE)?;
+        for &(string, offset) in &ranlibs {
+            at += index.pwrite_with(string, at, LE)?;
+            at += index.pwrite_with(offset, at, LE)?;
+        }
+        at += index.pwrite_with(strings.len() as u32, at, LE)?;
+        index[at..].copy_from_slice(&strings);
+
+        sink.write_all(MAGIC)?;
+        let mut offset = MAGIC.len() as u64;
+        for (name, data, mode) in Some((SYMDEF, &index, 0)).into_iter()
+            .chain(self.members.iter().map(|member| (member.name.as_str(), &member.data, 0o644))) {
+            // the name is padded with nuls, and the contents with zeros, to keep every member 8 byte aligned
+            let name_size = bsd_name_size(name, offset);
+            let size = bsd_member_size(name, offset, data.len() as u64) - SIZEOF_HEADER;
+            let mut contents = Vec::with_capacity(size as usize);
+            contents.extend_from_slice(name.as_bytes());
+            contents.resize(name_size as usize, 0);
+            contents.extend_from_slice(data);
+            contents.resize(size as usize, 0);
+            write_member(sink, &format!("#1/{}", name_size), Some(mode), &contents, 0)?;
+            offset += SIZEOF_HEADER + size;
+        }
+        Ok(())
+    }
+}
+
+fn padded(size: u64, align: u64) -> u64 {
+    size.next_multiple_of(align)
+}
+
+/// The size of the name of a BSD member at `offset`, which ends where the member's contents are 8 byte aligned
+fn bsd_name_size(name: &str, offset: u64) -> u64 {
+    padded(offset + SIZEOF_HEADER + name.len() as u64 + 1, 8) - offset - SIZEOF_HEADER
+}
+
+/// The size of a BSD member at `offset` with its header and name, and its contents padded to 8 bytes
+fn bsd_member_size(name: &str, offset: u64, size: u64) -> u64 {
+    SIZEOF_HEADER + bsd_name_size(name, offset) + padded(size, 8)
+}
+
+/// Write the header of the member `name` and its `data`, padded to an even size with `pad`.
+/// A member without a `mode` isn't a file, and its date, owner, group and mode are left blank
+fn write_member<W: Write>(sink: &mut W, name: &str, mode: Option<u32>, data: &[u8], pad: u8) -> Result<(), Error> {
+    // name, date, uid, gid, mode (in octal) and size, each left aligned in its field, and the terminator
+    match mode {
+        Some(mode) => writeln!(sink, "{:<16}{:<12}{:<6}{:<6}{:<8o}{:<10}`", name, 0, 0, 0, mode, data.len())?,
+        None => writeln!(sink, "{:<48}{:<10}`", name, data.len())?,
+    }
+    sink.write_all(data)?;
+    if data.len() % 2 == 1 {
+        sink.write_all(&[pad])?;
+    }
+    Ok(())
+}
+
+/// The externally visible symbols the object `data` defines, in the order of its symbol table
+fn defined_symbols(data: &[u8]) -> Result<Vec<String>, Error> {
+    match Object::parse(data)? {
+        Object::Elf(elf) => {
+            Ok(elf.syms.iter().filter(|sym| {
+                let bind = sym.st_bind();
+                (bind == STB_GLOBAL || bind == STB_WEAK || bind == STB_GNU_UNIQUE) && sym.st_shndx != SHN_UNDEF as usize
+            }).filter_map(|sym| elf.strtab.get(sym.st_name).and_then(|name| name.ok()).map(str::to_string)).collect())
+        },
+        Object::Mach(goblin::mach::Mach::Binary(mach)) => {
+            let mut symbols = Vec::new();
+            for symbol in mach.symbols() {
+                let (name, nlist) = symbol?;
+                if !nlist.is_stab() && nlist.is_global() && !nlist.is_undefined() {
+                    symbols.push(name.to_string());
+                }
+            }
+            Ok(symbols)
+        },
+        _ => Err(format_err!("it is neither an ELF nor a Mach-O object")),
+    }
+}
@@ -1,20 +1,103 @@
 //! An artifact is a platform independent binary object file format abstraction.
 
 use string_interner::DefaultStringInterner;
-use indexmap::IndexMap;
+use indexmap::{IndexMap, IndexSet};
 use failure::Error;
-use target_lexicon::{Triple, BinaryFormat};
+use target_lexicon::{Architecture, Triple, BinaryFormat};
 
-use std::io::Write;
-use std::fs::File;
-use std::collections::BTreeSet;
+use std::io::{self, Read, Seek, SeekFrom, Cursor, Write};
+use std::fs::{File, OpenOptions};
+use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::collections::hash_map::Entry;
+use std::mem;
+use std::sync::Arc;
+use scroll::Pwrite;
 
 use elf;
-use mach;
+use flat;
+use target::make_ctx;
+use encoder::RelocEncoder;
+use backend::{self, ObjectBackend};
+use sha1::Sha1;
+
+mod decl;
+pub use self::decl::{Decl, Scope, Visibility, FunctionDecl, DataDecl, CStringDecl, AbsoluteDecl, DebugSectionDecl, SectionDecl};
 
 /// A blob of binary bytes, representing a function body, or data object
 pub type Data = Vec<u8>;
 
+/// Where the bytes of a definition come from.
+///
+/// Definitions backed by a file are not read until the artifact is written, and are then streamed
+/// into the output, which allows emitting data objects far larger than is reasonable to hold in memory.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub enum DataSource {
+    /// The bytes are held in memory
+    Bytes(Data),
+    /// The bytes are the `size` bytes starting at `offset` in the file at `path`
+    File { path: PathBuf, offset: u64, size: u64 },
+    /// The bytes are this many zeros, which are not held anywhere
+    Zeros(u64),
+    /// The bytes are `size` bytes of `pattern` repeated, e.g. `0xff` for erased flash, or `0xdeadbeef` for poisoned
+    /// memory; the last repetition is truncated if `size` is not a multiple of the pattern's length.
+    /// Only the pattern is held in memory, and the pattern must not be empty
+    Fill { pattern: Data, size: u64 },
+}
+
+impl DataSource {
+    /// The number of bytes this source provides
+    pub fn len(&self) -> u64 {
+        match *self {
+            DataSource::Bytes(ref data) => data.len() as u64,
+            DataSource::File { size, .. } => size,
+            DataSource::Zeros(size) => size,
+            DataSource::Fill { size, .. } => size,
+        }
+    }
+    /// Whether this source provides no bytes at all
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Copy the bytes of this source into `sink`; it is an error if fewer than `len` bytes are available.
+    pub(crate) fn write_to<W: Write>(&self, sink: &mut W) -> io::Result<()> {
+        match *self {
+            DataSource::Bytes(ref data) => sink.write_all(data),
+            DataSource::File { ref path, offset, size } => {
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(offset))?;
+                let copied = io::copy(&mut file.take(size), sink)?;
+                if copied != size {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                        format!("expected {} bytes at offset {} in {}, but only {} were available", size, offset, path.display(), copied)));
+                }
+                Ok(())
+            }
+            DataSource::Zeros(size) => io::copy(&mut io::repeat(0).take(size), sink).map(|_| ()),
+            DataSource::Fill { ref pattern, size } => {
+                if pattern.is_empty() && size > 0 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "fill pattern is empty"));
+                }
+                // whole repetitions, so each chunk starts at the start of the pattern
+                let chunk = pattern.repeat((4096 / pattern.len().max(1)).max(1));
+                let mut remaining = size;
+                while remaining > 0 {
+                    let len = remaining.min(chunk.len() as u64);
+                    sink.write_all(&chunk[..len as usize])?;
+                    remaining -= len;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl From<Data> for DataSource {
+    fn from(data: Data) -> Self {
+        DataSource::Bytes(data)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
 /// A raw relocation and its addend, to optionally override the "auto" relocation behavior of faerie.
 /// **NB**: This is implementation defined, and can break code invariants if used improperly, you have been warned.
@@ -23,8 +106,238 @@ pub struct RelocOverride {
     pub addend: i32,
 }
 
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+/// The kind of relocation a [link](struct.Artifact.html#method.link_with) creates
+pub enum Reloc {
+    /// Let faerie deduce the relocation from the declarations of the two symbols being linked
+    Auto,
+    /// A raw, implementation defined relocation and its addend; see [RelocOverride](struct.RelocOverride.html).
+    /// A relocation whose field is a bit range scattered across an instruction word, as in packed or VLIW encodings,
+    /// is linked at the offset of the word, and its type tells the linker which bits it modifies.
+    ///
+    /// The `size` in bytes (1, 2, 4 or 8) of the field is the one of the relocation type when it is `None`; it is
+    /// needed for a type the architecture's [encoder](encoder/trait.RelocEncoder.html) doesn't know the field of,
+    /// when the addend is written in place of the field, as on i386.
+    Raw { reloc: u32, addend: i32, size: Option<u8> },
+    /// The `size` byte (4 or 8) field is filled in with the size of the target definition, plus `addend`.
+    /// **NB**: This is currently only supported for ELF targets
+    Size { size: u8, addend: i32 },
+    /// The `size` byte (1, 2, 4 or 8) field is filled in with the address of the target, plus `addend`.
+    /// When the target is a debug section, this is the offset `addend` into it,
+    /// e.g. the offset of a compilation unit in `.debug_info` from an index like `.debug_names` or `.gdb_index`.
+    /// A 4 byte field is zero extended, e.g. `R_X86_64_32`, so the linker fails if the target ends up above 4 GiB,
+    /// as it can outside of the small code model.
+    /// **NB**: This is currently only supported for ELF targets
+    Absolute { size: u8, addend: i32 },
+    /// The 4 byte field is filled in with the address of the target, plus `addend`, which is sign extended when
+    /// it is used, e.g. `R_X86_64_32S` for a 32-bit displacement in the small code model. The linker fails if the
+    /// address is outside of the signed 32-bit range.
+    /// **NB**: This is currently only supported for ELF targets
+    SignedAbsolute { addend: i32 },
+    /// The `size` byte (4 or 8) field is filled in with the offset of the target's GOT entry from the GOT base,
+    /// plus `addend`, e.g. for `mov eax, [ebx + sym@GOT]` with the GOT base in `ebx`. The linker allocates the entry.
+    /// **NB**: This is currently only supported for ELF targets
+    GotOffset { size: u8, addend: i32 },
+    /// The `size` byte (1, 2, 4 or 8) field is filled in with the address of the target, plus `addend`, relative to
+    /// the address of the field itself. Unlike the `-4` faerie deduces for an instruction which ends with the field,
+    /// `addend` is used as is, e.g. `-7` when the field is followed by a 3 byte immediate, or `-1` for the 1 byte
+    /// displacement of a short jump.
+    /// **NB**: This is currently only supported for ELF targets; not every architecture has every size
+    Relative { size: u8, addend: i32 },
+    /// The `size` byte (4 or 8) field is filled in with the address of the target, plus `addend`, relative to the
+    /// GOT base, e.g. `R_386_GOTOFF` for a `DW_EH_PE_datarel` pointer in `.eh_frame`, or `R_X86_64_GOTOFF64`.
+    /// **NB**: This is currently only supported for x86 ELF targets, and only 8 bytes on x86-64
+    DataRelative { size: u8, addend: i32 },
+    /// The 4 byte field is filled in with the address of the target's GOT entry, plus `addend`, relative to the
+    /// field, e.g. `R_X86_64_GOTPCREL` for `mov rax, [rip + sym@GOTPCREL]`, whose addend is `-4`. Unlike a load
+    /// faerie deduces, which only goes through the GOT for an import, this goes through it for a definition too,
+    /// so a global one can be preempted. The linker allocates the entry. On AArch64 the field is an `adrp` of the
+    /// entry's page, followed by the `ldr` of the entry.
+    /// **NB**: This is currently only supported for x86-64 and AArch64 ELF targets
+    Got { addend: i32 },
+    /// The 4 byte field is filled in with the address of the target function's PLT entry, plus `addend`, relative
+    /// to the field. Unlike a call faerie deduces, which the linker resolves directly to a function defined in the
+    /// same object, this refers to the function's own symbol, so a global function is called through the PLT,
+    /// by its canonical address, even when it is defined here.
+    Plt { addend: i32 },
+    /// The 4 byte field is filled in with the address of the GOT base, plus `addend`, relative to the field, e.g.
+    /// `R_386_GOTPC` for the `add ebx, _GLOBAL_OFFSET_TABLE_` of an i386 position independent function's prologue.
+    /// The target must be the GOT base, `_GLOBAL_OFFSET_TABLE_`; see
+    /// [link_got_base](struct.Artifact.html#method.link_got_base), which works out the addend.
+    /// **NB**: This is currently only supported for x86 ELF targets
+    GotBase { addend: i32 },
+    /// The 4 byte field is filled in with the thread-local data object's location, as the TLS access `model`
+    /// requires; the field is the displacement at the offset of the instruction sequence the model describes.
+    /// `addend` is an offset into the object, e.g. of a field of a thread-local struct, which only the local exec
+    /// model and the offset of a local dynamic access can locate; the others locate the object itself, or the
+    /// module's TLS block, and the code adds the offset to its address. Mach-O has only the general dynamic and
+    /// initial exec models, which both load the address of the variable's descriptor, e.g. the field of
+    /// `movq _sym@TLVP(%rip), %rdi`, or the `adrp` followed by the `ldr` of its page offset on ARM64.
+    /// **NB**: This is currently only supported for x86-64 ELF targets, and x86-64 and ARM64 Mach-O targets
+    Tls { model: TlsModel, addend: i32 },
+    /// The `size` byte (4 or 8) field is filled in with the offset of the target from the start of its section,
+    /// plus `addend`, e.g. for a table of offsets into a section which needs no relocations. faerie knows this
+    /// offset, so it is written in place and no relocation is emitted; the target must be a definition which
+    /// can't be replaced by another object's, i.e. not an import, an absolute symbol or a weak definition.
+    /// In ELF every definition but zero-initialized data, which shares `.bss`, has a section of its own.
+    /// **NB**: This is currently only supported for ELF targets
+    SectionOffset { size: u8, addend: i32 },
+    /// The `size` byte (4 or 8) field is filled in with the offset of the target debug section, plus `addend`,
+    /// in the section the linker concatenates from the debug sections of that name of every object, e.g. a
+    /// `DW_FORM_strp` into `.debug_str`, or the `DW_AT_stmt_list` of a compilation unit in `.debug_line`. This is
+    /// the section relative relocation DWARF producers emit for references between debug sections, e.g. COFF's
+    /// `SECREL`; in ELF, where debug sections are not loaded, it is an absolute relocation against the section.
+    /// **NB**: This is currently only supported for ELF targets
+    DebugSecRel { size: u8, addend: i32 },
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+/// How code accesses a thread-local data object, from the most general to the most restrictive; see
+/// Drepper's "ELF Handling For Thread-Local Storage" for the instruction sequences the linker expects
+pub enum TlsModel {
+    /// Any thread-local data object, including one in a shared library loaded at runtime; the offset of the
+    /// field is of the `lea` of the `data16 lea sym@tlsgd(%rip), %rdi; data16 data16 rex.W call __tls_get_addr@PLT`
+    /// sequence on x86-64, and the call is relocated too
+    GeneralDynamic,
+    /// The TLS block of the module which defines the thread-local data object, whose address several accesses
+    /// of the module's objects can share; the offset of the field is of the `lea` of the
+    /// `lea sym@tlsld(%rip), %rdi; call __tls_get_addr@PLT` sequence on x86-64, and the call is relocated too.
+    /// The object must be defined by this artifact.
+    LocalDynamic,
+    /// The 32-bit offset of the thread-local data object in the TLS block of its module, which a local dynamic
+    /// access adds to the address of the block, e.g. of `lea sym@dtpoff(%rax), %reg`.
+    /// The object must be defined by this artifact.
+    LocalDynamicOffset,
+    /// A thread-local data object of the executable, or of a shared library which is loaded with it; the field is
+    /// the displacement of a `mov sym@gottpoff(%rip), %reg` of the object's offset from the thread pointer
+    InitialExec,
+    /// A thread-local data object defined in the executable; the field is the 32-bit offset of the object from the
+    /// thread pointer, e.g. of `mov %fs:sym@tpoff, %reg`
+    LocalExec,
+}
+
+/// How faerie infers the relocation of a link with `Reloc::Auto`, from the declarations it links
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum AutoRelocation {
+    /// There is one relocation for the link, which faerie infers, e.g. a pointer from data to a function
+    Unambiguous,
+    /// The link may mean more than one thing, and faerie infers the most likely; the explicit `Reloc` to link with
+    /// for each meaning is suggested
+    Ambiguous(&'static str),
+    /// There is no relocation for the link, e.g. from a function to a debug section, which has no address
+    Unsupported,
+}
+
+/// How the code of an artifact refers to functions and data, which decides the relocations faerie infers for the
+/// links from its functions with `Reloc::Auto`
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub enum RelocModel {
+    /// The code is linked into an executable at a fixed address, e.g. a fully static executable or a kernel, and
+    /// everything it refers to is linked into it: calls are direct, and data is addressed by its absolute 32-bit
+    /// address, sign extended, as in the small and kernel code models, e.g. `mov eax, [x]`
+    Static,
+    /// The code is position independent: data is addressed relative to the code, or through the GOT if it may be
+    /// in another module, and imported functions are called through their GOT entry, as `-fno-plt` code does.
+    /// The function is bound when the library is loaded, instead of lazily on its first call, and the linker
+    /// relaxes the call to a direct one if it is linked statically.
+    ///
+    /// i386 has no PC relative addressing of data, so its functions address local data relative to the GOT base,
+    /// which the function has loaded into a register, and other data and imported functions through the GOT.
+    Pic,
+    /// The code is position independent, as with `Pic`, but functions are called through the PLT, in case they
+    /// are in another module
+    #[default]
+    PicWithPlt,
+}
+
+impl AutoRelocation {
+    /// Classify a link from `from` to `to` with `Reloc::Auto`
+    pub fn classify(from: &Decl, to: &Decl) -> Self {
+        match (from, to) {
+            // a call, or e.g. `lea rax, [rip + f]`, which in a shared library needs the canonical address of a
+            // function defined elsewhere; faerie relocates it as a call, through the PLT
+            (&Decl::Function(_), &Decl::Function(_)) | (&Decl::Function(_), &Decl::FunctionImport) => {
+                AutoRelocation::Ambiguous("Reloc::Plt to call the function, or Reloc::Relative for its address")
+            },
+            (&Decl::Function(_), &Decl::DebugSection(_)) => AutoRelocation::Unsupported,
+            (&Decl::Function(_), _) => AutoRelocation::Unambiguous,
+            (&Decl::Data(_), _) | (&Decl::Section(_), _) | (&Decl::DebugSection(_), _) => AutoRelocation::Unambiguous,
+            _ => AutoRelocation::Unsupported,
+        }
+    }
+}
+
+impl Reloc {
+    /// Is this a relocation to the size of its target?
+    pub fn is_size(&self) -> bool {
+        matches!(*self, Reloc::Size { .. })
+    }
+    /// Is this a relocation to the offset of its target's GOT entry?
+    pub fn is_got_offset(&self) -> bool {
+        matches!(*self, Reloc::GotOffset { .. })
+    }
+    /// Is this a relocation to the address of its target's GOT entry relative to the field?
+    pub fn is_got(&self) -> bool {
+        matches!(*self, Reloc::Got { .. })
+    }
+    /// Is this a relocation to the address of its target relative to the field?
+    pub fn is_relative(&self) -> bool {
+        matches!(*self, Reloc::Relative { .. })
+    }
+    /// Is this a relocation to the address of its target relative to the GOT base?
+    pub fn is_data_relative(&self) -> bool {
+        matches!(*self, Reloc::DataRelative { .. })
+    }
+    /// Is this a relocation to the PLT entry of its target?
+    pub fn is_plt(&self) -> bool {
+        matches!(*self, Reloc::Plt { .. })
+    }
+    /// Is this a relocation to the GOT base relative to the field?
+    pub fn is_got_base(&self) -> bool {
+        matches!(*self, Reloc::GotBase { .. })
+    }
+    /// Is this a raw, implementation defined relocation?
+    pub fn is_raw(&self) -> bool {
+        matches!(*self, Reloc::Raw { .. })
+    }
+    /// Is this a relocation to a thread-local data object?
+    pub fn is_tls(&self) -> bool {
+        matches!(*self, Reloc::Tls { .. })
+    }
+    /// Is this the offset of its target within its section, which is written in place of a relocation?
+    pub fn is_section_offset(&self) -> bool {
+        matches!(*self, Reloc::SectionOffset { .. })
+    }
+    /// Is this a sized relocation to the address of its target?
+    pub fn is_absolute(&self) -> bool {
+        matches!(*self, Reloc::Absolute { .. } | Reloc::SignedAbsolute { .. })
+    }
+    /// The size in bytes of the field this relocation fills in, and its addend, if it says so itself; the size of
+    /// the others depends on the relocation the backend picks, but is always at least 4 bytes
+    pub(crate) fn field(&self) -> (Option<u8>, i32) {
+        match *self {
+            Reloc::Auto => (None, 0),
+            Reloc::Raw { size, addend, .. } => (size, addend),
+            Reloc::Size { size, addend } | Reloc::Absolute { size, addend } | Reloc::GotOffset { size, addend } |
+            Reloc::Relative { size, addend } | Reloc::DataRelative { size, addend } |
+            Reloc::SectionOffset { size, addend } | Reloc::DebugSecRel { size, addend } => (Some(size), addend),
+            Reloc::SignedAbsolute { addend } => (Some(4), addend),
+            Reloc::Got { addend } | Reloc::Plt { addend } | Reloc::GotBase { addend } | Reloc::Tls { addend, .. } => (None, addend),
+        }
+    }
+}
+
+impl From<RelocOverride> for Reloc {
+    fn from(ovr: RelocOverride) -> Self {
+        Reloc::Raw { reloc: ovr.reloc, addend: ovr.addend, size: None }
+    }
+}
+
 type StringID = usize;
-type Relocation = (StringID, StringID, u64, Option<RelocOverride>);
+type Relocation = (StringID, StringID, u64, Reloc);
+/// The definition with the field, the definition whose address is added, the definition whose address is subtracted,
+/// and the offset of the field
+type Difference = (StringID, StringID, StringID, u64);
 
 /// The kinds of errors that can befall someone creating an Artifact
 #[derive(Fail, Debug)]
@@ -35,12 +348,199 @@ pub enum ArtifactError {
     ImportDefined(String),
     #[fail(display = "Attempt to add a relocation to an import: {}", _0)]
     RelocateImport(String),
+    #[fail(display = "Attempt to define an absolute symbol: {}", _0)]
+    AbsoluteDefined(String),
     // FIXME: don't use debugging prints for decl formats
     #[fail(display = "Incompatible declarations, old declaration {:?} is incompatible with new {:?}", old, new)]
     /// An incompatble declaration occurred, please see the [absorb](enum.Decl.html#method.absorb) method on `Decl`
     IncompatibleDeclaration { old: Decl, new: Decl },
     #[fail(display = "duplicate definition of symbol: {}", _0)]
     DuplicateDefinition(String),
+    #[fail(display = "Attempt to relocate to the size of {}, which is not a sized definition", _0)]
+    UnsizedRelocationTarget(String),
+    #[fail(display = "Unsupported relocation size: {} bytes", _0)]
+    UnsupportedRelocationSize(u8),
+    #[fail(display = "Absolute symbol {} has conflicting values in the batch", _0)]
+    ConflictingAbsolute(String),
+    #[fail(display = "Attempt to register {} as a constructor or destructor, but it is not a function", _0)]
+    NotAFunction(String),
+    #[fail(display = "Attempt to set how {} is resolved, but it is not an import", _0)]
+    NotAnImport(String),
+    #[fail(display = "Invalid Mach-O segment name: {:?}", _0)]
+    /// A segment name must be non-empty, at most 16 bytes, and contain no NUL bytes
+    InvalidSegmentName(String),
+    #[fail(display = "Invalid note owner name: {:?}", _0)]
+    /// A note owner must be non-empty and contain no interior NUL bytes
+    InvalidNoteOwner(String),
+    #[fail(display = "Note in section {} is too large: {} bytes", _0, _1)]
+    NoteTooLarge(String, usize),
+    #[fail(display = "Invalid package metadata: {:?}", _0)]
+    /// Package metadata is a JSON string, which is null terminated in its note, so it cannot contain a NUL byte
+    InvalidPackageMetadata(String),
+    #[fail(display = "Invalid linker directive: {:?}", _0)]
+    /// The strings of a linker directive are null terminated in the object, so they cannot be empty or contain a NUL byte
+    InvalidLinkerDirective(LinkerDirective),
+    #[fail(display = "Relocation from {} to {} cannot be resolved without a linker", _0, _1)]
+    /// The target is an import or a section which is not loaded, so it has no known address
+    UnresolvableRelocation(String, String),
+    #[fail(display = "Relocation from {} to {} does not fit in its field", _0, _1)]
+    RelocationOverflow(String, String),
+    #[fail(display = "Relocation from {} to {} of a {} byte field at {:#x} is outside of {}, which is {} bytes", from, to, size, at, from, len)]
+    /// The field a link fills in must be within the contents of the definition it is from
+    RelocationOutOfBounds {
+        from: String,
+        to: String,
+        at: u64,
+        size: u8,
+        len: u64,
+    },
+    #[fail(display = "Relocation from {} to {} at {:#x} has the addend {}, which does not fit in its {} byte field", from, to, at, addend, size)]
+    /// The addend of a link to a 1 or 2 byte field must fit in the field, as it is written there by some backends
+    AddendOverflow {
+        from: String,
+        to: String,
+        at: u64,
+        addend: i32,
+        size: u8,
+    },
+    #[fail(display = "Attempt to take the address of {}, which is not loaded", _0)]
+    NotAddressable(String),
+    #[fail(display = "Object has {} sections, more than the limit of {}; larger objects need extended section indices \
+                      (ELF's SHT_SYMTAB_SHNDX, or COFF's bigobj), which are not supported, so the definitions must be \
+                      split across several artifacts", count, limit)]
+    /// The limit is the lower of the format's and the artifact's [max_sections](struct.Artifact.html#structfield.max_sections)
+    TooManySections { count: usize, limit: usize },
+    #[fail(display = "Attempt to set the unwind information of {}, which is not a defined function", _0)]
+    NotADefinedFunction(String),
+    #[fail(display = "Invalid alias {} of {}", _0, _1)]
+    /// The target must be a function, data object or string, and only functions and data objects can be weak
+    InvalidAlias(String, String),
+    #[fail(display = "Invalid COMDAT group member {} of {}", _0, _1)]
+    /// The member must be a function, data object or string with contents, and the leader a global or weak one,
+    /// which is not already in another group
+    InvalidComdat(String, String),
+    #[fail(display = "Attempt to set the symbol attributes of {}, which is not a function, data object or string", _0)]
+    /// Only a definition has a symbol of its own with attributes, and a common symbol has no section to keep
+    NotADefinition(String),
+    #[fail(display = "Attempt to export {} from a DLL, but it is not a global function or data object", _0)]
+    /// Only a global (or weak) function or data object which is not common can be exported
+    NotExportable(String),
+    #[fail(display = "Invalid link order of {} by {}", _0, _1)]
+    /// The section must be a custom section, and the definition it is ordered by a function, data object, string or
+    /// custom section
+    InvalidLinkOrder(String, String),
+    #[fail(display = "Invalid line table entry {} of {}", _0, _1)]
+    /// The entry must be of a function, and refer to a declared `.debug_line` debug section
+    InvalidLineEntry(String, String),
+    #[fail(display = "Attempt to relocate to the PLT entry of {}, which is not a function", _0)]
+    NotAPltTarget(String),
+    #[fail(display = "Attempt to relocate to the GOT base with {}, which is not an import of _GLOBAL_OFFSET_TABLE_", _0)]
+    NotTheGotBase(String),
+    #[fail(display = "Attempt to rename {}, which is not a local declaration", _0)]
+    NotLocal(String),
+    #[fail(display = "Invalid versioned symbol {:?} of {}", _1, _0)]
+    /// Only a global definition or an import has a versioned symbol, `foo@VERSION`, and only a definition's version
+    /// can be the default, `foo@@VERSION`
+    InvalidSymbolVersion(String, String),
+    #[fail(display = "Invalid SFrame rows for {}: {}", _0, _1)]
+    InvalidSFrame(String, String),
+    #[fail(display = "Attempt to set an x86 ISA level for architecture {}", _0)]
+    NotX86(String),
+    #[fail(display = "Architecture {} has no control flow protection property", _0)]
+    /// Control flow protection is a program property of x86 and AArch64 only
+    NoControlFlowProtection(String),
+    #[fail(display = "the following symbols are declared but not defined: {:?}", _0)]
+    UndefinedSymbols(Vec<String>),
+    #[fail(display = "Unsupported relocation {:?} from {} ({:?}) to {} ({:?}) at {:#x} in {}", reloc, from, from_decl, to, to_decl, at, target)]
+    /// The target has no relocation for a link of this shape, e.g. from a function to a debug section; `target` is
+    /// its architecture and binary format, e.g. `x86_64 ELF`, or the artifact's triple when no target has one
+    UnsupportedRelocation { from: String, to: String, from_decl: Box<Decl>, to_decl: Box<Decl>, at: u64, reloc: Reloc, target: String },
+    #[fail(display = "Ambiguous relocation from {} to {}; link with {}", from, to, suggestion)]
+    /// With [strict_auto](struct.Artifact.html#structfield.strict_auto), faerie doesn't guess the relocation of a link
+    AmbiguousRelocation { from: String, to: String, suggestion: &'static str },
+    #[fail(display = "Architecture {} does not exist in {}", _0, _1)]
+    /// e.g. an ELF architecture faerie doesn't know the relocations of, unless the artifact has a
+    /// [reloc_encoder](struct.ArtifactBuilder.html#method.reloc_encoder)
+    UnsupportedArchitecture(String, String),
+    #[fail(display = "Architecture {} has {}-bit pointers, which {} has no class of object file for", _0, _1, _2)]
+    /// e.g. MSP430 in ELF, unless the artifact's [reloc_encoder](struct.ArtifactBuilder.html#method.reloc_encoder)
+    /// chooses the class
+    UnsupportedPointerWidth(String, u8, String),
+    #[fail(display = "Binary format {} is not supported", _0)]
+    /// There is no built-in backend for the format, e.g. WebAssembly; see [emit_with](struct.Artifact.html#method.emit_with)
+    UnsupportedBinaryFormat(String),
+    #[fail(display = "Invalid difference of {} and {}", _0, _1)]
+    /// Both symbols must be functions, data objects or strings, whose addresses are known relative to each other
+    InvalidDifference(String, String),
+    #[fail(display = "Invalid alignment of {}: {} is not a power of two", _0, _1)]
+    InvalidAlignment(String, u64),
+    #[fail(display = "Invalid string character size of section {}: 0 bytes", _0)]
+    InvalidEntrySize(String),
+    #[fail(display = "Invalid unwind section {}: it is read-only data, not writable, executable or strings", _0)]
+    /// The linker and unwinders parse the call frame information of an unwind section as it is
+    InvalidUnwindSection(String),
+    #[fail(display = "Invalid combination of flags of section {}", _0)]
+    /// A section which is not allocated can't be writable, executable or without bits, and a table of constants
+    /// the linker merges has contents, which aren't strings
+    InvalidSectionFlags(String),
+    #[fail(display = "Attempt to fill {} with an empty pattern", _0)]
+    EmptyFillPattern(String),
+    #[fail(display = "Attempt to define the zero-initialized data object {} with contents which are not all zeros", _0)]
+    NonZeroDefinition(String),
+    #[fail(display = "Attempt to define the string {} with contents which are not null-terminated", _0)]
+    /// Strings are placed in sections the linker merges, which must consist of null-terminated strings
+    UnterminatedString(String),
+    #[fail(display = "Attempt to set the section of {}, which is not a string", _0)]
+    NotAString(String),
+    #[fail(display = "Invalid section name: {:?}", _0)]
+    /// A section name must be non-empty and contain no NUL bytes
+    InvalidSectionName(String),
+    #[fail(display = "Attempt to set the section of {}, which is not a function, data object or string with a section", _0)]
+    /// Imports, absolute symbols and common symbols are in no section, and sections are already named
+    NotPlaceable(String),
+    #[fail(display = "Attempt to add a relocation to {}, which is zero-initialized", _0)]
+    /// A zero-initialized data object has no contents in the object file to relocate
+    RelocateZeroInit(String),
+    #[fail(display = "Attempt to relocate to the thread-local storage of {}, which is not thread-local data", _0)]
+    NotThreadLocal(String),
+    #[fail(display = "Attempt to write the offset of {} within its section, which is not a definition of this artifact, or may be replaced", _0)]
+    /// The target of a section offset must be defined here, and not weak, so its offset is known when it is emitted
+    ExternalSectionOffset(String),
+    #[fail(display = "Attempt to relocate to the offset of {} within its debug section, which is not a debug section", _0)]
+    NotADebugSection(String),
+    #[fail(display = "Attempt to relocate to the address of {}, which is thread-local", _0)]
+    /// Each thread has its own copy of a thread-local data object, so it must be relocated with a TLS model
+    ThreadLocalAddress(String),
+    #[fail(display = "Attempt to locate an offset into the thread-local data {} with a TLS model which only locates its start", _0)]
+    /// Only the local exec model's offset from the thread pointer, and a local dynamic offset, can be offset into the object
+    ThreadLocalOffset(String),
+    #[fail(display = "Attempt to locate the thread-local data {} in the TLS block of this module, which does not define it", _0)]
+    /// The local dynamic model locates objects of the module's own TLS block, so its target can't be an import
+    ThreadLocalImport(String),
+    #[fail(display = "Invalid common symbol {}: it must be global, and not thread-local", _0)]
+    /// The linker merges common symbols by name across objects, into its own zero-initialized data
+    InvalidCommon(String),
+    #[fail(display = "Failed to spill the body of {} to {}: {}", _0, _1, _2)]
+    /// The body of a function could not be appended to the artifact's spill file
+    Spill(String, String, String),
+    #[fail(display = "Attempt to redefine or remove the definition of {}, which is not defined", _0)]
+    /// Only a definition can be replaced or removed; a declaration which is not defined yet is defined with `define`
+    NotDefined(String),
+}
+
+impl ArtifactError {
+    /// `target` has no relocation for `link`
+    pub(crate) fn unsupported_relocation(link: &LinkAndDecl, target: String) -> Self {
+        ArtifactError::UnsupportedRelocation {
+            from: link.from.name.to_string(),
+            to: link.to.name.to_string(),
+            from_decl: Box::new(*link.from.decl),
+            to_decl: Box::new(*link.to.decl),
+            at: link.at,
+            reloc: link.reloc,
+            target,
+        }
+    }
 }
 
 ///////////////////////////////////////////////
@@ -58,109 +558,57 @@ pub enum ArtifactError {
 //   _and then_ global definitions
 ///////////////////////////////////////////////
 /// The properties associated with a symbolic reference
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct Prop {
+    /// Visible outside of this artifact; this includes weak definitions
     pub global: bool,
     pub function: bool,
     pub writable: bool,
     pub cstring: bool,
+    pub cold: bool,
+    pub debug: bool,
+    pub weak: bool,
+    /// The alignment of a zero-initialized data object, which has a size but no contents in the object file
+    pub zero_init: Option<u64>,
+    /// Each thread has its own copy, which is initialized from the definition
+    pub tls: bool,
+    /// A custom section, which is emitted under its declared name, and only has a section symbol
+    pub section: bool,
+    /// Executable, without being a function
+    pub executable: bool,
+    /// The size of the characters of a table of strings, if it is not 1
+    pub entsize: Option<u64>,
+    /// A string, or table of strings, which is kept intact rather than merged with identical strings
+    pub no_merge: bool,
+    /// An indirect function, whose contents are the resolver of its implementation
+    pub ifunc: bool,
+    /// A function of Thumb code, on 32-bit ARM
+    pub thumb: bool,
+    /// A common symbol, which the linker allocates, merged with any others of its name, rather than this artifact
+    pub common: bool,
+    /// A section of call frame information for unwinding, e.g. `.eh_frame`
+    pub unwind: bool,
+    /// A custom section which is not part of the memory image
+    pub not_allocated: bool,
+    /// A custom section which takes up no space in the object, like `.bss`
+    pub no_bits: bool,
+    /// The raw ELF section type of a custom section, if it is not the one faerie picks
+    pub elf_type: Option<u32>,
+    /// The raw ELF section flags added to those of a custom section
+    pub elf_flags: u64,
+    /// Hidden outside of the module it is linked into, though visible to the other objects linked into it
+    pub hidden: bool,
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 struct InternalDefinition {
     prop: Prop,
     name: StringID,
-    data: Data,
+    data: DataSource,
 }
 // end note
 ///////////////////////////////////////////////
 
-/// The kind of declaration this is
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum Decl {
-    /// An import of a function/routine defined in a shared library
-    FunctionImport,
-    /// A GOT-based import of data defined in a shared library
-    DataImport,
-    /// A function defined in this artifact
-    Function { global: bool },
-    /// A data object defined in this artifact
-    Data { global: bool, writable: bool },
-    /// A null-terminated string object defined in this artifact
-    CString { global: bool }
-}
-
-impl Decl {
-    /// If it is compatible, absorb the new declaration (`other`) into the old (`self`); otherwise returns an error.
-    ///
-    /// The rule here is "C-ish", but essentially:
-    ///
-    /// 1. Duplicate declarations are no-ops / ignored.
-    /// 2. **If** the previous declaration was an [FunctionImport](enum.Decl.html#variant.FunctionImport) or [DataImport](enum.Decl.html#variant.DataImport),
-    ///    **then** if the subsequent declaration is a corresponding matching [Function](enum.Decl.html#variant.Function) or [Data](enum.Decl.html#variant.Data)
-    ///    declaration, it is said to be "upgraded", and forever after is considered a declaration in need of a definition.
-    /// 3. **If** the previous declaration was a `Function` or `Data` declaration,
-    ///    **then** a subsequent corresponding `FunctionImport` or `DataImport` is a no-op.
-    /// 4. Anything else is a [IncompatibleDeclaration](enum.ArtifactError.html#variant.IncompatibleDeclaration) error!
-    // ref https://github.com/m4b/faerie/issues/24
-    // ref https://github.com/m4b/faerie/issues/18
-    pub fn absorb(&mut self, other: Self) -> Result<(), Error> {
-        // FIXME: i can't think of a way offhand to not clone here, without unusual contortions
-        match self.clone() {
-            Decl::DataImport => {
-                match other {
-                    // data imports can be upgraded to any kind of data declaration
-                    Decl::Data { .. } => { *self = other; Ok(()) }
-                    Decl::DataImport => Ok(()),
-                    _ => Err(ArtifactError::IncompatibleDeclaration { old:*self, new: other }.into()),
-                }
-            }
-            Decl::FunctionImport => {
-                match other {
-                    // function imports can be upgraded to any kind of function declaration
-                    Decl::Function { .. } => { *self = other; Ok(()) }
-                    Decl::FunctionImport => Ok(()),
-                    _ => Err(ArtifactError::IncompatibleDeclaration { old:*self, new: other }.into()),
-                }
-            },
-            // a previous data declaration can only be re-declared a data import, or it must match exactly the
-            // next declaration
-            decl@Decl::Data { .. } => {
-                match other {
-                    Decl::DataImport => Ok(()),
-                    other => if decl == other { Ok(()) } else {
-                        Err(ArtifactError::IncompatibleDeclaration { old:*self, new: other }.into())
-                    }
-                }
-            }
-            // a previous function decl can only be re-declared a function import, or it must match exactly
-            // the next declaration
-            decl@Decl::Function { .. } => {
-                match other {
-                    Decl::FunctionImport => Ok(()),
-                    other => {
-                        if decl == other { Ok(()) } else {
-                            Err(ArtifactError::IncompatibleDeclaration { old:*self, new: other }.into())
-                        }
-                    },
-                }
-            },
-            decl => if decl == other { Ok(()) } else {
-                Err(ArtifactError::IncompatibleDeclaration { old:*self, new: other }.into())
-            }
-        }
-    }
-    /// Is this an import (function or data) from a shared library?
-    pub fn is_import(&self) -> bool {
-        use Decl::*;
-        match *self {
-            FunctionImport => true,
-            DataImport => true,
-            _ => false,
-        }
-    }
-}
-
 /// A declaration, plus a flag to track whether we have a definition for it yet
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 struct InternalDecl {
@@ -182,6 +630,160 @@ impl InternalDecl {
     }
 }
 
+/// The symbol of the GOT base, which the linker defines
+pub(crate) const GOT_BASE: &str = "_GLOBAL_OFFSET_TABLE_";
+
+/// The note type of a GNU build id
+pub const NT_GNU_BUILD_ID: u32 = 3;
+/// The note type `go tool buildid` looks for in the `Go` owner's `.note.go.buildid` section
+pub const NT_GO_BUILD_ID: u32 = 4;
+/// The note type of the `FDO` owner's package metadata, see https://systemd.io/ELF_PACKAGE_METADATA/
+pub const NT_FDO_PACKAGING_METADATA: u32 = 0xcafe_1a7e;
+/// The note type of the `GNU` owner's program properties in `.note.gnu.property`
+pub const NT_GNU_PROPERTY_TYPE_0: u32 = 5;
+/// The program property of the AArch64 features, e.g. BTI and PAC, which the code supports; the linker keeps
+/// only the features every object in the link supports
+pub const GNU_PROPERTY_AARCH64_FEATURE_1_AND: u32 = 0xc000_0000;
+/// The program property of the x86 features, e.g. IBT and SHSTK, which the code supports; the linker keeps
+/// only the features every object in the link supports
+pub const GNU_PROPERTY_X86_FEATURE_1_AND: u32 = 0xc000_0002;
+/// The program property of the x86 ISA levels, e.g. `x86-64-v2`, which the code needs
+pub const GNU_PROPERTY_X86_ISA_1_NEEDED: u32 = 0xc000_8002;
+
+/// An x86-64 microarchitecture level, as its bit in [GNU_PROPERTY_X86_ISA_1_NEEDED](constant.GNU_PROPERTY_X86_ISA_1_NEEDED.html)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IsaLevel {
+    /// `x86-64-baseline`: the ISA every x86-64 processor supports
+    Baseline = 1,
+    /// `x86-64-v2`: adds e.g. SSE4.2 and POPCNT
+    V2 = 2,
+    /// `x86-64-v3`: adds e.g. AVX2, BMI2 and FMA
+    V3 = 4,
+    /// `x86-64-v4`: adds AVX-512
+    V4 = 8,
+}
+
+/// A request to the linker which is embedded in an object, so that it is not needed on the link line,
+/// e.g. to link with a library the code calls into
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LinkerDirective {
+    /// Link with the library `name`, as `-l<name>` would: on ELF, `name` is in `.deplibs`, which lld reads, and on
+    /// Mach-O, it is the `LC_LINKER_OPTION` `-l<name>`, which ld64 reads
+    Library(String),
+    /// Pass the option `name` with `value` to the linker: on ELF, the pair is in `.linker-options`, and on Mach-O,
+    /// it is the `LC_LINKER_OPTION` of both, e.g. `-framework` and `Foundation`
+    Option(String, String),
+}
+
+impl LinkerDirective {
+    /// The strings of this directive, in the order they are emitted in the ELF section
+    pub(crate) fn strings(&self) -> Vec<&str> {
+        match *self {
+            LinkerDirective::Library(ref name) => vec![name],
+            LinkerDirective::Option(ref name, ref value) => vec![name, value],
+        }
+    }
+}
+
+/// A callee-saved register of x86-64, numbered as it is in a compact unwind encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnwindRegister {
+    Rbx = 1,
+    R12 = 2,
+    R13 = 3,
+    R14 = 4,
+    R15 = 5,
+}
+
+/// The compact unwind encoding of an x86-64 function which sets up a frame with `push rbp; mov rbp, rsp`, and then
+/// pushes the callee-saved `registers` in order. Returns `None` if more registers are saved than can be encoded,
+/// in which case the function has to be unwound with its DWARF CFI in `__eh_frame` instead.
+pub fn x86_64_frame_unwind(registers: &[UnwindRegister]) -> Option<u32> {
+    const UNWIND_X86_64_MODE_RBP_FRAME: u32 = 0x0100_0000;
+    if registers.len() > 5 {
+        return None;
+    }
+    // the registers are listed from the lowest address, i.e. the last one pushed, at this many slots below rbp
+    let offset = registers.len() as u32;
+    let locations = registers.iter().rev().enumerate().fold(0, |locations, (i, &register)| {
+        locations | (register as u32) << (3 * i)
+    });
+    Some(UNWIND_X86_64_MODE_RBP_FRAME | offset << 16 | locations)
+}
+
+/// The register the CFA of an [SFrameRow](struct.SFrameRow.html) is an offset from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SFrameBase {
+    StackPointer,
+    FramePointer,
+}
+
+/// A row of the SFrame stack trace information of a function, which applies from `offset` bytes into the function
+/// up to the next row: the CFA, the value of the stack pointer before the call, is `cfa_offset` from `cfa_base`,
+/// and the caller's frame pointer and the return address, once they are saved, are at `fp_offset` and `ra_offset`
+/// from the CFA.
+///
+/// On x86-64 the return address is always at `-8` from the CFA, so `ra_offset` is either that or `None`;
+/// on AArch64 it is saved along with the frame pointer, so `fp_offset` needs `ra_offset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SFrameRow {
+    pub offset: u32,
+    pub cfa_base: SFrameBase,
+    pub cfa_offset: i32,
+    pub fp_offset: Option<i32>,
+    pub ra_offset: Option<i32>,
+}
+
+/// A WebAssembly value type, of a parameter or result of a function
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WasmType {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+/// The type of a WebAssembly function, which every function and function import needs in a WebAssembly object,
+/// as calls are checked against it
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct WasmSignature {
+    pub params: Vec<WasmType>,
+    pub results: Vec<WasmType>,
+}
+
+/// A vendor specific note, e.g. a build id, which tooling can recover from the object file
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Note {
+    /// The name of the vendor or runtime which owns this note, and hence the meaning of `kind`
+    pub owner: String,
+    /// The owner specific type of this note
+    pub kind: u32,
+    /// The descriptor, or payload, of this note
+    pub desc: Vec<u8>,
+}
+
+impl Note {
+    fn new(section: &str, owner: &str, kind: u32, desc: Vec<u8>) -> Result<Self, ArtifactError> {
+        if owner.is_empty() || owner.contains('\0') {
+            return Err(ArtifactError::InvalidNoteOwner(owner.to_string()));
+        }
+        let note = Note { owner: owner.to_string(), kind, desc };
+        // the header's namesz and descsz fields are 32 bits, even in 64-bit objects
+        if note.size() > u32::MAX as usize {
+            return Err(ArtifactError::NoteTooLarge(section.to_string(), note.size()));
+        }
+        Ok(note)
+    }
+    /// The size of this note when emitted: a 12 byte header, followed by the null-terminated owner and
+    /// the descriptor, each padded to a 4 byte boundary
+    pub fn size(&self) -> usize {
+        12 + Self::align(self.owner.len() + 1) + Self::align(self.desc.len())
+    }
+    fn align(size: usize) -> usize {
+        (size + 3) & !3
+    }
+}
+
 /// A binding of a raw `name` to its declaration, `decl`
 #[derive(Debug)]
 pub struct Binding<'a> {
@@ -195,14 +797,23 @@ pub struct LinkAndDecl<'a> {
     pub from: Binding<'a>,
     pub to: Binding<'a>,
     pub at: u64,
-    pub reloc: Option<RelocOverride>,
+    pub reloc: Reloc,
+}
+
+/// A 4 byte field at `at` in `from`, which is filled in with the address of `to` minus the address of `minus`
+#[derive(Debug)]
+pub(crate) struct DifferenceAndDecl<'a> {
+    pub from: Binding<'a>,
+    pub to: Binding<'a>,
+    pub minus: Binding<'a>,
+    pub at: u64,
 }
 
 /// A definition of a symbol with its properties the various backends receive
 #[derive(Debug)]
-pub(crate) struct Definition<'a> {
+pub struct Definition<'a> {
     pub name: &'a str,
-    pub data: &'a [u8],
+    pub data: &'a DataSource,
     pub prop: &'a Prop,
 }
 
@@ -235,13 +846,18 @@ pub enum ImportKind {
     Data,
 }
 
+/// Can `architecture` only execute Thumb code?
+fn is_thumb_only(architecture: Architecture) -> bool {
+    matches!(architecture, Architecture::Thumbv6m | Architecture::Thumbv7em | Architecture::Thumbv7m)
+}
+
 impl ImportKind {
     fn from_decl(decl: &Decl) -> Option<Self> {
-        match decl {
-            &Decl::DataImport => {
+        match *decl {
+            Decl::DataImport => {
                 Some (ImportKind::Data)
             },
-            &Decl::FunctionImport => {
+            Decl::FunctionImport => {
                 Some (ImportKind::Function)
             },
             _ => None
@@ -249,11 +865,72 @@ impl ImportKind {
     }
 }
 
+/// How the linker selects the copy of a COMDAT group it keeps, when objects define the same group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComdatSelection {
+    /// Any copy, and the others are discarded, as for an inline function or a vtable emitted in each object that
+    /// uses it; this is ELF's only selection
+    Any,
+    /// Any copy, but every copy must be the same size, as COFF's `IMAGE_COMDAT_SELECT_SAME_SIZE`
+    SameSize,
+    /// Any copy, but every copy must have the same contents, as COFF's `IMAGE_COMDAT_SELECT_EXACT_MATCH`, e.g. for
+    /// the instantiations of a generic function which must not differ between objects
+    ExactMatch,
+}
+
+/// The type of a symbol, which tells a linker, loader or debugger what it refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    /// A data object
+    Object,
+    /// A function
+    Function,
+    /// An indirect function, whose address is the result of calling its resolver
+    Ifunc,
+    /// A thread-local variable
+    Tls,
+}
+
+/// The attributes of the symbol of a definition which override the ones derived from its declaration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct SymbolAttributes {
+    pub size: Option<u64>,
+    pub kind: Option<SymbolKind>,
+    pub other: Option<u8>,
+}
+
+/// What is stripped from an object file when it is emitted, as `strip` would remove it from the emitted object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strip {
+    /// Nothing
+    None,
+    /// The local symbols, including the file symbol and the section symbols, which no relocation refers to,
+    /// like `strip --discard-all`
+    DiscardLocals,
+    /// The debug sections, like `strip --strip-debug`
+    Debug,
+    /// Everything that isn't needed to link the object: the debug sections, and the local symbols which no
+    /// relocation refers to
+    All,
+}
+
 /// Builder for creating an artifact
 pub struct ArtifactBuilder {
     target: Triple,
     name: Option<String>,
     library: bool,
+    legacy_ctors: bool,
+    relro: bool,
+    executable_stack: bool,
+    max_sections: Option<usize>,
+    reloc_model: RelocModel,
+    function_align: Option<u64>,
+    data_align: Option<u64>,
+    function_sections: bool,
+    inline_addends: bool,
+    strict_auto: bool,
+    reloc_encoder: Option<Arc<dyn RelocEncoder>>,
+    spill: Option<PathBuf>,
 }
 
 impl ArtifactBuilder {
@@ -263,6 +940,18 @@ impl ArtifactBuilder {
             target,
             name: None,
             library: false,
+            legacy_ctors: false,
+            relro: false,
+            executable_stack: false,
+            max_sections: None,
+            reloc_model: RelocModel::default(),
+            function_align: None,
+            data_align: None,
+            function_sections: true,
+            inline_addends: false,
+            strict_auto: false,
+            reloc_encoder: None,
+            spill: None,
         }
     }
     /// Set this artifacts name
@@ -275,10 +964,104 @@ impl ArtifactBuilder {
         self.library = is_library;
         self
     }
+    /// Set whether constructors and destructors are emitted in the legacy `.ctors` and `.dtors` sections
+    pub fn legacy_ctors(mut self, legacy_ctors: bool) -> Self {
+        self.legacy_ctors = legacy_ctors;
+        self
+    }
+    /// Set whether read-only data which is relocated is placed in `.data.rel.ro`
+    pub fn relro(mut self, relro: bool) -> Self {
+        self.relro = relro;
+        self
+    }
+    /// Set whether the object requires an executable stack
+    pub fn executable_stack(mut self, executable_stack: bool) -> Self {
+        self.executable_stack = executable_stack;
+        self
+    }
+    /// Set the most sections an object emitted from this artifact may have
+    pub fn max_sections(mut self, max_sections: usize) -> Self {
+        self.max_sections = Some(max_sections);
+        self
+    }
+    /// Set whether the code is position independent, which is `RelocModel::Static` if not, and otherwise
+    /// `RelocModel::PicWithPlt`, unless the model is already `RelocModel::Pic`
+    #[deprecated(note = "use `reloc_model` instead")]
+    pub fn pic(mut self, pic: bool) -> Self {
+        self.reloc_model = match (pic, self.reloc_model) {
+            (false, _) => RelocModel::Static,
+            (true, RelocModel::Static) => RelocModel::PicWithPlt,
+            (true, model) => model,
+        };
+        self
+    }
+    /// Set whether functions call imported functions through the GOT, which is `RelocModel::Pic`, instead of
+    /// through the PLT, which is `RelocModel::PicWithPlt` unless the model is already `RelocModel::Static`
+    #[deprecated(note = "use `reloc_model` instead")]
+    pub fn no_plt(mut self, no_plt: bool) -> Self {
+        self.reloc_model = match (no_plt, self.reloc_model) {
+            (true, _) => RelocModel::Pic,
+            (false, RelocModel::Pic) => RelocModel::PicWithPlt,
+            (false, model) => model,
+        };
+        self
+    }
+    /// Set how the code refers to functions and data
+    pub fn reloc_model(mut self, reloc_model: RelocModel) -> Self {
+        self.reloc_model = reloc_model;
+        self
+    }
+    /// Set the alignment of each function, in bytes, which must be a power of two
+    pub fn function_align(mut self, align: u64) -> Self {
+        self.function_align = Some(align);
+        self
+    }
+    /// Set the alignment of each data object with contents, in bytes, which must be a power of two
+    pub fn data_align(mut self, align: u64) -> Self {
+        self.data_align = Some(align);
+        self
+    }
+    /// Set whether each function is emitted in its own section
+    pub fn function_sections(mut self, function_sections: bool) -> Self {
+        self.function_sections = function_sections;
+        self
+    }
+    /// Set whether the addend of each relocation which carries it is also written in place of its field
+    pub fn inline_addends(mut self, inline_addends: bool) -> Self {
+        self.inline_addends = inline_addends;
+        self
+    }
+    /// Set whether emitting fails on a link whose relocation faerie would have to guess, rather than picking one
+    pub fn strict_auto(mut self, strict_auto: bool) -> Self {
+        self.strict_auto = strict_auto;
+        self
+    }
+    /// Set the encoder which selects the ELF relocations for the links, instead of the one for the target's architecture
+    pub fn reloc_encoder(mut self, encoder: Arc<dyn RelocEncoder>) -> Self {
+        self.reloc_encoder = Some(encoder);
+        self
+    }
+    /// Set the file which the bodies of the functions are appended to as they are defined, instead of being held in memory
+    pub fn spill(mut self, path: PathBuf) -> Self {
+        self.spill = Some(path);
+        self
+    }
     pub fn finish(self) -> Artifact {
         let name = self.name.unwrap_or("faerie.o".to_owned());
         let mut artifact = Artifact::new(self.target, name);
         artifact.is_library = self.library;
+        artifact.legacy_ctors = self.legacy_ctors;
+        artifact.relro = self.relro;
+        artifact.executable_stack = self.executable_stack;
+        artifact.max_sections = self.max_sections;
+        artifact.reloc_model = self.reloc_model;
+        artifact.function_align = self.function_align;
+        artifact.data_align = self.data_align;
+        artifact.function_sections = self.function_sections;
+        artifact.inline_addends = self.inline_addends;
+        artifact.strict_auto = self.strict_auto;
+        artifact.reloc_encoder = self.reloc_encoder;
+        artifact.spill = self.spill;
         artifact
     }
 }
@@ -292,14 +1075,122 @@ pub struct Artifact {
     pub target: Triple,
     /// Whether this is a static library or not
     pub is_library: bool,
+    /// Whether constructors and destructors are emitted in the legacy `.ctors` and `.dtors` sections, which some
+    /// older runtimes scan, instead of `.init_array` and `.fini_array`
+    pub legacy_ctors: bool,
+    /// Whether read-only data which is the source of any relocation is placed in `.data.rel.ro`, which the linker
+    /// gathers into the RELRO segment, so that it is only writable until the dynamic linker has relocated it.
+    /// Otherwise, it is placed with the other read-only data, which in a position independent executable or
+    /// shared library forces the dynamic linker to write to read-only pages (text relocations).
+    ///
+    /// **NB**: this currently only applies to ELF targets
+    pub relro: bool,
+    /// Whether the object requires an executable stack, e.g. for trampolines built on the stack.
+    /// Every ELF object is emitted with an empty `.note.GNU-stack` section, which marks the stack non-executable
+    /// unless this is set, in which case the section is flagged executable; an object without the section at all
+    /// makes the linker assume the stack must be executable.
+    ///
+    /// **NB**: this currently only applies to ELF targets
+    pub executable_stack: bool,
+    /// The most sections an object emitted from this artifact may have, if lower than the limit of its format,
+    /// e.g. for a consumer with a smaller limit; emitting an object with more sections is an error
+    pub max_sections: Option<usize>,
+    /// How the code refers to functions and data, which decides the relocations inferred for the links from
+    /// functions with `Reloc::Auto`; by default, calls go through the PLT and imported data through the GOT.
+    /// A `Reloc::Plt` is still through the PLT with any model.
+    ///
+    /// **NB**: this currently only applies to x86-64 and i386 ELF targets
+    pub reloc_model: RelocModel,
+    /// The alignment of each function, if not the default of 16 bytes, e.g. 64 to start each on a cache line.
+    /// Each function's section is aligned to it, or each function within `.text` if not `function_sections`.
+    ///
+    /// **NB**: this currently only applies to ELF targets and flat binaries
+    pub function_align: Option<u64>,
+    /// The alignment of each data object with contents, if not the default of 8 bytes for writable data and 1 for
+    /// read-only data; strings and custom sections keep their own. Zero-initialized data is aligned as it is declared.
+    ///
+    /// **NB**: this currently only applies to ELF targets and flat binaries
+    pub data_align: Option<u64>,
+    /// Whether each function is emitted in its own section, `.text.<name>`, so the linker can discard the unused
+    /// ones with `--gc-sections`, as `-ffunction-sections` does; this is the default. Otherwise, the functions are
+    /// concatenated into `.text`, and the cold ones into `.text.unlikely`, each at an offset aligned to
+    /// `function_align`, as a compiler without `-ffunction-sections` emits them.
+    ///
+    /// **NB**: this currently only applies to ELF targets
+    pub function_sections: bool,
+    /// Whether the addend of each relocation which carries it (RELA) is also written in place of the field the
+    /// relocation applies to, as it is when relocations don't carry it (REL), for tools which read it from there.
+    /// This is non-standard; linkers ignore the field's contents, as it is overwritten. Only fields which hold
+    /// nothing but their value are written, not those which are part of an instruction, e.g. AArch64's `bl`.
+    ///
+    /// **NB**: this currently only applies to ELF targets
+    pub inline_addends: bool,
+    /// Whether emitting the artifact fails on a link with `Reloc::Auto` that faerie would have to guess the
+    /// relocation of, e.g. from a function to a function, which may be a call or take the function's address, or
+    /// that it has no relocation for; the error names the explicit `Reloc` to link with instead. Otherwise, faerie
+    /// picks the most likely relocation; see [AutoRelocation](enum.AutoRelocation.html).
+    pub strict_auto: bool,
+    /// The encoder which selects the ELF relocations for the links, if not the built-in one for the target's
+    /// architecture, e.g. for an architecture faerie doesn't support yet
+    ///
+    /// **NB**: this currently only applies to ELF targets
+    pub reloc_encoder: Option<Arc<dyn RelocEncoder>>,
+    /// The file which the bodies of the functions are appended to as they are [defined](#method.define), so that
+    /// the artifact only holds their location, and writing it streams them from the file into the object.
+    /// The file must not change until the artifact has been written, and it is not removed afterwards.
+    ///
+    /// **NB**: only the bodies passed to `define` are spilled; those of other definitions stay in memory
+    pub spill: Option<PathBuf>,
     // will keep this for now; may be useful to pre-partition code and data vectors, not sure
     code: Vec<(StringID, Data)>,
     data: Vec<(StringID, Data)>,
     imports: Vec<(StringID, ImportKind)>,
     import_links: Vec<Relocation>,
     links: Vec<Relocation>,
+    differences: Vec<Difference>,
     declarations: IndexMap<StringID, InternalDecl>,
     definitions: BTreeSet<InternalDefinition>,
+    notes: IndexMap<String, Vec<Note>>,
+    /// Whether the GNU build id is computed from the contents when emitted
+    content_buildid: bool,
+    /// Whether the links and differences are emitted in the order of the definitions they are in and their offsets
+    deterministic: bool,
+    constructors: Vec<(StringID, Option<u16>)>,
+    destructors: Vec<(StringID, Option<u16>)>,
+    /// Each function with a transactional memory clone, and its clone
+    tm_clones: Vec<(StringID, StringID)>,
+    static_imports: BTreeSet<StringID>,
+    weak_imports: BTreeSet<StringID>,
+    dll_imports: BTreeSet<StringID>,
+    dll_exports: IndexSet<StringID>,
+    macho_segments: IndexMap<String, String>,
+    empty_sections: IndexSet<String>,
+    debug_addrs: IndexSet<StringID>,
+    metadata: IndexMap<StringID, Vec<u8>>,
+    /// Each alias, and the definition it is another name for
+    aliases: IndexMap<StringID, StringID>,
+    symbol_attributes: IndexMap<StringID, SymbolAttributes>,
+    /// The definitions which are kept when the linker discards the unreferenced ones
+    no_dead_strip: IndexSet<StringID>,
+    alias_visibilities: IndexMap<StringID, Visibility>,
+    compact_unwind: IndexMap<StringID, u32>,
+    wasm_signatures: IndexMap<StringID, WasmSignature>,
+    sframes: IndexMap<StringID, Vec<SFrameRow>>,
+    /// Each local declaration which is emitted under another symbol name, and each versioned global definition or
+    /// import, and that name
+    symbol_names: IndexMap<StringID, StringID>,
+    /// Each definition which is placed in a section of another name than its own
+    placements: IndexMap<StringID, String>,
+    /// Each definition in a COMDAT group, and the group's leader and selection
+    comdats: IndexMap<StringID, (StringID, ComdatSelection)>,
+    /// Each custom section which is ordered by the section of another definition, and that definition
+    link_orders: IndexMap<StringID, StringID>,
+    gnu_properties: BTreeMap<u32, Vec<u8>>,
+    directives: IndexSet<LinkerDirective>,
+    /// The pooled strings and constants, by whether they are strings and their contents
+    pool: IndexMap<(bool, Vec<u8>), StringID>,
+    /// Each pooled constant which is merged with identical constants, and its size
+    pool_entsizes: IndexMap<StringID, u64>,
     strings: DefaultStringInterner,
 }
 
@@ -313,27 +1204,258 @@ impl Artifact {
             imports: Vec::new(),
             import_links: Vec::new(),
             links: Vec::new(),
+            differences: Vec::new(),
             name,
             target,
             is_library: false,
+            legacy_ctors: false,
+            relro: false,
+            executable_stack: false,
+            max_sections: None,
+            reloc_model: RelocModel::default(),
+            function_align: None,
+            data_align: None,
+            function_sections: true,
+            inline_addends: false,
+            strict_auto: false,
+            reloc_encoder: None,
+            spill: None,
             declarations: IndexMap::new(),
             definitions: BTreeSet::new(),
+            notes: IndexMap::new(),
+            content_buildid: false,
+            deterministic: false,
+            constructors: Vec::new(),
+            destructors: Vec::new(),
+            tm_clones: Vec::new(),
+            static_imports: BTreeSet::new(),
+            weak_imports: BTreeSet::new(),
+            dll_imports: BTreeSet::new(),
+            dll_exports: IndexSet::new(),
+            macho_segments: IndexMap::new(),
+            empty_sections: IndexSet::new(),
+            debug_addrs: IndexSet::new(),
+            metadata: IndexMap::new(),
+            aliases: IndexMap::new(),
+            symbol_attributes: IndexMap::new(),
+            no_dead_strip: IndexSet::new(),
+            alias_visibilities: IndexMap::new(),
+            compact_unwind: IndexMap::new(),
+            wasm_signatures: IndexMap::new(),
+            sframes: IndexMap::new(),
+            symbol_names: IndexMap::new(),
+            placements: IndexMap::new(),
+            comdats: IndexMap::new(),
+            link_orders: IndexMap::new(),
+            gnu_properties: BTreeMap::new(),
+            directives: IndexSet::new(),
+            pool: IndexMap::new(),
+            pool_entsizes: IndexMap::new(),
             strings: DefaultStringInterner::default(),
         }
     }
     /// Get an iterator over this artifact's imports
-    pub fn imports<'a>(&'a self) -> Box<Iterator<Item = (&'a str, &'a ImportKind)> + 'a> {
+    pub fn imports<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a str, &'a ImportKind)> + 'a> {
         Box::new(self.imports.iter().map(move |&(id, ref kind)| (self.strings.resolve(id).unwrap(), kind)))
     }
-    pub(crate) fn definitions<'a>(&'a self) -> Box<Iterator<Item = Definition<'a>> + 'a> {
+    /// Is the import `name` resolved statically, i.e., never from a shared library?
+    pub(crate) fn is_static_import(&self, name: &str) -> bool {
+        self.strings.get(name).is_some_and(|id| self.static_imports.contains(&id))
+    }
+    /// The visibility of the alias `name` outside of the module it is linked into
+    pub(crate) fn alias_visibility(&self, name: &str) -> Visibility {
+        self.strings.get(name).and_then(|id| self.alias_visibilities.get(&id)).cloned().unwrap_or_default()
+    }
+    /// The imports from a DLL, and the name of the pointer to each in the import address table
+    pub fn dll_imports<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a str, String)> + 'a> {
+        Box::new(self.dll_imports.iter().map(move |&id| {
+            let name = self.strings.resolve(id).unwrap();
+            (name, format!("__imp_{}", name))
+        }))
+    }
+    /// The definitions exported from a DLL, in the order they were exported
+    pub fn dll_exports<'a>(&'a self) -> Box<dyn Iterator<Item = &'a str> + 'a> {
+        Box::new(self.dll_exports.iter().map(move |&id| self.strings.resolve(id).unwrap()))
+    }
+    /// The contents of the `.drectve` section, which export the [DLL exports](#method.set_dll_export) and keep the
+    /// global [no dead strip](#method.set_no_dead_strip) definitions, and link with the
+    /// [libraries](#method.add_linker_directive) the artifact asks for, e.g.
+    /// ` /EXPORT:foo /EXPORT:counter,DATA /INCLUDE:init /DEFAULTLIB:m`; data is exported with `,DATA`, so that an
+    /// importer can't call it
+    pub fn linker_directives(&self) -> String {
+        let mut directives = String::new();
+        for &id in &self.dll_exports {
+            let name = self.strings.resolve(id).unwrap();
+            match self.declarations.get(&id).map(|int| int.decl) {
+                Some(Decl::Data(_)) => directives.push_str(&format!(" /EXPORT:{},DATA", name)),
+                _ => directives.push_str(&format!(" /EXPORT:{}", name)),
+            }
+        }
+        // the linker can only be told to include a symbol other objects could refer to
+        let exported = self.exported_symbols(BinaryFormat::Coff);
+        for name in self.no_dead_strip().filter(|name| exported.iter().any(|symbol| symbol == name)) {
+            directives.push_str(&format!(" /INCLUDE:{}", name));
+        }
+        for directive in &self.directives {
+            if let LinkerDirective::Library(ref name) = *directive {
+                if name.contains(' ') {
+                    directives.push_str(&format!(" /DEFAULTLIB:\"{}\"", name));
+                } else {
+                    directives.push_str(&format!(" /DEFAULTLIB:{}", name));
+                }
+            }
+        }
+        directives
+    }
+    /// The definitions which are kept when the linker discards the unreferenced ones, in the order they were set
+    pub fn no_dead_strip<'a>(&'a self) -> Box<dyn Iterator<Item = &'a str> + 'a> {
+        Box::new(self.no_dead_strip.iter().map(move |&id| self.strings.resolve(id).unwrap()))
+    }
+    /// Is the definition `name` kept when the linker discards the unreferenced ones?
+    pub(crate) fn is_no_dead_strip(&self, name: &str) -> bool {
+        self.strings.get(name).is_some_and(|id| self.no_dead_strip.contains(&id))
+    }
+    /// The symbols whose attributes override the ones derived from their declarations, and the attributes
+    pub(crate) fn symbol_attributes<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a str, SymbolAttributes)> + 'a> {
+        Box::new(self.symbol_attributes.iter().map(move |(&id, &attributes)| (self.strings.resolve(id).unwrap(), attributes)))
+    }
+    /// Is the import `name` weak, i.e., resolved to zero when nothing defines it?
+    pub(crate) fn is_weak_import(&self, name: &str) -> bool {
+        self.strings.get(name).is_some_and(|id| self.weak_imports.contains(&id))
+    }
+    /// The segment the Mach-O section `section` is assigned to, if it is not its standard segment
+    pub(crate) fn macho_segment(&self, section: &str) -> Option<&str> {
+        self.macho_segments.get(section).map(String::as_str)
+    }
+    /// Get an iterator over this artifact's absolute symbols
+    pub(crate) fn absolutes<'a>(&'a self) -> Box<dyn Iterator<Item = Binding<'a>> + 'a> {
+        Box::new(self.declarations.iter().filter(|&(_, int)| int.decl.is_absolute()).map(move |(&id, int)| {
+            Binding { name: self.strings.resolve(id).expect("absolute has a name"), decl: &int.decl }
+        }))
+    }
+    /// Get an iterator over this artifact's definitions, in the order they are emitted:
+    /// the local definitions, and then the global ones
+    pub fn definitions<'a>(&'a self) -> Box<dyn Iterator<Item = Definition<'a>> + 'a> {
         Box::new(self.definitions.iter().map(move |int_def| Definition::from((int_def, &self.strings))))
     }
-    /// Get this artifacts relocations
-    pub(crate) fn links<'a>(&'a self) -> Box<Iterator<Item = LinkAndDecl<'a>> + 'a> {
-        Box::new(self.links.iter().map(move |&(ref from, ref to, ref at, ref reloc)| {
+    /// Get an iterator over this artifact's declarations, in the order they were declared, and whether each is defined
+    pub fn declared<'a>(&'a self) -> Box<dyn Iterator<Item = (Binding<'a>, bool)> + 'a> {
+        Box::new(self.declarations.iter().map(move |(&id, int)| (self.binding(id), int.defined)))
+    }
+    /// Get the declaration of `name`, as it has been resolved by every declaration of it so far, if it is declared
+    pub fn declaration<'a, T: AsRef<str>>(&'a self, name: T) -> Option<Binding<'a>> {
+        self.strings.get(name.as_ref()).filter(|id| self.declarations.contains_key(id)).map(|id| self.binding(id))
+    }
+    /// Get the definition of `name`, if it has been defined
+    pub fn definition<'a, T: AsRef<str>>(&'a self, name: T) -> Option<Definition<'a>> {
+        let id = self.strings.get(name.as_ref())?;
+        self.definitions.iter().find(|int_def| int_def.name == id).map(|int_def| Definition::from((int_def, &self.strings)))
+    }
+    /// Is the import `name` satisfied by this artifact, i.e., has it since been declared as one of the artifact's
+    /// own functions or data objects and defined, or declared absolute, so that it is no longer left to the linker?
+    pub fn is_import_satisfied<T: AsRef<str>>(&self, name: T) -> bool {
+        self.strings.get(name.as_ref()).and_then(|id| self.declarations.get(&id))
+            .is_some_and(|int| !int.decl.is_import() && (int.defined || int.decl.is_absolute()))
+    }
+    /// Get an iterator over this artifact's aliases, and the definition each is another name for
+    pub(crate) fn aliases<'a>(&'a self) -> Box<dyn Iterator<Item = (Binding<'a>, Binding<'a>)> + 'a> {
+        Box::new(self.aliases.iter().map(move |(&alias, &target)| (self.binding(alias), self.binding(target))))
+    }
+    /// Get this artifact's functions which have a compact unwind encoding, and their encodings, in the order they were set
+    pub(crate) fn compact_unwind<'a>(&'a self) -> Box<dyn Iterator<Item = (Binding<'a>, u32)> + 'a> {
+        Box::new(self.compact_unwind.iter().map(move |(&id, &encoding)| (self.binding(id), encoding)))
+    }
+    /// The WebAssembly signature of the function or function import `name`, if it has been set
+    pub(crate) fn wasm_signature(&self, name: &str) -> Option<&WasmSignature> {
+        self.strings.get(name).and_then(|id| self.wasm_signatures.get(&id))
+    }
+    /// Get this artifact's functions which have SFrame stack trace information, and their rows, in the order they were set
+    pub(crate) fn sframes<'a>(&'a self) -> Box<dyn Iterator<Item = (Binding<'a>, &'a [SFrameRow])> + 'a> {
+        Box::new(self.sframes.iter().map(move |(&id, rows)| (self.binding(id), &rows[..])))
+    }
+    /// Get this artifact's declarations which are emitted under another symbol name, and those names
+    pub(crate) fn symbol_names<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a str, &'a str)> + 'a> {
+        Box::new(self.symbol_names.iter().map(move |(&name, &symbol)| {
+            (self.strings.resolve(name).expect("declaration has a name"), self.strings.resolve(symbol).expect("symbol name"))
+        }))
+    }
+    /// Get this artifact's definitions which are placed in a section of another name, and those names
+    pub(crate) fn placements<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a str, &'a str)> + 'a> {
+        Box::new(self.placements.iter().map(move |(&name, section)| {
+            (self.strings.resolve(name).expect("declaration has a name"), section.as_str())
+        }))
+    }
+    /// Is `decl` a function of Thumb code, as every function of a Thumb-only target is?
+    pub(crate) fn is_thumb(&self, decl: &Decl) -> bool {
+        match *decl {
+            Decl::Function(f) => is_thumb_only(self.target.architecture) || f.is_thumb(),
+            _ => false,
+        }
+    }
+    /// Get this artifact's definitions which are in a COMDAT group, and the group's leader and selection; the leader
+    /// is a member of its own group. A custom backend can use this for a selection the built-in backends don't support.
+    pub fn comdats<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a str, &'a str, ComdatSelection)> + 'a> {
+        Box::new(self.comdats.iter().map(move |(&member, &(leader, selection))| {
+            (self.strings.resolve(member).expect("declaration has a name"), self.strings.resolve(leader).expect("leader has a name"), selection)
+        }))
+    }
+    /// Get this artifact's custom sections which are ordered by the section of another definition, and that definition
+    pub(crate) fn link_orders<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a str, &'a str)> + 'a> {
+        Box::new(self.link_orders.iter().map(move |(&section, &definition)| {
+            (self.strings.resolve(section).expect("section has a name"), self.strings.resolve(definition).expect("definition has a name"))
+        }))
+    }
+    /// Check that an object with `count` sections fits within both the limit of its format, `format_limit`,
+    /// and the artifact's own limit
+    pub(crate) fn check_section_count(&self, count: usize, format_limit: usize) -> Result<(), ArtifactError> {
+        let limit = self.max_sections.map_or(format_limit, |max| max.min(format_limit));
+        if count > limit {
+            return Err(ArtifactError::TooManySections { count, limit });
+        }
+        Ok(())
+    }
+    /// Get an iterator over the names of this artifact's empty sections, in the order they were added
+    pub(crate) fn empty_sections<'a>(&'a self) -> Box<dyn Iterator<Item = &'a str> + 'a> {
+        Box::new(self.empty_sections.iter().map(String::as_str))
+    }
+    /// Get an iterator over this artifact's linker directives, in the order they were added
+    pub(crate) fn directives<'a>(&'a self) -> Box<dyn Iterator<Item = &'a LinkerDirective> + 'a> {
+        Box::new(self.directives.iter())
+    }
+    /// Get an iterator over this artifact's note sections, and the notes in each, in the order they were added
+    pub(crate) fn notes<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a str, &'a [Note])> + 'a> {
+        Box::new(self.notes.iter().map(|(section, notes)| (section.as_str(), notes.as_slice())))
+    }
+    /// Get this artifact's constructors, and their priorities, in the order they were added
+    pub fn constructors<'a>(&'a self) -> Box<dyn Iterator<Item = (Binding<'a>, Option<u16>)> + 'a> {
+        Box::new(self.constructors.iter().map(move |&(id, priority)| (self.binding(id), priority)))
+    }
+    /// Get this artifact's destructors, and their priorities, in the order they were added
+    pub fn destructors<'a>(&'a self) -> Box<dyn Iterator<Item = (Binding<'a>, Option<u16>)> + 'a> {
+        Box::new(self.destructors.iter().map(move |&(id, priority)| (self.binding(id), priority)))
+    }
+    /// Get this artifact's functions with a transactional memory clone, and their clones, in the order they were added
+    pub(crate) fn tm_clones<'a>(&'a self) -> Box<dyn Iterator<Item = (Binding<'a>, Binding<'a>)> + 'a> {
+        Box::new(self.tm_clones.iter().map(move |&(function, clone)| (self.binding(function), self.binding(clone))))
+    }
+    fn binding<'a>(&'a self, id: StringID) -> Binding<'a> {
+        Binding {
+            name: self.strings.resolve(id).expect("declaration has a name"),
+            decl: &self.declarations.get(&id).expect("declaration present").decl,
+        }
+    }
+    /// Get this artifacts relocations, in the order they were added, or if the artifact is
+    /// [deterministic](#method.set_deterministic), in the order of the definitions they are in and their offsets
+    pub fn links<'a>(&'a self) -> Box<dyn Iterator<Item = LinkAndDecl<'a>> + 'a> {
+        let mut links = self.links.iter().collect::<Vec<_>>();
+        if self.deterministic {
+            let ranks = self.definition_ranks();
+            links.sort_by_key(|&&(from, _, at, _)| (ranks.get(&from).cloned(), at));
+        }
+        Box::new(links.into_iter().map(move |(from, to, at, reloc)| {
             // FIXME: I think its safe to unwrap since the links are only ever constructed by us and we
             // ensure it has a declaration
-            let (ref from_decl, ref to_decl) = (self.declarations.get(from).expect("declaration present"), self.declarations.get(to).unwrap());
+            let (from_decl, to_decl) = (self.declarations.get(from).expect("declaration present"), self.declarations.get(to).unwrap());
             let from = Binding { name: self.strings.resolve(*from).expect("from link"), decl: &from_decl.decl};
             let to = Binding { name: self.strings.resolve(*to).expect("to link"), decl: &to_decl.decl};
             LinkAndDecl {
@@ -344,16 +1466,77 @@ impl Artifact {
             }
         }))
     }
+    /// Get the relocations in the definition of `name`, in the order they were added
+    pub fn links_from<'a, T: AsRef<str>>(&'a self, name: T) -> Box<dyn Iterator<Item = LinkAndDecl<'a>> + 'a> {
+        let name = name.as_ref().to_string();
+        Box::new(self.links().filter(move |link| link.from.name == name))
+    }
+    /// Get the relocations which refer to `name`, in the order they were added
+    pub fn links_to<'a, T: AsRef<str>>(&'a self, name: T) -> Box<dyn Iterator<Item = LinkAndDecl<'a>> + 'a> {
+        let name = name.as_ref().to_string();
+        Box::new(self.links().filter(move |link| link.to.name == name))
+    }
+    /// Get this artifact's symbol differences, in the order they were added, or if the artifact is deterministic, in
+    /// the order of the definitions they are in and their offsets
+    pub(crate) fn differences<'a>(&'a self) -> Box<dyn Iterator<Item = DifferenceAndDecl<'a>> + 'a> {
+        let mut differences = self.differences.iter().collect::<Vec<_>>();
+        if self.deterministic {
+            let ranks = self.definition_ranks();
+            differences.sort_by_key(|&&(from, _, _, at)| (ranks.get(&from).cloned(), at));
+        }
+        Box::new(differences.into_iter().map(move |&(from, to, minus, at)| {
+            DifferenceAndDecl { from: self.binding(from), to: self.binding(to), minus: self.binding(minus), at }
+        }))
+    }
+    /// The position of each definition in the order they are emitted
+    fn definition_ranks(&self) -> HashMap<StringID, usize> {
+        self.definitions.iter().enumerate().map(|(rank, int_def)| (int_def.name, rank)).collect()
+    }
     /// Declare and define a new symbolic reference with the given `decl` and given `definition`.
     /// This is sugar for `declare` and then `define`
-    pub fn declare_with<T: AsRef<str>>(&mut self, name: T, decl: Decl, definition: Vec<u8>) -> Result<(), Error> {
+    pub fn declare_with<T: AsRef<str>, D: Into<Decl>>(&mut self, name: T, decl: D, definition: Vec<u8>) -> Result<(), Error> {
         self.declare(name.as_ref(), decl)?;
         self.define(name, definition)?;
         Ok(())
     }
+    /// Declare and define a new zero-initialized data object of `size` bytes aligned to `align`, with the given `decl`,
+    /// e.g. a large static buffer; neither its contents nor any space for them exists, in memory or the object file.
+    /// This is `decl` made [zero_init](struct.DataDecl.html#method.zero_init), and then
+    /// [define_zeroed](struct.Artifact.html#method.define_zeroed).
+    pub fn declare_bss<T: AsRef<str>>(&mut self, name: T, decl: DataDecl, size: u64, align: u64) -> Result<(), Error> {
+        self.declare(name.as_ref(), decl.zero_init(align))?;
+        self.define_zeroed(name, size)?;
+        Ok(())
+    }
     /// Declare a new symbolic reference, with the given `decl`.
     /// **Note**: All declarations _must_ precede their definitions.
-    pub fn declare<T: AsRef<str>>(&mut self, name: T, decl: Decl) -> Result<(), Error> {
+    pub fn declare<T: AsRef<str>, D: Into<Decl>>(&mut self, name: T, decl: D) -> Result<(), Error> {
+        let decl = decl.into();
+        if let Decl::Data(d) = decl {
+            match d.align() {
+                Some(align) if !align.is_power_of_two() => return Err(ArtifactError::InvalidAlignment(name.as_ref().to_string(), align).into()),
+                _ => (),
+            }
+            if d.is_common() && (!d.is_global() || d.is_thread_local()) {
+                return Err(ArtifactError::InvalidCommon(name.as_ref().to_string()).into());
+            }
+        }
+        if let Decl::Section(d) = decl {
+            if d.strings_entsize() == Some(0) {
+                return Err(ArtifactError::InvalidEntrySize(name.as_ref().to_string()).into());
+            }
+            if d.merge_entsize() == Some(0) {
+                return Err(ArtifactError::InvalidEntrySize(name.as_ref().to_string()).into());
+            }
+            let custom = d.merge_entsize().is_some() || !d.is_allocated() || d.is_no_bits();
+            if d.is_unwind() && (d.is_writable() || d.is_executable() || d.strings_entsize().is_some() || custom) {
+                return Err(ArtifactError::InvalidUnwindSection(name.as_ref().to_string()).into());
+            }
+            let unloaded = !d.is_allocated() && (d.is_writable() || d.is_executable() || d.is_no_bits());
+            if unloaded || (d.merge_entsize().is_some() && (d.strings_entsize().is_some() || d.is_no_bits())) {
+                return Err(ArtifactError::InvalidSectionFlags(name.as_ref().to_string()).into());
+            }
+        }
         let decl_name = self.strings.get_or_intern(name.as_ref());
         let previous_was_import;
         let new_idecl = {
@@ -367,7 +1550,7 @@ impl Artifact {
                 // we have to check because otherwise duplicate imports cause an error
                 // FIXME: ditto fixme, below, use orderset
                 let mut present = false;
-                for &(ref name, _) in self.imports.iter() {
+                for (name, _) in self.imports.iter() {
                     if *name == decl_name {
                         present = true;
                     }
@@ -383,7 +1566,7 @@ impl Artifact {
             _ if previous_was_import => {
                 let mut index = None;
                 // FIXME: do binary search or make imports an indexmap
-                for (i, &(ref name, _)) in self.imports.iter().enumerate() {
+                for (i, (name, _)) in self.imports.iter().enumerate() {
                     if *name == decl_name {
                         index = Some (i);
                     }
@@ -407,7 +1590,33 @@ impl Artifact {
     /// Defines a _previously declared_ program object.
     /// **NB**: If you attempt to define an import, this will return an error.
     /// If you attempt to define something which has not been declared, this will return an error.
+    /// If the artifact has a [spill](#structfield.spill) file, the body of a function is appended to it instead.
     pub fn define<T: AsRef<str>>(&mut self, name: T, data: Vec<u8>) -> Result<(), ArtifactError> {
+        let function = self.strings.get(name.as_ref())
+            .and_then(|id| self.declarations.get(&id))
+            .is_some_and(|stype| match stype.decl {
+                Decl::Function(_) => !stype.defined,
+                _ => false,
+            });
+        match self.spill {
+            Some(ref path) if function && !data.is_empty() => {
+                let spill = |path: &PathBuf| -> io::Result<u64> {
+                    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                    let offset = file.seek(SeekFrom::End(0))?;
+                    file.write_all(&data)?;
+                    Ok(offset)
+                };
+                let offset = spill(path).map_err(|e| ArtifactError::Spill(name.as_ref().to_string(), path.display().to_string(), e.to_string()))?;
+                let source = DataSource::File { path: path.clone(), offset, size: data.len() as u64 };
+                self.define_from(name, source)
+            },
+            _ => self.define_from(name, DataSource::Bytes(data)),
+        }
+    }
+    /// Defines a _previously declared_ program object whose bytes come from `source`, e.g. a range of a file
+    /// which is only read when the artifact is written.
+    /// This has all of the same invariants as [define](struct.Artifact.html#method.define).
+    pub fn define_from<T: AsRef<str>>(&mut self, name: T, data: DataSource) -> Result<(), ArtifactError> {
         let decl_name = self.strings.get_or_intern(name.as_ref());
         match self.declarations.get_mut(&decl_name) {
             Some(ref mut stype) => {
@@ -415,15 +1624,86 @@ impl Artifact {
                     return Err(ArtifactError::DuplicateDefinition(name.as_ref().to_string()));
                 }
                 let prop = match stype.decl {
-                    Decl::CString { global } => Prop { global, function: false, writable: false, cstring: true },
-                    Decl::Data { global, writable } => Prop { global, function: false, writable, cstring: false },
-                    Decl::Function { global } => Prop { global, function: true, writable: false, cstring: false},
+                    Decl::CString(c) => Prop { global: c.is_global(), cstring: true, no_merge: !c.is_mergeable(), ..Prop::default() },
+                    Decl::Data(d) => Prop {
+                        global: d.scope() != Scope::Local,
+                        writable: d.is_writable(),
+                        weak: d.is_weak(),
+                        zero_init: d.align(),
+                        tls: d.is_thread_local(),
+                        entsize: self.pool_entsizes.get(&decl_name).cloned(),
+                        common: d.is_common(),
+                        hidden: d.is_hidden(),
+                        ..Prop::default()
+                    },
+                    Decl::Function(f) => Prop {
+                        global: f.scope() != Scope::Local,
+                        function: true,
+                        cold: f.is_cold() || f.is_noreturn(),
+                        weak: f.is_weak(),
+                        ifunc: f.is_ifunc(),
+                        thumb: is_thumb_only(self.target.architecture) || f.is_thumb(),
+                        hidden: f.is_hidden(),
+                        ..Prop::default()
+                    },
+                    Decl::DebugSection(d) => Prop {
+                        cstring: d.is_strings(),
+                        debug: true,
+                        no_merge: d.is_strings() && !d.is_mergeable(),
+                        ..Prop::default()
+                    },
+                    Decl::Section(d) => Prop {
+                        writable: d.is_writable(),
+                        cstring: d.strings_entsize().is_some(),
+                        section: true,
+                        executable: d.is_executable(),
+                        entsize: d.strings_entsize().or(d.merge_entsize()),
+                        no_merge: d.strings_entsize().is_some() && !d.is_mergeable(),
+                        unwind: d.is_unwind(),
+                        not_allocated: !d.is_allocated(),
+                        no_bits: d.is_no_bits(),
+                        elf_type: d.elf_section_type(),
+                        elf_flags: d.elf_section_flags(),
+                        ..Prop::default()
+                    },
                     _ if stype.decl.is_import() => return Err(ArtifactError::ImportDefined(name.as_ref().to_string()).into()),
+                    Decl::Absolute(_) => return Err(ArtifactError::AbsoluteDefined(name.as_ref().to_string())),
                     _ => unimplemented!("New Decl variant added but not covered in define method"),
                 };
-                self.definitions.insert(InternalDefinition {
-                    name: decl_name,
-                    data,
+                if let DataSource::Fill { ref pattern, size } = data {
+                    if pattern.is_empty() && size > 0 {
+                        return Err(ArtifactError::EmptyFillPattern(name.as_ref().to_string()));
+                    }
+                }
+                // the linker splits a merged string section at each null character
+                let entsize = prop.entsize.unwrap_or(1);
+                let terminated = match data {
+                    DataSource::Bytes(ref bytes) => {
+                        let len = bytes.len() as u64;
+                        len >= entsize && len.is_multiple_of(entsize) && bytes[(len - entsize) as usize..].iter().all(|&byte| byte == 0)
+                    },
+                    DataSource::Zeros(size) => size > 0 && size.is_multiple_of(entsize),
+                    DataSource::Fill { ref pattern, size } => {
+                        size >= entsize && size.is_multiple_of(entsize)
+                            && (size - entsize..size).all(|i| pattern[(i % pattern.len() as u64) as usize] == 0)
+                    },
+                    DataSource::File { .. } => true,
+                };
+                if prop.cstring && !prop.debug && !terminated {
+                    return Err(ArtifactError::UnterminatedString(name.as_ref().to_string()));
+                }
+                // zero-initialized data, and a section without bits, only records its size
+                let zeroed = prop.zero_init.is_some() || prop.no_bits;
+                let data = match data {
+                    DataSource::Bytes(ref bytes) if zeroed && bytes.iter().all(|&byte| byte == 0) => DataSource::Zeros(bytes.len() as u64),
+                    DataSource::Zeros(_) => data,
+                    DataSource::Fill { ref pattern, size } if zeroed && pattern.iter().all(|&byte| byte == 0) => DataSource::Zeros(size),
+                    _ if zeroed => return Err(ArtifactError::NonZeroDefinition(name.as_ref().to_string())),
+                    data => data,
+                };
+                self.definitions.insert(InternalDefinition {
+                    name: decl_name,
+                    data,
                     prop,
                 });
                 stype.define();
@@ -432,38 +1712,395 @@ impl Artifact {
         }
         Ok(())
     }
+    /// Replace the contents of the definition of `name` with `data`, which are checked as they are by
+    /// [define](#method.define), e.g. to emit the artifact again after recompiling a function in a long-lived session.
+    /// Its declaration and the links from and to it are kept; a link from it which no longer fits in its new contents
+    /// is an error when the artifact is emitted. If the new contents are rejected, the definition is left as it was.
+    ///
+    /// To replace its links as well, [remove](#method.remove_definition) the definition and define it again.
+    pub fn redefine<T: AsRef<str>>(&mut self, name: T, data: Vec<u8>) -> Result<(), ArtifactError> {
+        let old = self.take_definition(name.as_ref())?;
+        if let Err(e) = self.define(name.as_ref(), data) {
+            self.declarations.get_mut(&old.name).expect("declaration present").define();
+            self.definitions.insert(old);
+            return Err(e);
+        }
+        Ok(())
+    }
+    /// Remove the definition of `name`, and the links and differences from it, so that it can be defined again with
+    /// new contents and links. Its declaration, and the links to it from the other definitions, are kept; until it
+    /// is defined again, emitting the artifact fails, as it is declared but not defined.
+    pub fn remove_definition<T: AsRef<str>>(&mut self, name: T) -> Result<(), ArtifactError> {
+        let old = self.take_definition(name.as_ref())?;
+        self.links.retain(|&(from, _, _, _)| from != old.name);
+        self.differences.retain(|&(from, _, _, _)| from != old.name);
+        Ok(())
+    }
+    /// Remove the definition of `name`, and mark its declaration as not defined
+    fn take_definition(&mut self, name: &str) -> Result<InternalDefinition, ArtifactError> {
+        let id = self.strings.get(name)
+            .filter(|id| self.declarations.contains_key(id))
+            .ok_or_else(|| ArtifactError::Undeclared(name.to_string()))?;
+        let old = self.definitions.iter().find(|int_def| int_def.name == id).cloned()
+            .ok_or_else(|| ArtifactError::NotDefined(name.to_string()))?;
+        self.definitions.remove(&old);
+        self.declarations.get_mut(&id).expect("declaration present").defined = false;
+        Ok(old)
+    }
+    /// Define the null-terminated string `contents` in this artifact's pool, and get the name of the local string it
+    /// is defined as, to link to; an offset into it is the addend of an explicit relocation. Defining the same
+    /// contents again gets the same string, which the linker merges with identical strings from other objects.
+    pub fn define_cstring(&mut self, contents: Vec<u8>) -> Result<String, Error> {
+        self.define_pooled(true, contents)
+    }
+    /// Define the read-only constant `contents`, e.g. a floating point literal, in this artifact's pool, and get the
+    /// name of the local data object it is defined as, to link to; an offset into it is the addend of an explicit
+    /// relocation. Defining the same contents again gets the same constant, and a constant whose size is a power
+    /// of two is merged by the linker with identical constants of its size from other objects.
+    ///
+    /// **NB**: constants are only merged by the linker for ELF targets
+    pub fn define_const(&mut self, contents: Vec<u8>) -> Result<String, Error> {
+        self.define_pooled(false, contents)
+    }
+    fn define_pooled(&mut self, string: bool, contents: Vec<u8>) -> Result<String, Error> {
+        let key = (string, contents);
+        if let Some(&id) = self.pool.get(&key) {
+            return Ok(self.strings.resolve(id).expect("pooled definition has a name").to_string());
+        }
+        let (prefix, decl): (&str, Decl) = if string { ("str", Decl::cstring().into()) } else { ("const", Decl::data().into()) };
+        // skip any name the frontend has already declared
+        let name = (self.pool.len()..)
+            .map(|n| format!(".L{}.{}", prefix, n))
+            .find(|name| self.strings.get(name.as_str()).is_none_or(|id| !self.declarations.contains_key(&id)))
+            .expect("an unused name");
+        // the string is checked before it is declared, so an invalid one isn't left undefined
+        if string && key.1.last() != Some(&0) {
+            return Err(ArtifactError::UnterminatedString(name).into());
+        }
+        self.declare(&name, decl)?;
+        let id = self.strings.get_or_intern(name.as_str());
+        let size = key.1.len() as u64;
+        if !string && size.is_power_of_two() {
+            self.pool_entsizes.insert(id, size);
+        }
+        self.define(&name, key.1.clone())?;
+        self.pool.insert(key, id);
+        Ok(name)
+    }
+    /// Defines a _previously declared_ data object as `size` zeros, which are not held in memory; if it was declared
+    /// [zero_init](struct.DataDecl.html#method.zero_init), they take up no space in the object file either.
+    /// This has all of the same invariants as [define](struct.Artifact.html#method.define).
+    pub fn define_zeroed<T: AsRef<str>>(&mut self, name: T, size: u64) -> Result<(), ArtifactError> {
+        self.define_from(name, DataSource::Zeros(size))
+    }
+    /// Defines a _previously declared_ data object as `size` bytes of `pattern` repeated, of which only the pattern
+    /// is held in memory; the last repetition is truncated if `size` is not a multiple of the pattern's length,
+    /// e.g. 5 bytes of `[0xde, 0xad]` are `de ad de ad de`.
+    /// This has all of the same invariants as [define](struct.Artifact.html#method.define).
+    pub fn define_filled<T: AsRef<str>>(&mut self, name: T, pattern: Vec<u8>, size: u64) -> Result<(), ArtifactError> {
+        self.define_from(name, DataSource::Fill { pattern, size })
+    }
+    /// Declare `alias` as another name for the previously declared function, data object or string `target`,
+    /// which is bound with its own `scope`; e.g. a local alias of a global function can be referred to
+    /// without the risk of the global name being preempted by another definition when linking.
+    ///
+    /// Relocations from the alias are relocations from `target`, and the alias of an alias is another name for
+    /// the same definition.
+    /// An import or a common symbol can't be aliased, as it has no section or address in this artifact for the alias to share.
+    pub fn define_alias<A: AsRef<str>, T: AsRef<str>>(&mut self, alias: A, target: T, scope: Scope) -> Result<(), Error> {
+        self.define_alias_with(alias, target, scope, Visibility::Default)
+    }
+    /// Declare `alias` as another name for `target`, as [define_alias](#method.define_alias) does, which has the
+    /// `visibility` outside of the module it is linked into, e.g. a hidden alias of a global function which the
+    /// module's own calls bind to, so they can't be preempted. The visibility of a local alias is meaningless.
+    ///
+    /// **NB**: Mach-O only has hidden (private external) symbols, so a protected alias is only supported for ELF targets
+    pub fn define_alias_with<A: AsRef<str>, T: AsRef<str>>(&mut self, alias: A, target: T, scope: Scope, visibility: Visibility) -> Result<(), Error> {
+        let (alias, target) = (alias.as_ref(), target.as_ref());
+        let target_id = self.strings.get_or_intern(target);
+        let target_id = self.aliases.get(&target_id).cloned().unwrap_or(target_id);
+        let invalid = || ArtifactError::InvalidAlias(alias.to_string(), target.to_string());
+        let decl: Decl = match self.declarations.get(&target_id).map(|int| int.decl) {
+            Some(Decl::Function(f)) => f.with_scope(scope).into(),
+            // the linker places a common symbol, so its alias would have no address
+            Some(Decl::Data(d)) if d.is_common() => return Err(invalid().into()),
+            Some(Decl::Data(d)) => d.with_scope(scope).into(),
+            Some(Decl::CString(_)) => match scope {
+                Scope::Local => Decl::cstring().local().into(),
+                Scope::Global => Decl::cstring().global().into(),
+                Scope::Weak => return Err(invalid().into()),
+            },
+            Some(_) => return Err(invalid().into()),
+            None => return Err(ArtifactError::Undeclared(target.to_string()).into()),
+        };
+        let alias_id = self.strings.get_or_intern(alias);
+        if self.declarations.contains_key(&alias_id) {
+            return Err(ArtifactError::DuplicateDefinition(alias.to_string()).into());
+        }
+        let mut int = InternalDecl::new(decl);
+        // it is defined by its target
+        int.define();
+        self.declarations.insert(alias_id, int);
+        self.aliases.insert(alias_id, target_id);
+        if visibility != Visibility::Default {
+            self.alias_visibilities.insert(alias_id, visibility);
+        }
+        Ok(())
+    }
+    /// Declare `entry` as a local data object which maps `function` to its line program at `offset` in the
+    /// previously declared `.debug_line` debug section, so a tool can find the line program of an address without
+    /// scanning the section: the entry is the pointer sized address of `function`, followed by the 4 byte
+    /// (32-bit DWARF) offset, and both are relocated.
+    pub fn define_line_entry<E: AsRef<str>, F: AsRef<str>>(&mut self, entry: E, function: F, offset: u32) -> Result<(), Error> {
+        let (entry, function) = (entry.as_ref(), function.as_ref());
+        let invalid = || ArtifactError::InvalidLineEntry(entry.to_string(), function.to_string());
+        {
+            let decl = |name: &str| self.strings.get(name).and_then(|id| self.declarations.get(&id)).map(|int| int.decl);
+            match decl(function) {
+                Some(Decl::Function(_)) | Some(Decl::FunctionImport) => (),
+                Some(_) => return Err(invalid().into()),
+                None => return Err(ArtifactError::Undeclared(function.to_string()).into()),
+            }
+            if !decl(".debug_line").is_some_and(|decl| decl.is_debug_section()) {
+                return Err(invalid().into());
+            }
+        }
+        // the offset is the addend of the relocation
+        if offset > i32::MAX as u32 {
+            return Err(ArtifactError::RelocationOverflow(entry.to_string(), ".debug_line".to_string()).into());
+        }
+        let pointer = if make_ctx(&self.target).is_big() { 8 } else { 4 };
+        self.declare_with(entry, Decl::data(), vec![0; pointer + 4])?;
+        self.link(Link { from: entry, to: function, at: 0 })?;
+        self.link_with(Link { from: entry, to: ".debug_line", at: pointer as u64 }, Reloc::Absolute { size: 4, addend: offset as i32 })
+    }
     /// Declare `import` to be an import with `kind`.
     /// This is just sugar for `declare("name", Decl::FunctionImport)` or `declare("data", Decl::DataImport)`
     pub fn import<T: AsRef<str>>(&mut self, import: T, kind: ImportKind) -> Result<(), Error> {
         self.declare(
             import.as_ref(),
-            match &kind {
-                &ImportKind::Function => Decl::FunctionImport,
-                &ImportKind::Data => Decl::DataImport,
+            match kind {
+                ImportKind::Function => Decl::FunctionImport,
+                ImportKind::Data => Decl::DataImport,
             },
         )?;
         Ok(())
     }
+    /// Set whether the previously declared `import` may be resolved from a shared library, which it may by default.
+    ///
+    /// An import which is not dynamic has to be defined by another object in the same link, so when linking a shared
+    /// library or an executable, the linker neither adds it to `.dynsym` nor leaves it for the dynamic linker
+    /// (it is emitted as an undefined symbol with hidden visibility).
+    ///
+    /// **NB**: this is currently only emitted for ELF targets
+    pub fn set_dynamic_import<T: AsRef<str>>(&mut self, import: T, dynamic: bool) -> Result<(), ArtifactError> {
+        let name = import.as_ref();
+        let id = self.strings.get_or_intern(name);
+        match self.declarations.get(&id).map(|int| int.decl) {
+            Some(ref decl) if decl.is_import() => (),
+            Some(_) => return Err(ArtifactError::NotAnImport(name.to_string())),
+            None => return Err(ArtifactError::Undeclared(name.to_string())),
+        }
+        if dynamic {
+            self.static_imports.remove(&id);
+        } else {
+            self.static_imports.insert(id);
+        }
+        Ok(())
+    }
+    /// Set whether the previously declared `import` is weak, which it is not by default.
+    ///
+    /// A weak import, e.g. a C `__attribute__((weak))` extern, is not an error when nothing defines it; its address
+    /// is then zero, so code checks it before using it. It is emitted as a weak undefined symbol in ELF, and as a
+    /// weak reference in Mach-O.
+    pub fn set_weak_import<T: AsRef<str>>(&mut self, import: T, weak: bool) -> Result<(), ArtifactError> {
+        let name = import.as_ref();
+        let id = self.strings.get_or_intern(name);
+        match self.declarations.get(&id).map(|int| int.decl) {
+            Some(ref decl) if decl.is_import() => (),
+            Some(_) => return Err(ArtifactError::NotAnImport(name.to_string())),
+            None => return Err(ArtifactError::Undeclared(name.to_string())),
+        }
+        if weak {
+            self.weak_imports.insert(id);
+        } else {
+            self.weak_imports.remove(&id);
+        }
+        Ok(())
+    }
+    /// Set whether the previously declared `import` is imported from a DLL, i.e. is `__declspec(dllimport)`,
+    /// which it is not by default.
+    ///
+    /// The address of a DLL import is only known once the loader has filled in the import address table, so rather
+    /// than relocating to the import itself, a backend relocates to its entry in the table, the pointer named by
+    /// [dll_imports](#method.dll_imports), e.g. `__imp_foo`, and the code loads the address from there.
+    ///
+    /// **NB**: this is only supported by a [custom backend](backend/trait.ObjectBackend.html) for COFF; ELF and
+    /// Mach-O have no DLL imports
+    pub fn set_dll_import<T: AsRef<str>>(&mut self, import: T, dll: bool) -> Result<(), ArtifactError> {
+        let name = import.as_ref();
+        let id = self.strings.get_or_intern(name);
+        match self.declarations.get(&id).map(|int| int.decl) {
+            Some(ref decl) if decl.is_import() => (),
+            Some(_) => return Err(ArtifactError::NotAnImport(name.to_string())),
+            None => return Err(ArtifactError::Undeclared(name.to_string())),
+        }
+        if dll {
+            self.dll_imports.insert(id);
+        } else {
+            self.dll_imports.remove(&id);
+        }
+        Ok(())
+    }
+    /// Export the previously declared global function or data object `definition` from the DLL it is linked into,
+    /// i.e. make it `__declspec(dllexport)`.
+    ///
+    /// A backend passes the exports to the linker as the `/EXPORT:` directives of
+    /// [linker_directives](#method.linker_directives), in the `.drectve` section, which adds them to the DLL's
+    /// export table (`.edata`).
+    ///
+    /// **NB**: this is only supported by a [custom backend](backend/trait.ObjectBackend.html) for COFF; ELF and
+    /// Mach-O export every global symbol
+    pub fn set_dll_export<T: AsRef<str>>(&mut self, definition: T) -> Result<(), ArtifactError> {
+        let name = definition.as_ref();
+        let id = self.strings.get_or_intern(name);
+        match self.declarations.get(&id).map(|int| int.decl) {
+            Some(Decl::Function(f)) if f.scope() != Scope::Local => (),
+            Some(Decl::Data(d)) if d.scope() != Scope::Local && !d.is_common() => (),
+            Some(_) => return Err(ArtifactError::NotExportable(name.to_string())),
+            None => return Err(ArtifactError::Undeclared(name.to_string())),
+        }
+        self.dll_exports.insert(id);
+        Ok(())
+    }
+    /// Assign the Mach-O `section` to the segment named `segment`, which need not be a standard segment.
+    ///
+    /// The sections are `__text`, `__cstring` and `__textcoal_nt`, in `__TEXT` by default, and `__data`, `__const`,
+    /// which holds read-only data, and `__datacoal_nt`, in `__DATA` by default, and `__compact_unwind`, in `__LD`; e.g. assigning `__const` to
+    /// `__DATA_CONST` makes its contents read-only once the dynamic linker has fixed them up.
+    /// An object file has a single segment holding every section, each of which names the segment it is linked into;
+    /// the linker then sets each segment's protections from its name, e.g. `__TEXT` is readable and executable.
+    pub fn set_macho_segment<S: AsRef<str>, G: AsRef<str>>(&mut self, section: S, segment: G) -> Result<(), ArtifactError> {
+        let segment = segment.as_ref();
+        if segment.is_empty() || segment.len() > 16 || segment.contains('\0') {
+            return Err(ArtifactError::InvalidSegmentName(segment.to_string()));
+        }
+        self.macho_segments.insert(section.as_ref().to_string(), segment.to_string());
+        Ok(())
+    }
     /// Link a relocation at `link.at` from `link.from` to `link.to`
     /// **NB**: If either `link.from` or `link.to` is undeclared, then this will return an error.
     /// If `link.from` is an import you previously declared, this will also return an error.
     pub fn link<'a>(&mut self, link: Link<'a>) -> Result<(), Error> {
-        self.link_aux(link, None)
-    }
-    /// A variant of `link` with a RelocOverride provided. Has all of the same invariants as
-    /// `link`.
-    pub fn link_with<'a>(&mut self, link: Link<'a>, reloc: RelocOverride) -> Result<(), Error> {
-        self.link_aux(link, Some(reloc))
+        self.link_with(link, Reloc::Auto)
     }
-
-    /// Shared implementation of `link` and `link_with`.
-    fn link_aux<'a>(&mut self, link: Link<'a>, reloc: Option<RelocOverride>) -> Result<(), Error> {
+    /// A variant of `link` with the kind of relocation, `reloc`, provided; e.g. a [RelocOverride](struct.RelocOverride.html).
+    /// Has all of the same invariants as `link`.
+    /// If `reloc` is a `Reloc::Size`, then `link.to` must also be declared as a function, data object, or string.
+    /// If `reloc` is a `Reloc::GotOffset` or a `Reloc::Relative`, then `link.to` must not be a debug section,
+    /// which has no address.
+    /// If `reloc` is a `Reloc::Plt`, then `link.to` must be declared as a function, or a function import.
+    /// If `reloc` is a `Reloc::Tls`, then `link.to` must be declared as thread-local data, or a data import,
+    /// and thread-local data can only be the target of a `Reloc::Tls` or a raw relocation.
+    pub fn link_with<'a, R: Into<Reloc>>(&mut self, link: Link<'a>, reloc: R) -> Result<(), Error> {
+        let reloc = reloc.into();
         let (link_from, link_to) = (self.strings.get_or_intern(link.from), self.strings.get_or_intern(link.to));
+        // an alias has no contents of its own
+        let link_from = self.aliases.get(&link_from).cloned().unwrap_or(link_from);
         match (self.declarations.get(&link_from), self.declarations.get(&link_to)) {
-            (Some(ref from_type), Some(_)) => {
+            (Some(from_type), Some(to_type)) => {
                 if from_type.decl.is_import() {
                     return Err(ArtifactError::RelocateImport(link.from.to_string()).into());
                 }
+                if let Decl::Data(d) = from_type.decl {
+                    if d.is_zero_init() {
+                        return Err(ArtifactError::RelocateZeroInit(link.from.to_string()).into());
+                    }
+                }
+                if let Reloc::Absolute { size, .. } = reloc {
+                    if !size.is_power_of_two() || size > 8 {
+                        return Err(ArtifactError::UnsupportedRelocationSize(size).into());
+                    }
+                }
+                if let Reloc::Raw { size: Some(size), .. } = reloc {
+                    if !size.is_power_of_two() || size > 8 {
+                        return Err(ArtifactError::UnsupportedRelocationSize(size).into());
+                    }
+                }
+                // only a TLS model locates a thread-local data object; a raw relocation is checked by the encoder
+                match to_type.decl {
+                    Decl::Data(d) if d.is_thread_local() => if !reloc.is_tls() && !reloc.is_raw() && !reloc.is_section_offset() {
+                        return Err(ArtifactError::ThreadLocalAddress(link.to.to_string()).into());
+                    },
+                    // an import may be thread-local in the library which defines it
+                    Decl::DataImport => (),
+                    _ => if reloc.is_tls() {
+                        return Err(ArtifactError::NotThreadLocal(link.to.to_string()).into());
+                    },
+                }
+                if let Reloc::Tls { model, addend } = reloc {
+                    if addend != 0 && model != TlsModel::LocalExec && model != TlsModel::LocalDynamicOffset {
+                        return Err(ArtifactError::ThreadLocalOffset(link.to.to_string()).into());
+                    }
+                    let local_dynamic = model == TlsModel::LocalDynamic || model == TlsModel::LocalDynamicOffset;
+                    if local_dynamic && to_type.decl.is_import() {
+                        return Err(ArtifactError::ThreadLocalImport(link.to.to_string()).into());
+                    }
+                }
+                if reloc.is_plt() {
+                    match to_type.decl {
+                        Decl::Function(_) | Decl::FunctionImport => (),
+                        _ => return Err(ArtifactError::NotAPltTarget(link.to.to_string()).into()),
+                    }
+                }
+                if reloc.is_got_base() && (link.to != GOT_BASE || !to_type.decl.is_import()) {
+                    return Err(ArtifactError::NotTheGotBase(link.to.to_string()).into());
+                }
+                if let Reloc::GotOffset { size, .. } | Reloc::DataRelative { size, .. } = reloc {
+                    if size != 4 && size != 8 {
+                        return Err(ArtifactError::UnsupportedRelocationSize(size).into());
+                    }
+                }
+                if let Reloc::Relative { size, .. } = reloc {
+                    if !size.is_power_of_two() || size > 8 {
+                        return Err(ArtifactError::UnsupportedRelocationSize(size).into());
+                    }
+                }
+                let addressed = reloc.is_got_offset() || reloc.is_relative() || reloc.is_data_relative() || reloc.is_got();
+                if addressed && to_type.decl.is_debug_section() {
+                    return Err(ArtifactError::NotAddressable(link.to.to_string()).into());
+                }
+                if let Reloc::Size { size, .. } = reloc {
+                    if size != 4 && size != 8 {
+                        return Err(ArtifactError::UnsupportedRelocationSize(size).into());
+                    }
+                    // only definitions have a size
+                    if to_type.decl.is_import() || to_type.decl.is_absolute() || to_type.decl.is_debug_section() || to_type.decl.is_section() {
+                        return Err(ArtifactError::UnsizedRelocationTarget(link.to.to_string()).into());
+                    }
+                }
+                if let Reloc::DebugSecRel { size, .. } = reloc {
+                    if size != 4 && size != 8 {
+                        return Err(ArtifactError::UnsupportedRelocationSize(size).into());
+                    }
+                    if !to_type.decl.is_debug_section() {
+                        return Err(ArtifactError::NotADebugSection(link.to.to_string()).into());
+                    }
+                }
+                if let Reloc::SectionOffset { size, .. } = reloc {
+                    if size != 4 && size != 8 {
+                        return Err(ArtifactError::UnsupportedRelocationSize(size).into());
+                    }
+                    // a common symbol is placed by the linker
+                    let weak = match to_type.decl {
+                        Decl::Function(f) => f.is_weak(),
+                        Decl::Data(d) => d.is_weak() || d.is_common(),
+                        _ => false,
+                    };
+                    if to_type.decl.is_import() || to_type.decl.is_absolute() || weak {
+                        return Err(ArtifactError::ExternalSectionOffset(link.to.to_string()).into());
+                    }
+                }
                 let link = (link_from, link_to, link.at, reloc);
                 self.links.push(link);
             }
@@ -477,51 +2114,1062 @@ impl Artifact {
         Ok(())
 
     }
+    /// Link the 4 byte field at `at` in `from` to the address of the GOT base relative to the address `base` bytes
+    /// into `from`, which the code holds in a register, e.g. the return address of the `call __x86.get_pc_thunk.bx`
+    /// of an i386 position independent function's prologue, which is the address of the
+    /// `add ebx, _GLOBAL_OFFSET_TABLE_` which follows it, and whose immediate is the field. The GOT base,
+    /// `_GLOBAL_OFFSET_TABLE_`, is imported unless it is already.
+    ///
+    /// **NB**: This is currently only supported for x86 ELF targets
+    pub fn link_got_base(&mut self, from: &str, at: u64, base: u64) -> Result<(), Error> {
+        // the relocation is relative to the field, so its addend is the field's distance from the address
+        let addend = at as i64 - base as i64;
+        if addend < i64::from(i32::MIN) || addend > i64::from(i32::MAX) {
+            return Err(format_err!("The GOT base field at {:#x} in {} is too far from {:#x}", at, from, base));
+        }
+        if !self.strings.get(GOT_BASE).is_some_and(|id| self.declarations.contains_key(&id)) {
+            self.import(GOT_BASE, ImportKind::Data)?;
+        }
+        self.link_with(Link { from, to: GOT_BASE, at }, Reloc::GotBase { addend: addend as i32 })
+    }
+    /// Link the 4 byte field at `link.at` in `link.from` to the address of `link.to` minus the address of `minus`,
+    /// e.g. for a jump table of offsets from its start, or i386 position independent code which addresses data
+    /// relative to a label. Both `link.to` and `minus` must be functions, data objects or strings, and the field's
+    /// contents are replaced.
+    ///
+    /// On Mach-O this is a section difference relocation and its pair, which are scattered on i386.
+    /// ELF has no relocation for the difference of two arbitrary symbols, so `minus` must be `link.from` itself,
+    /// and the field is relocated relative to itself.
+    pub fn link_difference<'a>(&mut self, link: Link<'a>, minus: &str) -> Result<(), Error> {
+        let (from, to, minus_id) = (self.strings.get_or_intern(link.from), self.strings.get_or_intern(link.to), self.strings.get_or_intern(minus));
+        // an alias has no contents of its own
+        let from = self.aliases.get(&from).cloned().unwrap_or(from);
+        let minus_id = self.aliases.get(&minus_id).cloned().unwrap_or(minus_id);
+        match self.declarations.get(&from) {
+            Some(decl) if decl.decl.is_import() => return Err(ArtifactError::RelocateImport(link.from.to_string()).into()),
+            Some(&InternalDecl { decl: Decl::Data(d), .. }) if d.is_zero_init() => return Err(ArtifactError::RelocateZeroInit(link.from.to_string()).into()),
+            Some(_) => (),
+            None => return Err(ArtifactError::Undeclared(link.from.to_string()).into()),
+        }
+        for &(id, name) in &[(to, link.to), (minus_id, minus)] {
+            match self.declarations.get(&id).map(|decl| decl.decl) {
+                Some(Decl::Data(d)) if d.is_thread_local() => return Err(ArtifactError::ThreadLocalAddress(name.to_string()).into()),
+                Some(Decl::Data(d)) if d.is_common() => return Err(ArtifactError::InvalidDifference(link.to.to_string(), minus.to_string()).into()),
+                Some(Decl::Function(_)) | Some(Decl::Data(_)) | Some(Decl::CString(_)) => (),
+                Some(_) => return Err(ArtifactError::InvalidDifference(link.to.to_string(), minus.to_string()).into()),
+                None => return Err(ArtifactError::Undeclared(name.to_string()).into()),
+            }
+        }
+        self.differences.push((from, to, minus_id, link.at));
+        Ok(())
+    }
+    /// Define the jump table `table` as a 4 byte entry for each of `targets`, in order, holding the offset of the
+    /// target's label from the start of the table, as a computed `switch` adds to the table's address. Each target
+    /// is a definition and the offset of the label within it.
+    ///
+    /// An entry whose label is in `table` itself, e.g. a table inlined into the function which jumps through it,
+    /// is resolved now, so a table whose labels are all local needs no relocations. Any other entry is relocated
+    /// relative to itself, since the table and its target are in different sections. The targets must be defined
+    /// by this artifact, and not weak, so the offsets are fixed by the link.
+    ///
+    /// **NB**: entries which need relocating are currently only supported for ELF targets
+    pub fn define_jump_table(&mut self, table: &str, targets: &[(&str, u64)]) -> Result<(), Error> {
+        let id = self.strings.get_or_intern(table);
+        // an alias has no contents of its own
+        let id = self.aliases.get(&id).cloned().unwrap_or(id);
+        match self.declarations.get(&id).map(|decl| decl.decl) {
+            Some(Decl::Data(d)) if d.is_zero_init() => return Err(ArtifactError::RelocateZeroInit(table.to_string()).into()),
+            Some(Decl::Data(d)) if d.is_thread_local() => return Err(ArtifactError::ThreadLocalAddress(table.to_string()).into()),
+            Some(Decl::Function(_)) | Some(Decl::Data(_)) => (),
+            Some(_) => return Err(ArtifactError::NotAddressable(table.to_string()).into()),
+            None => return Err(ArtifactError::Undeclared(table.to_string()).into()),
+        }
+        let ctx = make_ctx(&self.target);
+        let mut entries = vec![0; 4 * targets.len()];
+        let mut links = Vec::new();
+        for (i, &(target, offset)) in targets.iter().enumerate() {
+            let target_id = self.strings.get_or_intern(target);
+            let target_id = self.aliases.get(&target_id).cloned().unwrap_or(target_id);
+            match self.declarations.get(&target_id).map(|decl| decl.decl) {
+                Some(Decl::Function(f)) if f.is_weak() => return Err(ArtifactError::ExternalSectionOffset(target.to_string()).into()),
+                Some(Decl::Data(d)) if d.is_weak() || d.is_common() => return Err(ArtifactError::ExternalSectionOffset(target.to_string()).into()),
+                Some(Decl::Data(d)) if d.is_thread_local() => return Err(ArtifactError::ThreadLocalAddress(target.to_string()).into()),
+                Some(Decl::Function(_)) | Some(Decl::Data(_)) | Some(Decl::CString(_)) => (),
+                Some(ref decl) if decl.is_import() || decl.is_absolute() => return Err(ArtifactError::ExternalSectionOffset(target.to_string()).into()),
+                Some(_) => return Err(ArtifactError::InvalidDifference(target.to_string(), table.to_string()).into()),
+                None => return Err(ArtifactError::Undeclared(target.to_string()).into()),
+            }
+            let at = 4 * i as u64;
+            if target_id == id {
+                if offset > i32::MAX as u64 {
+                    return Err(format_err!("The label {:#x} in {} is too far from the start of the table", offset, table));
+                }
+                entries.pwrite_with(offset as u32, at as usize, ctx.le)?;
+            } else {
+                // the field is relative to itself, so its distance from the start of the table is added back
+                let addend = at + offset;
+                if addend > i32::MAX as u64 {
+                    return Err(format_err!("The label {:#x} in {} is too far from the entry {} of {}", offset, target, i, table));
+                }
+                links.push((target, at, addend as i32));
+            }
+        }
+        self.define(table, entries)?;
+        for (to, at, addend) in links {
+            self.link_with(Link { from: table, to, at }, Reloc::Relative { size: 4, addend })?;
+        }
+        Ok(())
+    }
 
-    /// Get set of non-import declarations that have not been defined. This must be an empty set in
+    /// Get the index of the address of the previously declared `symbol` in the DWARF 5 `.debug_addr` table, which
+    /// `DW_FORM_addrx` attributes refer to; the same symbol always has the same index, in the order they were added.
+    ///
+    /// The table is the `.debug_addr` debug section, which is defined by faerie: a header, for a unit whose
+    /// `DW_AT_addr_base` is 8, followed by a pointer sized entry for each address. Only these entries are relocated,
+    /// rather than every address in `.debug_info`.
+    ///
+    /// **NB**: as with other debug sections, this is currently only supported for ELF targets
+    pub fn debug_addr<T: AsRef<str>>(&mut self, symbol: T) -> Result<u64, Error> {
+        const DEBUG_ADDR: &str = ".debug_addr";
+        let id = self.strings.get_or_intern(symbol.as_ref());
+        match self.declarations.get(&id).map(|int| int.decl) {
+            Some(Decl::DebugSection(_)) => return Err(ArtifactError::NotAddressable(symbol.as_ref().to_string()).into()),
+            Some(_) => (),
+            None => return Err(ArtifactError::Undeclared(symbol.as_ref().to_string()).into()),
+        }
+        if let Some(index) = self.debug_addrs.get_index_of(&id) {
+            return Ok(index as u64);
+        }
+        // the table is ours, so it can't already have been defined by the caller
+        if self.debug_addrs.is_empty() {
+            self.declare(DEBUG_ADDR, Decl::debug_section())?;
+            self.define(DEBUG_ADDR, Vec::new())?;
+        }
+        let (index, _) = self.debug_addrs.insert_full(id);
+        let ctx = make_ctx(&self.target);
+        let size = if ctx.is_big() { 8 } else { 4 };
+        // unit_length, version, address_size and segment_selector_size, then the addresses, which are relocated
+        let mut table = vec![0; 8 + self.debug_addrs.len() * size];
+        table.pwrite_with((4 + self.debug_addrs.len() * size) as u32, 0, ctx.le)?;
+        table.pwrite_with(5u16, 4, ctx.le)?;
+        table[6] = size as u8;
+        let debug_addr = self.strings.get_or_intern(DEBUG_ADDR);
+        let old = self.definitions.iter().find(|def| def.name == debug_addr).expect(".debug_addr is defined").clone();
+        self.definitions.remove(&old);
+        self.definitions.insert(InternalDefinition { data: DataSource::Bytes(table), ..old });
+        self.links.push((debug_addr, id, (8 + index * size) as u64, Reloc::Auto));
+        Ok(index as u64)
+    }
+
+    /// Add a note of type `kind`, whose descriptor is `desc`, namespaced by `owner`, to the note section `section`.
+    /// Notes added to the same section are emitted in the order they were added.
+    ///
+    /// **NB**: notes are currently only emitted for ELF targets
+    pub fn add_note<S: AsRef<str>, O: AsRef<str>>(&mut self, section: S, owner: O, kind: u32, desc: Vec<u8>) -> Result<(), ArtifactError> {
+        let note = Note::new(section.as_ref(), owner.as_ref(), kind, desc)?;
+        self.notes.entry(section.as_ref().to_string()).or_default().push(note);
+        Ok(())
+    }
+    /// Set the build id note for the runtime `owner` to `id`, replacing any build id previously set for `owner`.
+    ///
+    /// The note is placed in its own section: `.note.gnu.build-id` with type `NT_GNU_BUILD_ID` when the owner is `GNU`,
+    /// `.note.go.buildid` with the type `go tool buildid` expects when the owner is `Go`,
+    /// and otherwise `.note.<owner>.buildid` (lowercased) with type `NT_GNU_BUILD_ID`.
+    /// As with [add_note](struct.Artifact.html#method.add_note), the note is only emitted for ELF targets; for Mach-O
+    /// targets, the first 16 bytes of the `GNU` build id are the UUID of an `LC_UUID` load command.
+    pub fn set_buildid_note<O: AsRef<str>>(&mut self, owner: O, id: Vec<u8>) -> Result<(), ArtifactError> {
+        let owner = owner.as_ref();
+        let (section, kind) = match owner {
+            "GNU" => (".note.gnu.build-id".to_string(), NT_GNU_BUILD_ID),
+            "Go" => (".note.go.buildid".to_string(), NT_GO_BUILD_ID),
+            _ => (format!(".note.{}.buildid", owner.to_lowercase()), NT_GNU_BUILD_ID),
+        };
+        let note = Note::new(&section, owner, kind, id)?;
+        self.notes.insert(section, vec![note]);
+        if owner == "GNU" {
+            self.content_buildid = false;
+        }
+        Ok(())
+    }
+    /// Set the GNU build id note to a SHA-1 of this artifact's contents, computed when it is emitted, so that
+    /// identical builds get identical ids and any change to what is loaded changes the id.
+    /// A later [set_buildid_note](struct.Artifact.html#method.set_buildid_note) for `GNU` replaces it.
+    ///
+    /// The hash is over an encoding of the allocated contents, which doesn't depend on symbol table order or on
+    /// relocations, as they haven't been applied yet. A string is encoded as its length, as a little endian `u64`,
+    /// and then its bytes; every other number is little endian too. The hash is over, in order:
+    ///
+    /// 1. each definition which is part of the memory image, i.e. not a debug section or a custom section which is
+    ///    not allocated, in the order of their names' bytes:
+    ///    - its name, and the name of the section it was [placed](#method.set_section) in, or an empty string
+    ///    - its flags, as a `u32`: 1 if it is code, 2 if it is writable, 4 if it is thread-local, 8 if it is strings,
+    ///      and 16 if it is zero-initialized
+    ///    - the alignment requested for it, as a `u64`: as declared for zero-initialized data,
+    ///      [function_align](#structfield.function_align) for a function, [data_align](#structfield.data_align) for
+    ///      other data, or 0 for the default of the format
+    ///    - its size, as a `u64`, and its contents, unless it is zero-initialized
+    /// 2. each note section other than `.note.gnu.build-id`, in the order they were added: its name and how many notes
+    ///    it has, as a `u64`, then for each of its notes, its owner, its type as a `u32`, and its descriptor
+    ///
+    /// The build id note itself is left out, as it holds the hash, and so is debug information, so that a stripped
+    /// object and its debug file share the id.
+    /// As with [set_buildid_note](struct.Artifact.html#method.set_buildid_note), it is the `LC_UUID` of Mach-O targets.
+    pub fn set_content_buildid(&mut self) -> Result<(), ArtifactError> {
+        self.set_buildid_note("GNU", vec![0; 20])?;
+        self.content_buildid = true;
+        Ok(())
+    }
+    /// Set whether the object emitted from this artifact is the same bytes for the same definitions, declarations and
+    /// links, whatever order the links and differences were added in. They are then emitted in the order of the
+    /// definitions they are in and their offsets, as [links](#method.links) yields them, and each relocation table of an
+    /// ELF object is sorted by offset.
+    ///
+    /// The symbols and sections are always in the order they were declared and defined, and faerie writes no
+    /// timestamps, so an artifact built in the same order is the same bytes whether or not this is set.
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+    /// Whether the object emitted from this artifact doesn't depend on the order the links were added in; see
+    /// [set_deterministic](#method.set_deterministic)
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+    /// The GNU build id note computed from this artifact's contents, if one was requested with
+    /// [set_content_buildid](struct.Artifact.html#method.set_content_buildid)
+    pub(crate) fn content_buildid(&self) -> Result<Option<Note>, Error> {
+        if !self.content_buildid {
+            return Ok(None);
+        }
+        fn string(sha1: &mut Sha1, bytes: &[u8]) {
+            sha1.update(&(bytes.len() as u64).to_le_bytes());
+            sha1.update(bytes);
+        }
+        let mut definitions = self.definitions().filter(|def| !def.prop.debug && !def.prop.not_allocated).collect::<Vec<_>>();
+        definitions.sort_by_key(|def| def.name);
+        let mut sha1 = Sha1::new();
+        for def in definitions {
+            let zeroed = def.prop.zero_init.is_some() || def.prop.no_bits;
+            let flags = u32::from(def.prop.function || def.prop.executable)
+                | u32::from(def.prop.writable) << 1
+                | u32::from(def.prop.tls) << 2
+                | u32::from(def.prop.cstring) << 3
+                | u32::from(zeroed) << 4;
+            let align = match def.prop.zero_init {
+                Some(align) => align,
+                None if def.prop.function => self.function_align.unwrap_or(0),
+                None if !def.prop.cstring && !def.prop.section => self.data_align.unwrap_or(0),
+                None => 0,
+            };
+            string(&mut sha1, def.name.as_bytes());
+            let placement = self.strings.get(def.name).and_then(|id| self.placements.get(&id)).map_or("", String::as_str);
+            string(&mut sha1, placement.as_bytes());
+            sha1.update(&flags.to_le_bytes());
+            sha1.update(&align.to_le_bytes());
+            sha1.update(&def.data.len().to_le_bytes());
+            if !zeroed {
+                def.data.write_to(&mut sha1)?;
+            }
+        }
+        for (section, notes) in self.notes().filter(|&(section, _)| section != ".note.gnu.build-id") {
+            string(&mut sha1, section.as_bytes());
+            sha1.update(&(notes.len() as u64).to_le_bytes());
+            for note in notes {
+                string(&mut sha1, note.owner.as_bytes());
+                sha1.update(&note.kind.to_le_bytes());
+                string(&mut sha1, &note.desc);
+            }
+        }
+        Ok(Some(Note::new(".note.gnu.build-id", "GNU", NT_GNU_BUILD_ID, sha1.finish())?))
+    }
+    /// The descriptor of the GNU build id note, computed from the contents if it was requested with
+    /// [set_content_buildid](struct.Artifact.html#method.set_content_buildid)
+    pub(crate) fn gnu_buildid(&self) -> Result<Option<Vec<u8>>, Error> {
+        if let Some(note) = self.content_buildid()? {
+            return Ok(Some(note.desc));
+        }
+        Ok(self.notes.get(".note.gnu.build-id").and_then(|notes| notes.first()).map(|note| note.desc.clone()))
+    }
+    /// Set the package metadata note to `json`, replacing any package metadata previously set.
+    ///
+    /// The metadata, e.g. `{"type":"rpm","name":"hello","version":"1.0"}`, is placed null terminated in a note of the
+    /// `FDO` owner in `.note.package`, which `readelf -n` and systemd's coredump handling recognize.
+    /// It is not parsed, so it is up to the caller that it is valid JSON.
+    /// As with [add_note](struct.Artifact.html#method.add_note), this is currently only emitted for ELF targets.
+    pub fn set_package_note(&mut self, json: &str) -> Result<(), ArtifactError> {
+        if json.contains('\0') {
+            return Err(ArtifactError::InvalidPackageMetadata(json.to_string()));
+        }
+        let mut desc = Vec::with_capacity(json.len() + 1);
+        desc.extend_from_slice(json.as_bytes());
+        desc.push(0);
+        let note = Note::new(".note.package", "FDO", NT_FDO_PACKAGING_METADATA, desc)?;
+        self.notes.insert(".note.package".to_string(), vec![note]);
+        Ok(())
+    }
+    /// Set the program property `kind` to `data`, replacing any previously set for `kind`; e.g. the property
+    /// [GNU_PROPERTY_X86_FEATURE_1_AND](constant.GNU_PROPERTY_X86_FEATURE_1_AND.html) is a 4 byte bitmask of features.
+    ///
+    /// The properties are merged into the descriptor of a single `GNU` note in `.note.gnu.property`, sorted by kind,
+    /// and each is padded to the size of a pointer, so the linker can merge them with those of the other objects.
+    /// As with [add_note](struct.Artifact.html#method.add_note), this is currently only emitted for ELF targets.
+    pub fn set_gnu_property(&mut self, kind: u32, data: Vec<u8>) -> Result<(), ArtifactError> {
+        const SECTION: &str = ".note.gnu.property";
+        let ctx = make_ctx(&self.target);
+        let align = if ctx.is_big() { 8 } else { 4 };
+        let mut properties = self.gnu_properties.clone();
+        properties.insert(kind, data);
+        let mut desc = Vec::new();
+        for (&kind, data) in &properties {
+            if data.len() > u32::MAX as usize {
+                return Err(ArtifactError::NoteTooLarge(SECTION.to_string(), data.len()));
+            }
+            desc.extend(vec![0; 8]);
+            let at = desc.len() - 8;
+            desc.pwrite_with(kind, at, ctx.le).expect("property header fits");
+            desc.pwrite_with(data.len() as u32, at + 4, ctx.le).expect("property header fits");
+            desc.extend_from_slice(data);
+            while desc.len() % align != 0 { desc.push(0) }
+        }
+        let note = Note::new(SECTION, "GNU", NT_GNU_PROPERTY_TYPE_0, desc)?;
+        self.notes.insert(SECTION.to_string(), vec![note]);
+        self.gnu_properties = properties;
+        Ok(())
+    }
+    /// Set the x86-64 microarchitecture level `level` the code needs, as the program property
+    /// [GNU_PROPERTY_X86_ISA_1_NEEDED](constant.GNU_PROPERTY_X86_ISA_1_NEEDED.html), so that the dynamic loader refuses
+    /// to run the linked binary on a processor which doesn't support it, rather than it faulting on an illegal
+    /// instruction. The levels include each other, so only the bit of `level` is set.
+    pub fn set_min_isa_level(&mut self, level: IsaLevel) -> Result<(), ArtifactError> {
+        match self.target.architecture {
+            Architecture::X86_64 | Architecture::I386 | Architecture::I586 | Architecture::I686 => (),
+            architecture => return Err(ArtifactError::NotX86(architecture.to_string())),
+        }
+        let mut data = vec![0; 4];
+        data.pwrite_with(level as u32, 0, make_ctx(&self.target).le).expect("level fits");
+        self.set_gnu_property(GNU_PROPERTY_X86_ISA_1_NEEDED, data)
+    }
+    /// Set the control flow protection the code supports: whether each indirect branch lands on a marker
+    /// instruction (`endbr64` with IBT on x86, `bti` on AArch64), if `branches`, and whether return addresses are
+    /// protected (by the shadow stack with SHSTK on x86, by pointer authentication with PAC on AArch64), if `returns`.
+    ///
+    /// These are the bits of [GNU_PROPERTY_X86_FEATURE_1_AND](constant.GNU_PROPERTY_X86_FEATURE_1_AND.html) or
+    /// [GNU_PROPERTY_AARCH64_FEATURE_1_AND](constant.GNU_PROPERTY_AARCH64_FEATURE_1_AND.html), which the linker
+    /// only keeps if every object in the link sets them, so the loader enables the protection for the whole binary.
+    pub fn set_control_flow_protection(&mut self, branches: bool, returns: bool) -> Result<(), ArtifactError> {
+        let kind = match self.target.architecture {
+            Architecture::X86_64 | Architecture::I386 | Architecture::I586 | Architecture::I686 => GNU_PROPERTY_X86_FEATURE_1_AND,
+            Architecture::Aarch64 => GNU_PROPERTY_AARCH64_FEATURE_1_AND,
+            architecture => return Err(ArtifactError::NoControlFlowProtection(architecture.to_string())),
+        };
+        // IBT and BTI are the first bit, SHSTK and PAC the second
+        let features = u32::from(branches) | u32::from(returns) << 1;
+        let mut data = vec![0; 4];
+        data.pwrite_with(features, 0, make_ctx(&self.target).le).expect("features fit");
+        self.set_gnu_property(kind, data)
+    }
+
+    /// Add the section `name`, which is emitted empty, with no flags, for tools which only look for its presence.
+    /// Adding the same section again has no effect.
+    ///
+    /// `.note.GNU-stack` is always emitted, so adding it is the same as
+    /// [mark_non_executable_stack](struct.Artifact.html#method.mark_non_executable_stack).
+    ///
+    /// **NB**: empty sections are currently only emitted for ELF targets
+    pub fn add_empty_section<S: AsRef<str>>(&mut self, name: S) {
+        match name.as_ref() {
+            ".note.GNU-stack" => self.mark_non_executable_stack(),
+            name => { self.empty_sections.insert(name.to_string()); },
+        }
+    }
+    /// Add the linker directive `directive`, e.g. to link with a library without passing it on the link line.
+    /// Directives are emitted in the order they were added, and adding the same directive again has no effect.
+    ///
+    /// A library is also a `/DEFAULTLIB:` of the COFF [linker_directives](#method.linker_directives), which have no
+    /// equivalent of an option.
+    ///
+    /// **NB**: directives are only emitted for ELF and Mach-O targets; GNU ld ignores them, as it doesn't read
+    /// `.deplibs` or `.linker-options`
+    pub fn add_linker_directive(&mut self, directive: LinkerDirective) -> Result<(), ArtifactError> {
+        if directive.strings().iter().any(|string| string.is_empty() || string.contains('\0')) {
+            return Err(ArtifactError::InvalidLinkerDirective(directive));
+        }
+        self.directives.insert(directive);
+        Ok(())
+    }
+    /// Mark the object as not requiring an executable stack, by emitting `.note.GNU-stack` without the executable flag;
+    /// this is the default, unless [executable_stack](struct.Artifact.html#structfield.executable_stack) is set.
+    pub fn mark_non_executable_stack(&mut self) {
+        self.executable_stack = false;
+    }
+
+    /// Register the previously declared `function` as a constructor, which the runtime calls before `main`.
+    ///
+    /// Constructors with a `priority` run before those without, in increasing order of priority,
+    /// and constructors with the same priority run in the order they were added.
+    /// Priorities up to 100 are conventionally reserved for the implementation.
+    ///
+    /// With [legacy_ctors](struct.Artifact.html#structfield.legacy_ctors), constructors are emitted in `.ctors`, which
+    /// is run in the reverse order of appearance, whereas `.init_array` is run in order;
+    /// so `.ctors` is emitted reversed, and its priority sections are numbered `65535 - priority`, as GCC does,
+    /// which keeps the order of execution the same.
+    ///
+    /// Mach-O has no priorities, so in a Mach-O object they only order the constructors of this artifact, whose
+    /// pointers are in `__mod_init_func`. A COFF backend places the pointers in `.CRT$XCU`.
+    pub fn add_constructor<T: AsRef<str>>(&mut self, function: T, priority: Option<u16>) -> Result<(), ArtifactError> {
+        let id = self.function_id(function.as_ref())?;
+        self.constructors.push((id, priority));
+        Ok(())
+    }
+    /// Register the previously declared `function` as a destructor, which the runtime calls after `main` returns.
+    ///
+    /// Destructors run in the reverse order to constructors: those without a priority run first, then those with
+    /// a priority, in decreasing order of priority, and destructors with the same priority run in the reverse of
+    /// the order they were added. As with [add_constructor](struct.Artifact.html#method.add_constructor),
+    /// the legacy `.dtors`, which is run in order of appearance, is emitted so this order is kept, as is the Mach-O
+    /// `__mod_term_func`, which is run in reverse.
+    pub fn add_destructor<T: AsRef<str>>(&mut self, function: T, priority: Option<u16>) -> Result<(), ArtifactError> {
+        let id = self.function_id(function.as_ref())?;
+        self.destructors.push((id, priority));
+        Ok(())
+    }
+    /// Register `clone` as the transactional memory clone of `function`, both previously declared functions, as GCC
+    /// does for code compiled with `-fgnu-tm`. The runtime calls the clone in place of the function within a
+    /// transaction.
+    ///
+    /// Each pair is emitted as an entry of `.tm_clone_table`: a pointer to the function followed by one to its clone.
+    /// The table is delimited by the local symbols `__TMC_LIST__` and `__TMC_END__`; the global ones the runtime walks
+    /// the linked table with are defined by `crtbegin.o` and `crtend.o`, around every object's table.
+    ///
+    /// **NB**: the table is currently only emitted for ELF targets
+    pub fn add_tm_clone<F: AsRef<str>, C: AsRef<str>>(&mut self, function: F, clone: C) -> Result<(), ArtifactError> {
+        let function = self.function_id(function.as_ref())?;
+        let clone = self.function_id(clone.as_ref())?;
+        self.tm_clones.push((function, clone));
+        Ok(())
+    }
+    fn function_id(&mut self, function: &str) -> Result<StringID, ArtifactError> {
+        let id = self.strings.get_or_intern(function);
+        match self.declarations.get(&id).map(|int| int.decl) {
+            Some(Decl::Function(_)) | Some(Decl::FunctionImport) => Ok(id),
+            Some(_) => Err(ArtifactError::NotAFunction(function.to_string())),
+            None => Err(ArtifactError::Undeclared(function.to_string())),
+        }
+    }
+    /// Set the compact unwind `encoding` of the previously declared `function`, e.g. one from
+    /// [x86_64_frame_unwind](fn.x86_64_frame_unwind.html), replacing any set before. The linker prefers it to
+    /// the function's entry in `__eh_frame`, which is only needed for a function without a compact encoding.
+    ///
+    /// **NB**: this is only emitted for x86-64 Mach-O targets, as an entry in the `__LD,__compact_unwind` section
+    pub fn set_compact_unwind<T: AsRef<str>>(&mut self, function: T, encoding: u32) -> Result<(), ArtifactError> {
+        let name = function.as_ref();
+        let id = self.strings.get_or_intern(name);
+        // an alias is unwound as its definition is
+        let id = self.aliases.get(&id).cloned().unwrap_or(id);
+        match self.declarations.get(&id).map(|int| int.decl) {
+            Some(Decl::Function(_)) => (),
+            Some(_) => return Err(ArtifactError::NotADefinedFunction(name.to_string())),
+            None => return Err(ArtifactError::Undeclared(name.to_string())),
+        }
+        self.compact_unwind.insert(id, encoding);
+        Ok(())
+    }
+    /// Set the WebAssembly `signature` of the previously declared function or function import `function`,
+    /// replacing any set before. The definition of a function is its body, as it is in the code section: the
+    /// declarations of its locals, followed by its instructions and the final `end`.
+    ///
+    /// **NB**: this is only emitted for WebAssembly targets, where every function needs one
+    pub fn set_wasm_signature<T: AsRef<str>>(&mut self, function: T, signature: WasmSignature) -> Result<(), ArtifactError> {
+        let id = self.function_id(function.as_ref())?;
+        self.wasm_signatures.insert(id, signature);
+        Ok(())
+    }
+    /// Emit the previously declared local `name` as the symbol `symbol`, which may be the name of another declaration,
+    /// e.g. a `static` function which has the same name as a global function of another unit amalgamated into this
+    /// artifact. Links, like everything else, still refer to it as `name`, which tells the two apart.
+    ///
+    /// **NB**: this is currently only supported for ELF targets
+    pub fn set_symbol_name<T: AsRef<str>, U: AsRef<str>>(&mut self, name: T, symbol: U) -> Result<(), ArtifactError> {
+        let name = name.as_ref();
+        let id = self.strings.get_or_intern(name);
+        let local = match self.declarations.get(&id).map(|int| int.decl) {
+            Some(Decl::Function(f)) => f.scope() == Scope::Local,
+            Some(Decl::Data(d)) => d.scope() == Scope::Local,
+            Some(Decl::CString(c)) => !c.is_global(),
+            Some(Decl::Absolute(abs)) => abs.scope() == Scope::Local,
+            Some(_) => false,
+            None => return Err(ArtifactError::Undeclared(name.to_string())),
+        };
+        if !local {
+            return Err(ArtifactError::NotLocal(name.to_string()));
+        }
+        let symbol = self.strings.get_or_intern(symbol.as_ref());
+        self.symbol_names.insert(id, symbol);
+        Ok(())
+    }
+    /// Emit the previously declared global definition or import `name` as the versioned symbol `symbol`, as the GNU
+    /// assembler's `.symver` does: `symbol` is `foo@VERSION`, or `foo@@VERSION` for the default version of `foo`,
+    /// which programs linked against a shared library bind to, and `foo` need not be `name`, e.g. `foo_v1` defined as
+    /// the older `foo@MYLIB_1.0`. The version script of a shared library defines its versions, and an import binds to
+    /// the version of the definition in the shared library it is resolved from, e.g. `memcpy@GLIBC_2.2.5` rather than
+    /// the latest one. Only a definition's version can be the default.
+    ///
+    /// **NB**: this is currently only supported for ELF targets
+    pub fn set_symbol_version<T: AsRef<str>, S: AsRef<str>>(&mut self, name: T, symbol: S) -> Result<(), ArtifactError> {
+        let (name, symbol) = (name.as_ref(), symbol.as_ref());
+        let id = self.strings.get_or_intern(name);
+        let (global, import) = match self.declarations.get(&id).map(|int| int.decl) {
+            Some(Decl::Function(f)) => (f.scope() != Scope::Local, false),
+            Some(Decl::Data(d)) => (d.scope() != Scope::Local, false),
+            Some(Decl::CString(c)) => (c.is_global(), false),
+            Some(Decl::Absolute(abs)) => (abs.scope() != Scope::Local, false),
+            Some(Decl::FunctionImport) | Some(Decl::DataImport) => (true, true),
+            Some(_) => (false, false),
+            None => return Err(ArtifactError::Undeclared(name.to_string())),
+        };
+        let valid = match symbol.find('@') {
+            Some(at) => {
+                let version = &symbol[at + 1..];
+                let (default, version) = version.strip_prefix('@').map_or((false, version), |version| (true, version));
+                at > 0 && !version.is_empty() && !version.contains('@') && !symbol.contains('\0') && (!import || !default)
+            },
+            None => false,
+        };
+        if !global || !valid {
+            return Err(ArtifactError::InvalidSymbolVersion(name.to_string(), symbol.to_string()));
+        }
+        let symbol = self.strings.get_or_intern(symbol);
+        self.symbol_names.insert(id, symbol);
+        Ok(())
+    }
+    /// The id of the previously declared function, data object or string `name`, which has a symbol of its own
+    fn symbol_definition(&mut self, name: &str) -> Result<StringID, ArtifactError> {
+        let id = self.strings.get_or_intern(name);
+        match self.declarations.get(&id).map(|int| int.decl) {
+            Some(Decl::Function(_)) | Some(Decl::CString(_)) => Ok(id),
+            Some(Decl::Data(d)) if !d.is_common() => Ok(id),
+            Some(_) => Err(ArtifactError::NotADefinition(name.to_string())),
+            None => Err(ArtifactError::Undeclared(name.to_string())),
+        }
+    }
+    /// Set the size of the symbol of the previously declared function, data object or string `name` to `size`, rather
+    /// than the size of its definition, e.g. to exclude the padding or trailing constant pool of a function.
+    ///
+    /// **NB**: this is only supported for ELF targets, as Mach-O symbols have no size
+    pub fn set_symbol_size<T: AsRef<str>>(&mut self, name: T, size: u64) -> Result<(), ArtifactError> {
+        let id = self.symbol_definition(name.as_ref())?;
+        self.symbol_attributes.entry(id).or_default().size = Some(size);
+        Ok(())
+    }
+    /// Set the type of the symbol of the previously declared function, data object or string `name` to `kind`,
+    /// rather than the type its declaration implies, e.g. to give a function defined as data the type of a function.
+    ///
+    /// **NB**: this is only supported for ELF targets, as Mach-O symbols have no type
+    pub fn set_symbol_kind<T: AsRef<str>>(&mut self, name: T, kind: SymbolKind) -> Result<(), ArtifactError> {
+        let id = self.symbol_definition(name.as_ref())?;
+        self.symbol_attributes.entry(id).or_default().kind = Some(kind);
+        Ok(())
+    }
+    /// Set the ELF `st_other` of the symbol of the previously declared function, data object or string `name` to
+    /// `other`, e.g. to a visibility, or to a processor specific attribute such as the local entry point of a
+    /// PowerPC64 ELFv2 function.
+    ///
+    /// **NB**: this is only supported for ELF targets
+    pub fn set_symbol_other<T: AsRef<str>>(&mut self, name: T, other: u8) -> Result<(), ArtifactError> {
+        let id = self.symbol_definition(name.as_ref())?;
+        self.symbol_attributes.entry(id).or_default().other = Some(other);
+        Ok(())
+    }
+    /// Set whether the previously declared function, data object or string `name`, or the definition it is an alias
+    /// of, is kept when the linker discards the definitions nothing refers to, e.g. with `--gc-sections` or
+    /// `-dead_strip`, which it is not by default; e.g. a function which is only ever looked up with `dlsym`.
+    ///
+    /// It is emitted as the `SHF_GNU_RETAIN` flag of the definition's ELF section, which is then always a section of
+    /// its own for a function, and as the `N_NO_DEAD_STRIP` description of its Mach-O symbol. A COFF backend passes
+    /// the global ones to the linker as the `/INCLUDE:` directives of [linker_directives](#method.linker_directives).
+    pub fn set_no_dead_strip<T: AsRef<str>>(&mut self, name: T, no_dead_strip: bool) -> Result<(), ArtifactError> {
+        let id = self.symbol_definition(name.as_ref())?;
+        // an alias is in the section of its definition
+        let id = self.aliases.get(&id).cloned().unwrap_or(id);
+        if no_dead_strip {
+            self.no_dead_strip.insert(id);
+        } else {
+            self.no_dead_strip.shift_remove(&id);
+        }
+        Ok(())
+    }
+    /// Place the previously declared string `name` in a section named `section`, e.g. `.rodata.str1.1`, rather than
+    /// its own section; the section is still one the linker merges identical strings in, and strings placed in
+    /// sections of the same name are merged together. A string's contents must be null-terminated.
+    ///
+    /// **NB**: this is currently only supported for ELF targets
+    pub fn set_string_section<T: AsRef<str>, S: AsRef<str>>(&mut self, name: T, section: S) -> Result<(), ArtifactError> {
+        let (name, section) = (name.as_ref(), section.as_ref());
+        let id = self.strings.get_or_intern(name);
+        match self.declarations.get(&id).map(|int| int.decl) {
+            Some(Decl::CString(_)) => (),
+            Some(_) => return Err(ArtifactError::NotAString(name.to_string())),
+            None => return Err(ArtifactError::Undeclared(name.to_string())),
+        }
+        if section.is_empty() || section.contains('\0') {
+            return Err(ArtifactError::InvalidSectionName(section.to_string()));
+        }
+        self.placements.insert(id, section.to_string());
+        Ok(())
+    }
+    /// Place the previously declared function or data object `name` in a section named `section`, e.g. `.text.hot`
+    /// or `.data.rel.ro`, rather than its own section, as `__attribute__((section))` does; its symbol and the links
+    /// into it are unchanged. The section has the flags of the definition, e.g. it is executable for a function, and
+    /// definitions placed in sections of the same name are gathered together by the linker, in the order of the
+    /// objects on the link line. A zero-initialized data object is placed in a section of that name which occupies no
+    /// space in the file, e.g. `.bss.hot`; a common symbol has no section, so it can't be placed.
+    ///
+    /// A string can be placed too, as with [set_string_section](#method.set_string_section).
+    ///
+    /// **NB**: this is currently only supported for ELF targets
+    pub fn set_section<T: AsRef<str>, S: AsRef<str>>(&mut self, name: T, section: S) -> Result<(), ArtifactError> {
+        let (name, section) = (name.as_ref(), section.as_ref());
+        let id = self.strings.get_or_intern(name);
+        match self.declarations.get(&id).map(|int| int.decl) {
+            Some(Decl::CString(_)) => return self.set_string_section(name, section),
+            Some(Decl::Function(_)) => (),
+            Some(Decl::Data(d)) if !d.is_common() => (),
+            Some(_) => return Err(ArtifactError::NotPlaceable(name.to_string())),
+            None => return Err(ArtifactError::Undeclared(name.to_string())),
+        }
+        if section.is_empty() || section.contains('\0') {
+            return Err(ArtifactError::InvalidSectionName(section.to_string()));
+        }
+        self.placements.insert(id, section.to_string());
+        Ok(())
+    }
+    /// Place the previously declared `member` in the COMDAT group of the previously declared `leader`, along with the
+    /// leader itself, so that when several objects define the group, e.g. an inline function and the data it refers
+    /// to, the linker keeps one copy of all of its definitions, chosen by `selection`, and discards the others rather
+    /// than reporting them as defined more than once. The group is named after its leader, which must be global or
+    /// weak, as must any member referred to from outside of the group.
+    ///
+    /// The selection must be the same for every member of a group.
+    ///
+    /// **NB**: ELF only supports `ComdatSelection::Any`, and each member is emitted in its own section. Mach-O has
+    /// no groups, so its global members are weak definitions instead, which the linker also keeps one copy of, by
+    /// name. The other selections are for a [custom backend](backend/trait.ObjectBackend.html), e.g. for COFF.
+    pub fn set_comdat<M: AsRef<str>, L: AsRef<str>>(&mut self, member: M, leader: L, selection: ComdatSelection) -> Result<(), ArtifactError> {
+        let (member, leader) = (member.as_ref(), leader.as_ref());
+        let invalid = || ArtifactError::InvalidComdat(member.to_string(), leader.to_string());
+        // an alias is in the section of its definition
+        let member_id = self.strings.get_or_intern(member);
+        let member_id = self.aliases.get(&member_id).cloned().unwrap_or(member_id);
+        let leader_id = self.strings.get_or_intern(leader);
+        let leader_def = self.aliases.get(&leader_id).cloned().unwrap_or(leader_id);
+        for &(name, id) in &[(member, member_id), (leader, leader_def)] {
+            match self.declarations.get(&id).map(|int| int.decl) {
+                Some(Decl::Function(_)) | Some(Decl::CString(_)) => (),
+                // zero-initialized data shares .bss
+                Some(Decl::Data(d)) if d.align().is_none() => (),
+                Some(_) => return Err(invalid()),
+                None => return Err(ArtifactError::Undeclared(name.to_string())),
+            }
+        }
+        let global = match self.declarations.get(&leader_id).map(|int| int.decl) {
+            Some(Decl::Function(f)) => f.scope() != Scope::Local,
+            Some(Decl::Data(d)) => d.scope() != Scope::Local,
+            Some(Decl::CString(c)) => c.is_global(),
+            _ => false,
+        };
+        let in_other_group = |id| self.comdats.get(&id).is_some_and(|&(other, _)| other != leader_id);
+        // every member of a group is selected together
+        let reselected = self.comdats.get(&leader_def).is_some_and(|&(_, other)| other != selection);
+        if !global || in_other_group(member_id) || in_other_group(leader_def) || reselected {
+            return Err(invalid());
+        }
+        self.comdats.insert(leader_def, (leader_id, selection));
+        self.comdats.insert(member_id, (leader_id, selection));
+        Ok(())
+    }
+    /// Order the previously declared custom `section` by the section of the previously declared `definition`, replacing
+    /// any order set before, so the linker places the contents of `section` from every object in the same order as
+    /// the sections of their definitions, and discards them along with them, e.g. for `.ARM.exidx`, or metadata
+    /// which must follow the functions it describes.
+    ///
+    /// **NB**: this is currently only supported for ELF targets, where `section` is flagged `SHF_LINK_ORDER`, and
+    /// linked to the section `definition` is placed in
+    pub fn set_link_order<S: AsRef<str>, D: AsRef<str>>(&mut self, section: S, definition: D) -> Result<(), ArtifactError> {
+        let (section, definition) = (section.as_ref(), definition.as_ref());
+        let invalid = || ArtifactError::InvalidLinkOrder(section.to_string(), definition.to_string());
+        let section_id = self.strings.get_or_intern(section);
+        // an alias is in the section of its definition
+        let definition_id = self.strings.get_or_intern(definition);
+        let definition_id = self.aliases.get(&definition_id).cloned().unwrap_or(definition_id);
+        match self.declarations.get(&section_id).map(|int| int.decl) {
+            Some(Decl::Section(_)) => (),
+            Some(_) => return Err(invalid()),
+            None => return Err(ArtifactError::Undeclared(section.to_string())),
+        }
+        match self.declarations.get(&definition_id).map(|int| int.decl) {
+            Some(Decl::Function(_)) | Some(Decl::Data(_)) | Some(Decl::CString(_)) => (),
+            Some(Decl::Section(_)) if definition_id != section_id => (),
+            Some(_) => return Err(invalid()),
+            None => return Err(ArtifactError::Undeclared(definition.to_string())),
+        }
+        self.link_orders.insert(section_id, definition_id);
+        Ok(())
+    }
+    /// Set the SFrame stack trace information of the previously defined `function` to `rows`, replacing any set before.
+    /// The rows must be in order of their offsets, and the first applies from the start of the function.
+    ///
+    /// The functions' rows are emitted in the `.sframe` section, which unwinders such as the kernel's use to walk the
+    /// stack without the full generality, and cost, of `.eh_frame`.
+    ///
+    /// **NB**: this is only emitted for x86-64 and AArch64 ELF targets
+    pub fn set_sframe<T: AsRef<str>>(&mut self, function: T, rows: Vec<SFrameRow>) -> Result<(), ArtifactError> {
+        let name = function.as_ref();
+        let id = self.strings.get_or_intern(name);
+        // an alias is unwound as its definition is
+        let id = self.aliases.get(&id).cloned().unwrap_or(id);
+        match self.declarations.get(&id).map(|int| int.decl) {
+            Some(Decl::Function(_)) => (),
+            Some(_) => return Err(ArtifactError::NotADefinedFunction(name.to_string())),
+            None => return Err(ArtifactError::Undeclared(name.to_string())),
+        }
+        let invalid = |reason: &str| Err(ArtifactError::InvalidSFrame(name.to_string(), reason.to_string()));
+        if rows.first().map(|row| row.offset) != Some(0) {
+            return invalid("the first row must be at offset 0");
+        }
+        if rows.windows(2).any(|pair| pair[0].offset >= pair[1].offset) {
+            return invalid("the rows must be in order of their offsets");
+        }
+        match self.target.architecture {
+            Architecture::X86_64 => if rows.iter().any(|row| row.ra_offset.is_some_and(|ra| ra != -8)) {
+                return invalid("the return address is always at -8 from the CFA");
+            },
+            Architecture::Aarch64 => if rows.iter().any(|row| row.fp_offset.is_some() && row.ra_offset.is_none()) {
+                return invalid("the frame pointer is saved with the return address");
+            },
+            _ => return invalid("SFrame is only defined for x86-64 and AArch64"),
+        }
+        self.sframes.insert(id, rows);
+        Ok(())
+    }
+    /// Resolve the references between a batch of `artifacts` whose values are already known: the imports of global
+    /// absolute symbols defined by a sibling artifact. Returns how many references were resolved.
+    ///
+    /// A pointer sized reference to such an import from a data object is replaced with the symbol's value,
+    /// and the import becomes a local absolute symbol once nothing relocates against it, so it is no longer undefined.
+    /// References from functions, and from data which is streamed from a file, are left to the linker, since they
+    /// depend on where the reference itself is placed. If siblings define the same global absolute symbol with
+    /// different values, this returns an error, and no artifact is changed; a global definition takes precedence
+    /// over weak ones, and otherwise the first weak definition is used.
+    pub fn resolve_batch(artifacts: &mut [Artifact]) -> Result<usize, Error> {
+        let mut values = HashMap::new();
+        for artifact in artifacts.iter() {
+            for abs in artifact.absolutes() {
+                match *abs.decl {
+                    Decl::Absolute(decl) if decl.scope() != Scope::Local => match values.entry(abs.name.to_string()) {
+                        Entry::Occupied(mut entry) => {
+                            let (value, weak) = *entry.get();
+                            // a global definition takes precedence over a weak one, as it does when linking
+                            if weak && !decl.is_weak() {
+                                entry.insert((decl.value(), false));
+                            } else if !weak && !decl.is_weak() && value != decl.value() {
+                                return Err(ArtifactError::ConflictingAbsolute(abs.name.to_string()).into());
+                            }
+                        },
+                        Entry::Vacant(entry) => { entry.insert((decl.value(), decl.is_weak())); },
+                    },
+                    _ => (),
+                }
+            }
+        }
+        let values = values.into_iter().map(|(name, (value, _weak))| (name, value)).collect();
+        let mut resolved = 0;
+        for artifact in artifacts.iter_mut() {
+            resolved += artifact.resolve_imports(&values);
+        }
+        Ok(resolved)
+    }
+    /// Resolve the imports whose values are known, as described in [resolve_batch](struct.Artifact.html#method.resolve_batch)
+    fn resolve_imports(&mut self, values: &HashMap<String, u64>) -> usize {
+        let known = self.imports.iter().filter_map(|&(id, _)| {
+            values.get(self.strings.resolve(id).expect("import has a name")).map(|&value| (id, value))
+        }).collect::<Vec<_>>();
+        let mut resolved = 0;
+        for (import, value) in known {
+            for link in mem::take(&mut self.links) {
+                let (from, to, at, reloc) = link;
+                if to == import && reloc == Reloc::Auto && self.patch(from, at, value) {
+                    resolved += 1;
+                } else {
+                    self.links.push(link);
+                }
+            }
+            // once nothing relocates against it, the import is just a constant
+            if self.links.iter().all(|&(_, to, _, _)| to != import) {
+                self.declarations.get_mut(&import).expect("import is declared").decl = Decl::absolute(value).into();
+                self.imports.retain(|&(id, _)| id != import);
+            }
+        }
+        resolved
+    }
+    /// Write the pointer sized `value` at offset `at` of the data object `name`, if it is held in memory
+    fn patch(&mut self, name: StringID, at: u64, value: u64) -> bool {
+        let ctx = make_ctx(&self.target);
+        let definition = match self.definitions.iter().find(|def| def.name == name) {
+            Some(def) if !def.prop.function && !def.prop.debug => def.clone(),
+            _ => return false,
+        };
+        let mut patched = definition.clone();
+        let written = match patched.data {
+            DataSource::Bytes(ref mut bytes) if ctx.is_big() => bytes.pwrite_with(value, at as usize, ctx.le).is_ok(),
+            // a value which doesn't fit is left for the linker to report
+            DataSource::Bytes(ref mut bytes) if value <= u64::from(u32::MAX) => bytes.pwrite_with(value as u32, at as usize, ctx.le).is_ok(),
+            DataSource::Bytes(_) => false,
+            DataSource::File { .. } | DataSource::Zeros(_) | DataSource::Fill { .. } => false,
+        };
+        if written {
+            self.definitions.remove(&definition);
+            self.definitions.insert(patched);
+        }
+        written
+    }
+
+    /// Attach the opaque `metadata` to the previously declared `symbol`, replacing any attached before, e.g. to
+    /// correlate it with the frontend's own representation. It is kept with the artifact, and never emitted.
+    pub fn set_metadata<T: AsRef<str>>(&mut self, symbol: T, metadata: Vec<u8>) -> Result<(), ArtifactError> {
+        let id = self.strings.get_or_intern(symbol.as_ref());
+        if !self.declarations.contains_key(&id) {
+            return Err(ArtifactError::Undeclared(symbol.as_ref().to_string()));
+        }
+        self.metadata.insert(id, metadata);
+        Ok(())
+    }
+    /// Get the metadata attached to `symbol`, if any
+    pub fn metadata<T: AsRef<str>>(&self, symbol: T) -> Option<&[u8]> {
+        self.strings.get(symbol.as_ref()).and_then(|id| self.metadata.get(&id)).map(Vec::as_slice)
+    }
+    /// Get an iterator over the symbols with attached metadata, and their metadata, in the order it was first attached
+    pub fn metadata_entries<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a str, &'a [u8])> + 'a> {
+        Box::new(self.metadata.iter().map(move |(&id, metadata)| {
+            (self.strings.resolve(id).expect("metadata has a symbol"), metadata.as_slice())
+        }))
+    }
+
+    /// Get set of non-import, non-absolute declarations that have not been defined. This must be an empty set in
     /// order to `emit` the artifact.
     pub fn undefined_symbols(&self) -> Vec<String> {
         let mut syms = Vec::new();
-        for (&name, _) in self.declarations.iter().filter(|&(_, &int)| !int.defined && !int.decl.is_import()) {
+        for (&name, _) in self.declarations.iter().filter(|&(_, &int)| !int.defined && !int.decl.is_import() && !int.decl.is_absolute()) {
             syms.push(String::from(self.strings.resolve(name).expect("declaration has a name")));
         }
         syms
     }
+    /// Check that the alignments of functions and of data objects which override the defaults are powers of two
+    pub(crate) fn check_alignments(&self) -> Result<(), ArtifactError> {
+        for &(what, align) in &[("functions", self.function_align), ("data objects", self.data_align)] {
+            match align {
+                Some(align) if !align.is_power_of_two() => return Err(ArtifactError::InvalidAlignment(what.to_string(), align)),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+    /// Check that every declaration which must be defined in order to `emit` the artifact is
+    fn check_defined(&self) -> Result<(), ArtifactError> {
+        let undef = self.undefined_symbols();
+        if !undef.is_empty() {
+            return Err(ArtifactError::UndefinedSymbols(undef));
+        }
+        Ok(())
+    }
+    /// Check that faerie doesn't have to guess the relocation of any link, if it is `strict_auto`
+    fn check_auto_links(&self) -> Result<(), ArtifactError> {
+        if !self.strict_auto {
+            return Ok(());
+        }
+        for link in self.links().filter(|link| link.reloc == Reloc::Auto) {
+            let (from, to) = (link.from.name.to_string(), link.to.name.to_string());
+            match AutoRelocation::classify(link.from.decl, link.to.decl) {
+                AutoRelocation::Unambiguous => (),
+                AutoRelocation::Ambiguous(suggestion) => return Err(ArtifactError::AmbiguousRelocation { from, to, suggestion }),
+                AutoRelocation::Unsupported => return Err(ArtifactError::unsupported_relocation(&link, self.target.to_string())),
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the field each link and difference fills in is within the definition it is from, and that the
+    /// addend of a link to a field of 1 or 2 bytes fits in it, before a backend writes a relocation which would
+    /// corrupt the neighbouring contents, or be silently truncated
+    fn check_link_fields(&self) -> Result<(), ArtifactError> {
+        let lengths = self.definitions().map(|def| (def.name, def.data.len())).collect::<HashMap<_, _>>();
+        let fields = self.links().map(|link| (link.from.name, link.to.name, link.at, link.reloc.field()))
+            .chain(self.differences().map(|difference| (difference.from.name, difference.to.name, difference.at, (Some(4), 0))));
+        for (from, to, at, (size, addend)) in fields {
+            let len = match lengths.get(from) {
+                Some(&len) => len,
+                None => continue,
+            };
+            let size = size.unwrap_or(4);
+            // a custom encoder may pick a relocation of another size, so only the offset itself is known to be in it
+            let field = if self.reloc_encoder.is_some() { 1 } else { size };
+            if at.checked_add(u64::from(field)).is_none_or(|end| end > len) {
+                return Err(ArtifactError::RelocationOutOfBounds { from: from.to_string(), to: to.to_string(), at, size, len });
+            }
+            // a field of 1 or 2 bytes holds a signed or unsigned value of its size
+            let bits = u32::from(size) * 8;
+            if size < 4 && (i64::from(addend) < -(1 << (bits - 1)) || i64::from(addend) >= 1 << bits) {
+                return Err(ArtifactError::AddendOverflow { from: from.to_string(), to: to.to_string(), at, addend, size });
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the externally visible symbols this artifact defines, named as they are in an object file of `format`,
+    /// i.e. with the `_` prefix of Mach-O. An archive's symbol index maps these to this artifact's member,
+    /// so an archive can be built without reading the emitted objects back.
+    pub fn exported_symbols(&self, format: BinaryFormat) -> Vec<String> {
+        let prefix = if format == BinaryFormat::Macho { "_" } else { "" };
+        self.declarations.iter().filter(|&(_, int)| match int.decl {
+            Decl::Function(f) => f.scope() != Scope::Local,
+            Decl::Data(d) => d.scope() != Scope::Local,
+            Decl::CString(c) => c.is_global(),
+            Decl::Absolute(abs) => abs.scope() != Scope::Local,
+            _ => false,
+        }).map(|(&name, _)| {
+            // with its version, if it has one
+            let symbol = self.symbol_names.get(&name).cloned().unwrap_or(name);
+            format!("{}{}", prefix, self.strings.resolve(symbol).expect("declaration has a name"))
+        }).collect()
+    }
 
     /// Emit a blob of bytes representing the object file in the format specified in the target the
     /// `Artifact` was constructed with.
+    ///
+    /// The output only depends on what was declared, defined and linked, and in which order, so artifacts built
+    /// the same way emit identical bytes, in any process, for reproducible builds.
     pub fn emit(&self) -> Result<Vec<u8>, Error> {
         self.emit_as(self.target.binary_format)
     }
 
     /// Emit a blob of bytes representing an object file in the given format.
     pub fn emit_as(&self, format: BinaryFormat) -> Result<Vec<u8>, Error> {
-        let undef = self.undefined_symbols();
-        if undef.is_empty() {
-            match format {
-                BinaryFormat::Elf => elf::to_bytes(self),
-                BinaryFormat::Macho => mach::to_bytes(self),
-                _ => Err(format_err!(
-                    "binary format {} is not supported",
-                    self.target.binary_format
-                )),
-            }
-        } else {
-            Err(format_err!(
-                "the following symbols are declared but not defined: {:?}",
-                undef
-            ))
+        let mut buffer = Cursor::new(Vec::new());
+        self.emit_to(&mut buffer, format)?;
+        Ok(buffer.into_inner())
+    }
+
+    /// Emit a blob of bytes representing an object file in each of the given `formats`, e.g. for several platforms
+    /// from the same artifact, in the order they are given. The artifact is checked once, rather than for each
+    /// format, and each format's object is then emitted as [emit_as](#method.emit_as) would, so one which can't
+    /// be emitted doesn't prevent the others.
+    pub fn emit_formats(&self, formats: &[BinaryFormat]) -> Vec<(BinaryFormat, Result<Vec<u8>, Error>)> {
+        let checked = self.check_defined().and_then(|()| self.check_auto_links()).and_then(|()| self.check_link_fields()).is_ok();
+        formats.iter().map(|&format| {
+            let bytes = if checked {
+                backend::builtin(format)
+                    .ok_or_else(|| ArtifactError::UnsupportedBinaryFormat(format.to_string()).into())
+                    .and_then(|backend| {
+                        let mut buffer = Cursor::new(Vec::new());
+                        backend.write(self, &mut buffer).map(|()| buffer.into_inner())
+                    })
+            } else {
+                // the check's error, for each format
+                self.emit_as(format)
+            };
+            (format, bytes)
+        }).collect()
+    }
+
+    /// Emit a blob of bytes representing an object file written by `backend`, e.g. of a format faerie doesn't support
+    pub fn emit_with(&self, backend: &dyn ObjectBackend) -> Result<Vec<u8>, Error> {
+        self.check_defined()?;
+        self.check_auto_links()?;
+        self.check_link_fields()?;
+        let mut buffer = Cursor::new(Vec::new());
+        backend.write(self, &mut buffer)?;
+        Ok(buffer.into_inner())
+    }
+
+    /// Emit the object file in the given format split in two, like `objcopy --only-keep-debug` followed by
+    /// `objcopy --strip-debug --add-gnu-debuglink`: the stripped object, which has no debug sections,
+    /// and the debug object, which has the debug sections, the full symbol table and empty placeholders for all other sections.
+    ///
+    /// The stripped object is returned first, and refers to the debug object by the file name `debuglink`,
+    /// along with the debug object's CRC-32 so debuggers can check it matches.
+    /// **NB**: This is currently only supported for ELF
+    pub fn split_debug<T: AsRef<str>>(&self, format: BinaryFormat, debuglink: T) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        self.check_defined()?;
+        self.check_auto_links()?;
+        self.check_link_fields()?;
+        match format {
+            BinaryFormat::Elf => elf::split_debug(self, debuglink.as_ref()),
+            _ => Err(format_err!(
+                "splitting debug information for binary format {} is not supported",
+                format
+            )),
+        }
+    }
+
+    /// Emit the object file in the given format without what `strip` removes; the global symbols, and any local
+    /// symbol a relocation refers to, e.g. the section symbols of the definitions which are relocated against, are kept,
+    /// so the object still links.
+    /// **NB**: This is currently only supported for ELF
+    pub fn emit_stripped(&self, format: BinaryFormat, strip: Strip) -> Result<Vec<u8>, Error> {
+        self.check_defined()?;
+        self.check_auto_links()?;
+        self.check_link_fields()?;
+        match format {
+            BinaryFormat::Elf => elf::strip(self, strip),
+            _ => Err(format_err!(
+                "stripping binary format {} is not supported",
+                format
+            )),
         }
     }
 
-    /// Emit and write to disk a blob of bytes representing the object file in the format specified
-    /// in the target the `Artifact` was constructed with.
-    pub fn write(&self, sink: File) -> Result<(), Error> {
+    /// Emit a flat binary, like `objcopy -O binary` does for a linked image: the contents of each definition at
+    /// its address, with gaps zero filled, starting from the address `base`, and nothing else.
+    ///
+    /// The functions are placed first, then the read-only data, then the writable data, each in the order they are
+    /// emitted in an object file, and each aligned as its section would be. Debug sections are not loaded, so they
+    /// are left out, along with notes, constructors, destructors and transactional memory clones, which have no meaning
+    /// without a linker.
+    /// Every relocation is applied in place, so it is an error if any refers to an import, or does not fit in its field.
+    ///
+    /// **NB**: This is currently only supported for x86-64
+    pub fn to_flat_binary(&self, base: u64) -> Result<Vec<u8>, Error> {
+        self.check_defined()?;
+        self.check_auto_links()?;
+        self.check_link_fields()?;
+        flat::to_bytes(self, base)
+    }
+
+    /// Serialize the object file in the given format directly into `sink`.
+    fn emit_to<T: Write + Seek>(&self, mut sink: T, format: BinaryFormat) -> Result<(), Error> {
+        // before anything is written to the sink
+        let backend = backend::builtin(format).ok_or_else(|| ArtifactError::UnsupportedBinaryFormat(format.to_string()))?;
+        self.check_defined()?;
+        self.check_auto_links()?;
+        self.check_link_fields()?;
+        backend.write(self, &mut sink)
+    }
+
+    /// Emit and write to `sink`, e.g. a file on disk, a blob of bytes representing the object file in the format
+    /// specified in the target the `Artifact` was constructed with.
+    pub fn write<T: Write + Seek>(&self, sink: T) -> Result<(), Error> {
         self.write_as(sink, self.target.binary_format)
     }
 
-    /// Emit and write to disk a blob of bytes representing an object file in the given format.
-    pub fn write_as(&self, mut sink: File, format: BinaryFormat) -> Result<(), Error> {
+    /// Emit and write to `sink` a blob of bytes representing an object file in the given format.
+    /// The object is streamed into `sink`, e.g. a `File` or a `BufWriter` of one, so file backed definitions
+    /// are never fully held in memory.
+    pub fn write_as<T: Write + Seek>(&self, sink: T, format: BinaryFormat) -> Result<(), Error> {
+        self.emit_to(sink, format)
+    }
+
+    /// Emit an object file in the given format and write it to `sink`, which need not be seekable, e.g. a pipe.
+    /// The backends go back to fill in e.g. headers, so the object is built in memory and written all at once;
+    /// use [write_as](#method.write_as) to stream it into a sink which can seek.
+    pub fn write_to<W: Write>(&self, mut sink: W, format: BinaryFormat) -> Result<(), Error> {
         let bytes = self.emit_as(format)?;
         sink.write_all(&bytes)?;
         Ok(())
@@ -0,0 +1,617 @@
+//! Declarations of the symbolic references in an artifact, and builders for their attributes.
+
+use failure::Error;
+
+use artifact::ArtifactError;
+
+/// The kind of declaration this is
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Decl {
+    /// An import of a function/routine defined in a shared library
+    FunctionImport,
+    /// A GOT-based import of data defined in a shared library
+    DataImport,
+    /// A function defined in this artifact
+    Function(FunctionDecl),
+    /// A data object defined in this artifact
+    Data(DataDecl),
+    /// A null-terminated string object defined in this artifact
+    CString(CStringDecl),
+    /// A symbol with a fixed value which does not live in any section, e.g. a hardware register address
+    Absolute(AbsoluteDecl),
+    /// A section of debugging information, e.g. DWARF's `.debug_info`, which is emitted under the declared name
+    DebugSection(DebugSectionDecl),
+    /// A section which is loaded at runtime, with the permissions and contents it is declared with,
+    /// which is emitted under the declared name
+    Section(SectionDecl),
+}
+
+impl Decl {
+    /// Begin declaring a function, which is local to this artifact by default
+    pub fn function() -> FunctionDecl {
+        FunctionDecl::default()
+    }
+    /// Begin declaring a read-only data object, which is local to this artifact by default
+    pub fn data() -> DataDecl {
+        DataDecl::default()
+    }
+    /// Begin declaring a null-terminated string, which is local to this artifact by default
+    pub fn cstring() -> CStringDecl {
+        CStringDecl::default()
+    }
+    /// Begin declaring an absolute symbol with the fixed `value`, which is local to this artifact by default
+    pub fn absolute(value: u64) -> AbsoluteDecl {
+        AbsoluteDecl { scope: Scope::Local, hidden: false, value }
+    }
+    /// Begin declaring a debug section; its name is the name of the section, e.g. `.debug_info`.
+    /// This includes prebuilt indexes of the debug info, e.g. `.debug_names` or `.gdb_index`, whose references
+    /// into the other debug sections are [Absolute](enum.Reloc.html#variant.Absolute) relocations.
+    ///
+    /// An automatic relocation from a debug section is the address of a function or data object, which is pointer sized,
+    /// or the offset of another debug section, which is 32-bit as in 32-bit DWARF; an offset into the other section,
+    /// e.g. of a string in `.debug_str`, or a 64-bit DWARF offset, is an `Absolute` relocation with that addend and size
+    pub fn debug_section() -> DebugSectionDecl {
+        DebugSectionDecl::default()
+    }
+    /// Begin declaring a custom section which is loaded at runtime; its name is the name of the section, e.g.
+    /// `.rodata.tables`. It is read-only and not executable by default. Like a debug section, it has no symbol
+    /// of its own, and relocations against it are relative to its start.
+    ///
+    /// **NB**: This is currently only supported for ELF targets
+    pub fn section() -> SectionDecl {
+        SectionDecl::default()
+    }
+    /// If it is compatible, absorb the new declaration (`other`) into the old (`self`); otherwise returns an error.
+    ///
+    /// The rule here is "C-ish", but essentially:
+    ///
+    /// 1. Duplicate declarations are no-ops / ignored.
+    /// 2. **If** the previous declaration was an [FunctionImport](enum.Decl.html#variant.FunctionImport) or [DataImport](enum.Decl.html#variant.DataImport),
+    ///    **then** if the subsequent declaration is a corresponding matching [Function](enum.Decl.html#variant.Function) or [Data](enum.Decl.html#variant.Data)
+    ///    declaration, it is said to be "upgraded", and forever after is considered a declaration in need of a definition.
+    /// 3. **If** the previous declaration was a `Function` or `Data` declaration,
+    ///    **then** a subsequent corresponding `FunctionImport` or `DataImport` is a no-op.
+    /// 4. Anything else is a [IncompatibleDeclaration](enum.ArtifactError.html#variant.IncompatibleDeclaration) error!
+    // ref https://github.com/m4b/faerie/issues/24
+    // ref https://github.com/m4b/faerie/issues/18
+    pub fn absorb(&mut self, other: Self) -> Result<(), Error> {
+        // FIXME: i can't think of a way offhand to not clone here, without unusual contortions
+        match self.clone() {
+            Decl::DataImport => {
+                match other {
+                    // data imports can be upgraded to any kind of data declaration
+                    Decl::Data(_) => { *self = other; Ok(()) }
+                    Decl::DataImport => Ok(()),
+                    _ => Err(ArtifactError::IncompatibleDeclaration { old:*self, new: other }.into()),
+                }
+            }
+            Decl::FunctionImport => {
+                match other {
+                    // function imports can be upgraded to any kind of function declaration
+                    Decl::Function(_) => { *self = other; Ok(()) }
+                    Decl::FunctionImport => Ok(()),
+                    _ => Err(ArtifactError::IncompatibleDeclaration { old:*self, new: other }.into()),
+                }
+            },
+            // a previous data declaration can only be re-declared a data import, or it must match exactly the
+            // next declaration
+            decl@Decl::Data(_) => {
+                match other {
+                    Decl::DataImport => Ok(()),
+                    other => if decl == other { Ok(()) } else {
+                        Err(ArtifactError::IncompatibleDeclaration { old:*self, new: other }.into())
+                    }
+                }
+            }
+            // a previous function decl can only be re-declared a function import, or it must match exactly
+            // the next declaration
+            decl@Decl::Function(_) => {
+                match other {
+                    Decl::FunctionImport => Ok(()),
+                    other => {
+                        if decl == other { Ok(()) } else {
+                            Err(ArtifactError::IncompatibleDeclaration { old:*self, new: other }.into())
+                        }
+                    },
+                }
+            },
+            decl => if decl == other { Ok(()) } else {
+                Err(ArtifactError::IncompatibleDeclaration { old:*self, new: other }.into())
+            }
+        }
+    }
+    /// Is this an import (function or data) from a shared library?
+    pub fn is_import(&self) -> bool {
+        use self::Decl::*;
+        matches!(*self, FunctionImport | DataImport)
+    }
+    /// Is this an absolute symbol, i.e., one whose value is fixed at declaration time?
+    pub fn is_absolute(&self) -> bool {
+        matches!(*self, Decl::Absolute(_))
+    }
+    /// Is this a debug section?
+    pub fn is_debug_section(&self) -> bool {
+        matches!(*self, Decl::DebugSection(_))
+    }
+    /// Is this a custom section which is loaded at runtime?
+    pub fn is_section(&self) -> bool {
+        matches!(*self, Decl::Section(_))
+    }
+}
+
+/// The visibility of a definition outside of this artifact
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Scope {
+    /// Only visible inside of this artifact
+    #[default]
+    Local,
+    /// Visible outside of this artifact
+    Global,
+    /// Visible outside of this artifact, but any global definition of the same name takes precedence,
+    /// and the linker picks only one of several weak definitions, e.g. a C++ inline function
+    Weak,
+}
+
+/// The visibility of a global symbol outside of the module, i.e. the executable or shared library, it is linked into
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Visibility {
+    /// Visible to other modules, which may preempt it with a definition of their own
+    #[default]
+    Default,
+    /// Visible to other modules, but references from its own module always bind to its definition in the module
+    Protected,
+    /// Not visible to other modules, though the other objects linked into the module can refer to it
+    Hidden,
+}
+
+/// The attributes of a function defined in this artifact
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct FunctionDecl {
+    scope: Scope,
+    cold: bool,
+    noreturn: bool,
+    ifunc: bool,
+    thumb: bool,
+    hidden: bool,
+}
+
+impl FunctionDecl {
+    /// Make this function visible outside of this artifact
+    pub fn global(mut self) -> Self {
+        self.scope = Scope::Global; self
+    }
+    /// Make this function only visible inside of this artifact
+    pub fn local(mut self) -> Self {
+        self.scope = Scope::Local; self
+    }
+    /// Make this function visible outside of this artifact, yielding to any other definition of it
+    pub fn weak(mut self) -> Self {
+        self.scope = Scope::Weak; self
+    }
+    /// Set the `scope` of this function
+    pub fn with_scope(mut self, scope: Scope) -> Self {
+        self.scope = scope; self
+    }
+    /// Mark this function as unlikely to be executed, e.g. an error path, so it is placed away from hot code
+    pub fn cold(mut self) -> Self {
+        self.cold = true; self
+    }
+    /// Mark this function as never returning to its caller, e.g. a panic handler or `abort`.
+    /// Such functions are only ever entered on exceptional paths, and so are placed with cold code.
+    ///
+    /// **NB**: this only affects where the function is placed, as `cold` does; faerie emits no call frame
+    /// information of its own, so leaving it out for a function which never returns is up to the frontend
+    pub fn noreturn(mut self) -> Self {
+        self.noreturn = true; self
+    }
+    /// Make this function an indirect function (GNU ifunc): its definition is the resolver, which is called with
+    /// no arguments when the program is loaded and returns the address of the implementation to call, e.g. the
+    /// one suited to the CPU. A dynamic linker calls it, and a static executable calls it from its startup code,
+    /// for each `R_X86_64_IRELATIVE` between `__rela_iplt_start` and `__rela_iplt_end`, which the linker creates.
+    /// **NB**: This is currently only supported for ELF targets
+    pub fn ifunc(mut self) -> Self {
+        self.ifunc = true; self
+    }
+    /// Mark this function as Thumb code, on 32-bit ARM, rather than ARM code. Calls between ARM and Thumb code are
+    /// interworked by the linker, which switches to the state of the function it calls; on a Thumb-only target,
+    /// e.g. `thumbv7m`, every function is Thumb code.
+    /// **NB**: This is currently only supported for ELF targets
+    pub fn thumb(mut self) -> Self {
+        self.thumb = true; self
+    }
+    /// Hide this function from outside of the linked module, e.g. a shared library, although the other objects
+    /// linked with this artifact can still call it; a private extern in Mach-O
+    pub fn hidden(mut self) -> Self {
+        self.hidden = true; self
+    }
+    /// The scope of this function
+    pub fn scope(&self) -> Scope {
+        self.scope
+    }
+    /// Is this function visible outside of this artifact, and not weak?
+    pub fn is_global(&self) -> bool {
+        self.scope == Scope::Global
+    }
+    /// Is this function weak?
+    pub fn is_weak(&self) -> bool {
+        self.scope == Scope::Weak
+    }
+    /// Is this function marked cold?
+    pub fn is_cold(&self) -> bool {
+        self.cold
+    }
+    /// Does this function never return?
+    pub fn is_noreturn(&self) -> bool {
+        self.noreturn
+    }
+    /// Is this an indirect function, whose definition is its resolver?
+    pub fn is_ifunc(&self) -> bool {
+        self.ifunc
+    }
+    /// Is this function marked as Thumb code?
+    pub fn is_thumb(&self) -> bool {
+        self.thumb
+    }
+    /// Is this function hidden outside of the linked module?
+    pub fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+}
+
+impl From<FunctionDecl> for Decl {
+    fn from(decl: FunctionDecl) -> Self {
+        Decl::Function(decl)
+    }
+}
+
+/// The attributes of a data object defined in this artifact
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct DataDecl {
+    scope: Scope,
+    writable: bool,
+    zero_init: Option<u64>,
+    thread_local: bool,
+    common: bool,
+    hidden: bool,
+}
+
+impl DataDecl {
+    /// Make this data object visible outside of this artifact
+    pub fn global(mut self) -> Self {
+        self.scope = Scope::Global; self
+    }
+    /// Make this data object only visible inside of this artifact
+    pub fn local(mut self) -> Self {
+        self.scope = Scope::Local; self
+    }
+    /// Make this data object visible outside of this artifact, yielding to any other definition of it
+    pub fn weak(mut self) -> Self {
+        self.scope = Scope::Weak; self
+    }
+    /// Set the `scope` of this data object
+    pub fn with_scope(mut self, scope: Scope) -> Self {
+        self.scope = scope; self
+    }
+    /// Make this data object writable at runtime
+    pub fn writable(mut self) -> Self {
+        self.writable = true; self
+    }
+    /// Make this data object read-only at runtime
+    pub fn read_only(mut self) -> Self {
+        self.writable = false; self
+    }
+    /// Make this data object zero-initialized and writable, aligned to `align` bytes, which must be a power of two.
+    /// It is defined with only its size, by [define_zeroed](../struct.Artifact.html#method.define_zeroed), and takes up
+    /// no space in the object file, e.g. in ELF's `.bss`
+    pub fn zero_init(mut self, align: u64) -> Self {
+        self.writable = true;
+        self.zero_init = Some(align); self
+    }
+    /// Make this data object a common symbol, e.g. a C tentative definition: global, zero-initialized and aligned to
+    /// `align` bytes, as [zero_init](struct.DataDecl.html#method.zero_init). It is defined with only its size, but is
+    /// allocated by the linker rather than this artifact, which merges it with any common symbols of the same name,
+    /// keeping the largest, or drops it for a definition of the name, instead of reporting a multiple definition.
+    /// Like an import, it has no section in this artifact, so it can't be aliased, or located relative to another
+    /// definition.
+    pub fn common(mut self, align: u64) -> Self {
+        self.scope = Scope::Global;
+        self.common = true;
+        self.zero_init(align)
+    }
+    /// Make this data object thread-local and writable: each thread has its own copy, which is initialized from
+    /// the definition, e.g. in ELF's `.tdata`, or `.tbss` when it is also zero-initialized. It can only be
+    /// addressed by a [TLS relocation](../enum.Reloc.html#variant.Tls). In Mach-O its symbol is a descriptor in
+    /// `__thread_vars`, and its initial value is in `__thread_data` or `__thread_bss`.
+    ///
+    /// **NB**: This is currently only supported for ELF and Mach-O targets
+    pub fn thread_local(mut self) -> Self {
+        self.writable = true;
+        self.thread_local = true; self
+    }
+    /// Hide this data object from outside of the linked module, e.g. a shared library, although the other objects
+    /// linked with this artifact can still refer to it; a private extern in Mach-O
+    pub fn hidden(mut self) -> Self {
+        self.hidden = true; self
+    }
+    /// The scope of this data object
+    pub fn scope(&self) -> Scope {
+        self.scope
+    }
+    /// Is this data object visible outside of this artifact, and not weak?
+    pub fn is_global(&self) -> bool {
+        self.scope == Scope::Global
+    }
+    /// Is this data object weak?
+    pub fn is_weak(&self) -> bool {
+        self.scope == Scope::Weak
+    }
+    /// Is this data object writable?
+    pub fn is_writable(&self) -> bool {
+        self.writable
+    }
+    /// Is this data object zero-initialized?
+    pub fn is_zero_init(&self) -> bool {
+        self.zero_init.is_some()
+    }
+    /// Is this data object thread-local?
+    pub fn is_thread_local(&self) -> bool {
+        self.thread_local
+    }
+    /// Is this data object a common symbol?
+    pub fn is_common(&self) -> bool {
+        self.common
+    }
+    /// Is this data object hidden outside of the linked module?
+    pub fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+    /// The alignment of this data object, if it is zero-initialized
+    pub fn align(&self) -> Option<u64> {
+        self.zero_init
+    }
+}
+
+impl From<DataDecl> for Decl {
+    fn from(decl: DataDecl) -> Self {
+        Decl::Data(decl)
+    }
+}
+
+/// The attributes of a null-terminated string defined in this artifact
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct CStringDecl {
+    global: bool,
+    no_merge: bool,
+}
+
+impl CStringDecl {
+    /// Make this string visible outside of this artifact
+    pub fn global(mut self) -> Self {
+        self.global = true; self
+    }
+    /// Make this string only visible inside of this artifact
+    pub fn local(mut self) -> Self {
+        self.global = false; self
+    }
+    /// Keep this string intact, so the linker doesn't merge it with identical strings, e.g. to keep the offsets of
+    /// a table of strings which is indexed by them
+    pub fn no_merge(mut self) -> Self {
+        self.no_merge = true; self
+    }
+    /// Is this string visible outside of this artifact?
+    pub fn is_global(&self) -> bool {
+        self.global
+    }
+    /// May the linker merge this string with identical strings?
+    pub fn is_mergeable(&self) -> bool {
+        !self.no_merge
+    }
+}
+
+impl From<CStringDecl> for Decl {
+    fn from(decl: CStringDecl) -> Self {
+        Decl::CString(decl)
+    }
+}
+
+/// The attributes of an absolute symbol
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AbsoluteDecl {
+    scope: Scope,
+    hidden: bool,
+    value: u64,
+}
+
+impl AbsoluteDecl {
+    /// Make this symbol visible outside of this artifact
+    pub fn global(mut self) -> Self {
+        self.scope = Scope::Global; self
+    }
+    /// Make this symbol only visible inside of this artifact
+    pub fn local(mut self) -> Self {
+        self.scope = Scope::Local; self
+    }
+    /// Make this symbol visible outside of this artifact, yielding to any other definition of it
+    pub fn weak(mut self) -> Self {
+        self.scope = Scope::Weak; self
+    }
+    /// Set the `scope` of this symbol
+    pub fn with_scope(mut self, scope: Scope) -> Self {
+        self.scope = scope; self
+    }
+    /// Hide this symbol from outside of the linked module, e.g. a shared library, although the other objects
+    /// linked with this artifact can still refer to it
+    pub fn hidden(mut self) -> Self {
+        self.hidden = true; self
+    }
+    /// The scope of this symbol
+    pub fn scope(&self) -> Scope {
+        self.scope
+    }
+    /// Is this symbol visible outside of this artifact, and not weak?
+    pub fn is_global(&self) -> bool {
+        self.scope == Scope::Global
+    }
+    /// Is this symbol weak?
+    pub fn is_weak(&self) -> bool {
+        self.scope == Scope::Weak
+    }
+    /// Is this symbol hidden outside of the linked module?
+    pub fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+    /// The fixed value of this symbol
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+}
+
+impl From<AbsoluteDecl> for Decl {
+    fn from(decl: AbsoluteDecl) -> Self {
+        Decl::Absolute(decl)
+    }
+}
+
+/// The attributes of a debug section defined in this artifact.
+/// Debug sections are never loaded at runtime.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct DebugSectionDecl {
+    strings: bool,
+    no_merge: bool,
+}
+
+impl DebugSectionDecl {
+    /// This section is a table of null-terminated strings, e.g. `.debug_str`, which the linker may merge
+    pub fn strings(mut self) -> Self {
+        self.strings = true; self
+    }
+    /// Keep this table of strings intact, so the linker doesn't merge it with identical strings, e.g. when the
+    /// offsets of its strings are known elsewhere without being relocated
+    pub fn no_merge(mut self) -> Self {
+        self.no_merge = true; self
+    }
+    /// Is this section a table of strings?
+    pub fn is_strings(&self) -> bool {
+        self.strings
+    }
+    /// May the linker merge the strings of this section with identical strings?
+    pub fn is_mergeable(&self) -> bool {
+        self.strings && !self.no_merge
+    }
+}
+
+impl From<DebugSectionDecl> for Decl {
+    fn from(decl: DebugSectionDecl) -> Self {
+        Decl::DebugSection(decl)
+    }
+}
+
+/// The attributes of a custom section defined in this artifact, which is loaded at runtime unless it is not allocated
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct SectionDecl {
+    writable: bool,
+    executable: bool,
+    strings: Option<u64>,
+    no_merge: bool,
+    unwind: bool,
+    entries: Option<u64>,
+    not_allocated: bool,
+    no_bits: bool,
+    elf_type: Option<u32>,
+    elf_flags: u64,
+}
+
+impl SectionDecl {
+    /// Make this section writable at runtime
+    pub fn writable(mut self) -> Self {
+        self.writable = true; self
+    }
+    /// Make this section executable at runtime, without it being a function
+    pub fn executable(mut self) -> Self {
+        self.executable = true; self
+    }
+    /// This section is a table of null-terminated strings of `entsize` byte characters, e.g. 2 for UTF-16, which
+    /// the linker may merge with the strings of other sections of the same name; each ends with `entsize` zero bytes
+    pub fn strings(mut self, entsize: u64) -> Self {
+        self.strings = Some(entsize); self
+    }
+    /// Keep this table of strings intact, so the linker doesn't merge it with identical strings
+    pub fn no_merge(mut self) -> Self {
+        self.no_merge = true; self
+    }
+    /// This section is a table of constants of `entsize` bytes each, e.g. 8 for doubles, which the linker may merge
+    /// with identical constants of other sections of the same name; its size is a multiple of `entsize`
+    pub fn merge(mut self, entsize: u64) -> Self {
+        self.entries = Some(entsize); self
+    }
+    /// Leave this section out of the memory image, so it is only kept in the linked file, e.g. `.comment`
+    pub fn not_allocated(mut self) -> Self {
+        self.not_allocated = true; self
+    }
+    /// This section takes up memory at runtime but no space in the object, like `.bss`; its definition is zeros
+    pub fn no_bits(mut self) -> Self {
+        self.no_bits = true; self
+    }
+    /// Emit this section with the ELF section type `sh_type`, e.g. `SHT_INIT_ARRAY` or `SHT_NOTE`, instead of the
+    /// one faerie picks for its contents
+    pub fn elf_type(mut self, sh_type: u32) -> Self {
+        self.elf_type = Some(sh_type); self
+    }
+    /// Add the raw ELF section flags `flags`, e.g. `SHF_GNU_RETAIN`, to the ones faerie derives from the others
+    pub fn elf_flags(mut self, flags: u64) -> Self {
+        self.elf_flags |= flags; self
+    }
+    /// This section is the call frame information which unwinders read at runtime, i.e. `.eh_frame`, whose CIEs
+    /// and FDEs the linker parses, e.g. to build `.eh_frame_hdr`. It is read-only, and an automatic relocation from
+    /// it is the 4 byte PC relative pointer of the common `DW_EH_PE_pcrel | DW_EH_PE_sdata4` encoding; the
+    /// `.debug_frame` which debuggers read is a [debug section](enum.Decl.html#method.debug_section) instead
+    pub fn unwind(mut self) -> Self {
+        self.unwind = true; self
+    }
+    /// Is this section writable?
+    pub fn is_writable(&self) -> bool {
+        self.writable
+    }
+    /// Is this section executable?
+    pub fn is_executable(&self) -> bool {
+        self.executable
+    }
+    /// The size of the characters of this section's strings, if it is a table of strings
+    pub fn strings_entsize(&self) -> Option<u64> {
+        self.strings
+    }
+    /// May the linker merge the strings of this section with identical strings?
+    pub fn is_mergeable(&self) -> bool {
+        self.strings.is_some() && !self.no_merge
+    }
+    /// Is this section call frame information for unwinding?
+    pub fn is_unwind(&self) -> bool {
+        self.unwind
+    }
+    /// The size of the constants of this section, if it is a table of constants the linker may merge
+    pub fn merge_entsize(&self) -> Option<u64> {
+        self.entries
+    }
+    /// Is this section part of the memory image?
+    pub fn is_allocated(&self) -> bool {
+        !self.not_allocated
+    }
+    /// Does this section take up no space in the object?
+    pub fn is_no_bits(&self) -> bool {
+        self.no_bits
+    }
+    /// The raw ELF section type of this section, if it is not the one faerie picks
+    pub fn elf_section_type(&self) -> Option<u32> {
+        self.elf_type
+    }
+    /// The raw ELF section flags added to the ones faerie derives
+    pub fn elf_section_flags(&self) -> u64 {
+        self.elf_flags
+    }
+}
+
+impl From<SectionDecl> for Decl {
+    fn from(decl: SectionDecl) -> Self {
+        Decl::Section(decl)
+    }
+}
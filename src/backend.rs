@@ -0,0 +1,66 @@
+//! Writing an artifact as an object file, for each binary format
+
+use failure::Error;
+use target_lexicon::BinaryFormat;
+
+use std::fmt;
+use std::io::{Seek, Write};
+
+use Artifact;
+use {elf, mach, wasm};
+
+/// Where an object file is written; it can be seeked, so a backend can go back and fill in e.g. a header
+pub trait Sink: Write + Seek {}
+
+impl<T: Write + Seek> Sink for T {}
+
+/// Writes an artifact as an object file of one binary format.
+///
+/// The built-in backends are chosen by the binary format an artifact is emitted as; an artifact can be emitted with
+/// its own using [emit_with](../struct.Artifact.html#method.emit_with), e.g. for a container faerie doesn't know,
+/// which is written from the artifact's [definitions](../struct.Artifact.html#method.definitions),
+/// [imports](../struct.Artifact.html#method.imports) and [links](../struct.Artifact.html#method.links).
+pub trait ObjectBackend: fmt::Debug + Send + Sync {
+    /// Write the object file of `artifact` to `sink`; every declaration which must be defined is
+    fn write(&self, artifact: &Artifact, sink: &mut dyn Sink) -> Result<(), Error>;
+}
+
+/// The built-in backend for `format`, if there is one
+pub(crate) fn builtin(format: BinaryFormat) -> Option<&'static dyn ObjectBackend> {
+    match format {
+        BinaryFormat::Elf => Some(&Elf),
+        BinaryFormat::Macho => Some(&Macho),
+        BinaryFormat::Wasm => Some(&Wasm),
+        _ => None,
+    }
+}
+
+/// ELF relocatable objects
+#[derive(Debug, Clone, Copy)]
+pub struct Elf;
+
+impl ObjectBackend for Elf {
+    fn write(&self, artifact: &Artifact, sink: &mut dyn Sink) -> Result<(), Error> {
+        elf::write(artifact, sink)
+    }
+}
+
+/// Mach-O object files
+#[derive(Debug, Clone, Copy)]
+pub struct Macho;
+
+impl ObjectBackend for Macho {
+    fn write(&self, artifact: &Artifact, sink: &mut dyn Sink) -> Result<(), Error> {
+        mach::write(artifact, sink)
+    }
+}
+
+/// WebAssembly object files, which `wasm-ld` links
+#[derive(Debug, Clone, Copy)]
+pub struct Wasm;
+
+impl ObjectBackend for Wasm {
+    fn write(&self, artifact: &Artifact, sink: &mut dyn Sink) -> Result<(), Error> {
+        wasm::write(artifact, sink)
+    }
+}
@@ -73,12 +73,12 @@ fn run (args: Args) -> Result<(), Error> {
     // it is a runtime error to define a symbol _without_ declaring it first
     obj.declarations(
         [
-            ("deadbeef",   Decl::Function { global: false }),
-            ("main",       Decl::Function { global: true }),
-            ("str.1",      Decl::CString { global: false }),
+            ("deadbeef",   Decl::function().into()),
+            ("main",       Decl::function().global().into()),
+            ("str.1",      Decl::cstring().into()),
             ("DEADBEEF",   Decl::DataImport),
-            ("STATIC",     Decl::Data { global: true, writable: true }),
-            ("STATIC_REF", Decl::Data { global: true, writable: true }),
+            ("STATIC",     Decl::data().global().writable().into()),
+            ("STATIC_REF", Decl::data().global().writable().into()),
             ("printf",     Decl::FunctionImport),
         ].into_iter().cloned()
     )?;
@@ -194,7 +194,7 @@ fn deadbeef (args: Args) -> Result<(), Error> {
     // FIXME: need to state this isn't a string, but some linkers don't seem to care \o/
     // gold complains though:
     // ld.gold: warning: deadbeef.o: last entry in mergeable string section '.data.DEADBEEF' not null terminated
-    obj.declare("DEADBEEF", Decl::Data { global: true, writable: false })?;
+    obj.declare("DEADBEEF", Decl::data().global())?;
     obj.define("DEADBEEF", [0xef, 0xbe, 0xad, 0xde].to_vec())?;
     obj.write(file)?;
     if let Some(output) = args.link {
@@ -7,22 +7,26 @@
 
 use goblin;
 use failure::Error;
-use {artifact, Artifact, Decl, Ctx, ImportKind};
-use artifact::LinkAndDecl;
+use {artifact, encoder, Artifact, AbsoluteDecl, Decl, Ctx, ImportKind, DataSource, Note, Reloc, Scope, Strip};
+use {SFrameBase, SFrameRow, ComdatSelection, LinkerDirective, SymbolKind, Visibility};
+use encoder::RelocEncoder;
+use artifact::{ArtifactError, Binding, LinkAndDecl, SymbolAttributes, GOT_BASE};
 use target::make_ctx;
 
-use std::collections::{HashMap, hash_map};
+use std::collections::{HashMap, HashSet, hash_map};
 use std::fmt;
-use std::io::{Seek, Cursor, BufWriter, Write};
+use std::mem;
+use std::io::{self, Seek, BufWriter, Cursor, Write};
 use std::io::SeekFrom::*;
-use scroll::IOwrite;
+use scroll::{IOwrite, Pwrite};
 use string_interner::DefaultStringInterner;
 use indexmap::IndexMap;
-use target_lexicon::Architecture;
+use target_lexicon::{Architecture, PointerWidth};
 
 use goblin::elf::header::{self, Header};
-use goblin::elf::section_header::{SectionHeader};
+use goblin::elf::section_header::{SectionHeader, SHF_GROUP, SHF_LINK_ORDER, SHF_MERGE, SHF_STRINGS, SHN_LORESERVE, SHT_GROUP, SHT_NOBITS, SHT_REL};
 use goblin::elf::reloc;
+use goblin::elf::sym;
 
 // interned string idx
 type StringIndex = usize;
@@ -34,11 +38,12 @@ type Section = SectionHeader;
 
 struct MachineTag(u16);
 
-impl From<Architecture> for MachineTag {
-    fn from(architecture: Architecture) -> MachineTag {
+impl MachineTag {
+    /// The machine of `architecture`, if it exists in ELF
+    fn new(architecture: Architecture) -> Option<MachineTag> {
         use target_lexicon::Architecture::*;
         use goblin::elf::header::*;
-        MachineTag(match architecture {
+        Some(MachineTag(match architecture {
             X86_64 => EM_X86_64,
             I386 |
             I586 |
@@ -67,9 +72,8 @@ impl From<Architecture> for MachineTag {
             Sparcv9 => EM_SPARCV9,
             Msp430 => EM_MSP430,
             Unknown => EM_NONE,
-            Asmjs => panic!("asm.js does not exist in ELF"),
-            Wasm32 => panic!("wasm32 does not exist in ELF"),
-        })
+            Asmjs | Wasm32 => return None,
+        }))
     }
 }
 
@@ -79,12 +83,20 @@ enum SymbolType {
     Function,
     /// A data object
     Object,
+    /// A thread-local data object, whose value is its offset in the thread-local storage of the object
+    Tls,
+    /// An indirect function, whose value is its resolver
+    Ifunc,
     /// An impor
     Import,
     /// A section reference
     Section,
     /// A file reference
     File,
+    /// A symbol with a fixed value, not relative to any section
+    Absolute,
+    /// A common data object, which the linker allocates, whose value is its alignment
+    Common,
     /// None
     None,
 }
@@ -93,7 +105,10 @@ enum SymbolType {
 struct SymbolBuilder {
     name_offset: usize,
     global: bool,
+    weak: bool,
+    visibility: u8,
     size: u64,
+    value: u64,
     typ: SymbolType,
 }
 
@@ -102,30 +117,54 @@ impl SymbolBuilder {
     pub fn new(typ: SymbolType) -> Self {
         SymbolBuilder {
             global: false,
+            weak: false,
+            visibility: 0,
             name_offset: 0,
             typ,
             size: 0,
+            value: 0,
         }
     }
     /// Set the size of this symbol; for functions, it should be the routines size in bytes
     pub fn size(mut self, size: usize) -> Self {
         self.size = size as u64; self
     }
+    /// Set the value of this symbol; for absolute symbols, this is the symbol's fixed address
+    pub fn value(mut self, value: u64) -> Self {
+        self.value = value; self
+    }
     /// Is this symbol local in scope?
     pub fn local(mut self, local: bool) -> Self {
         self.global = !local; self
     }
+    /// Is this symbol weak, i.e., does any other definition of it take precedence?
+    pub fn weak(mut self, weak: bool) -> Self {
+        self.weak = weak; self
+    }
+    /// Is this symbol hidden outside of the linked module?
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.visibility = if hidden { STV_HIDDEN } else { 0 }; self
+    }
+    /// Set the visibility of this symbol outside of the linked module
+    pub fn visibility(mut self, visibility: Visibility) -> Self {
+        self.visibility = match visibility {
+            Visibility::Default => 0,
+            Visibility::Protected => STV_PROTECTED,
+            Visibility::Hidden => STV_HIDDEN,
+        };
+        self
+    }
     /// Set the symbol name as a byte offset into the corresponding strtab
     pub fn name_offset(mut self, name_offset: usize) -> Self {
         self.name_offset = name_offset; self
     }
     /// Finalize and create the symbol
     pub fn create(self) -> Symbol {
-        use goblin::elf::sym::{STT_NOTYPE, STT_FILE, STT_FUNC, STT_SECTION, STT_OBJECT, STB_LOCAL, STB_GLOBAL};
-        use goblin::elf::section_header::SHN_ABS;
+        use goblin::elf::sym::{STT_NOTYPE, STT_FILE, STT_FUNC, STT_SECTION, STT_OBJECT, STT_TLS, STT_GNU_IFUNC, STB_LOCAL, STB_GLOBAL, STB_WEAK};
+        use goblin::elf::section_header::{SHN_ABS, SHN_COMMON};
         let mut st_shndx = 0;
         let mut st_info = 0;
-        let st_value = 0;
+        let st_value = self.value;
         match self.typ {
             SymbolType::Function => {
                 st_info |= STT_FUNC;
@@ -133,9 +172,14 @@ impl SymbolBuilder {
             SymbolType::Object => {
                 st_info |= STT_OBJECT;
             },
+            SymbolType::Tls => {
+                st_info |= STT_TLS;
+            },
+            SymbolType::Ifunc => {
+                st_info |= STT_GNU_IFUNC;
+            },
             SymbolType::Import => {
                 st_info = STT_NOTYPE;
-                st_info |= STB_GLOBAL << 4;
             },
             SymbolType::Section => {
                 st_info |= STT_SECTION;
@@ -146,18 +190,28 @@ impl SymbolBuilder {
                 // knowledge™
                 st_shndx = SHN_ABS as usize;
             },
+            SymbolType::Absolute => {
+                st_info = STT_NOTYPE;
+                st_shndx = SHN_ABS as usize;
+            },
+            SymbolType::Common => {
+                st_info |= STT_OBJECT;
+                st_shndx = SHN_COMMON as usize;
+            },
             SymbolType::None => {
                 st_info = STT_NOTYPE
             },
         }
-        if self.global {
+        if self.weak {
+            st_info |= STB_WEAK << 4;
+        } else if self.global {
             st_info |= STB_GLOBAL << 4;
         } else {
             st_info |= STB_LOCAL << 4;
         }
         Symbol {
             st_name: self.name_offset,
-            st_other: 0,
+            st_other: self.visibility,
             st_size: self.size,
             st_info,
             st_shndx,
@@ -175,6 +229,9 @@ enum SectionType {
     StrTab,
     SymTab,
     Relocation,
+    Note,
+    InitArray,
+    FiniArray,
     None,
 }
 
@@ -184,6 +241,7 @@ struct SectionBuilder {
     exec: bool,
     write: bool,
     alloc: bool,
+    tls: bool,
     size: u64,
     name_offset: usize,
 }
@@ -196,6 +254,7 @@ impl SectionBuilder {
             exec: false,
             write: false,
             alloc: false,
+            tls: false,
             name_offset: 0,
             size,
         }
@@ -212,6 +271,10 @@ impl SectionBuilder {
     pub fn writable(mut self, writable:bool) -> Self {
         self.write = writable; self
     }
+    /// Make this section hold thread-local storage, i.e. the template each thread's copy is initialized from
+    pub fn tls(mut self, tls: bool) -> Self {
+        self.tls = tls; self
+    }
 
     /// Set the byte offset of this section's name in the corresponding strtab
     pub fn name_offset(mut self, name_offset: usize) -> Self {
@@ -237,6 +300,9 @@ impl SectionBuilder {
         if self.alloc {
             shdr.sh_flags |= SHF_ALLOC as u64
         }
+        if self.tls {
+            shdr.sh_flags |= SHF_TLS as u64
+        }
         match self.typ {
             SectionType::Bits => {
                 shdr.sh_addralign = if self.exec { 0x10 } else if self.write { 0x8 } else { 1 };
@@ -267,6 +333,21 @@ impl SectionBuilder {
                 shdr.sh_flags = 0;
                 shdr.sh_type = SHT_RELA
             },
+            // arrays of pointers to the functions the runtime calls in turn
+            SectionType::InitArray => {
+                shdr.sh_entsize = if ctx.is_big() { 0x8 } else { 0x4 };
+                shdr.sh_addralign = shdr.sh_entsize;
+                shdr.sh_type = SHT_INIT_ARRAY;
+            },
+            SectionType::FiniArray => {
+                shdr.sh_entsize = if ctx.is_big() { 0x8 } else { 0x4 };
+                shdr.sh_addralign = shdr.sh_entsize;
+                shdr.sh_type = SHT_FINI_ARRAY;
+            },
+            SectionType::Note => {
+                shdr.sh_addralign = 0x4;
+                shdr.sh_type = SHT_NOTE;
+            },
             SectionType::None => shdr.sh_type = SHT_NULL,
         }
         shdr
@@ -319,25 +400,86 @@ impl RelocationBuilder {
     }
 }
 
+/// Which part of an artifact an object file holds, when its debug sections are split into their own file
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Part {
+    /// Everything
+    Whole,
+    /// Everything but the debug sections
+    Stripped,
+    /// The debug sections, along with the full symbol table; other sections are present, but hold no data
+    Debug,
+}
+
+impl Part {
+    /// Are the contents of a definition declared as `decl` written in this part?
+    fn has_contents(self, decl: &Decl) -> bool {
+        match self {
+            Part::Whole => true,
+            Part::Stripped => !decl.is_debug_section(),
+            Part::Debug => decl.is_debug_section(),
+        }
+    }
+}
+
 //#[derive(Debug)]
 /// An intermediate ELF object file container
 struct Elf<'a> {
     name: &'a str,
-    code: IndexMap<StringIndex, &'a [u8]>,
+    code: IndexMap<StringIndex, &'a DataSource>,
     relocations: IndexMap<StringIndex, (Section, Vec<Relocation>)>,
+    blobs: Vec<(Section, Vec<u8>)>,
     symbols: IndexMap<StringIndex, Symbol>,
     special_symbols: Vec<Symbol>,
     section_symbols: IndexMap<StringIndex, Symbol>,
     imports: HashMap<StringIndex, ImportKind>,
-    sections: HashMap<StringIndex, Section>,
+    sections: IndexMap<StringIndex, Section>,
     offsets: HashMap<StringIndex, Offset>,
     sizeof_strtab: Offset,
     strings: DefaultStringInterner,
     sizeof_bits: Offset,
-    nsections: u16,
+    nsections: usize,
     ctx: Ctx,
     architecture: Architecture,
     nlocals: usize,
+    part: Part,
+    executable_stack: bool,
+    /// Selects the relocations for the links
+    encoder: &'a dyn RelocEncoder,
+    /// Whether relocations carry their addend, or it is written in place, as i386 requires
+    rela: bool,
+    /// Whether the addend is also written in place when relocations carry it
+    inline_addends: bool,
+    /// The relocation for a pointer to a symbol
+    pointer_reloc: u32,
+    /// The addends written in place of each section's relocations, when they are not RELA, and the section offsets
+    /// written in place of a relocation: the offset, the size of the field, and the value
+    implicit_addends: HashMap<usize, Vec<(u64, usize, i64)>>,
+    /// Whether any relocation is relative to the GOT, so the object refers to the GOT base
+    uses_got: bool,
+    /// The definition each alias is another name for
+    aliases: HashMap<StringIndex, StringIndex>,
+    /// The symbol name of each local declaration which isn't emitted under its own name
+    symbol_names: HashMap<&'a str, &'a str>,
+    /// The section of each definition which isn't placed in its own
+    placements: HashMap<&'a str, &'a str>,
+    /// The alignment of each function, and of each data object with contents, if not the defaults
+    function_align: Option<u64>,
+    data_align: Option<u64>,
+    /// Whether each function is in its own section
+    function_sections: bool,
+    /// The contents of each section the functions which aren't in their own section are appended to, and the functions
+    text_sections: IndexMap<&'static str, (Vec<u8>, Vec<StringIndex>)>,
+    /// The leader of the COMDAT group of each definition which is in one
+    comdats: IndexMap<StringIndex, StringIndex>,
+    /// The leader of each COMDAT group, and the index of its section among the blobs
+    groups: Vec<(StringIndex, usize)>,
+    /// The functions of Thumb code, and their aliases, whose symbol values have the low bit set
+    thumb: HashSet<StringIndex>,
+    /// How many local labels there are, which the relocations relative to a field rather than their target refer to
+    nlabels: usize,
+    /// Whether each relocation table is sorted by offset
+    deterministic: bool,
 }
 
 impl<'a> fmt::Debug for Elf<'a> {
@@ -359,10 +501,23 @@ impl<'a> fmt::Debug for Elf<'a> {
 
 const STRTAB_LINK: u16 = 1;
 const SYMTAB_LINK: u16 = 2;
+/// The visibility of a symbol which is not exported from the linked module, which goblin does not define
+const STV_HIDDEN: u8 = 2;
+/// The visibility of a symbol which the linked module's own references always bind to
+const STV_PROTECTED: u8 = 3;
+/// The flag of a group whose copies in other objects are discarded, which goblin does not define
+const GRP_COMDAT: u32 = 1;
+/// The type of the `.eh_frame` section on x86-64, which goblin does not define
+const SHT_X86_64_UNWIND: u32 = 0x7000_0001;
+/// The flag of a section which the linker keeps when it collects garbage, which goblin does not define
+const SHF_GNU_RETAIN: u64 = 0x20_0000;
+/// The index of the OS ABI in the identification bytes of the header, and the GNU ABI, which goblin does not define
+const EI_OSABI: usize = 7;
+const ELFOSABI_GNU: u8 = 3;
 
 impl<'a> Elf<'a> {
-    pub fn new(artifact: &'a Artifact) -> Self {
-        let ctx = make_ctx(&artifact.target);
+    pub fn new(artifact: &'a Artifact, part: Part, encoder: &'a dyn RelocEncoder) -> Self {
+        let ctx = encoder.ctx().unwrap_or_else(|| make_ctx(&artifact.target));
         let mut offsets = HashMap::new();
         let mut strings = DefaultStringInterner::default();
         let mut special_symbols = Vec::new();
@@ -389,16 +544,24 @@ impl<'a> Elf<'a> {
 
         }
 
+        let comdats = artifact.comdats()
+            .map(|(member, leader, _)| (strings.get_or_intern(member), strings.get_or_intern(leader)))
+            .collect();
+        let thumb = artifact.definitions()
+            .filter(|def| def.prop.thumb)
+            .map(|def| strings.get_or_intern(def.name))
+            .collect();
         let sizeof_bits = Header::size(&ctx);
         Elf {
             name: &artifact.name,
             code:        IndexMap::new(),
             relocations: IndexMap::new(),
+            blobs:       Vec::new(),
             imports:     HashMap::new(),
             symbols:     IndexMap::new(),
             special_symbols,
             section_symbols,
-            sections:    HashMap::new(),
+            sections:    IndexMap::new(),
             nsections:   4,
             offsets,
             strings,
@@ -407,8 +570,32 @@ impl<'a> Elf<'a> {
             ctx,
             architecture: artifact.target.architecture,
             nlocals: 0,
+            part,
+            executable_stack: artifact.executable_stack,
+            encoder,
+            rela: encoder.rela(),
+            inline_addends: artifact.inline_addends,
+            pointer_reloc: encoder.pointer(artifact),
+            implicit_addends: HashMap::new(),
+            uses_got: false,
+            aliases: HashMap::new(),
+            symbol_names: artifact.symbol_names().collect(),
+            placements: artifact.placements().collect(),
+            function_align: artifact.function_align,
+            data_align: artifact.data_align,
+            function_sections: artifact.function_sections,
+            text_sections: IndexMap::new(),
+            comdats,
+            groups: Vec::new(),
+            thumb,
+            nlabels: 0,
+            deterministic: artifact.is_deterministic(),
         }
     }
+    /// The name of the symbol the declaration `name` is emitted as
+    fn symbol_name<'b>(&self, name: &'b str) -> &'b str where 'a: 'b {
+        self.symbol_names.get(name).cloned().unwrap_or(name)
+    }
     fn new_string(&mut self, name: String) -> (StringIndex, usize) {
         let size = name.len() + 1;
         let idx = self.strings.get_or_intern(name);
@@ -423,38 +610,58 @@ impl<'a> Elf<'a> {
             }
         }
     }
-    pub fn add_definition(&mut self, name: &str, data: &'a [u8], prop: &artifact::Prop) {
+    /// Add the definition `name`; `relro` places read-only data in `.data.rel.ro`, as it is relocated
+    pub fn add_definition(&mut self, name: &str, data: &'a DataSource, prop: &artifact::Prop, relro: bool) {
         // we need this because sh_info requires nsections + nlocals to add as delimiter; see the associated FunFact
-        if !prop.global { self.nlocals += 1; }
+        // debug and custom sections only have a section symbol
+        if !prop.global && !prop.debug && !prop.section { self.nlocals += 1; }
         // FIXME: this is kind of hacky?
         let segment_name =
-          if prop.function { "text" } else {
+          if prop.function {
+              // cold code is gathered away from hot code by the linker
+              if prop.cold { "text.unlikely" } else { "text" }
+          } else if prop.tls {
+              // the template of each thread's copy
+              "tdata"
+          } else if relro {
+              // only writable until it is relocated
+              "data.rel.ro"
+          } else {
               // we'd add ro here once the prop supports that
               "data"
           };
-        // intern section and symbol name strings
-        let (_reloc_idx, _reloc_section_offset) = self.new_string(format!(".reloc.{}.{}", segment_name, name));
-        let (_text_idx, section_offset) = self.new_string(format!(".{}.{}", segment_name, name));
-        // can do prefix optimization here actually, because .text.*
-        let (idx, offset) = self.new_string(name.to_string());
+        // intern section and symbol name strings; the symbol name is later merged into the tail of the section name
+        // debug and custom sections are named exactly as they were declared
+        // as are definitions placed in a section of another name
+        let section_name = match self.placements.get(name) {
+            _ if prop.debug || prop.section => name.to_string(),
+            Some(section) => section.to_string(),
+            None => format!(".{}.{}", segment_name, self.symbol_name(name)),
+        };
+        let (_text_idx, section_offset) = self.new_string(section_name);
+        let idx = self.strings.get_or_intern(name);
+        let (_, offset) = self.new_string(self.symbol_name(name).to_string());
         // store the size of this code
-        let size = data.len();
+        let size = data.len() as usize;
         debug!("idx: {:?} @ {:#x} - new strtab offset: {:#x}", idx, offset, self.sizeof_strtab);
         // build symbol based on this _and_ the properties of the definition
-        let mut symbol = SymbolBuilder::new(if prop.function { SymbolType::Function } else { SymbolType::Object })
+        let typ = if prop.ifunc { SymbolType::Ifunc } else if prop.function { SymbolType::Function } else if prop.tls { SymbolType::Tls } else { SymbolType::Object };
+        let mut symbol = SymbolBuilder::new(typ)
             .size(size)
             .name_offset(offset)
             .local(!prop.global)
+            .weak(prop.weak)
+            .hidden(prop.hidden)
             .create();
         // the symbols section reference/index will be the current number of sections
-        symbol.st_shndx = self.symbols.len() + 3; // null + strtab + symtab
+        symbol.st_shndx = self.sections.len() + 3; // null + strtab + symtab
 
         // now we build the section a la LLVM "function sections"
         let mut section_symbol = SymbolBuilder::new(SymbolType::Section).create();
         // the symbols section reference/index will be the current number of sections
-        section_symbol.st_shndx = self.symbols.len() + 3; // null + strtab + symtab
+        section_symbol.st_shndx = self.sections.len() + 3; // null + strtab + symtab
         // insert it into our symbol table
-        self.symbols.insert(idx, symbol);
+        if !prop.debug && !prop.section { self.symbols.insert(idx, symbol); }
         self.section_symbols.insert(idx, section_symbol);
         // FIXME: probably add padding alignment
 
@@ -462,7 +669,8 @@ impl<'a> Elf<'a> {
             let stype =
                 if prop.function {
                     SectionType::Bits
-                } else if prop.cstring {
+                // strings whose offsets are relied upon are kept as they are, rather than merged
+                } else if prop.cstring && !prop.no_merge {
                     SectionType::String
                 } else {
                     SectionType::Data
@@ -471,99 +679,835 @@ impl<'a> Elf<'a> {
             let tmp = SectionBuilder::new(size as u64)
                 .name_offset(section_offset)
                 .section_type(stype)
-                .alloc().writable(prop.writable);
+                .writable(prop.writable || relro)
+                .tls(prop.tls);
+            // debug sections are not loaded at runtime, nor are custom sections which are not allocated
+            let tmp = if prop.debug || prop.not_allocated { tmp } else { tmp.alloc() };
 
             // FIXME: I don't like this at all; can make exec() take bool but doesn't match other section properties
-            if prop.function { tmp.exec().create(&self.ctx) } else { tmp.create(&self.ctx) }
+            if prop.function || prop.executable { tmp.exec().create(&self.ctx) } else { tmp.create(&self.ctx) }
         };
+        // strings and custom sections keep their own alignment
+        let align = if prop.function { self.function_align } else if !prop.cstring && !prop.debug && !prop.section { self.data_align } else { None };
+        if let Some(align) = align {
+            section.sh_addralign = align;
+        }
         // the offset is the head of how many program bits we've added
         section.sh_offset = self.sizeof_bits as u64;
         // NB this is very brittle
         // - it means the entry is a sequence of 1 byte each, i.e., a cstring
-        if !prop.function { section.sh_entsize = 1 };
-        self.sections.insert(idx, section);
+        if prop.cstring || (!prop.function && !prop.debug && !prop.section) { section.sh_entsize = 1 };
+        // call frame information is a sequence of pointer aligned entries, which x86-64 gives a type of its own
+        if prop.unwind {
+            section.sh_addralign = if self.ctx.is_big() { 8 } else { 4 };
+            if self.machine() == Some(header::EM_X86_64) {
+                section.sh_type = SHT_X86_64_UNWIND;
+            }
+        }
+        // the strings of a custom section may have wider characters, which are aligned
+        if let Some(entsize) = prop.entsize {
+            section.sh_entsize = entsize;
+            section.sh_addralign = section.sh_addralign.max(entsize);
+        }
+        // a table of constants, rather than strings, is merged by its entries
+        if prop.entsize.is_some() && !prop.cstring {
+            section.sh_flags |= u64::from(SHF_MERGE);
+        }
+        // the attributes a custom section is declared with override the ones faerie picks
+        if let Some(sh_type) = prop.elf_type {
+            section.sh_type = sh_type;
+        }
+        section.sh_flags |= prop.elf_flags;
         self.nsections += 1;
+        // a debug file keeps the layout of the stripped sections, but not their contents
+        if (self.part == Part::Debug && !prop.debug) || prop.no_bits {
+            section.sh_type = SHT_NOBITS;
+            self.sections.insert(idx, section);
+            return;
+        }
+        self.sections.insert(idx, section);
         // increment the size
         self.sizeof_bits += size;
 
         self.code.insert(idx, data);
     }
-    pub fn import(&mut self, import: String, kind: &ImportKind) {
-        let (idx, offset) = self.new_string(import);
-        let symbol = SymbolBuilder::new(SymbolType::Import).name_offset(offset).create();
-        self.imports.insert(idx, kind.clone());
+    /// Append the function `name` to `.text`, or `.text.unlikely` if it is cold, at an offset aligned to the function
+    /// alignment, rather than adding it in its own section; the sections are added by `add_text_sections`
+    pub fn add_to_text(&mut self, name: &str, data: &DataSource, prop: &artifact::Prop) -> io::Result<()> {
+        if !prop.global { self.nlocals += 1; }
+        let align = self.function_align.unwrap_or(0x10) as usize;
+        let idx = self.strings.get_or_intern(name);
+        let (_, offset) = self.new_string(self.symbol_name(name).to_string());
+        let part = self.part;
+        let (ref mut bytes, ref mut functions) = *self.text_sections.entry(if prop.cold { ".text.unlikely" } else { ".text" }).or_default();
+        bytes.resize(bytes.len().next_multiple_of(align), 0);
+        let value = bytes.len() as u64;
+        if part == Part::Debug {
+            // only the layout is kept
+            bytes.resize(bytes.len() + data.len() as usize, 0);
+        } else {
+            data.write_to(bytes)?;
+        }
+        functions.push(idx);
+        let symbol = SymbolBuilder::new(if prop.ifunc { SymbolType::Ifunc } else { SymbolType::Function })
+            .size(data.len() as usize)
+            .value(value)
+            .name_offset(offset)
+            .local(!prop.global)
+            .weak(prop.weak)
+            .hidden(prop.hidden)
+            .create();
+        // the section index is set once every definition has its section
         self.symbols.insert(idx, symbol);
+        Ok(())
     }
-    pub fn link(&mut self, l: &LinkAndDecl) {
-        debug!("Link: {:?}", l);
-        let (from_idx, to_idx) = {
-            let to_idx = self.strings.get_or_intern(l.to.name);
-            let from_idx = self.strings.get_or_intern(l.from.name);
-            let (to_idx, _, _) = self.symbols.get_full(&to_idx).expect("to_idx present in symbols");
-            let (from_idx, _, _) = self.symbols.get_full(&from_idx).expect("from_idx present in symbols");
-            (from_idx, to_idx)
-        };
-        let (reloc, addend) = if let Some(ovr) = l.reloc {
-            (ovr.reloc, i64::from(ovr.addend))
-        } else {
-            match *l.from.decl {
-                Decl::Function {..} => {
-                    match *l.to.decl {
-                        // NB: this now forces _all_ function references, whether local or not, through the PLT
-                        // although we're not in the worst company here: https://github.com/ocaml/ocaml/pull/1330
-                        Decl::Function {..} | Decl::FunctionImport => (reloc::R_X86_64_PLT32, -4),
-                        Decl::Data {..} => (reloc::R_X86_64_PC32, -4),
-                        Decl::CString {..} => (reloc::R_X86_64_PC32, 0),
-                        Decl::DataImport => (reloc::R_X86_64_GOTPCREL, -4),
-                    }
-                },
-                Decl::Data {..} => {
-                    if self.ctx.is_big() {
-                        // Select an absolute relocation that is the size of a pointer.
-                        (reloc::R_X86_64_64, 0)
-                    } else {
-                        (reloc::R_X86_64_32, 0)
-                    }
+    /// Add the sections the functions which aren't in their own section were appended to, which follow the
+    /// definition sections, and place the functions in them
+    pub fn add_text_sections(&mut self) {
+        for (name, (bytes, functions)) in mem::replace(&mut self.text_sections, IndexMap::new()) {
+            let (_idx, name_offset) = self.new_string(name.to_string());
+            let mut section = SectionBuilder::new(bytes.len() as u64)
+                .name_offset(name_offset)
+                .section_type(SectionType::Bits)
+                .alloc()
+                .exec()
+                .create(&self.ctx);
+            section.sh_addralign = self.function_align.unwrap_or(0x10);
+            let shndx = 3 + self.sections.len() + self.blobs.len();
+            for idx in functions {
+                self.symbols.get_mut(&idx).expect("function has a symbol").st_shndx = shndx;
+            }
+            if self.part == Part::Debug {
+                section.sh_type = SHT_NOBITS;
+                self.add_blob(section, Vec::new());
+            } else {
+                self.add_blob(section, bytes);
+            }
+        }
+    }
+    /// Add a section for each COMDAT group, listing the sections of its members and their relocations, which are
+    /// flagged as in a group; the groups are the last of the blobs, after which the relocation sections are numbered,
+    /// so every relocation must have been added
+    pub fn add_groups(&mut self) -> goblin::error::Result<()> {
+        let mut groups: IndexMap<StringIndex, Vec<usize>> = IndexMap::new();
+        for (&member, &leader) in &self.comdats {
+            // an alias of a member has no section of its own
+            if self.aliases.contains_key(&member) { continue }
+            let shndx = self.symbols[&member].st_shndx;
+            groups.entry(leader).or_default().push(shndx);
+        }
+        if groups.is_empty() {
+            return Ok(());
+        }
+        let relocations = 3 + self.sections.len() + self.blobs.len() + groups.len();
+        let (_idx, name_offset) = self.new_string(".group".to_string());
+        for (leader, mut members) in groups {
+            // the members are listed in the order of their sections
+            members.sort();
+            let mut bytes = Vec::with_capacity(4 * (1 + 2 * members.len()));
+            bytes.iowrite_with(GRP_COMDAT, self.ctx.le)?;
+            for shndx in members {
+                self.sections.get_index_mut(shndx - 3).expect("member has a section").1.sh_flags |= u64::from(SHF_GROUP);
+                bytes.iowrite_with(shndx as u32, self.ctx.le)?;
+                if let Some((i, _, &mut (ref mut section, _))) = self.relocations.get_full_mut(&shndx) {
+                    section.sh_flags |= u64::from(SHF_GROUP);
+                    bytes.iowrite_with((relocations + i) as u32, self.ctx.le)?;
                 }
-                _ => panic!("unsupported relocation {:?}", l),
             }
+            let mut section = SectionBuilder::new(bytes.len() as u64)
+                .name_offset(name_offset)
+                .section_type(SectionType::Data)
+                .create(&self.ctx);
+            section.sh_type = SHT_GROUP;
+            section.sh_flags = 0;
+            section.sh_link = SYMTAB_LINK as u32;
+            section.sh_entsize = 4;
+            section.sh_addralign = 4;
+            // the signature symbol, sh_info, is only known once the symbols are final, when the object is written
+            self.groups.push((leader, self.blobs.len()));
+            self.add_blob(section, bytes);
+        }
+        Ok(())
+    }
+    /// Add the zero-initialized data object `name`, aligned to `align`, to `.bss`, which holds every zero-initialized
+    /// definition, or `.tbss` when it is thread-local; the section occupies memory when loaded, but nothing in the file.
+    /// A common symbol is in no section, and the linker allocates it.
+    pub fn add_zeroed(&mut self, name: &str, size: u64, align: u64, prop: &artifact::Prop) {
+        if prop.common {
+            let idx = self.strings.get_or_intern(name);
+            let (_, offset) = self.new_string(self.symbol_name(name).to_string());
+            let symbol = SymbolBuilder::new(SymbolType::Common)
+                .size(size as usize)
+                .value(align)
+                .name_offset(offset)
+                .local(false)
+                .hidden(prop.hidden)
+                .create();
+            self.symbols.insert(idx, symbol);
+            return;
+        }
+        if !prop.global { self.nlocals += 1; }
+        // thread-local data is zero-initialized in the template of each thread's copy instead
+        // unless it is placed in a section of another name, which holds the zero-initialized definitions placed there
+        let section_name = match self.placements.get(name) {
+            Some(section) => section.to_string(),
+            None => if prop.tls { ".tbss" } else { ".bss" }.to_string(),
         };
+        let (bss, bss_offset) = self.new_string(section_name);
+        if !self.sections.contains_key(&bss) {
+            let mut section = SectionBuilder::new(0)
+                .name_offset(bss_offset)
+                .section_type(SectionType::Data)
+                .writable(true)
+                .tls(prop.tls)
+                .alloc()
+                .create(&self.ctx);
+            section.sh_type = SHT_NOBITS;
+            section.sh_addralign = 1;
+            section.sh_offset = self.sizeof_bits as u64;
+            self.nsections += 1;
+            self.sections.insert(bss, section);
+        }
+        let (section_idx, _, section) = self.sections.get_full_mut(&bss).expect(".bss was added");
+        section.sh_addralign = section.sh_addralign.max(align);
+        let value = section.sh_size.next_multiple_of(align);
+        section.sh_size = value + size;
+        let idx = self.strings.get_or_intern(name);
+        let (_, offset) = self.new_string(self.symbol_name(name).to_string());
+        let mut symbol = SymbolBuilder::new(if prop.tls { SymbolType::Tls } else { SymbolType::Object })
+            .size(size as usize)
+            .value(value)
+            .name_offset(offset)
+            .local(!prop.global)
+            .weak(prop.weak)
+            .hidden(prop.hidden)
+            .create();
+        symbol.st_shndx = section_idx + 3; // null + strtab + symtab
+        self.symbols.insert(idx, symbol);
+    }
+    /// Add the note section `name`, holding `notes`, which is placed after the code at a 4 byte boundary
+    pub fn add_note_section(&mut self, name: &str, notes: &[Note]) -> goblin::error::Result<()> {
+        let mut bytes = Vec::with_capacity(notes.iter().map(Note::size).sum());
+        for note in notes {
+            // namesz includes the null terminator, and both the name and the descriptor are padded to 4 bytes
+            bytes.iowrite_with(note.owner.len() as u32 + 1, self.ctx.le)?;
+            bytes.iowrite_with(note.desc.len() as u32, self.ctx.le)?;
+            bytes.iowrite_with(note.kind, self.ctx.le)?;
+            bytes.extend_from_slice(note.owner.as_bytes());
+            bytes.push(0);
+            while bytes.len() % 4 != 0 { bytes.push(0) }
+            bytes.extend_from_slice(&note.desc);
+            while bytes.len() % 4 != 0 { bytes.push(0) }
+        }
+        let (_idx, name_offset) = self.new_string(name.to_string());
+        let mut section = SectionBuilder::new(bytes.len() as u64)
+            .name_offset(name_offset)
+            .section_type(SectionType::Note)
+            .alloc()
+            .create(&self.ctx);
+        // the program properties are pointer sized words, so the note is too
+        if name == ".note.gnu.property" && self.ctx.is_big() {
+            section.sh_addralign = 0x8;
+        }
+        self.add_blob(section, bytes);
+        Ok(())
+    }
+    /// Add a `.gnu_debuglink` section, which tells debuggers to load the debug file named `file`, whose CRC-32 is `crc`
+    pub fn add_debuglink(&mut self, file: &str, crc: u32) -> goblin::error::Result<()> {
+        let mut bytes = Vec::with_capacity(file.len() + 8);
+        bytes.extend_from_slice(file.as_bytes());
+        bytes.push(0);
+        while bytes.len() % 4 != 0 { bytes.push(0) }
+        bytes.iowrite_with(crc, self.ctx.le)?;
+        let (_idx, name_offset) = self.new_string(".gnu_debuglink".to_string());
+        let mut section = SectionBuilder::new(bytes.len() as u64)
+            .name_offset(name_offset)
+            .section_type(SectionType::Data)
+            .create(&self.ctx);
+        // the crc is read as an aligned word
+        section.sh_addralign = 0x4;
+        self.add_blob(section, bytes);
+        Ok(())
+    }
+    /// Add `.deplibs`, which holds the libraries of the `directives`, and `.linker-options`, which holds the pairs
+    /// of their options, each string null terminated; either is only added if it isn't empty
+    pub fn add_linker_directives(&mut self, directives: &[&LinkerDirective]) {
+        // LLVM's SHT_LLVM_LINKER_OPTIONS and SHT_LLVM_DEPENDENT_LIBRARIES
+        const SHT_LINKER_OPTIONS: u32 = 0x6fff_4c01;
+        const SHT_DEPENDENT_LIBRARIES: u32 = 0x6fff_4c04;
+        const SHF_EXCLUDE: u64 = 0x8000_0000;
+        let (libraries, options): (Vec<&LinkerDirective>, Vec<&LinkerDirective>) = directives.iter().cloned()
+            .partition(|directive| matches!(**directive, LinkerDirective::Library(_)));
+        for &(name, typ, flags, ref directives) in &[
+            (".deplibs", SHT_DEPENDENT_LIBRARIES, (SHF_MERGE | SHF_STRINGS) as u64, libraries),
+            // the options are only for the linker, which doesn't copy them to its output
+            (".linker-options", SHT_LINKER_OPTIONS, SHF_EXCLUDE, options),
+        ] {
+            if directives.is_empty() {
+                continue;
+            }
+            let mut bytes = Vec::new();
+            for string in directives.iter().flat_map(|directive| directive.strings()) {
+                bytes.extend_from_slice(string.as_bytes());
+                bytes.push(0);
+            }
+            let (_idx, name_offset) = self.new_string(name.to_string());
+            let mut section = SectionBuilder::new(bytes.len() as u64)
+                .name_offset(name_offset)
+                .section_type(SectionType::Bits)
+                .create(&self.ctx);
+            section.sh_type = typ;
+            section.sh_flags = flags;
+            if typ == SHT_DEPENDENT_LIBRARIES {
+                section.sh_entsize = 1;
+            }
+            self.add_blob(section, bytes);
+        }
+    }
+    /// Add the section `name`, which is empty, and has no flags
+    pub fn add_empty_section(&mut self, name: &str) {
+        let (_idx, name_offset) = self.new_string(name.to_string());
+        let section = SectionBuilder::new(0)
+            .name_offset(name_offset)
+            .section_type(SectionType::Bits)
+            .create(&self.ctx);
+        self.add_blob(section, Vec::new());
+    }
+    /// Add the section `name`, an array holding a pointer to each function in `functions`, in order
+    pub fn add_function_array(&mut self, name: &str, typ: SectionType, functions: &[Binding]) {
+        let pointer = if self.ctx.is_big() { 8 } else { 4 };
+        let (_idx, name_offset) = self.new_string(name.to_string());
+        let mut section = SectionBuilder::new((functions.len() * pointer) as u64)
+            .name_offset(name_offset)
+            .section_type(typ)
+            .alloc()
+            .writable(true)
+            .create(&self.ctx);
+        section.sh_addralign = pointer as u64;
+        // the blob sections follow the definition sections
+        let shndx = 3 + self.sections.len() + self.blobs.len();
+        self.add_blob(section, vec![0; functions.len() * pointer]);
+        let (reloc, addend) = (self.pointer_reloc, 0);
+        for (i, function) in functions.iter().enumerate() {
+            let to_name = self.strings.get_or_intern(function.name);
+            let sym_idx = self.symbol_index(to_name, function.decl);
+            let size = self.encoder.field_size(reloc);
+            self.add_relocation(name, shndx, reloc, sym_idx, ((i * pointer) as u64, size), addend);
+        }
+    }
+    /// Add `.tm_clone_table`, which holds a pair of pointers for each of the `clones`: to the function, then to its
+    /// transactional memory clone; it is delimited by the local symbols `__TMC_LIST__` and `__TMC_END__`, which must
+    /// be added before any relocation, as the local symbols are reordered
+    pub fn add_tm_clone_table(&mut self, clones: Vec<(Binding, Binding)>) {
+        const TABLE: &str = ".tm_clone_table";
+        let pointer = if self.ctx.is_big() { 8 } else { 4 };
+        // the blob sections follow the definition sections
+        let shndx = 3 + self.sections.len() + self.blobs.len();
+        for &(name, value) in &[("__TMC_LIST__", 0), ("__TMC_END__", clones.len() * 2 * pointer)] {
+            let (idx, offset) = self.new_string(name.to_string());
+            let mut symbol = SymbolBuilder::new(SymbolType::Object)
+                .value(value as u64)
+                .name_offset(offset)
+                .local(true)
+                .create();
+            symbol.st_shndx = shndx;
+            self.symbols.insert(idx, symbol);
+            self.nlocals += 1;
+        }
+        // the local symbols must precede the global ones
+        self.symbols.sort_by(|_, a, _, b| (a.st_bind() != sym::STB_LOCAL).cmp(&(b.st_bind() != sym::STB_LOCAL)));
+        let entries = clones.into_iter().flat_map(|(function, clone)| vec![function, clone]).collect::<Vec<_>>();
+        self.add_function_array(TABLE, SectionType::Data, &entries);
+    }
+    /// Add the `.sframe` section, which holds the SFrame stack trace information of each of the `functions`,
+    /// in the format's version 1: the header, then a descriptor of each function, then their rows
+    pub fn add_sframe(&mut self, functions: &[(Binding, &[SFrameRow])]) -> Result<(), Error> {
+        const SFRAME_MAGIC: u16 = 0xdee2;
+        const SFRAME_VERSION_1: u8 = 1;
+        const SFRAME_ABI_AARCH64_ENDIAN_BIG: u8 = 1;
+        const SFRAME_ABI_AARCH64_ENDIAN_LITTLE: u8 = 2;
+        const SFRAME_ABI_AMD64_ENDIAN_LITTLE: u8 = 3;
+        const HEADER_SIZE: usize = 28;
+        const FDE_SIZE: usize = 17;
+        let le = self.ctx.le;
+        let (abi, fixed_ra_offset, reloc) = match self.architecture {
+            Architecture::X86_64 => (SFRAME_ABI_AMD64_ENDIAN_LITTLE, -8i8, reloc::R_X86_64_PC32),
+            Architecture::Aarch64 if le.is_little() => (SFRAME_ABI_AARCH64_ENDIAN_LITTLE, 0, encoder::R_AARCH64_PREL32),
+            Architecture::Aarch64 => (SFRAME_ABI_AARCH64_ENDIAN_BIG, 0, encoder::R_AARCH64_PREL32),
+            architecture => return Err(format_err!("SFrame is not defined for architecture {}", architecture)),
+        };
+        let mut fdes = Vec::with_capacity(functions.len() * FDE_SIZE);
+        let mut fres = Vec::new();
+        let mut nfres = 0;
+        for &(ref function, rows) in functions {
+            let idx = self.strings.get_or_intern(function.name);
+            let size = self.symbols[&idx].st_size;
+            if size > u64::from(u32::MAX) {
+                return Err(format_err!("{} is too large for SFrame", function.name));
+            }
+            if let Some(row) = rows.iter().find(|row| u64::from(row.offset) >= size) {
+                return Err(format_err!("SFrame row at {:#x} is outside of {}", row.offset, function.name));
+            }
+            // the start of each row is as wide as the last one needs
+            let last = rows.last().map_or(0, |row| row.offset);
+            let (fre_type, address_size) = if last <= 0xff { (0, 1) } else if last <= 0xffff { (1, 2) } else { (2, 4) };
+            let start = fres.len();
+            for row in rows {
+                let mut offsets = vec![row.cfa_offset];
+                // the return address is only saved at a varying offset where it isn't fixed
+                if fixed_ra_offset == 0 {
+                    offsets.extend(row.ra_offset);
+                }
+                offsets.extend(row.fp_offset);
+                let fits = |min: i32, max: i32| offsets.iter().all(|&offset| offset >= min && offset <= max);
+                let (offset_size, width) = if fits(i8::MIN.into(), i8::MAX.into()) {
+                    (0, 1)
+                } else if fits(i16::MIN.into(), i16::MAX.into()) {
+                    (1, 2)
+                } else {
+                    (2, 4)
+                };
+                let base = match row.cfa_base { SFrameBase::FramePointer => 0, SFrameBase::StackPointer => 1 };
+                let at = fres.len();
+                fres.extend(vec![0; address_size]);
+                match address_size {
+                    1 => fres.pwrite_with(row.offset as u8, at, le)?,
+                    2 => fres.pwrite_with(row.offset as u16, at, le)?,
+                    _ => fres.pwrite_with(row.offset, at, le)?,
+                };
+                fres.push(base | (offsets.len() as u8) << 1 | offset_size << 5);
+                for offset in offsets {
+                    let at = fres.len();
+                    fres.extend(vec![0; width]);
+                    match width {
+                        1 => fres.pwrite_with(offset as i8, at, le)?,
+                        2 => fres.pwrite_with(offset as i16, at, le)?,
+                        _ => fres.pwrite_with(offset, at, le)?,
+                    };
+                }
+            }
+            // the function's address is relocated
+            let at = fdes.len();
+            fdes.extend(vec![0; FDE_SIZE]);
+            fdes.pwrite_with(size as u32, at + 4, le)?;
+            fdes.pwrite_with(start as u32, at + 8, le)?;
+            fdes.pwrite_with(rows.len() as u32, at + 12, le)?;
+            fdes.pwrite_with(fre_type as u8, at + 16, le)?;
+            nfres += rows.len();
+        }
+        let mut bytes = vec![0; HEADER_SIZE];
+        bytes.pwrite_with(SFRAME_MAGIC, 0, le)?;
+        bytes.pwrite_with(SFRAME_VERSION_1, 2, le)?;
+        bytes.pwrite_with(abi, 4, le)?;
+        bytes.pwrite_with(fixed_ra_offset, 6, le)?;
+        bytes.pwrite_with(functions.len() as u32, 8, le)?;
+        bytes.pwrite_with(nfres as u32, 12, le)?;
+        bytes.pwrite_with(fres.len() as u32, 16, le)?;
+        bytes.pwrite_with(fdes.len() as u32, 24, le)?;
+        bytes.extend(fdes);
+        bytes.extend(fres);
 
-        let sym_idx = match *l.to.decl {
-            Decl::Function {..} | Decl::Data {..} | Decl::CString {..} => to_idx + 2,
-            // +2 for NOTYPE and FILE symbols
-            Decl::FunctionImport | Decl::DataImport => {
-                to_idx + self.special_symbols.len() + self.section_symbols.len()
-                // + special_symbols.len() + section_symbols.len() because this is where the import
-                // symbols begin
+        let (_idx, name_offset) = self.new_string(".sframe".to_string());
+        let mut section = SectionBuilder::new(bytes.len() as u64)
+            .name_offset(name_offset)
+            .section_type(SectionType::Data)
+            .alloc()
+            .create(&self.ctx);
+        section.sh_addralign = 0x8;
+        // the blob sections follow the definition sections
+        let shndx = 3 + self.sections.len() + self.blobs.len();
+        self.add_blob(section, bytes);
+        for (i, (function, _)) in functions.iter().enumerate() {
+            let to_name = self.strings.get_or_intern(function.name);
+            let sym_idx = self.symbol_index(to_name, function.decl);
+            let size = self.encoder.field_size(reloc);
+            self.add_relocation(".sframe", shndx, reloc, sym_idx, ((HEADER_SIZE + i * FDE_SIZE) as u64, size), 0);
+        }
+        Ok(())
+    }
+    /// Add a section whose contents, `bytes`, are placed after the code, aligned to the section's alignment
+    fn add_blob(&mut self, mut section: Section, bytes: Vec<u8>) {
+        let align = section.sh_addralign as usize;
+        let padding = (align - self.sizeof_bits % align) % align;
+        section.sh_offset = (self.sizeof_bits + padding) as u64;
+        self.sizeof_bits += padding + bytes.len();
+        self.nsections += 1;
+        self.blobs.push((section, bytes));
+    }
+    pub fn import(&mut self, import: String, kind: &ImportKind, dynamic: bool, weak: bool) {
+        let idx = self.strings.get_or_intern(import.as_str());
+        // a versioned import is emitted with its version
+        let (_, offset) = self.new_string(self.symbol_name(&import).to_string());
+        // a hidden undefined symbol has to be resolved by the static linker
+        let symbol = SymbolBuilder::new(SymbolType::Import)
+            .name_offset(offset)
+            .local(false)
+            .weak(weak)
+            .hidden(!dynamic)
+            .create();
+        self.imports.insert(idx, kind.clone());
+        self.symbols.insert(idx, symbol);
+    }
+    pub fn absolute(&mut self, name: String, decl: AbsoluteDecl) {
+        let local = decl.scope() == Scope::Local;
+        if local { self.nlocals += 1; }
+        let idx = self.strings.get_or_intern(name.as_str());
+        let (_, offset) = self.new_string(self.symbol_name(&name).to_string());
+        let symbol = SymbolBuilder::new(SymbolType::Absolute)
+            .name_offset(offset)
+            .value(decl.value())
+            .local(local)
+            .weak(decl.is_weak())
+            .hidden(decl.is_hidden())
+            .create();
+        self.symbols.insert(idx, symbol);
+    }
+    /// Override the size, type and `st_other` of the symbol `name` with the `attributes` it was given
+    pub fn symbol_attributes(&mut self, name: &str, attributes: SymbolAttributes) {
+        use goblin::elf::sym::{STT_FUNC, STT_OBJECT, STT_TLS, STT_GNU_IFUNC};
+        let idx = self.strings.get_or_intern(name);
+        let symbol = match self.symbols.get_mut(&idx) {
+            Some(symbol) => symbol,
+            // a stripped debug object has no symbol for it
+            None => return,
+        };
+        if let Some(size) = attributes.size {
+            symbol.st_size = size;
+        }
+        if let Some(kind) = attributes.kind {
+            let typ = match kind {
+                SymbolKind::Object => STT_OBJECT,
+                SymbolKind::Function => STT_FUNC,
+                SymbolKind::Ifunc => STT_GNU_IFUNC,
+                SymbolKind::Tls => STT_TLS,
+            };
+            symbol.st_info = (symbol.st_info & 0xf0) | typ;
+        }
+        if let Some(other) = attributes.other {
+            symbol.st_other = other;
+        }
+    }
+    /// Flag the section of the definition `name` so that the linker keeps it when it collects garbage
+    pub fn retain(&mut self, name: &str) {
+        let idx = self.strings.get_or_intern(name);
+        if let Some(section) = self.sections.get_mut(&idx) {
+            section.sh_flags |= SHF_GNU_RETAIN;
+        } else if let Some(shndx) = self.symbols.get(&idx).map(|symbol| symbol.st_shndx) {
+            // zero-initialized data is in a shared .bss
+            if let Some((_, section)) = self.sections.get_index_mut(shndx - 3) {
+                section.sh_flags |= SHF_GNU_RETAIN;
             }
+        }
+    }
+    /// Add the symbol `alias`, declared as `decl`, at the same place as the definition `target`
+    pub fn alias(&mut self, alias: &str, target: &str, decl: &Decl, visibility: Visibility) {
+        let (local, weak, typ) = match *decl {
+            Decl::Function(f) if f.is_ifunc() => (f.scope() == Scope::Local, f.is_weak(), SymbolType::Ifunc),
+            Decl::Function(f) => (f.scope() == Scope::Local, f.is_weak(), SymbolType::Function),
+            Decl::Data(d) => (d.scope() == Scope::Local, d.is_weak(), SymbolType::Object),
+            Decl::CString(c) => (!c.is_global(), false, SymbolType::Object),
+            _ => unreachable!("only definitions have aliases"),
         };
+        let target = self.strings.get_or_intern(target);
+        let (shndx, value, size) = {
+            let symbol = &self.symbols[&target];
+            (symbol.st_shndx, symbol.st_value, symbol.st_size)
+        };
+        let idx = self.strings.get_or_intern(alias);
+        let (_, offset) = self.new_string(self.symbol_name(alias).to_string());
+        let mut symbol = SymbolBuilder::new(typ)
+            .size(size as usize)
+            .value(value)
+            .name_offset(offset)
+            .local(local)
+            .weak(weak)
+            .visibility(visibility)
+            .create();
+        symbol.st_shndx = shndx;
+        self.symbols.insert(idx, symbol);
+        self.aliases.insert(idx, target);
+        if self.thumb.contains(&target) {
+            self.thumb.insert(idx);
+        }
+        if local {
+            self.nlocals += 1;
+            // the local symbols must precede the global ones
+            self.symbols.sort_by(|_, a, _, b| (a.st_bind() != sym::STB_LOCAL).cmp(&(b.st_bind() != sym::STB_LOCAL)));
+        }
+    }
+    /// The `e_machine` of the object, if its architecture exists in ELF
+    fn machine(&self) -> Option<u16> {
+        self.encoder.machine().or_else(|| MachineTag::new(self.architecture).map(|machine| machine.0))
+    }
+    /// Add the relocation `reloc`, with `addend`, for the link `l`
+    pub fn link(&mut self, l: &LinkAndDecl, reloc: u32, addend: i64) {
+        debug!("Link: {:?}", l);
+        let to_name = self.strings.get_or_intern(l.to.name);
+        let (from_shndx, from_offset) = self.placement(l.from.name);
+        // the PLT entry of a function is only used for its own symbol
+        // and a custom section has none, so it is always relocated against its section symbol
+        let sym_idx = if (self.encoder.refers_to_symbol(reloc) || l.reloc.is_plt()) && !l.to.decl.is_section() {
+            self.symbol_table_index(to_name)
+        } else {
+            self.symbol_index(to_name, l.to.decl)
+        };
+        if self.encoder.uses_got(reloc) {
+            self.uses_got = true;
+        }
 
-        let reloc = RelocationBuilder::new(reloc).sym(sym_idx).offset(l.at).addend(addend).create();
-        self.add_reloc(l.from.name, reloc, from_idx)
+        // a raw relocation may be of a type the encoder doesn't know the field of
+        let size = match l.reloc {
+            Reloc::Raw { size: Some(size), .. } => usize::from(size),
+            _ => self.encoder.field_size(reloc),
+        };
+        self.add_relocation(l.from.name, from_shndx, reloc, sym_idx, (from_offset + l.at, size), addend)
+    }
+    /// The section index of the definition `name`, and its offset in the section, which is 0 unless it is a function
+    /// which isn't in its own section
+    fn placement(&mut self, name: &str) -> (usize, u64) {
+        let idx = self.strings.get_or_intern(name);
+        // debug and custom sections only have a section symbol, whose value is 0
+        let symbol = self.section_symbols.get(&idx).or_else(|| self.symbols.get(&idx)).expect("definition has a symbol");
+        (symbol.st_shndx, symbol.st_value)
+    }
+    /// Write the offset of the target of the link `l` within its section, plus `addend`, in place of its `size` byte
+    /// field; no relocation is needed, as the target is defined in this object
+    fn section_offset(&mut self, l: &LinkAndDecl, size: u8, addend: i32) {
+        let to_idx = self.strings.get_or_intern(l.to.name);
+        // debug and custom sections only have a section symbol, and start at their own offset 0
+        let offset = self.symbols.get(&to_idx).map_or(0, |symbol| symbol.st_value);
+        let (from_shndx, from_offset) = self.placement(l.from.name);
+        let value = (offset as i64).wrapping_add(i64::from(addend));
+        self.implicit_addends.entry(from_shndx).or_default().push((from_offset + l.at, usize::from(size), value));
+    }
+    /// Add a local label at each of the `fields`, an offset in a definition, for the relocations which are relative
+    /// to the field rather than to their target; they must be added before any relocation, as the local symbols are
+    /// reordered
+    fn add_labels(&mut self, fields: &[(&str, u64)]) -> Vec<StringIndex> {
+        let mut labels = Vec::with_capacity(fields.len());
+        for &(from, at) in fields {
+            let (shndx, offset) = self.placement(from);
+            let (idx, name_offset) = self.new_string(format!(".Lpcrel_hi{}", self.nlabels));
+            self.nlabels += 1;
+            let mut symbol = SymbolBuilder::new(SymbolType::None)
+                .value(offset + at)
+                .name_offset(name_offset)
+                .local(true)
+                .create();
+            symbol.st_shndx = shndx;
+            self.symbols.insert(idx, symbol);
+            self.nlocals += 1;
+            labels.push(idx);
+        }
+        if !labels.is_empty() {
+            // the local symbols must precede the global ones
+            self.symbols.sort_by(|_, a, _, b| (a.st_bind() != sym::STB_LOCAL).cmp(&(b.st_bind() != sym::STB_LOCAL)));
+        }
+        labels
+    }
+    /// Relocate the field at `at` in `from` with `reloc`, relative to the local `label`
+    fn link_label(&mut self, from: &str, at: u64, label: StringIndex, reloc: u32) {
+        let (from_shndx, from_offset) = self.placement(from);
+        let sym_idx = self.symbol_table_index(label);
+        self.add_relocation(from, from_shndx, reloc, sym_idx, (from_offset + at, self.encoder.field_size(reloc)), 0)
+    }
+    /// Mark the instructions `reloc` applies to at `at` in `from` as ones the linker may relax, if they are
+    fn relax(&mut self, from: &str, at: u64, reloc: u32) {
+        if let Some(relaxation) = self.encoder.relaxation(reloc) {
+            let (from_shndx, from_offset) = self.placement(from);
+            self.add_relocation(from, from_shndx, relaxation, 0, (from_offset + at, 0), 0)
+        }
+    }
+    /// Relocate the call at `at` in `from` to the runtime `function` with `reloc` and `addend`,
+    /// importing the function unless the object already refers to it
+    fn call(&mut self, from: &str, at: u64, function: &str, reloc: u32, addend: i64) {
+        let function = match self.strings.get(function) {
+            Some(idx) if self.symbols.contains_key(&idx) => idx,
+            _ => {
+                self.import(function.to_string(), &ImportKind::Function, true, false);
+                self.strings.get(function).expect("function was imported")
+            },
+        };
+        let (from_shndx, from_offset) = self.placement(from);
+        let sym_idx = self.symbol_table_index(function);
+        self.add_relocation(from, from_shndx, reloc, sym_idx, (from_offset + at, self.encoder.field_size(reloc)), addend)
+    }
+    /// Add the relocation `typ` against the symbol `sym_idx` of the `size` byte field at `offset` in the section `shndx`,
+    /// which is `relocee`; without RELA, or with inline addends, the addend is written in place of the field when the
+    /// section is, unless the field is part of an instruction
+    fn add_relocation(&mut self, relocee: &str, shndx: usize, typ: u32, sym_idx: usize, (offset, size): (u64, usize), addend: i64) {
+        // the addend of an instruction is encoded in it
+        if (!self.rela || self.inline_addends) && self.encoder.is_value_field(typ) {
+            self.implicit_addends.entry(shndx).or_default().push((offset, size, addend));
+        }
+        let reloc = RelocationBuilder::new(typ).sym(sym_idx).offset(offset);
+        let reloc = if self.rela { reloc.addend(addend) } else { reloc.rel() };
+        self.add_reloc(relocee, reloc.create(), shndx)
+    }
+    /// Remove the local symbols, including the file and section symbols, which no relocation refers to,
+    /// and renumber the symbols of the relocations; every relocation must have been added
+    pub fn discard_locals(&mut self) {
+        self.discard_unreferenced(|symbol| symbol.st_bind() == sym::STB_LOCAL)
+    }
+    /// Remove the section symbols which no relocation refers to; each definition has its own section, and most are
+    /// only relocated against, if at all, by their own symbol, so an object may otherwise have thousands unused
+    pub fn discard_section_symbols(&mut self) {
+        self.discard_unreferenced(|symbol| symbol.st_type() == sym::STT_SECTION)
+    }
+    /// Remove the `discardable` symbols which no relocation refers to, and renumber the symbols of the relocations;
+    /// every relocation must have been added
+    fn discard_unreferenced<F: Fn(&Symbol) -> bool>(&mut self, discardable: F) {
+        let referenced = self.relocations.values()
+            .flat_map(|(_, relocs)| relocs.iter().map(|reloc| reloc.r_sym))
+            .collect::<HashSet<_>>();
+        let keep = self.special_symbols.iter().chain(self.section_symbols.values()).chain(self.symbols.values())
+            .enumerate()
+            // the null symbol is always first
+            .map(|(i, symbol)| i == 0 || !discardable(symbol) || referenced.contains(&i))
+            .collect::<Vec<_>>();
+        let mut remap = Vec::with_capacity(keep.len());
+        let mut kept = 0;
+        for &keep in &keep {
+            remap.push(kept);
+            if keep { kept += 1 }
+        }
+        for (_, relocs) in self.relocations.values_mut() {
+            for reloc in relocs.iter_mut() {
+                reloc.r_sym = remap[reloc.r_sym];
+            }
+        }
+        let mut keep = keep.into_iter();
+        self.special_symbols.retain(|_| keep.next().expect("symbol is kept or not"));
+        self.section_symbols.retain(|_, _| keep.next().expect("symbol is kept or not"));
+        self.symbols.retain(|_, _| keep.next().expect("symbol is kept or not"));
+        self.nlocals = self.symbols.values().filter(|symbol| symbol.st_bind() == sym::STB_LOCAL).count();
+    }
+    /// Flag the custom `section` `SHF_LINK_ORDER` and link it to the section of `definition`, whose order the linker
+    /// places it in; every definition must have its section
+    pub fn link_order(&mut self, section: &str, definition: &str) {
+        let (shndx, _) = self.placement(definition);
+        let idx = self.strings.get_or_intern(section);
+        let section = self.sections.get_mut(&idx).expect("custom section is defined");
+        section.sh_flags |= u64::from(SHF_LINK_ORDER);
+        section.sh_link = shndx as u32;
+    }
+    /// Add an undefined reference to the GOT base, as assemblers do for code which is relative to it,
+    /// unless the object already refers to it
+    pub fn add_got_base(&mut self) {
+        if self.strings.get(GOT_BASE).is_some_and(|idx| self.symbols.contains_key(&idx)) {
+            return;
+        }
+        self.import(GOT_BASE.to_string(), &ImportKind::Data, true, false);
+    }
+    /// The index in the symbol table of the symbol that relocations against `name`, declared as `decl`, refer to
+    fn symbol_index(&self, name: StringIndex, decl: &Decl) -> usize {
+        match *decl {
+            // another definition may take the place of a weak one when linking, including a partial link,
+            // so it must be relocated against its own symbol, which the linker rebinds
+            Decl::Function(f) if f.is_weak() => self.symbol_table_index(name),
+            Decl::Data(d) if d.is_weak() => self.symbol_table_index(name),
+            // the linker only calls the resolver of an indirect function relocated against its own symbol
+            Decl::Function(f) if f.is_ifunc() => self.symbol_table_index(name),
+            // the section of a COMDAT group member may be discarded for another object's copy, which only its own
+            // symbol is rebound to
+            _ if self.comdats.contains_key(&self.aliases.get(&name).cloned().unwrap_or(name)) => self.symbol_table_index(name),
+            // the linker only switches between ARM and Thumb code when calling a function's own symbol, whose low
+            // bit is set for Thumb code
+            Decl::Function(_) if self.machine() == Some(header::EM_ARM) => self.symbol_table_index(name),
+            // other defined symbols are relocated against their section symbol
+            Decl::Function(_) | Decl::Data(_) | Decl::CString(_) | Decl::DebugSection(_) | Decl::Section(_) => {
+                // an alias shares the section of its definition
+                let name = self.aliases.get(&name).cloned().unwrap_or(name);
+                match self.section_symbols.get_full(&name) {
+                    // +2 for NOTYPE and FILE symbols
+                    Some((section_idx, _, _)) => section_idx + 2,
+                    // zero-initialized data shares .bss, and functions may share .text, so they are relocated
+                    // against their own symbol
+                    None => self.symbol_table_index(name),
+                }
+            },
+            Decl::FunctionImport | Decl::DataImport | Decl::Absolute(_) => self.symbol_table_index(name),
+        }
+    }
+    /// The index in the symbol table of the symbol `name` itself
+    fn symbol_table_index(&self, name: StringIndex) -> usize {
+        let (idx, _, _) = self.symbols.get_full(&name).expect("to_idx present in symbols");
+        // + special_symbols.len() + section_symbols.len() because this is where the regular symbols begin
+        idx + self.special_symbols.len() + self.section_symbols.len()
     }
-    fn add_reloc(&mut self, relocee: &str, reloc: Relocation, idx: usize) {
-        debug!("add reloc for symbol {} - reloc: {:?}", idx, &reloc);
+    fn add_reloc(&mut self, relocee: &str, reloc: Relocation, shndx: usize) {
+        debug!("add reloc for section {} - reloc: {:?}", shndx, &reloc);
         let reloc_size = Relocation::size(reloc.r_addend.is_some(), self.ctx) as u64;
-        if self.relocations.contains_key(&idx) {
+        if self.relocations.contains_key(&shndx) {
             debug!("{} has relocs", relocee);
-            let &mut (ref mut section, ref mut relocs) = self.relocations.get_mut(&idx).unwrap();
+            let &mut (ref mut section, ref mut relocs) = self.relocations.get_mut(&shndx).unwrap();
             // its size is currently how many relocations there are
             section.sh_size += section.sh_entsize;
             relocs.push(reloc);
         } else {
             debug!("{} does NOT have relocs", relocee);
             // now create the relocation section
-            let (_reloc_idx, reloc_section_offset) = self.new_string(format!(".reloc.{}", relocee));
+            let (_reloc_idx, reloc_section_offset) = self.new_string(format!(".reloc.{}", self.symbol_name(relocee)));
             let mut reloc_section = SectionBuilder::new(reloc_size).name_offset(reloc_section_offset).section_type(SectionType::Relocation).create(&self.ctx);
+            if !self.rela {
+                reloc_section.sh_type = SHT_REL;
+                reloc_section.sh_entsize = reloc_size;
+            }
             // its sh_link always points to the symtable
             reloc_section.sh_link = SYMTAB_LINK as u32;
-            // info tells us which relocation this is relative to
-            reloc_section.sh_info = (idx + 3) as u32;
-            self.relocations.insert(idx, (reloc_section, vec![reloc]));
+            // info tells us which section this relocation applies to
+            reloc_section.sh_info = shndx as u32;
+            self.relocations.insert(shndx, (reloc_section, vec![reloc]));
             self.nsections += 1;
         }
     }
+    /// Lay out the string table so that a name which is a suffix of another name shares its bytes,
+    /// e.g., `foo` is stored in the tail of `.text.foo`, and update every name offset accordingly.
+    /// Returns the strings which actually need to be written, in order.
+    fn merge_strtab(&mut self) -> (Vec<StringIndex>, HashMap<Offset, Offset>) {
+        let strings = &self.strings;
+        let mut names = self.offsets.keys().cloned().collect::<Vec<_>>();
+        // sorting by the reversed names, longest first, places every suffix right after a name containing it
+        names.sort_by(|&a, &b| {
+            let a = strings.resolve(a).expect("strtab name").bytes().rev();
+            let b = strings.resolve(b).expect("strtab name").bytes().rev();
+            b.cmp(a)
+        });
+        let mut layout = Vec::new();
+        let mut remap = HashMap::new();
+        let mut previous: Option<(&str, Offset)> = None;
+        // for the null value in the strtab
+        self.sizeof_strtab = 1;
+        for idx in names {
+            let name = strings.resolve(idx).expect("strtab name");
+            let offset = match previous {
+                Some((containing, containing_offset)) if containing.ends_with(name) => {
+                    containing_offset + containing.len() - name.len()
+                },
+                _ => {
+                    let offset = self.sizeof_strtab;
+                    self.sizeof_strtab += name.len() + 1;
+                    layout.push(idx);
+                    previous = Some((name, offset));
+                    offset
+                }
+            };
+            let old_offset = self.offsets.insert(idx, offset).expect("strtab name has an offset");
+            remap.insert(old_offset, offset);
+        }
+        let remap_name = |offset: &mut usize| if let Some(new) = remap.get(offset) { *offset = *new };
+        for symbol in self.special_symbols.iter_mut()
+            .chain(self.section_symbols.values_mut())
+            .chain(self.symbols.values_mut()) {
+            remap_name(&mut symbol.st_name);
+        }
+        for section in self.sections.values_mut()
+            .chain(self.relocations.values_mut().map(|&mut (ref mut section, _)| section))
+            .chain(self.blobs.iter_mut().map(|&mut (ref mut section, _)| section)) {
+            remap_name(&mut section.sh_name);
+        }
+        (layout, remap)
+    }
     pub fn write<T: Write + Seek>(mut self, file: T) -> goblin::error::Result<()> {
         let mut file = BufWriter::new(file);
         /////////////////////////////////////
@@ -572,8 +1516,10 @@ impl<'a> Elf<'a> {
         let sizeof_symtab = (self.symbols.len() +
                              self.special_symbols.len() +
                              self.section_symbols.len()) * Symbol::size(self.ctx.container);
-        let sizeof_relocs = self.relocations.iter().fold(0, |acc, (_, &(ref _shdr, ref rels))| rels.len() + acc) * Relocation::size(true, self.ctx);
+        let sizeof_relocs = self.relocations.iter().fold(0, |acc, (_, (_shdr, rels))| rels.len() + acc) * Relocation::size(self.rela, self.ctx);
         let nonexec_stack_note_name_offset = self.new_string(".note.GNU-stack".into()).1;
+        let (strtab_layout, remap) = self.merge_strtab();
+        let nonexec_stack_note_name_offset = remap[&nonexec_stack_note_name_offset];
         let strtab_offset = self.sizeof_bits as u64;
         let symtab_offset = strtab_offset + self.sizeof_strtab as u64;
         let reloc_offset = symtab_offset + sizeof_symtab as u64;
@@ -585,15 +1531,19 @@ impl<'a> Elf<'a> {
         // Header
         /////////////////////////////////////
         let mut header = Header::new(self.ctx);
-        let machine: MachineTag = self.architecture.into();
-        header.e_machine = machine.0;
+        header.e_machine = self.machine().expect("the machine is checked when the object is built");
+        header.e_flags = self.encoder.flags();
         header.e_type = header::ET_REL;
         header.e_shoff = sh_offset;
-        header.e_shnum = self.nsections;
+        header.e_shnum = self.nsections as u16;
         header.e_shstrndx = STRTAB_LINK;
+        // the GNU linker only honours SHF_GNU_RETAIN in an object of the GNU ABI, which gas marks it as too
+        if self.sections.values().any(|section| section.sh_flags & SHF_GNU_RETAIN != 0) {
+            header.e_ident[EI_OSABI] = ELFOSABI_GNU;
+        }
         
         file.iowrite_with(header, self.ctx)?;
-        let after_header = file.seek(Current(0))?;
+        let after_header = file.stream_position()?;
         debug!("after_header {:#x}", after_header);
         assert_eq!(after_header, Header::size(&self.ctx) as u64);
 
@@ -601,10 +1551,35 @@ impl<'a> Elf<'a> {
         // Code
         /////////////////////////////////////
 
-        for (_idx, bytes) in self.code.drain(..) {
-            file.write_all(bytes)?;
+        for (idx, data) in self.code.drain(..) {
+            let (section_idx, _, _) = self.sections.get_full(&idx).expect("code has a section");
+            match self.implicit_addends.get(&(section_idx + 3)) {
+                Some(addends) => {
+                    let mut bytes = Vec::with_capacity(data.len() as usize);
+                    data.write_to(&mut bytes)?;
+                    write_implicit_addends(&mut bytes, addends, self.ctx)?;
+                    file.write_all(&bytes)?;
+                },
+                None => data.write_to(&mut file)?,
+            }
+        }
+        let signatures = self.groups.iter()
+            .map(|&(leader, blob)| (blob, self.symbol_table_index(leader) as u32))
+            .collect::<HashMap<_, _>>();
+        let mut blob_sections = Vec::with_capacity(self.blobs.len());
+        for (i, (mut section, mut bytes)) in self.blobs.drain(..).enumerate() {
+            if let Some(&signature) = signatures.get(&i) {
+                section.sh_info = signature;
+            }
+            if let Some(addends) = self.implicit_addends.get(&(3 + self.sections.len() + i)) {
+                write_implicit_addends(&mut bytes, addends, self.ctx)?;
+            }
+            let position = file.stream_position()?;
+            for _ in position..section.sh_offset { file.iowrite(0u8)?; }
+            file.write_all(&bytes)?;
+            blob_sections.push(section);
         }
-        let after_code = file.seek(Current(0))?;
+        let after_code = file.stream_position()?;
         debug!("after_code {:#x}", after_code);
         assert_eq!(after_code, strtab_offset);
 
@@ -637,12 +1612,13 @@ impl<'a> Elf<'a> {
         /////////////////////////////////////
         file.seek(Start(strtab_offset))?;
         file.iowrite(0u8)?; // for the null value in the strtab;
-        for (_id, string) in self.strings.iter() {
+        for idx in strtab_layout {
+            let string = self.strings.resolve(idx).expect("strtab name");
             debug!("String: {:?}", string);
             file.write_all(string.as_bytes())?;
             file.iowrite(0u8)?;
         }
-        let after_strtab = file.seek(Current(0))?;
+        let after_strtab = file.stream_position()?;
         debug!("after_strtab {:#x}", after_strtab);
         assert_eq!(after_strtab, symtab_offset);
 
@@ -657,17 +1633,19 @@ impl<'a> Elf<'a> {
             debug!("Section Symbol: {:?}", symbol);
             file.iowrite_with(symbol, self.ctx)?;
         }
-        for (id, symbol) in self.symbols.into_iter() {
+        for (id, mut symbol) in self.symbols.into_iter() {
             debug!("Symbol: {:?}", symbol);
-            file.iowrite_with(symbol, self.ctx)?;
-            match self.sections.get(&id) {
-                Some(section) => {
-                    section_headers.push(section.clone());
-                },
-                None => () // FIXME: warn
+            // the low bit of a Thumb function's address marks it as Thumb code
+            if self.thumb.contains(&id) {
+                symbol.st_value |= 1;
             }
+            file.iowrite_with(symbol, self.ctx)?;
         }
-        let after_symtab = file.seek(Current(0))?;
+        // the sections were numbered in the order they were added
+        section_headers.extend(self.sections.into_iter().map(|(_id, section)| section));
+        // the blob sections are numbered after the definitions, before any relocation section refers to them
+        section_headers.extend(blob_sections);
+        let after_symtab = file.stream_position()?;
         debug!("after_symtab {:#x} - shdr_size {}", after_symtab, Section::size(&self.ctx));
         assert_eq!(after_symtab, reloc_offset);
 
@@ -679,22 +1657,30 @@ impl<'a> Elf<'a> {
             section.sh_offset = roffset;
             roffset += section.sh_size;
             section_headers.push(section);
+            // a relocation which applies to the same field as another stays after it, as e.g. a pair must
+            if self.deterministic {
+                relocations.sort_by_key(|relocation| relocation.r_offset);
+            }
             for relocation in relocations.drain(..) {
                 debug!("Relocation: {:?}", relocation);
                 file.iowrite_with(relocation, (relocation.r_addend.is_some(), self.ctx))?;
             }
         }
-        let after_relocs = file.seek(Current(0))?;
+        let after_relocs = file.stream_position()?;
         debug!("after_relocs {:#x}", after_relocs);
         assert_eq!(after_relocs, sh_offset);
 
         /////////////////////////////////////
         // Non-executable stack note.
         /////////////////////////////////////
-        let nonexec_stack = SectionBuilder::new(0)
+        let mut nonexec_stack = SectionBuilder::new(0)
             .name_offset(nonexec_stack_note_name_offset)
-            .section_type(SectionType::Bits)
-            .create(&self.ctx);
+            .section_type(SectionType::Bits);
+        if self.executable_stack {
+            nonexec_stack = nonexec_stack.exec();
+        }
+        let mut nonexec_stack = nonexec_stack.create(&self.ctx);
+        nonexec_stack.sh_addralign = 1;
         section_headers.push(nonexec_stack);
 
         /////////////////////////////////////
@@ -706,7 +1692,7 @@ impl<'a> Elf<'a> {
             file.iowrite_with(shdr, self.ctx)?;
         }
 
-        let after_shdrs = file.seek(Current(0))?;
+        let after_shdrs = file.stream_position()?;
         let expected = sh_offset + shdr_size;
         debug!("after_shdrs {:#x}", after_shdrs);
         assert_eq!(after_shdrs, expected);
@@ -715,22 +1701,370 @@ impl<'a> Elf<'a> {
     }
 }
 
-pub fn to_bytes(artifact: &Artifact) -> Result<Vec<u8>, Error> {
+/// Write each of the `addends` into its field in `bytes`, replacing whatever was there, as RELA would
+fn write_implicit_addends(bytes: &mut [u8], addends: &[(u64, usize, i64)], ctx: Ctx) -> goblin::error::Result<()> {
+    for &(offset, size, addend) in addends {
+        let offset = offset as usize;
+        match size {
+            1 => { bytes.pwrite_with(addend as u8, offset, ctx.le)?; },
+            2 => { bytes.pwrite_with(addend as u16, offset, ctx.le)?; },
+            8 => { bytes.pwrite_with(addend as u64, offset, ctx.le)?; },
+            _ => { bytes.pwrite_with(addend as u32, offset, ctx.le)?; },
+        }
+    }
+    Ok(())
+}
+
+fn absolute_decl(decl: &Decl) -> AbsoluteDecl {
+    match *decl {
+        Decl::Absolute(abs) => abs,
+        _ => unreachable!("absolutes are declared absolute"),
+    }
+}
+
+/// The CRC-32, as in zlib, which `.gnu_debuglink` uses to check a debug file matches its stripped object
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+pub fn write<T: Write + Seek>(artifact: &Artifact, sink: T) -> Result<(), Error> {
+    let elf = build(artifact, Part::Whole)?;
+    artifact.check_section_count(elf.nsections, SHN_LORESERVE as usize)?;
+    elf.write(sink)?;
+    Ok(())
+}
+
+/// Write the stripped object, which links to the debug object by the file name `debuglink`, and the debug object
+pub fn split_debug(artifact: &Artifact, debuglink: &str) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    let mut debug = Cursor::new(Vec::new());
+    let elf = build(artifact, Part::Debug)?;
+    artifact.check_section_count(elf.nsections, SHN_LORESERVE as usize)?;
+    elf.write(&mut debug)?;
+    let debug = debug.into_inner();
+    let mut stripped = Cursor::new(Vec::new());
+    let mut elf = build(artifact, Part::Stripped)?;
+    elf.add_debuglink(debuglink, crc32(&debug))?;
+    artifact.check_section_count(elf.nsections, SHN_LORESERVE as usize)?;
+    elf.write(&mut stripped)?;
+    Ok((stripped.into_inner(), debug))
+}
+
+/// Write the object without what `strip` removes
+pub fn strip(artifact: &Artifact, strip: Strip) -> Result<Vec<u8>, Error> {
+    let part = match strip {
+        Strip::Debug | Strip::All => Part::Stripped,
+        Strip::None | Strip::DiscardLocals => Part::Whole,
+    };
+    let mut elf = build(artifact, part)?;
+    if strip == Strip::DiscardLocals || strip == Strip::All {
+        elf.discard_locals();
+    }
+    artifact.check_section_count(elf.nsections, SHN_LORESERVE as usize)?;
+    let mut bytes = Cursor::new(Vec::new());
+    elf.write(&mut bytes)?;
+    Ok(bytes.into_inner())
+}
+
+/// The relocation type and addend of `link`, unless it is raw, or written in place
+fn encode_link(encoder: &dyn RelocEncoder, artifact: &Artifact, link: &LinkAndDecl) -> Option<Result<(u32, i64), Error>> {
+    match link.reloc {
+        Reloc::Raw { .. } | Reloc::SectionOffset { .. } => None,
+        _ => Some(encoder.encode(artifact, link)),
+    }
+}
+
+/// The fewest links worth encoding on a thread of their own
+const PARALLEL_LINKS: usize = 4096;
+
+/// How many threads encode the relocations of an artifact: as many as there are CPUs with the `parallel` feature,
+/// otherwise only the one emitting it
+fn encoder_threads() -> usize {
+    if cfg!(feature = "parallel") {
+        ::std::thread::available_parallelism().map_or(1, |threads| threads.get())
+    } else {
+        1
+    }
+}
+
+/// Encode the relocations of `links`, in order, on up to `threads` threads, if there are enough links to make up
+/// for starting them
+fn encode_links(encoder: &dyn RelocEncoder, artifact: &Artifact, links: &[LinkAndDecl], threads: usize) -> Vec<Option<Result<(u32, i64), Error>>> {
+    use std::thread;
+    if threads <= 1 || links.len() < 2 * PARALLEL_LINKS {
+        return links.iter().map(|link| encode_link(encoder, artifact, link)).collect();
+    }
+    let chunk = links.len().div_ceil(threads).max(PARALLEL_LINKS);
+    thread::scope(|scope| {
+        let encoders = links.chunks(chunk).map(|links| {
+            scope.spawn(move || links.iter().map(|link| encode_link(encoder, artifact, link)).collect::<Vec<_>>())
+        }).collect::<Vec<_>>();
+        encoders.into_iter().flat_map(|encoder| encoder.join().expect("encoder thread panicked")).collect()
+    })
+}
+
+fn build<'a>(artifact: &'a Artifact, part: Part) -> Result<Elf<'a>, Error> {
+    build_on(artifact, part, encoder_threads())
+}
+
+/// Build the object of `artifact`, encoding its relocations on up to `threads` threads
+fn build_on<'a>(artifact: &'a Artifact, part: Part, threads: usize) -> Result<Elf<'a>, Error> {
     // TODO: make new fully construct the elf object, e.g., the definitions, imports, and links don't take self
     // this means that a call to new has a fully constructed object ready to marshal into bytes, similar to the mach backend
-    let mut elf = Elf::new(&artifact);
-    for def in artifact.definitions() {
+    let architecture = artifact.target.architecture;
+    let encoder = match artifact.reloc_encoder {
+        Some(ref encoder) => Some(&**encoder),
+        None => encoder::builtin(architecture),
+    };
+    if encoder.and_then(|encoder| encoder.machine()).or_else(|| MachineTag::new(architecture).map(|machine| machine.0)).is_none() {
+        return Err(ArtifactError::UnsupportedArchitecture(architecture.to_string(), "ELF".to_string()).into());
+    }
+    // ELF has no 16-bit class
+    if encoder.and_then(|encoder| encoder.ctx()).is_none() && artifact.target.pointer_width() == Ok(PointerWidth::U16) {
+        return Err(ArtifactError::UnsupportedPointerWidth(architecture.to_string(), 16, "ELF".to_string()).into());
+    }
+    // rather than guessing the relocations of an architecture faerie doesn't know
+    let encoder = encoder.ok_or_else(|| ArtifactError::UnsupportedArchitecture(architecture.to_string(), "ELF".to_string()))?;
+    let mut elf = Elf::new(artifact, part, encoder);
+    artifact.check_alignments()?;
+    // a group section is only flagged GRP_COMDAT, and the linker keeps any copy
+    if let Some((_, leader, selection)) = artifact.comdats().find(|&(_, _, selection)| selection != ComdatSelection::Any) {
+        return Err(format_err!("ELF does not support the COMDAT selection {:?} of the group {}", selection, leader));
+    }
+    // only a PE/COFF loader fills in an import address table, or reads an export table
+    if let Some((name, _)) = artifact.dll_imports().next() {
+        return Err(format_err!("ELF does not support importing {} from a DLL", name));
+    }
+    if let Some(name) = artifact.dll_exports().next() {
+        return Err(format_err!("ELF does not support exporting {} from a DLL", name));
+    }
+    // local absolute symbols must precede any global symbol, and global ones follow the definitions
+    for abs in artifact.absolutes().filter(|abs| absolute_decl(abs.decl).scope() == Scope::Local) {
+        debug!("Absolute: {:?}", abs);
+        elf.absolute(abs.name.to_string(), absolute_decl(abs.decl));
+    }
+    let relocated = if artifact.relro {
+        artifact.links().filter(|link| !link.reloc.is_section_offset()).map(|link| link.from.name).collect::<HashSet<_>>()
+    } else {
+        HashSet::new()
+    };
+    // a stripped object has no trace of the debug sections
+    for def in artifact.definitions().filter(|def| part != Part::Stripped || !def.prop.debug) {
         debug!("Def: {:?}", def);
-        elf.add_definition(def.name, def.data, def.prop);
+        let prop = def.prop;
+        let relro = !prop.function && !prop.writable && !prop.cstring && !prop.debug && !prop.section && relocated.contains(def.name);
+        match prop.zero_init {
+            Some(align) => elf.add_zeroed(def.name, def.data.len(), align, prop),
+            // a COMDAT group member must be in a section of its own, which the linker may discard, as must a function
+            // it keeps, and one placed in a section of another name
+            None if prop.function && !artifact.function_sections && !elf.comdats.contains_key(&elf.strings.get_or_intern(def.name))
+                && !artifact.is_no_dead_strip(def.name) && !elf.placements.contains_key(def.name) => {
+                elf.add_to_text(def.name, def.data, prop)?
+            },
+            None => elf.add_definition(def.name, def.data, prop, relro),
+        }
+    }
+    elf.add_text_sections();
+    for (section, definition) in artifact.link_orders() {
+        debug!("Link order: {} -> {}", section, definition);
+        elf.link_order(section, definition);
+    }
+    for abs in artifact.absolutes().filter(|abs| absolute_decl(abs.decl).scope() != Scope::Local) {
+        debug!("Absolute: {:?}", abs);
+        elf.absolute(abs.name.to_string(), absolute_decl(abs.decl));
+    }
+    for (alias, target) in artifact.aliases() {
+        debug!("Alias: {} -> {}", alias.name, target.name);
+        elf.alias(alias.name, target.name, alias.decl, artifact.alias_visibility(alias.name));
+    }
+    for (name, attributes) in artifact.symbol_attributes() {
+        debug!("Symbol attributes: {} -> {:?}", name, attributes);
+        elf.symbol_attributes(name, attributes);
+    }
+    for name in artifact.no_dead_strip() {
+        debug!("No dead strip: {}", name);
+        elf.retain(name);
     }
     for (ref import, ref kind) in artifact.imports() {
         debug!("Import: {:?} -> {:?}", import, kind);
-        elf.import(import.to_string(), kind);
+        elf.import(import.to_string(), kind, !artifact.is_static_import(import), artifact.is_weak_import(import));
+    }
+    // the table's delimiters are local symbols, which are added before the relocations that refer to symbols by index
+    let tm_clones = artifact.tm_clones().collect::<Vec<_>>();
+    if part != Part::Debug && !tm_clones.is_empty() {
+        elf.add_tm_clone_table(tm_clones);
+    }
+    // relocations are kept with the contents they apply to, and are dropped with any debug section they refer to
+    let links = artifact.links().filter(|link| {
+        part.has_contents(link.from.decl) && (part != Part::Stripped || !link.to.decl.is_debug_section())
+    }).map(|link| match link.reloc {
+        // a debug section is not loaded, so its address in the linked image is its offset
+        Reloc::DebugSecRel { size, addend } => LinkAndDecl { reloc: Reloc::Absolute { size, addend }, ..link },
+        _ => link,
+    }).collect::<Vec<_>>();
+    let encodings = encode_links(elf.encoder, artifact, &links, threads);
+    // the labels are local symbols, which are added before the relocations that refer to symbols by index
+    let fields = links.iter().zip(&encodings).filter_map(|(link, encoding)| match *encoding {
+        Some(Ok((reloc, _))) if elf.encoder.label_pair(reloc).is_some() => Some((link.from.name, link.at)),
+        _ => None,
+    }).collect::<Vec<_>>();
+    let mut labels = elf.add_labels(&fields).into_iter();
+    for (link, encoding) in links.into_iter().zip(encodings) {
+        if let Reloc::SectionOffset { size, addend } = link.reloc {
+            elf.section_offset(&link, size, addend);
+            continue;
+        }
+        elf.encoder.check(&link)?;
+        let (reloc, addend, pair, call) = match (link.reloc, encoding) {
+            (Reloc::Raw { reloc, addend, .. }, _) => {
+                elf.link(&link, reloc, i64::from(addend));
+                continue;
+            },
+            (_, encoding) => {
+                let (reloc, addend) = encoding.expect("the relocation of every other link is encoded")?;
+                (reloc, addend, elf.encoder.pair(reloc), elf.encoder.call(reloc))
+            },
+        };
+        let (from, at) = (link.from.name, link.at);
+        elf.link(&link, reloc, addend);
+        elf.relax(from, at, reloc);
+        if let Some((offset, function, reloc, addend)) = call {
+            elf.call(from, at + offset, function, reloc, addend);
+        }
+        if let Some((offset, reloc)) = pair {
+            elf.link(&LinkAndDecl { at: at + offset, ..link }, reloc, addend);
+            elf.relax(from, at + offset, reloc);
+        }
+        if let Some((offset, reloc)) = elf.encoder.label_pair(reloc) {
+            let label = labels.next().expect("a label was added for the field");
+            elf.link_label(from, at + offset, label, reloc);
+            elf.relax(from, at + offset, reloc);
+        }
+    }
+    // the field's own address is the only one a relocation can subtract, so it is relative to the start of its definition
+    for difference in artifact.differences().filter(|difference| part.has_contents(difference.from.decl)) {
+        if difference.minus.name != difference.from.name {
+            return Err(ArtifactError::InvalidDifference(difference.to.name.to_string(), difference.minus.name.to_string()).into());
+        }
+        let link = LinkAndDecl { from: difference.from, to: difference.to, at: difference.at, reloc: Reloc::Relative { size: 4, addend: difference.at as i32 } };
+        elf.encoder.check(&link)?;
+        let (reloc, addend) = elf.encoder.encode(artifact, &link)?;
+        elf.link(&link, reloc, addend);
     }
-    for link in artifact.links() {
-        elf.link(&link);
+    let buildid = artifact.content_buildid()?;
+    for (section, notes) in artifact.notes() {
+        debug!("Note section: {} -> {:?}", section, notes);
+        match buildid {
+            Some(ref note) if section == ".note.gnu.build-id" => elf.add_note_section(section, ::std::slice::from_ref(note))?,
+            _ => elf.add_note_section(section, notes)?,
+        }
+    }
+    for section in artifact.empty_sections() {
+        debug!("Empty section: {}", section);
+        elf.add_empty_section(section);
+    }
+    // the directives are for linking the code, which a debug object doesn't have
+    if part != Part::Debug {
+        elf.add_linker_directives(&artifact.directives().collect::<Vec<_>>());
+    }
+    // the pointers are relocations on allocated contents, which a debug object doesn't have
+    if part != Part::Debug {
+        add_function_arrays(&mut elf, artifact.constructors(), artifact.legacy_ctors, true);
+        add_function_arrays(&mut elf, artifact.destructors(), artifact.legacy_ctors, false);
+        let sframes = artifact.sframes().collect::<Vec<_>>();
+        if !sframes.is_empty() {
+            elf.add_sframe(&sframes)?;
+        }
+    }
+    if elf.uses_got {
+        elf.add_got_base();
+    }
+    elf.discard_section_symbols();
+    elf.add_groups()?;
+    Ok(elf)
+}
+
+/// Add the arrays of constructors, if `init`, or destructors, grouped into a section per priority.
+///
+/// `.init_array` is run in order and `.fini_array` in reverse, and the linker sorts their priority sections by
+/// increasing priority. The legacy `.ctors` is run in reverse and `.dtors` in order, and the linker sorts their
+/// priority sections by name, so these are emitted reversed and numbered `65535 - priority`, as GCC does.
+fn add_function_arrays<'a, I: Iterator<Item = (Binding<'a>, Option<u16>)>>(elf: &mut Elf<'a>, functions: I, legacy: bool, init: bool) {
+    let mut sections: IndexMap<String, Vec<Binding>> = IndexMap::new();
+    for (function, priority) in functions {
+        let name = match (legacy, init) {
+            (false, true) => ".init_array",
+            (false, false) => ".fini_array",
+            (true, true) => ".ctors",
+            (true, false) => ".dtors",
+        };
+        let name = match priority {
+            Some(priority) if legacy => format!("{}.{:05}", name, 65535 - priority),
+            Some(priority) => format!("{}.{:05}", name, priority),
+            None => name.to_string(),
+        };
+        sections.entry(name).or_default().push(function);
+    }
+    for (name, mut functions) in sections {
+        let typ = if legacy {
+            functions.reverse();
+            SectionType::Data
+        } else if init {
+            SectionType::InitArray
+        } else {
+            SectionType::FiniArray
+        };
+        debug!("Function array: {} -> {:?}", name, functions);
+        elf.add_function_array(&name, typ, &functions);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_on, Part, PARALLEL_LINKS};
+    use std::io::Cursor;
+    use std::str::FromStr;
+    use target_lexicon::Triple;
+    use {Artifact, Decl, ImportKind, Link};
+
+    /// The object of `artifact`, with its relocations encoded on `threads` threads
+    fn emit_on(artifact: &Artifact, threads: usize) -> Vec<u8> {
+        let elf = build_on(artifact, Part::Whole, threads).expect("can build the object");
+        let mut bytes = Cursor::new(Vec::new());
+        elf.write(&mut bytes).expect("can write the object");
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn relocations_encoded_in_parallel_are_the_same_bytes() {
+        let triple = Triple::from_str("x86_64-unknown-linux-gnu").expect("is a triple");
+        let mut obj = Artifact::new(triple, "t.o".into());
+        obj.import("puts", ImportKind::Function).expect("can import puts");
+        obj.import("environ", ImportKind::Data).expect("can import environ");
+        let count = 3 * PARALLEL_LINKS;
+        let names = (0..count).map(|i| format!("f{}", i)).collect::<Vec<_>>();
+        for name in &names {
+            // call the next function, call puts, and load environ through the GOT
+            let code = vec![0xe8, 0, 0, 0, 0, 0xe8, 0, 0, 0, 0, 0x48, 0x8b, 0x05, 0, 0, 0, 0, 0xc3];
+            obj.declare_with(name, Decl::function().global(), code).expect("can declare and define the function");
+        }
+        obj.declare_with("table", Decl::data(), vec![0; 8 * count]).expect("can declare and define table");
+        for (i, name) in names.iter().enumerate() {
+            obj.link(Link { from: name, to: &names[(i + 1) % count], at: 1 }).expect("can link to the next function");
+            obj.link(Link { from: name, to: "puts", at: 6 }).expect("can link to puts");
+            obj.link(Link { from: name, to: "environ", at: 13 }).expect("can link to environ");
+            obj.link(Link { from: "table", to: name, at: 8 * i as u64 }).expect("can link from table");
+        }
+
+        let serial = emit_on(&obj, 1);
+        for &threads in &[2, 3, 8] {
+            assert!(emit_on(&obj, threads) == serial, "the object encoded on {} threads differs", threads);
+        }
     }
-    let mut buffer = Cursor::new(Vec::new());
-    elf.write(&mut buffer)?;
-    Ok(buffer.into_inner())
 }
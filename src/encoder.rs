@@ -0,0 +1,760 @@
+//! Selecting the ELF relocations an artifact's links are emitted as, for each architecture
+
+use failure::Error;
+use goblin::container::{Container, Endian};
+use goblin::elf::reloc;
+use target_lexicon::Architecture;
+
+use std::fmt;
+
+use {Artifact, Decl, Reloc, RelocModel, Scope, TlsModel};
+use artifact::{ArtifactError, LinkAndDecl};
+use target::make_ctx;
+use Ctx;
+
+/// Selects the ELF relocations for the links of an artifact, for one architecture.
+///
+/// The built-in encoders are chosen by the architecture of the artifact's target; an artifact can be given its own
+/// with [reloc_encoder](../struct.ArtifactBuilder.html#method.reloc_encoder), e.g. to support an architecture
+/// faerie doesn't, without changing faerie.
+pub trait RelocEncoder: fmt::Debug + Send + Sync {
+    /// The `e_machine` of the object file, if it is not the one of the target's architecture
+    fn machine(&self) -> Option<u16> {
+        None
+    }
+    /// The class and byte order of the object file, if they are not the ones of the target's pointer width and
+    /// endianness, e.g. for an architecture target-lexicon doesn't know
+    fn ctx(&self) -> Option<Ctx> {
+        None
+    }
+    /// The `e_flags` of the object file, e.g. the processor family on AVR
+    fn flags(&self) -> u32 {
+        0
+    }
+    /// Whether relocations carry their addend, or it is written in place of the field they apply to
+    fn rela(&self) -> bool {
+        true
+    }
+    /// Check that `link` can be represented, including when its relocation is raw
+    fn check(&self, _link: &LinkAndDecl) -> Result<(), Error> {
+        Ok(())
+    }
+    /// The relocation, and its addend, for `link` in `artifact`, when its relocation is not raw
+    fn encode(&self, artifact: &Artifact, link: &LinkAndDecl) -> Result<(u32, i64), Error>;
+    /// The relocation for a pointer to a symbol, e.g. an entry of `.init_array`
+    fn pointer(&self, artifact: &Artifact) -> u32;
+    /// Whether the relocation `reloc` refers to its target's own symbol rather than its section symbol,
+    /// e.g. because it is resolved to the symbol's size
+    fn refers_to_symbol(&self, _reloc: u32) -> bool {
+        false
+    }
+    /// Whether the relocation `reloc` is relative to the GOT, so the object refers to the GOT base
+    fn uses_got(&self, _reloc: u32) -> bool {
+        false
+    }
+    /// The size of the field the relocation `reloc` applies to, which holds its addend when relocations don't
+    fn field_size(&self, _reloc: u32) -> usize {
+        4
+    }
+    /// Whether the field the relocation `reloc` applies to holds nothing but its value, so its addend can be written
+    /// in place of it, rather than being bits of an instruction, e.g. the offset of an AArch64 `bl`
+    fn is_value_field(&self, _reloc: u32) -> bool {
+        true
+    }
+    /// The relocation of a later instruction which completes the address `reloc` begins, and its offset from the field,
+    /// e.g. the low 12 bits of the page an AArch64 `adrp` addresses. It has the same target and addend, and is added
+    /// for each link faerie encodes with `reloc`, but not for a raw relocation.
+    fn pair(&self, _reloc: u32) -> Option<(u64, u32)> {
+        None
+    }
+    /// The call to a runtime function which completes the access `reloc` begins: its offset from the field, the
+    /// function, which the object imports, and the relocation and addend of the call, e.g. `__tls_get_addr` for a
+    /// general or local dynamic TLS access. It is added for each link faerie encodes with `reloc`, but not for a raw relocation.
+    fn call(&self, _reloc: u32) -> Option<(u64, &'static str, u32, i64)> {
+        None
+    }
+    /// The relocation of a later instruction which completes the address `reloc` begins, and its offset from the field,
+    /// when it is relative to the field rather than to the target, e.g. the low 12 bits of the PC relative address a
+    /// RISC-V `auipc` begins. It refers to a local label faerie adds at the field, with no addend, and is added for
+    /// each link faerie encodes with `reloc`, but not for a raw relocation.
+    fn label_pair(&self, _reloc: u32) -> Option<(u64, u32)> {
+        None
+    }
+    /// The relocation which marks the instructions `reloc` applies to as ones the linker may relax, e.g. shorten into
+    /// a single RISC-V `jal` when the function it calls is near enough; it is added after `reloc`, at the same offset
+    /// and against no symbol, for each relocation faerie encodes, but not for a raw relocation.
+    fn relaxation(&self, _reloc: u32) -> Option<u32> {
+        None
+    }
+}
+
+/// The built-in encoder for `architecture`, if faerie knows its relocations
+pub(crate) fn builtin(architecture: Architecture) -> Option<&'static dyn RelocEncoder> {
+    match architecture {
+        Architecture::X86_64 => Some(&X86_64),
+        Architecture::I386 | Architecture::I586 | Architecture::I686 => Some(&I386),
+        Architecture::Aarch64 => Some(&Aarch64),
+        Architecture::Arm | Architecture::Armv4t | Architecture::Armv5te | Architecture::Armv7 | Architecture::Armv7s |
+        Architecture::Thumbv6m | Architecture::Thumbv7em | Architecture::Thumbv7m => Some(&Arm),
+        Architecture::Riscv32 | Architecture::Riscv64 => Some(&Riscv),
+        // faerie doesn't know the relocations of the others yet
+        _ => None,
+    }
+}
+
+fn is_thread_local(decl: &Decl) -> bool {
+    match *decl {
+        Decl::Data(d) => d.is_thread_local(),
+        _ => false,
+    }
+}
+
+fn is_unwind(decl: &Decl) -> bool {
+    match *decl {
+        Decl::Section(d) => d.is_unwind(),
+        _ => false,
+    }
+}
+
+fn unsupported(artifact: &Artifact, link: &LinkAndDecl) -> Error {
+    ArtifactError::unsupported_relocation(link, format!("{} ELF", artifact.target.architecture)).into()
+}
+
+/// The relocations of x86-64
+#[derive(Debug, Clone, Copy)]
+pub struct X86_64;
+
+impl RelocEncoder for X86_64 {
+    fn check(&self, l: &LinkAndDecl) -> Result<(), Error> {
+        let (from, to) = (l.from.name, l.to.name);
+        let reloc = match l.reloc {
+            Reloc::Raw { reloc, .. } => reloc,
+            Reloc::DataRelative { size: 4, .. } => {
+                return Err(format_err!("x86-64 has no 4 byte relocation from {} to {} relative to the GOT base", from, to))
+            },
+            _ => return Ok(()),
+        };
+        match reloc {
+            // the dynamic linker applies these to a linked image, they never appear in an object file
+            reloc::R_X86_64_COPY | reloc::R_X86_64_GLOB_DAT | reloc::R_X86_64_JUMP_SLOT |
+            reloc::R_X86_64_RELATIVE | reloc::R_X86_64_RELATIVE64 | reloc::R_X86_64_IRELATIVE => {
+                Err(format_err!("Dynamic relocation {} from {} against {} in a relocatable object", reloc, from, to))
+            },
+            // an import may be thread-local in the library which defines it
+            reloc::R_X86_64_DTPMOD64 | reloc::R_X86_64_DTPOFF64 | reloc::R_X86_64_TPOFF64 | reloc::R_X86_64_DTPOFF32 |
+            reloc::R_X86_64_TPOFF32 | reloc::R_X86_64_TLSGD | reloc::R_X86_64_TLSLD | reloc::R_X86_64_GOTTPOFF |
+            reloc::R_X86_64_GOTPC32_TLSDESC | reloc::R_X86_64_TLSDESC_CALL | reloc::R_X86_64_TLSDESC => match *l.to.decl {
+                Decl::Data(d) if d.is_thread_local() => Ok(()),
+                Decl::DataImport => Ok(()),
+                _ => Err(format_err!("TLS relocation {} from {} against non-TLS symbol {}", reloc, from, to)),
+            },
+            _ if is_thread_local(l.to.decl) => {
+                Err(format_err!("Relocation {} from {} against TLS symbol {}, which is not a TLS relocation", reloc, from, to))
+            },
+            // debug sections are not loaded, so they can only be referred to by offset
+            _ if l.to.decl.is_debug_section() && reloc != reloc::R_X86_64_32 && reloc != reloc::R_X86_64_64 => {
+                Err(format_err!("Relocation {} from {} against debug section {}, which is not absolute", reloc, from, to))
+            },
+            reloc::R_X86_64_PLT32 | reloc::R_X86_64_PLTOFF64 => match *l.to.decl {
+                Decl::Function(_) | Decl::FunctionImport => Ok(()),
+                _ => Err(format_err!("PLT relocation {} from {} against {}, which is not a function", reloc, from, to)),
+            },
+            reloc::R_X86_64_SIZE32 | reloc::R_X86_64_SIZE64 => match *l.to.decl {
+                Decl::Function(_) | Decl::Data(_) | Decl::CString(_) => Ok(()),
+                _ => Err(format_err!("Size relocation {} from {} against {}, which has no size", reloc, from, to)),
+            },
+            _ => Ok(()),
+        }
+    }
+    fn encode(&self, artifact: &Artifact, l: &LinkAndDecl) -> Result<(u32, i64), Error> {
+        let model = artifact.reloc_model;
+        Ok(match l.reloc {
+            Reloc::Raw { reloc, addend, .. } => (reloc, i64::from(addend)),
+            Reloc::Size { size: 8, addend } => (reloc::R_X86_64_SIZE64, i64::from(addend)),
+            Reloc::Size { addend, .. } => (reloc::R_X86_64_SIZE32, i64::from(addend)),
+            Reloc::Absolute { size: 8, addend } => (reloc::R_X86_64_64, i64::from(addend)),
+            Reloc::Absolute { size: 2, addend } => (reloc::R_X86_64_16, i64::from(addend)),
+            Reloc::Absolute { size: 1, addend } => (reloc::R_X86_64_8, i64::from(addend)),
+            Reloc::Absolute { addend, .. } => (reloc::R_X86_64_32, i64::from(addend)),
+            Reloc::SignedAbsolute { addend } => (reloc::R_X86_64_32S, i64::from(addend)),
+            Reloc::GotOffset { size: 8, addend } => (reloc::R_X86_64_GOT64, i64::from(addend)),
+            Reloc::GotOffset { addend, .. } => (reloc::R_X86_64_GOT32, i64::from(addend)),
+            Reloc::Relative { size: 8, addend } => (reloc::R_X86_64_PC64, i64::from(addend)),
+            Reloc::Relative { size: 2, addend } => (reloc::R_X86_64_PC16, i64::from(addend)),
+            Reloc::Relative { size: 1, addend } => (reloc::R_X86_64_PC8, i64::from(addend)),
+            Reloc::DataRelative { addend, .. } => (reloc::R_X86_64_GOTOFF64, i64::from(addend)),
+            Reloc::Got { addend } => (reloc::R_X86_64_GOTPCREL, i64::from(addend)),
+            Reloc::Plt { addend } => (reloc::R_X86_64_PLT32, i64::from(addend)),
+            Reloc::GotBase { addend } => (reloc::R_X86_64_GOTPC32, i64::from(addend)),
+            // the fields are PC relative displacements, except the offsets from the thread pointer and the TLS block
+            Reloc::Tls { model: TlsModel::GeneralDynamic, .. } => (reloc::R_X86_64_TLSGD, -4),
+            Reloc::Tls { model: TlsModel::LocalDynamic, .. } => (reloc::R_X86_64_TLSLD, -4),
+            Reloc::Tls { model: TlsModel::LocalDynamicOffset, addend } => (reloc::R_X86_64_DTPOFF32, i64::from(addend)),
+            Reloc::Tls { model: TlsModel::InitialExec, .. } => (reloc::R_X86_64_GOTTPOFF, -4),
+            Reloc::Tls { model: TlsModel::LocalExec, addend } => (reloc::R_X86_64_TPOFF32, i64::from(addend)),
+            // the backend writes the offset in place, there is no relocation
+            Reloc::SectionOffset { .. } | Reloc::DebugSecRel { .. } => return Err(unsupported(artifact, l)),
+            Reloc::Relative { addend, .. } => match *l.to.decl {
+                // call frame information locates the function itself
+                _ if is_unwind(l.from.decl) => (reloc::R_X86_64_PC32, i64::from(addend)),
+                // through the PLT, as when faerie deduces the relocation, in case the function is in a shared library
+                Decl::Function(_) | Decl::FunctionImport => (reloc::R_X86_64_PLT32, i64::from(addend)),
+                _ => (reloc::R_X86_64_PC32, i64::from(addend)),
+            },
+            Reloc::Auto => match *l.from.decl {
+                Decl::Function(_) => {
+                    match *l.to.decl {
+                        // NB: this now forces _all_ function references, whether local or not, through the PLT
+                        // although we're not in the worst company here: https://github.com/ocaml/ocaml/pull/1330
+                        // everything is linked into the executable, so the call is direct and the data is absolute
+                        Decl::Function(_) | Decl::FunctionImport if model == RelocModel::Static => (reloc::R_X86_64_PC32, -4),
+                        Decl::Data(_) | Decl::Section(_) | Decl::CString(_) | Decl::DataImport if model == RelocModel::Static => {
+                            (reloc::R_X86_64_32S, 0)
+                        },
+                        // the call is `call [rip + func@GOTPCREL]`, which the linker may relax to a direct call
+                        Decl::FunctionImport if model == RelocModel::Pic => (reloc::R_X86_64_GOTPCRELX, -4),
+                        Decl::Function(_) | Decl::FunctionImport => (reloc::R_X86_64_PLT32, -4),
+                        Decl::Data(_) | Decl::Section(_) => (reloc::R_X86_64_PC32, -4),
+                        Decl::CString(_) => (reloc::R_X86_64_PC32, 0),
+                        Decl::DataImport => (reloc::R_X86_64_GOTPCREL, -4),
+                        // the linker fills in the fixed value of the symbol directly
+                        Decl::Absolute(_) => (self.pointer(artifact), 0),
+                        Decl::DebugSection(_) => return Err(unsupported(artifact, l)),
+                    }
+                },
+                // e.g. DW_AT_stmt_list, or DW_FORM_line_strp in .debug_line, is a 32-bit DWARF offset into the other section
+                Decl::DebugSection(_) if l.to.decl.is_debug_section() => (reloc::R_X86_64_32, 0),
+                // e.g. the initial location of an FDE
+                _ if is_unwind(l.from.decl) => (reloc::R_X86_64_PC32, 0),
+                // e.g. DW_AT_low_pc, or a pointer to a function in a static table
+                Decl::Data(_) | Decl::DebugSection(_) | Decl::Section(_) => (self.pointer(artifact), 0),
+                _ => return Err(unsupported(artifact, l)),
+            },
+        })
+    }
+    fn pointer(&self, artifact: &Artifact) -> u32 {
+        if make_ctx(&artifact.target).is_big() { reloc::R_X86_64_64 } else { reloc::R_X86_64_32 }
+    }
+    fn refers_to_symbol(&self, reloc: u32) -> bool {
+        match reloc {
+            // a section symbol has no size, so the size is taken from the definition's own symbol
+            reloc::R_X86_64_SIZE32 | reloc::R_X86_64_SIZE64 => true,
+            // the GOT entry holds the symbol's own address, which may be preempted
+            reloc::R_X86_64_GOT32 | reloc::R_X86_64_GOT64 | reloc::R_X86_64_GOTPCREL => true,
+            // the linker resolves these from the symbol's TLS offset, which a section symbol doesn't have
+            reloc::R_X86_64_TLSGD | reloc::R_X86_64_TLSLD | reloc::R_X86_64_DTPOFF32 | reloc::R_X86_64_GOTTPOFF |
+            reloc::R_X86_64_TPOFF32 => true,
+            _ => false,
+        }
+    }
+    fn field_size(&self, reloc: u32) -> usize {
+        match reloc {
+            reloc::R_X86_64_64 | reloc::R_X86_64_PC64 | reloc::R_X86_64_SIZE64 | reloc::R_X86_64_DTPMOD64 |
+            reloc::R_X86_64_DTPOFF64 | reloc::R_X86_64_TPOFF64 | reloc::R_X86_64_GOTOFF64 | reloc::R_X86_64_GOT64 |
+            reloc::R_X86_64_GOTPCREL64 | reloc::R_X86_64_GOTPC64 | reloc::R_X86_64_GOTPLT64 | reloc::R_X86_64_PLTOFF64 => 8,
+            reloc::R_X86_64_16 | reloc::R_X86_64_PC16 => 2,
+            reloc::R_X86_64_8 | reloc::R_X86_64_PC8 => 1,
+            _ => 4,
+        }
+    }
+    fn call(&self, reloc: u32) -> Option<(u64, &'static str, u32, i64)> {
+        match reloc {
+            // the call follows the `data16 lea` and the `data16 data16 rex.W` prefixes of the `call`
+            reloc::R_X86_64_TLSGD => Some((8, "__tls_get_addr", reloc::R_X86_64_PLT32, -4)),
+            // the call directly follows the `lea`
+            reloc::R_X86_64_TLSLD => Some((5, "__tls_get_addr", reloc::R_X86_64_PLT32, -4)),
+            _ => None,
+        }
+    }
+}
+
+/// The relocations of i386, whose addends are written in place.
+///
+/// i386 has no PC relative addressing of data, so code addresses it directly, or in position independent code,
+/// relative to the GOT base, which the code holds in a register: a local definition by its offset from the
+/// GOT base, and anything else by its GOT entry
+#[derive(Debug, Clone, Copy)]
+pub struct I386;
+
+impl RelocEncoder for I386 {
+    fn rela(&self) -> bool {
+        false
+    }
+    fn check(&self, l: &LinkAndDecl) -> Result<(), Error> {
+        match l.reloc {
+            Reloc::Size { size: 8, .. } | Reloc::Absolute { size: 8, .. } | Reloc::GotOffset { size: 8, .. } |
+            Reloc::Relative { size: 8, .. } | Reloc::DataRelative { size: 8, .. } => {
+                Err(format_err!("i386 has no 8 byte relocation from {} to {}", l.from.name, l.to.name))
+            },
+            // position independent code holds the GOT base in a register, rather than addressing relative to the field
+            Reloc::Got { .. } => Err(format_err!("i386 has no relocation from {} to the GOT entry of {} relative to the field", l.from.name, l.to.name)),
+            _ => Ok(()),
+        }
+    }
+    fn encode(&self, artifact: &Artifact, l: &LinkAndDecl) -> Result<(u32, i64), Error> {
+        let model = artifact.reloc_model;
+        let pic = model != RelocModel::Static;
+        Ok(match l.reloc {
+            Reloc::Raw { reloc, addend, .. } => (reloc, i64::from(addend)),
+            Reloc::Size { addend, .. } => (reloc::R_386_SIZE32, i64::from(addend)),
+            Reloc::Absolute { size: 2, addend } => (reloc::R_386_16, i64::from(addend)),
+            Reloc::Absolute { size: 1, addend } => (reloc::R_386_8, i64::from(addend)),
+            Reloc::Absolute { addend, .. } | Reloc::SignedAbsolute { addend } => (reloc::R_386_32, i64::from(addend)),
+            Reloc::GotOffset { addend, .. } => (reloc::R_386_GOT32X, i64::from(addend)),
+            Reloc::Plt { addend } => (reloc::R_386_PLT32, i64::from(addend)),
+            Reloc::GotBase { addend } => (reloc::R_386_GOTPC, i64::from(addend)),
+            Reloc::DataRelative { addend, .. } => (reloc::R_386_GOTOFF, i64::from(addend)),
+            Reloc::Got { .. } | Reloc::Tls { .. } | Reloc::SectionOffset { .. } | Reloc::DebugSecRel { .. } => return Err(unsupported(artifact, l)),
+            Reloc::Relative { size: 2, addend } => (reloc::R_386_PC16, i64::from(addend)),
+            Reloc::Relative { size: 1, addend } => (reloc::R_386_PC8, i64::from(addend)),
+            Reloc::Relative { addend, .. } => match *l.to.decl {
+                _ if is_unwind(l.from.decl) => (reloc::R_386_PC32, i64::from(addend)),
+                Decl::Function(_) | Decl::FunctionImport => (reloc::R_386_PLT32, i64::from(addend)),
+                _ => (reloc::R_386_PC32, i64::from(addend)),
+            },
+            Reloc::Auto => match *l.from.decl {
+                Decl::Function(_) => match *l.to.decl {
+                    Decl::Function(_) | Decl::FunctionImport if model == RelocModel::Static => (reloc::R_386_PC32, -4),
+                    // the call is `call [ebx + func@GOT]`, relative to the GOT base
+                    Decl::FunctionImport if model == RelocModel::Pic => (reloc::R_386_GOT32X, 0),
+                    Decl::Function(_) | Decl::FunctionImport => (reloc::R_386_PLT32, -4),
+                    Decl::Data(d) if pic && d.scope() == Scope::Local => (reloc::R_386_GOTOFF, 0),
+                    Decl::CString(c) if pic && !c.is_global() => (reloc::R_386_GOTOFF, 0),
+                    Decl::Section(_) if pic => (reloc::R_386_GOTOFF, 0),
+                    Decl::Section(_) => (reloc::R_386_32, 0),
+                    Decl::Data(_) | Decl::CString(_) | Decl::DataImport if pic => (reloc::R_386_GOT32X, 0),
+                    Decl::Data(_) | Decl::CString(_) | Decl::DataImport | Decl::Absolute(_) => (reloc::R_386_32, 0),
+                    Decl::DebugSection(_) => return Err(unsupported(artifact, l)),
+                },
+                _ if is_unwind(l.from.decl) => (reloc::R_386_PC32, 0),
+                Decl::Data(_) | Decl::DebugSection(_) | Decl::Section(_) => (reloc::R_386_32, 0),
+                _ => return Err(unsupported(artifact, l)),
+            },
+        })
+    }
+    fn pointer(&self, _artifact: &Artifact) -> u32 {
+        reloc::R_386_32
+    }
+    fn refers_to_symbol(&self, reloc: u32) -> bool {
+        match reloc {
+            // a section symbol has no size
+            reloc::R_386_SIZE32 => true,
+            // the GOT entry holds the symbol's own address, which may be preempted
+            reloc::R_386_GOT32 | reloc::R_386_GOT32X => true,
+            _ => false,
+        }
+    }
+    fn uses_got(&self, reloc: u32) -> bool {
+        matches!(reloc, reloc::R_386_GOT32 | reloc::R_386_GOT32X | reloc::R_386_GOTOFF | reloc::R_386_GOTPC)
+    }
+    fn field_size(&self, reloc: u32) -> usize {
+        match reloc {
+            reloc::R_386_16 | reloc::R_386_PC16 => 2,
+            reloc::R_386_8 | reloc::R_386_PC8 => 1,
+            _ => 4,
+        }
+    }
+}
+
+// goblin does not define the AArch64 relocations
+pub(crate) const R_AARCH64_ABS64: u32 = 257;
+pub(crate) const R_AARCH64_ABS32: u32 = 258;
+pub(crate) const R_AARCH64_ABS16: u32 = 259;
+pub(crate) const R_AARCH64_PREL64: u32 = 260;
+pub(crate) const R_AARCH64_PREL32: u32 = 261;
+pub(crate) const R_AARCH64_PREL16: u32 = 262;
+pub(crate) const R_AARCH64_ADR_PREL_PG_HI21: u32 = 275;
+pub(crate) const R_AARCH64_ADD_ABS_LO12_NC: u32 = 277;
+pub(crate) const R_AARCH64_CALL26: u32 = 283;
+pub(crate) const R_AARCH64_ADR_GOT_PAGE: u32 = 311;
+pub(crate) const R_AARCH64_LD64_GOT_LO12_NC: u32 = 312;
+pub(crate) const R_AARCH64_PLT32: u32 = 314;
+
+/// The relocations of AArch64.
+///
+/// A function calls another with a `bl` at the link's offset. It addresses data with an `adrp` at the link's offset
+/// and the instruction which follows it, which adds the low 12 bits of the address: an `add` for a definition,
+/// or an `ldr` of its GOT entry for an import
+#[derive(Debug, Clone, Copy)]
+pub struct Aarch64;
+
+impl RelocEncoder for Aarch64 {
+    fn check(&self, l: &LinkAndDecl) -> Result<(), Error> {
+        let (from, to) = (l.from.name, l.to.name);
+        match l.reloc {
+            Reloc::Size { .. } => Err(format_err!("AArch64 has no relocation from {} to the size of {}", from, to)),
+            Reloc::GotOffset { .. } => Err(format_err!("AArch64 has no relocation from {} to the GOT offset of {}", from, to)),
+            Reloc::Absolute { size: 1, .. } | Reloc::Relative { size: 1, .. } => {
+                Err(format_err!("AArch64 has no 1 byte relocation from {} to {}", from, to))
+            },
+            // the dynamic linker applies these to a linked image, they never appear in an object file
+            Reloc::Raw { reloc, .. } if (1024..=1032).contains(&reloc) => {
+                Err(format_err!("Dynamic relocation {} from {} against {} in a relocatable object", reloc, from, to))
+            },
+            _ => Ok(()),
+        }
+    }
+    fn encode(&self, artifact: &Artifact, l: &LinkAndDecl) -> Result<(u32, i64), Error> {
+        Ok(match l.reloc {
+            Reloc::Raw { reloc, addend, .. } => (reloc, i64::from(addend)),
+            Reloc::Absolute { size: 8, addend } => (R_AARCH64_ABS64, i64::from(addend)),
+            Reloc::Absolute { size: 2, addend } => (R_AARCH64_ABS16, i64::from(addend)),
+            Reloc::Absolute { addend, .. } | Reloc::SignedAbsolute { addend } => (R_AARCH64_ABS32, i64::from(addend)),
+            Reloc::Relative { size: 8, addend } => (R_AARCH64_PREL64, i64::from(addend)),
+            Reloc::Relative { size: 2, addend } => (R_AARCH64_PREL16, i64::from(addend)),
+            Reloc::Relative { addend, .. } => (R_AARCH64_PREL32, i64::from(addend)),
+            Reloc::Plt { addend } => (R_AARCH64_PLT32, i64::from(addend)),
+            Reloc::Got { addend } => (R_AARCH64_ADR_GOT_PAGE, i64::from(addend)),
+            Reloc::Size { .. } | Reloc::GotOffset { .. } | Reloc::GotBase { .. } | Reloc::DataRelative { .. } |
+            Reloc::Tls { .. } | Reloc::SectionOffset { .. } | Reloc::DebugSecRel { .. } => {
+                return Err(unsupported(artifact, l))
+            },
+            Reloc::Auto => match *l.from.decl {
+                Decl::Function(_) => match *l.to.decl {
+                    // the linker routes the call through a veneer or the PLT if it must
+                    Decl::Function(_) | Decl::FunctionImport => (R_AARCH64_CALL26, 0),
+                    Decl::Data(_) | Decl::CString(_) | Decl::Section(_) => (R_AARCH64_ADR_PREL_PG_HI21, 0),
+                    Decl::DataImport => (R_AARCH64_ADR_GOT_PAGE, 0),
+                    Decl::Absolute(_) | Decl::DebugSection(_) => return Err(unsupported(artifact, l)),
+                },
+                Decl::DebugSection(_) if l.to.decl.is_debug_section() => (R_AARCH64_ABS32, 0),
+                _ if is_unwind(l.from.decl) => (R_AARCH64_PREL32, 0),
+                Decl::Data(_) | Decl::DebugSection(_) | Decl::Section(_) => (self.pointer(artifact), 0),
+                _ => return Err(unsupported(artifact, l)),
+            },
+        })
+    }
+    fn pointer(&self, artifact: &Artifact) -> u32 {
+        if make_ctx(&artifact.target).is_big() { R_AARCH64_ABS64 } else { R_AARCH64_ABS32 }
+    }
+    fn refers_to_symbol(&self, reloc: u32) -> bool {
+        match reloc {
+            // the GOT entry holds the symbol's own address, which may be preempted
+            R_AARCH64_ADR_GOT_PAGE | R_AARCH64_LD64_GOT_LO12_NC => true,
+            _ => false,
+        }
+    }
+    fn field_size(&self, reloc: u32) -> usize {
+        match reloc {
+            R_AARCH64_ABS64 | R_AARCH64_PREL64 => 8,
+            R_AARCH64_ABS16 | R_AARCH64_PREL16 => 2,
+            _ => 4,
+        }
+    }
+    fn is_value_field(&self, reloc: u32) -> bool {
+        match reloc {
+            R_AARCH64_ABS64 | R_AARCH64_ABS32 | R_AARCH64_ABS16 | R_AARCH64_PREL64 | R_AARCH64_PREL32 | R_AARCH64_PREL16 |
+            R_AARCH64_PLT32 => true,
+            // the others are instructions
+            _ => false,
+        }
+    }
+    fn pair(&self, reloc: u32) -> Option<(u64, u32)> {
+        match reloc {
+            R_AARCH64_ADR_PREL_PG_HI21 => Some((4, R_AARCH64_ADD_ABS_LO12_NC)),
+            R_AARCH64_ADR_GOT_PAGE => Some((4, R_AARCH64_LD64_GOT_LO12_NC)),
+            _ => None,
+        }
+    }
+}
+
+// goblin names this by its old name, R_ARM_THM_PC22
+const R_ARM_THM_CALL: u32 = reloc::R_ARM_THM_PC22;
+/// Version 5 of the ARM EABI, which GNU ld requires to link objects together
+const EF_ARM_EABI_VER5: u32 = 0x0500_0000;
+
+/// The relocations of 32-bit ARM, whose addends are written in place.
+///
+/// A function calls another with a `bl` at the link's offset, which the linker turns into a `blx`, or routes through
+/// a veneer, when the caller and the function it calls are not both ARM or both Thumb code; the linker tells them
+/// apart by the low bit of each function's symbol, which is set for Thumb code. It loads the address of data with
+/// a `movw` at the link's offset, followed by a `movt`. The addend of an instruction is the one encoded in it,
+/// as an assembler encodes it, e.g. -8 in the `bl` of ARM code and -4 in that of Thumb code, to account for the PC
+/// reading ahead; only the addends of data, e.g. a pointer, are written in place.
+#[derive(Debug, Clone, Copy)]
+pub struct Arm;
+
+impl RelocEncoder for Arm {
+    fn flags(&self) -> u32 {
+        EF_ARM_EABI_VER5
+    }
+    fn rela(&self) -> bool {
+        false
+    }
+    fn check(&self, l: &LinkAndDecl) -> Result<(), Error> {
+        let (from, to) = (l.from.name, l.to.name);
+        match l.reloc {
+            Reloc::Size { .. } => Err(format_err!("ARM has no relocation from {} to the size of {}", from, to)),
+            Reloc::GotOffset { .. } => Err(format_err!("ARM has no relocation from {} to the GOT offset of {}", from, to)),
+            Reloc::Absolute { size: 8, .. } | Reloc::Relative { size: 8, .. } => {
+                Err(format_err!("ARM has no 8 byte relocation from {} to {}", from, to))
+            },
+            Reloc::Relative { size, .. } if size < 4 => Err(format_err!("ARM has no {} byte relative relocation from {} to {}", size, from, to)),
+            _ => Ok(()),
+        }
+    }
+    fn encode(&self, artifact: &Artifact, l: &LinkAndDecl) -> Result<(u32, i64), Error> {
+        let thumb = artifact.is_thumb(l.from.decl);
+        let call = if thumb { R_ARM_THM_CALL } else { reloc::R_ARM_CALL };
+        Ok(match l.reloc {
+            Reloc::Raw { reloc, addend, .. } => (reloc, i64::from(addend)),
+            Reloc::Absolute { size: 2, addend } => (reloc::R_ARM_ABS16, i64::from(addend)),
+            Reloc::Absolute { size: 1, addend } => (reloc::R_ARM_ABS8, i64::from(addend)),
+            Reloc::Absolute { addend, .. } | Reloc::SignedAbsolute { addend } => (reloc::R_ARM_ABS32, i64::from(addend)),
+            Reloc::Relative { addend, .. } => (reloc::R_ARM_REL32, i64::from(addend)),
+            Reloc::Plt { addend } => (call, i64::from(addend)),
+            Reloc::Size { .. } | Reloc::GotOffset { .. } | Reloc::Got { .. } | Reloc::GotBase { .. } | Reloc::DataRelative { .. } |
+            Reloc::Tls { .. } | Reloc::SectionOffset { .. } | Reloc::DebugSecRel { .. } => {
+                return Err(unsupported(artifact, l))
+            },
+            Reloc::Auto => match *l.from.decl {
+                Decl::Function(_) => match *l.to.decl {
+                    Decl::Function(_) | Decl::FunctionImport => (call, 0),
+                    Decl::Data(_) | Decl::CString(_) | Decl::DataImport | Decl::Absolute(_) | Decl::Section(_) => {
+                        (if thumb { reloc::R_ARM_THM_MOVW_ABS_NC } else { reloc::R_ARM_MOVW_ABS_NC }, 0)
+                    },
+                    Decl::DebugSection(_) => return Err(unsupported(artifact, l)),
+                },
+                Decl::DebugSection(_) if l.to.decl.is_debug_section() => (reloc::R_ARM_ABS32, 0),
+                _ if is_unwind(l.from.decl) => (reloc::R_ARM_REL32, 0),
+                Decl::Data(_) | Decl::DebugSection(_) | Decl::Section(_) => (self.pointer(artifact), 0),
+                _ => return Err(unsupported(artifact, l)),
+            },
+        })
+    }
+    fn pointer(&self, _artifact: &Artifact) -> u32 {
+        reloc::R_ARM_ABS32
+    }
+    fn field_size(&self, reloc: u32) -> usize {
+        match reloc {
+            reloc::R_ARM_ABS16 => 2,
+            reloc::R_ARM_ABS8 => 1,
+            _ => 4,
+        }
+    }
+    fn is_value_field(&self, reloc: u32) -> bool {
+        match reloc {
+            reloc::R_ARM_ABS32 | reloc::R_ARM_REL32 | reloc::R_ARM_ABS16 | reloc::R_ARM_ABS8 => true,
+            // the others are instructions
+            _ => false,
+        }
+    }
+    fn pair(&self, reloc: u32) -> Option<(u64, u32)> {
+        match reloc {
+            reloc::R_ARM_MOVW_ABS_NC => Some((4, reloc::R_ARM_MOVT_ABS)),
+            reloc::R_ARM_THM_MOVW_ABS_NC => Some((4, reloc::R_ARM_THM_MOVT_ABS)),
+            _ => None,
+        }
+    }
+}
+
+// goblin does not define the RISC-V relocations
+pub(crate) const R_RISCV_32: u32 = 1;
+pub(crate) const R_RISCV_64: u32 = 2;
+pub(crate) const R_RISCV_CALL_PLT: u32 = 19;
+pub(crate) const R_RISCV_GOT_HI20: u32 = 20;
+pub(crate) const R_RISCV_PCREL_HI20: u32 = 23;
+pub(crate) const R_RISCV_PCREL_LO12_I: u32 = 24;
+pub(crate) const R_RISCV_RELAX: u32 = 51;
+pub(crate) const R_RISCV_32_PCREL: u32 = 57;
+pub(crate) const R_RISCV_PLT32: u32 = 59;
+/// Compressed instructions, and the double precision floating point ABI, of the `gc` extensions
+const EF_RISCV_RVC: u32 = 0x1;
+const EF_RISCV_FLOAT_ABI_DOUBLE: u32 = 0x4;
+
+/// The relocations of RISC-V, for code of the `gc` extensions with the double precision floating point ABI.
+///
+/// A function calls another with an `auipc` and `jalr` pair at the link's offset. It addresses data with an `auipc`
+/// at the link's offset and the instruction which follows it, which adds the low 12 bits of the address relative to
+/// the `auipc`: an `addi` for a definition, or an `ld` of its GOT entry for an import. Every one of these may be
+/// relaxed by the linker, which then deletes bytes from the code, so the code must not depend on the distance between
+/// two of its instructions unless a relocation says so.
+#[derive(Debug, Clone, Copy)]
+pub struct Riscv;
+
+impl RelocEncoder for Riscv {
+    fn flags(&self) -> u32 {
+        EF_RISCV_RVC | EF_RISCV_FLOAT_ABI_DOUBLE
+    }
+    fn check(&self, l: &LinkAndDecl) -> Result<(), Error> {
+        let (from, to) = (l.from.name, l.to.name);
+        match l.reloc {
+            Reloc::Size { .. } => Err(format_err!("RISC-V has no relocation from {} to the size of {}", from, to)),
+            Reloc::GotOffset { .. } => Err(format_err!("RISC-V has no relocation from {} to the GOT offset of {}", from, to)),
+            Reloc::Absolute { size, .. } if size < 4 => Err(format_err!("RISC-V has no {} byte relocation from {} to {}", size, from, to)),
+            Reloc::Relative { size, .. } if size != 4 => {
+                Err(format_err!("RISC-V has no {} byte relative relocation from {} to {}", size, from, to))
+            },
+            _ => Ok(()),
+        }
+    }
+    fn encode(&self, artifact: &Artifact, l: &LinkAndDecl) -> Result<(u32, i64), Error> {
+        Ok(match l.reloc {
+            Reloc::Raw { reloc, addend, .. } => (reloc, i64::from(addend)),
+            Reloc::Absolute { size: 8, addend } => (R_RISCV_64, i64::from(addend)),
+            Reloc::Absolute { addend, .. } | Reloc::SignedAbsolute { addend } => (R_RISCV_32, i64::from(addend)),
+            Reloc::Relative { addend, .. } => (R_RISCV_32_PCREL, i64::from(addend)),
+            Reloc::Plt { addend } => (R_RISCV_PLT32, i64::from(addend)),
+            Reloc::Got { addend } => (R_RISCV_GOT_HI20, i64::from(addend)),
+            Reloc::Size { .. } | Reloc::GotOffset { .. } | Reloc::GotBase { .. } | Reloc::DataRelative { .. } |
+            Reloc::Tls { .. } | Reloc::SectionOffset { .. } | Reloc::DebugSecRel { .. } => {
+                return Err(unsupported(artifact, l))
+            },
+            Reloc::Auto => match *l.from.decl {
+                Decl::Function(_) => match *l.to.decl {
+                    Decl::Function(_) | Decl::FunctionImport => (R_RISCV_CALL_PLT, 0),
+                    _ if is_thread_local(l.to.decl) => return Err(unsupported(artifact, l)),
+                    Decl::Data(_) | Decl::CString(_) | Decl::Section(_) => (R_RISCV_PCREL_HI20, 0),
+                    Decl::DataImport => (R_RISCV_GOT_HI20, 0),
+                    Decl::Absolute(_) | Decl::DebugSection(_) => return Err(unsupported(artifact, l)),
+                },
+                Decl::DebugSection(_) if l.to.decl.is_debug_section() => (R_RISCV_32, 0),
+                _ if is_unwind(l.from.decl) => (R_RISCV_32_PCREL, 0),
+                Decl::Data(_) | Decl::DebugSection(_) | Decl::Section(_) => (self.pointer(artifact), 0),
+                _ => return Err(unsupported(artifact, l)),
+            },
+        })
+    }
+    fn pointer(&self, artifact: &Artifact) -> u32 {
+        if make_ctx(&artifact.target).is_big() { R_RISCV_64 } else { R_RISCV_32 }
+    }
+    fn refers_to_symbol(&self, reloc: u32) -> bool {
+        match reloc {
+            // the GOT entry holds the symbol's own address, which may be preempted
+            R_RISCV_GOT_HI20 => true,
+            // relaxing the code moves the symbols after the bytes it deletes, but not an offset from its section
+            R_RISCV_CALL_PLT | R_RISCV_PCREL_HI20 => true,
+            _ => false,
+        }
+    }
+    fn field_size(&self, reloc: u32) -> usize {
+        match reloc {
+            R_RISCV_64 | R_RISCV_CALL_PLT => 8,
+            _ => 4,
+        }
+    }
+    fn is_value_field(&self, reloc: u32) -> bool {
+        match reloc {
+            R_RISCV_32 | R_RISCV_64 | R_RISCV_32_PCREL | R_RISCV_PLT32 => true,
+            // the others are instructions
+            _ => false,
+        }
+    }
+    fn label_pair(&self, reloc: u32) -> Option<(u64, u32)> {
+        match reloc {
+            R_RISCV_PCREL_HI20 | R_RISCV_GOT_HI20 => Some((4, R_RISCV_PCREL_LO12_I)),
+            _ => None,
+        }
+    }
+    fn relaxation(&self, reloc: u32) -> Option<u32> {
+        match reloc {
+            R_RISCV_CALL_PLT | R_RISCV_PCREL_HI20 | R_RISCV_PCREL_LO12_I | R_RISCV_GOT_HI20 => Some(R_RISCV_RELAX),
+            _ => None,
+        }
+    }
+}
+
+// goblin does not define the AVR relocations
+pub const R_AVR_32: u32 = 1;
+pub const R_AVR_13_PCREL: u32 = 3;
+pub const R_AVR_16: u32 = 4;
+pub const R_AVR_16_PM: u32 = 5;
+pub const R_AVR_LO8_LDI: u32 = 6;
+pub const R_AVR_HI8_LDI: u32 = 7;
+pub const R_AVR_CALL: u32 = 18;
+pub const R_AVR_LO8_LDI_GS: u32 = 24;
+pub const R_AVR_HI8_LDI_GS: u32 = 25;
+
+const EM_AVR: u16 = 83;
+
+/// The relocations of the 8-bit AVR microcontrollers, of the processor `family`, e.g. 5 for `avr5`, which is
+/// the ATmega328P's; GNU ld checks the family of each object it links. target-lexicon doesn't know AVR, so this
+/// is given to an artifact with [reloc_encoder](../struct.ArtifactBuilder.html#method.reloc_encoder).
+///
+/// Code is addressed by 16-bit words, but every offset and addend is in bytes: the linker halves the address of
+/// code for the instructions and pointers which hold a word address.
+/// A function calls another with a 4 byte `call` at the link's offset, and loads the address of data with an
+/// `ldi` of its low byte at the link's offset, followed by one of its high byte. A data object holds a pointer
+/// to data as its byte address, and to a function as its word address, which `icall` jumps to.
+/// A `Reloc::Relative` to a function is the 2 byte `rjmp` or `rcall` at the link's offset, whatever its size.
+#[derive(Debug, Clone, Copy)]
+pub struct Avr {
+    pub family: u32,
+}
+
+impl RelocEncoder for Avr {
+    fn machine(&self) -> Option<u16> {
+        Some(EM_AVR)
+    }
+    fn ctx(&self) -> Option<Ctx> {
+        // ELF has no 16-bit class
+        Some(Ctx::new(Container::Little, Endian::Little))
+    }
+    fn flags(&self) -> u32 {
+        self.family
+    }
+    fn check(&self, l: &LinkAndDecl) -> Result<(), Error> {
+        let (from, to) = (l.from.name, l.to.name);
+        match l.reloc {
+            Reloc::Size { .. } => Err(format_err!("AVR has no relocation from {} to the size of {}", from, to)),
+            Reloc::GotOffset { .. } => Err(format_err!("AVR has no relocation from {} to the GOT offset of {}", from, to)),
+            Reloc::Absolute { size: 8, .. } => Err(format_err!("AVR has no 8 byte relocation from {} to {}", from, to)),
+            Reloc::Absolute { size: 1, .. } => Err(format_err!("AVR has no 1 byte relocation from {} to {}", from, to)),
+            // there is no PC relative addressing of data
+            Reloc::Relative { .. } => match *l.to.decl {
+                Decl::Function(_) | Decl::FunctionImport => Ok(()),
+                _ => Err(format_err!("AVR has no relative relocation from {} to {}, which is not a function", from, to)),
+            },
+            _ => Ok(()),
+        }
+    }
+    fn encode(&self, artifact: &Artifact, l: &LinkAndDecl) -> Result<(u32, i64), Error> {
+        Ok(match l.reloc {
+            Reloc::Raw { reloc, addend, .. } => (reloc, i64::from(addend)),
+            Reloc::Absolute { size: 2, addend } => (R_AVR_16, i64::from(addend)),
+            Reloc::Absolute { addend, .. } | Reloc::SignedAbsolute { addend } => (R_AVR_32, i64::from(addend)),
+            Reloc::Relative { addend, .. } | Reloc::Plt { addend } => (R_AVR_13_PCREL, i64::from(addend)),
+            Reloc::Size { .. } | Reloc::GotOffset { .. } | Reloc::Got { .. } | Reloc::GotBase { .. } | Reloc::DataRelative { .. } |
+            Reloc::Tls { .. } | Reloc::SectionOffset { .. } | Reloc::DebugSecRel { .. } => {
+                return Err(unsupported(artifact, l))
+            },
+            Reloc::Auto => match *l.from.decl {
+                Decl::Function(_) => match *l.to.decl {
+                    Decl::Function(_) | Decl::FunctionImport => (R_AVR_CALL, 0),
+                    Decl::Data(_) | Decl::CString(_) | Decl::DataImport | Decl::Absolute(_) | Decl::Section(_) => (R_AVR_LO8_LDI, 0),
+                    Decl::DebugSection(_) => return Err(unsupported(artifact, l)),
+                },
+                // DWARF addresses and offsets are 32-bit, and code is addressed by bytes in debug information
+                Decl::DebugSection(_) => (R_AVR_32, 0),
+                Decl::Data(_) | Decl::Section(_) => match *l.to.decl {
+                    Decl::Function(_) | Decl::FunctionImport => (self.pointer(artifact), 0),
+                    _ => (R_AVR_16, 0),
+                },
+                _ => return Err(unsupported(artifact, l)),
+            },
+        })
+    }
+    fn pointer(&self, _artifact: &Artifact) -> u32 {
+        R_AVR_16_PM
+    }
+    fn field_size(&self, reloc: u32) -> usize {
+        match reloc {
+            R_AVR_32 | R_AVR_CALL => 4,
+            _ => 2,
+        }
+    }
+    fn is_value_field(&self, reloc: u32) -> bool {
+        match reloc {
+            R_AVR_32 | R_AVR_16 => true,
+            // the others are instructions, or a word address
+            _ => false,
+        }
+    }
+    fn pair(&self, reloc: u32) -> Option<(u64, u32)> {
+        match reloc {
+            R_AVR_LO8_LDI => Some((2, R_AVR_HI8_LDI)),
+            R_AVR_LO8_LDI_GS => Some((2, R_AVR_HI8_LDI_GS)),
+            _ => None,
+        }
+    }
+}
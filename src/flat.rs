@@ -0,0 +1,191 @@
+//! A flat binary, like `objcopy -O binary` produces: the contents of each definition at its address,
+//! with every relocation applied, and no headers, symbols, or relocations left for a linker
+
+use failure::Error;
+use goblin::elf::reloc;
+use scroll::Pwrite;
+use target_lexicon::Architecture;
+
+use std::collections::HashMap;
+
+use {Artifact, Decl, Reloc};
+use artifact::{ArtifactError, Definition, LinkAndDecl};
+
+/// How a relocation's field is computed from the address of its target `S`, its addend `A`,
+/// and the address of the field itself `P`
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    /// `S + A`, which must fit the field as a signed or unsigned value
+    Absolute { size: u8, signed: bool },
+    /// `S + A - P`
+    Relative { size: u8 },
+    /// The size of the target plus `A`
+    Size { size: u8 },
+}
+
+impl Field {
+    fn size(self) -> u8 {
+        match self {
+            Field::Absolute { size, .. } | Field::Relative { size } | Field::Size { size } => size,
+        }
+    }
+}
+
+/// The contents of a definition, placed in the flat binary at `address`
+struct Placed {
+    address: u64,
+    bytes: Vec<u8>,
+}
+
+/// The alignment of a definition, which is the alignment of the section it is emitted in for ELF
+fn alignment(def: &Definition, artifact: &Artifact) -> u64 {
+    let default = if def.prop.function { 0x10 } else if def.prop.writable { 0x8 } else { 1 };
+    if def.prop.function {
+        artifact.function_align.unwrap_or(default)
+    } else if !def.prop.cstring && def.prop.zero_init.is_none() {
+        artifact.data_align.unwrap_or(default)
+    } else {
+        default
+    }
+}
+
+/// Lay out the definitions of `artifact` starting at the address `base`, apply all of its relocations,
+/// and return the bytes from `base` up to the end of the last definition
+pub fn to_bytes(artifact: &Artifact, base: u64) -> Result<Vec<u8>, Error> {
+    if artifact.target.architecture != Architecture::X86_64 {
+        return Err(format_err!("flat binaries are not supported for architecture {}", artifact.target.architecture));
+    }
+    artifact.check_alignments()?;
+    // the code, then the read-only data, then the writable data, as a linker script would place them;
+    // debug sections are not loaded, so they are left out
+    let defs = artifact.definitions().filter(|def| !def.prop.debug).collect::<Vec<_>>();
+    // there is no thread pointer, so nothing to hold each thread's copy
+    if let Some(def) = defs.iter().find(|def| def.prop.tls) {
+        return Err(format_err!("thread-local data object {} is not supported in a flat binary", def.name));
+    }
+    // a flat binary has no sections, so there is nowhere to gather a custom section's contents from all objects
+    if let Some(def) = defs.iter().find(|def| def.prop.section) {
+        return Err(format_err!("custom section {} is not supported in a flat binary", def.name));
+    }
+    // nothing calls the resolver of an indirect function before the binary runs
+    if let Some(def) = defs.iter().find(|def| def.prop.ifunc) {
+        return Err(format_err!("indirect function {} is not supported in a flat binary", def.name));
+    }
+    let ordered = defs.iter().filter(|def| def.prop.function)
+        .chain(defs.iter().filter(|def| !def.prop.function && !def.prop.writable))
+        .chain(defs.iter().filter(|def| !def.prop.function && def.prop.writable));
+    let mut placed = Vec::with_capacity(defs.len());
+    let mut index = HashMap::new();
+    let mut end = base;
+    for def in ordered {
+        let align = alignment(def, artifact);
+        let address = end.checked_add((align - end % align) % align)
+            .ok_or_else(|| format_err!("{} does not fit in the address space", def.name))?;
+        let mut bytes = Vec::with_capacity(def.data.len() as usize);
+        def.data.write_to(&mut bytes)?;
+        end = address.checked_add(bytes.len() as u64)
+            .ok_or_else(|| format_err!("{} does not fit in the address space", def.name))?;
+        index.insert(def.name, placed.len());
+        placed.push(Placed { address, bytes });
+    }
+    for (alias, target) in artifact.aliases() {
+        let placement = index[target.name];
+        index.insert(alias.name, placement);
+    }
+
+    for link in artifact.links().filter(|link| !link.from.decl.is_debug_section()) {
+        let (field, addend) = field(&link)?;
+        let unresolvable = || ArtifactError::UnresolvableRelocation(link.from.name.to_string(), link.to.name.to_string());
+        let target = match *link.to.decl {
+            Decl::Absolute(abs) => i128::from(abs.value()),
+            Decl::Function(_) | Decl::Data(_) | Decl::CString(_) => {
+                let to = &placed[index[link.to.name]];
+                match field {
+                    Field::Size { .. } => i128::from(to.bytes.len() as u64),
+                    _ => i128::from(to.address),
+                }
+            },
+            Decl::FunctionImport | Decl::DataImport | Decl::DebugSection(_) | Decl::Section(_) => return Err(unresolvable().into()),
+        };
+        let from = &mut placed[index[link.from.name]];
+        let value = match field {
+            Field::Relative { .. } => target + addend - i128::from(from.address + link.at),
+            _ => target + addend,
+        };
+        let bits = 8 * u32::from(field.size());
+        let fits = match field {
+            _ if bits == 64 => value >= i128::from(i64::MIN) && value <= i128::from(u64::MAX),
+            Field::Absolute { signed: false, .. } => value >= 0 && value < 1 << bits,
+            _ => value >= -(1 << (bits - 1)) && value < 1 << (bits - 1),
+        };
+        if !fits {
+            return Err(ArtifactError::RelocationOverflow(link.from.name.to_string(), link.to.name.to_string()).into());
+        }
+        let at = link.at as usize;
+        if link.at + u64::from(field.size()) > from.bytes.len() as u64 {
+            return Err(format_err!("relocation at {:#x} is outside of {}", link.at, link.from.name));
+        }
+        // the field holds the low bits, i.e. the two's complement of a negative value
+        match field.size() {
+            8 => from.bytes.pwrite_with(value as u64, at, scroll::LE)?,
+            4 => from.bytes.pwrite_with(value as u32, at, scroll::LE)?,
+            2 => from.bytes.pwrite_with(value as u16, at, scroll::LE)?,
+            _ => from.bytes.pwrite_with(value as u8, at, scroll::LE)?,
+        };
+    }
+    for difference in artifact.differences().filter(|difference| !difference.from.decl.is_debug_section()) {
+        let value = i128::from(placed[index[difference.to.name]].address) - i128::from(placed[index[difference.minus.name]].address);
+        if value < i128::from(i32::MIN) || value > i128::from(i32::MAX) {
+            return Err(ArtifactError::RelocationOverflow(difference.from.name.to_string(), difference.to.name.to_string()).into());
+        }
+        let from = &mut placed[index[difference.from.name]];
+        if difference.at + 4 > from.bytes.len() as u64 {
+            return Err(format_err!("relocation at {:#x} is outside of {}", difference.at, difference.from.name));
+        }
+        from.bytes.pwrite_with(value as i32, difference.at as usize, scroll::LE)?;
+    }
+
+    let mut bytes = vec![0; (end - base) as usize];
+    for p in placed {
+        let start = (p.address - base) as usize;
+        bytes[start..start + p.bytes.len()].copy_from_slice(&p.bytes);
+    }
+    Ok(bytes)
+}
+
+/// The field of `link` and its addend, as the ELF backend would relocate it
+fn field(l: &LinkAndDecl) -> Result<(Field, i128), Error> {
+    const POINTER: Field = Field::Absolute { size: 8, signed: false };
+    let (field, addend) = match l.reloc {
+        Reloc::Raw { reloc, addend, .. } => (match reloc {
+            reloc::R_X86_64_64 => POINTER,
+            reloc::R_X86_64_32 => Field::Absolute { size: 4, signed: false },
+            reloc::R_X86_64_32S => Field::Absolute { size: 4, signed: true },
+            reloc::R_X86_64_PC32 | reloc::R_X86_64_PLT32 => Field::Relative { size: 4 },
+            reloc::R_X86_64_PC64 => Field::Relative { size: 8 },
+            reloc::R_X86_64_16 => Field::Absolute { size: 2, signed: false },
+            reloc::R_X86_64_PC16 => Field::Relative { size: 2 },
+            reloc::R_X86_64_8 => Field::Absolute { size: 1, signed: false },
+            reloc::R_X86_64_PC8 => Field::Relative { size: 1 },
+            reloc::R_X86_64_SIZE32 => Field::Size { size: 4 },
+            reloc::R_X86_64_SIZE64 => Field::Size { size: 8 },
+            _ => return Err(format_err!("relocation {} from {} to {} is not supported in a flat binary", reloc, l.from.name, l.to.name)),
+        }, addend),
+        Reloc::Size { size, addend } => (Field::Size { size }, addend),
+        Reloc::Absolute { size, addend } => (Field::Absolute { size, signed: false }, addend),
+        Reloc::SignedAbsolute { addend } => (Field::Absolute { size: 4, signed: true }, addend),
+        Reloc::Relative { size, addend } => (Field::Relative { size }, addend),
+        // there is no PLT, and the function is defined at its one address
+        Reloc::Plt { addend } => (Field::Relative { size: 4 }, addend),
+        // a flat binary has no GOT, nor thread pointer, nor sections
+        Reloc::GotOffset { .. } | Reloc::Got { .. } | Reloc::GotBase { .. } | Reloc::DataRelative { .. } | Reloc::Tls { .. } |
+        Reloc::SectionOffset { .. } | Reloc::DebugSecRel { .. } => return Err(ArtifactError::UnresolvableRelocation(l.from.name.to_string(), l.to.name.to_string()).into()),
+        Reloc::Auto => match (l.from.decl, l.to.decl) {
+            (&Decl::Function(_), &Decl::Function(_)) | (&Decl::Function(_), &Decl::Data(_)) => (Field::Relative { size: 4 }, -4),
+            (&Decl::Function(_), &Decl::CString(_)) => (Field::Relative { size: 4 }, 0),
+            (&Decl::Function(_), &Decl::FunctionImport) => (Field::Relative { size: 4 }, -4),
+            _ => (POINTER, 0),
+        },
+    };
+    Ok((field, i128::from(addend)))
+}
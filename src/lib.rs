@@ -15,6 +15,22 @@ type Ctx = container::Ctx;
 mod target;
 mod elf;
 mod mach;
+mod flat;
+mod wasm;
+mod sha1;
 
 pub mod artifact;
-pub use artifact::{Artifact, ArtifactBuilder, Link, ImportKind, Decl, RelocOverride};
+pub mod archive;
+pub mod encoder;
+pub mod backend;
+pub mod xref;
+pub use artifact::{Artifact, ArtifactBuilder, Link, ImportKind, Decl, Reloc, RelocOverride, TlsModel, DataSource, AutoRelocation, RelocModel};
+pub use artifact::{Scope, Visibility, FunctionDecl, DataDecl, CStringDecl, AbsoluteDecl, DebugSectionDecl, SectionDecl};
+pub use artifact::{Note, NT_GNU_BUILD_ID, NT_GO_BUILD_ID, NT_FDO_PACKAGING_METADATA, NT_GNU_PROPERTY_TYPE_0};
+pub use artifact::{GNU_PROPERTY_AARCH64_FEATURE_1_AND, GNU_PROPERTY_X86_FEATURE_1_AND, GNU_PROPERTY_X86_ISA_1_NEEDED};
+pub use artifact::{UnwindRegister, x86_64_frame_unwind, IsaLevel, Strip, SFrameBase, SFrameRow, ComdatSelection, SymbolKind};
+pub use artifact::{WasmType, WasmSignature, LinkerDirective};
+pub use encoder::RelocEncoder;
+pub use backend::ObjectBackend;
+pub use xref::{xref_report, XrefReport};
+pub use archive::{Archive, ArchiveFormat};
@@ -1,53 +1,58 @@
 //! The Mach 32/64 bit backend for transforming an artifact to a valid, mach-o object file.
 
-use {Artifact, Ctx};
-use artifact::{Decl, Definition};
+use {Artifact, Ctx, LinkerDirective, Reloc, TlsModel, Visibility};
+use artifact::{ArtifactError, Binding, ComdatSelection, Decl, Definition, LinkAndDecl, Scope};
 use target::make_ctx;
 
 use failure::Error;
 use indexmap::IndexMap;
 use string_interner::{DefaultStringInterner};
 //use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::{Seek, Cursor, BufWriter, Write};
-use std::io::SeekFrom::*;
 use scroll::{Pwrite, IOwrite};
 use scroll::ctx::SizeWith;
 use target_lexicon::Architecture;
 
 use goblin::mach::cputype;
 use goblin::mach::segment::{Section, Segment};
-use goblin::mach::load_command::SymtabCommand;
+use goblin::mach::load_command::{SymtabCommand, LC_LINKER_OPTION, LC_UUID};
 use goblin::mach::header::{Header, MH_OBJECT, MH_SUBSECTIONS_VIA_SYMBOLS};
 use goblin::mach::symbols::Nlist;
-use goblin::mach::relocation::{RelocationInfo, RelocType, SIZEOF_RELOCATION_INFO};
-use goblin::mach::constants::{S_REGULAR, S_CSTRING_LITERALS, S_ATTR_PURE_INSTRUCTIONS, S_ATTR_SOME_INSTRUCTIONS};
+use goblin::mach::relocation::{RelocationInfo, RelocType, SIZEOF_RELOCATION_INFO, GENERIC_RELOC_VANILLA};
+use goblin::mach::relocation::{ARM64_RELOC_UNSIGNED, ARM64_RELOC_BRANCH26, ARM64_RELOC_PAGE21, ARM64_RELOC_PAGEOFF12, ARM64_RELOC_GOT_LOAD_PAGE21, ARM64_RELOC_GOT_LOAD_PAGEOFF12};
+use goblin::mach::relocation::{ARM64_RELOC_TLVP_LOAD_PAGE21, ARM64_RELOC_TLVP_LOAD_PAGEOFF12};
+use goblin::mach::constants::{S_REGULAR, S_CSTRING_LITERALS, S_COALESCED, S_ZEROFILL, S_ATTR_PURE_INSTRUCTIONS, S_ATTR_SOME_INSTRUCTIONS, S_ATTR_DEBUG, SECTION_TYPE};
+use goblin::mach::constants::{S_MOD_INIT_FUNC_POINTERS, S_MOD_TERM_FUNC_POINTERS};
+use goblin::mach::constants::{S_THREAD_LOCAL_REGULAR, S_THREAD_LOCAL_ZEROFILL, S_THREAD_LOCAL_VARIABLES};
 
-struct CpuType(cputype::CpuType);
+struct CpuType(cputype::CpuType, cputype::CpuSubType);
 
-impl From<Architecture> for CpuType {
-    fn from(architecture: Architecture) -> CpuType {
+impl CpuType {
+    /// The cpu type of `architecture`, and its subtype, if it exists in Mach-O
+    fn new(architecture: Architecture) -> Option<CpuType> {
         use target_lexicon::Architecture::*;
         use mach::cputype::*;
-        CpuType(match architecture {
-            X86_64 => CPU_TYPE_X86_64,
+        Some(match architecture {
+            X86_64 => CpuType(CPU_TYPE_X86_64, CPU_SUBTYPE_X86_64_ALL),
             I386 |
             I586 |
-            I686 => CPU_TYPE_X86,
-            Aarch64 => CPU_TYPE_ARM64,
+            I686 => CpuType(CPU_TYPE_X86, CPU_SUBTYPE_I386_ALL),
+            Aarch64 => CpuType(CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_ALL),
+            Armv7 => CpuType(CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V7),
+            Armv7s => CpuType(CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V7S),
             Arm |
             Armv4t |
             Armv5te |
-            Armv7 |
-            Armv7s |
             Thumbv6m |
             Thumbv7em |
-            Thumbv7m => CPU_TYPE_ARM,
-            Sparc => CPU_TYPE_SPARC,
-            Powerpc  => CPU_TYPE_POWERPC,
+            Thumbv7m => CpuType(CPU_TYPE_ARM, CPU_SUBTYPE_ARM_ALL),
+            Sparc => CpuType(CPU_TYPE_SPARC, CPU_SUBTYPE_SPARC_ALL),
+            Powerpc  => CpuType(CPU_TYPE_POWERPC, CPU_SUBTYPE_POWERPC_ALL),
             Powerpc64 |
-            Powerpc64le => CPU_TYPE_POWERPC64,
-            Unknown => 0,
-            _ => panic!("requested architecture does not exist in MachO"),
+            Powerpc64le => CpuType(CPU_TYPE_POWERPC64, CPU_SUBTYPE_POWERPC_ALL),
+            Unknown => CpuType(0, 0),
+            _ => return None,
         })
     }
 }
@@ -58,6 +63,36 @@ type StrtableOffset = u64;
 const CODE_SECTION_INDEX: SectionIndex = 0;
 const DATA_SECTION_INDEX: SectionIndex = 1;
 const CSTRING_SECTION_INDEX: SectionIndex = 2;
+const WEAK_CODE_SECTION_INDEX: SectionIndex = 3;
+const WEAK_DATA_SECTION_INDEX: SectionIndex = 4;
+const CONST_SECTION_INDEX: SectionIndex = 5;
+const UNWIND_SECTION_INDEX: SectionIndex = 6;
+const INIT_SECTION_INDEX: SectionIndex = 7;
+const TERM_SECTION_INDEX: SectionIndex = 8;
+const THREAD_VARS_SECTION_INDEX: SectionIndex = 9;
+const THREAD_DATA_SECTION_INDEX: SectionIndex = 10;
+const BSS_SECTION_INDEX: SectionIndex = 11;
+const THREAD_BSS_SECTION_INDEX: SectionIndex = 12;
+
+/// The size of a 64-bit compact unwind entry: the function's address, its length, its encoding,
+/// and the addresses of its personality function and language specific data, which are unused
+const COMPACT_UNWIND_ENTRY_SIZE: u64 = 32;
+
+/// The thunk which the descriptor of every thread-local variable points to until dyld binds it, without the _
+/// prefix every symbol is written with
+const TLV_BOOTSTRAP: &str = "_tlv_bootstrap";
+
+/// The symbol is a weak definition, which the linker coalesces with any other definition of it
+const N_WEAK_DEF: u16 = 0x80;
+/// The description of a definition which the linker keeps when it dead strips, which goblin does not define
+const N_NO_DEAD_STRIP: u16 = 0x20;
+/// The undefined symbol is a weak reference, whose address is zero if nothing defines it
+const N_WEAK_REF: u16 = 0x40;
+
+/// The relocation refers to an address, not a symbol, and its own address has only 24 bits
+const R_SCATTERED: u32 = 0x8000_0000;
+/// A section difference whose first address is of a symbol which is not external; goblin has the wrong value
+const GENERIC_RELOC_LOCAL_SECTDIFF: RelocType = 4;
 
 /// A builder for creating a 32/64 bit Mach-o Nlist symbol
 #[derive(Debug)]
@@ -65,7 +100,12 @@ struct SymbolBuilder {
     name: StrtableOffset,
     section: Option<SectionIndex>,
     global: bool,
+    weak: bool,
+    hidden: bool,
     import: bool,
+    absolute: bool,
+    common: Option<u64>,
+    no_dead_strip: bool,
     offset: u64,
     segment_relative_offset: u64,
 }
@@ -77,7 +117,12 @@ impl SymbolBuilder {
             name,
             section: None,
             global: false,
+            weak: false,
+            hidden: false,
             import: false,
+            absolute: false,
+            common: None,
+            no_dead_strip: false,
             offset: 0,
             segment_relative_offset: 0,
         }
@@ -90,6 +135,14 @@ impl SymbolBuilder {
     pub fn global(mut self, global: bool) -> Self {
         self.global = global; self
     }
+    /// Is this symbol a weak definition?
+    pub fn weak(mut self, weak: bool) -> Self {
+        self.weak = weak; self
+    }
+    /// Is this symbol a private external, i.e., hidden outside of the linked image?
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden; self
+    }
     pub fn offset(mut self, offset: u64) -> Self {
         self.offset = offset; self
     }
@@ -101,21 +154,41 @@ impl SymbolBuilder {
     pub fn get_segment_relative_offset(&self) -> u64 {
         self.segment_relative_offset
     }
+    /// Returns the section this symbol belongs to, if any
+    pub fn get_section(&self) -> Option<SectionIndex> {
+        self.section
+    }
     /// Is this symbol an import?
     pub fn import(mut self) -> Self {
         self.import = true; self
     }
+    /// Is this symbol absolute, i.e., its offset is a fixed value not in any section?
+    pub fn absolute(mut self) -> Self {
+        self.absolute = true; self
+    }
+    /// Make this a common symbol aligned to `align` bytes, whose offset is its size
+    pub fn common(mut self, align: u64) -> Self {
+        self.common = Some(align); self
+    }
     /// Finalize and create the symbol
     /// The n_value (offset into section) is still unset, and needs to be generated by the client
     pub fn create(self) -> Nlist {
-        use goblin::mach::symbols::{N_EXT, N_UNDF, N_SECT, NO_SECT};
+        use goblin::mach::symbols::{N_EXT, N_PEXT, N_UNDF, N_SECT, N_ABS, NO_SECT};
         let n_strx = self.name;
         let mut n_sect = 0;
         let mut n_type = N_UNDF;
         let mut n_value = self.offset;
-        let n_desc = 0;
+        let mut n_desc = match (self.weak, self.import) {
+            (true, true) => N_WEAK_REF,
+            (true, false) => N_WEAK_DEF,
+            (false, _) => 0,
+        };
+        if self.no_dead_strip {
+            n_desc |= N_NO_DEAD_STRIP;
+        }
         if self.global {
             n_type |= N_EXT;
+            if self.hidden { n_type |= N_PEXT; }
         } else {
             n_type &= !N_EXT;
         }
@@ -124,11 +197,19 @@ impl SymbolBuilder {
             n_type |= N_SECT;
         }
 
-        if self.import {
+        if let Some(align) = self.common {
+            // an undefined external symbol with a value is common, and n_desc holds the log2 of its alignment
+            n_sect = NO_SECT as usize;
+            n_type = N_UNDF | N_EXT;
+            n_desc = (align.trailing_zeros() as u16 & 0xf) << 8;
+        } else if self.import {
             n_sect = NO_SECT as usize;
             // FIXME: this is broken i believe; we need to make it both undefined + global for imports
             n_type = N_EXT;
             n_value = 0;
+        } else if self.absolute {
+            n_sect = NO_SECT as usize;
+            n_type |= N_ABS;
         } else {
             n_type |= N_SECT;
         }
@@ -152,6 +233,7 @@ struct RelocationBuilder {
     symbol: SymbolIndex,
     relocation_offset: u64,
     absolute: bool,
+    length: Option<u32>,
     r_type: RelocType,
 }
 
@@ -162,6 +244,7 @@ impl RelocationBuilder {
             symbol,
             relocation_offset,
             absolute: false,
+            length: None,
             r_type,
         }
     }
@@ -169,12 +252,16 @@ impl RelocationBuilder {
     pub fn absolute(mut self) -> Self {
         self.absolute = true; self
     }
+    /// The field is 4 bytes, rather than the 8 of an absolute relocation
+    pub fn four_bytes(mut self) -> Self {
+        self.length = Some(2); self
+    }
     /// Finalize and create the relocation
     pub fn create(self) -> RelocationInfo {
         // it basically goes sort of backwards than what you'd expect because C bitfields are bonkers
         let r_symbolnum: u32 = self.symbol as u32;
         let r_pcrel: u32 = if self.absolute { 0 } else { 1 } << 24;
-        let r_length: u32 = if self.absolute { 3 } else { 2 } << 25;
+        let r_length: u32 = self.length.unwrap_or(if self.absolute { 3 } else { 2 }) << 25;
         let r_extern: u32 = 1 << 27;
         let r_type = (self.r_type as u32) << 28;
         // r_symbolnum, 24 bits, r_pcrel 1 bit, r_length 2 bits, r_extern 1 bit, r_type 4 bits
@@ -186,6 +273,16 @@ impl RelocationBuilder {
     }
 }
 
+/// Create a scattered relocation of the 4 byte field at `relocation_offset`, which refers to the address `value`
+fn scattered(relocation_offset: u64, r_type: RelocType, value: u64) -> RelocationInfo {
+    // r_address 24 bits, r_type 4 bits, r_length 2 bits, r_pcrel 1 bit, r_scattered 1 bit, followed by r_value
+    let r_address = relocation_offset as u32 | (r_type as u32) << 24 | 2 << 28 | R_SCATTERED;
+    RelocationInfo {
+        r_address: r_address as i32,
+        r_info: value as u32,
+    }
+}
+
 /// Helper to build sections
 #[derive(Debug, Clone)]
 struct SectionBuilder {
@@ -195,12 +292,12 @@ struct SectionBuilder {
     size: u64,
     flags: u32,
     sectname: &'static str,
-    segname: &'static str,
+    segname: String,
 }
 
 impl SectionBuilder {
     /// Create a new section builder with `sectname`, `segname` and `size`
-    pub fn new(sectname: &'static str, segname: &'static str, size: u64) -> Self {
+    pub fn new(sectname: &'static str, segname: &str, size: u64) -> Self {
         SectionBuilder {
             addr: 0,
             align: 4,
@@ -208,7 +305,7 @@ impl SectionBuilder {
             flags: S_REGULAR,
             size,
             sectname,
-            segname,
+            segname: segname.to_string(),
         }
     }
     /// Set the vm address of this section
@@ -232,7 +329,7 @@ impl SectionBuilder {
         let mut sectname = [0u8; 16];
         sectname.pwrite(self.sectname, 0).unwrap();
         let mut segname = [0u8; 16];
-        segname.pwrite(self.segname, 0).unwrap();
+        segname.pwrite(self.segname.as_str(), 0).unwrap();
         Section {
             sectname,
             segname,
@@ -251,10 +348,26 @@ impl SectionBuilder {
 type ArtifactCode<'a> = Vec<Definition<'a>>;
 type ArtifactData<'a> = Vec<Definition<'a>>;
 
+/// The definitions of an artifact, by the section of the segment each is placed in
+#[derive(Debug, Default)]
+struct SectionDefinitions<'a> {
+    code: ArtifactCode<'a>,
+    data: ArtifactData<'a>,
+    cstrings: Vec<Definition<'a>>,
+    weak_code: Vec<Definition<'a>>,
+    weak_data: Vec<Definition<'a>>,
+    consts: Vec<Definition<'a>>,
+    zerofill: Vec<Definition<'a>>,
+    thread_data: Vec<Definition<'a>>,
+    thread_bss: Vec<Definition<'a>>,
+}
+
 type StrTableIndex = usize;
 type StrTable = DefaultStringInterner;
 type Symbols = IndexMap<StrTableIndex, SymbolBuilder>;
 type Relocations = Vec<Vec<RelocationInfo>>;
+/// The fields of each definition which hold the addend of a relocation, and their values
+type Addends<'a> = HashMap<&'a str, Vec<(u64, i32)>>;
 
 /// A mach object symbol table
 #[derive(Debug, Default)]
@@ -269,9 +382,13 @@ struct SymbolTable {
 enum SymbolType {
     /// Which `section` this is defined in, the `absolute_offset` in the binary, and its
     /// `segment_relative_offset`
-    Defined { section: SectionIndex, absolute_offset: u64, segment_relative_offset: u64, global: bool },
-    /// An undefined symbol (an import)
-    Undefined,
+    Defined { section: SectionIndex, absolute_offset: u64, segment_relative_offset: u64, global: bool, weak: bool, hidden: bool },
+    /// An undefined symbol (an import), which is a weak reference if `weak`
+    Undefined { weak: bool },
+    /// A symbol with a fixed `value`, not defined in any section
+    Absolute { value: u64, global: bool, hidden: bool },
+    /// A common symbol of `size` bytes aligned to `align`, which the linker allocates
+    Common { size: u64, align: u64 },
 }
 
 impl SymbolTable {
@@ -301,11 +418,39 @@ impl SymbolTable {
          .and_then(|idx| self.symbols.get(&idx))
          .and_then(|sym| Some(sym.get_segment_relative_offset()))
     }
+    /// Lookup the address of this defined symbol
+    pub fn address(&self, symbol_name: &str) -> Option<u64> {
+        self.strtable.get(symbol_name)
+         .and_then(|idx| self.symbols.get(&idx))
+         .and_then(|sym| sym.get_section().map(|_| sym.offset))
+    }
+    /// Lookup the section this symbol is defined in
+    pub fn section(&self, symbol_name: &str) -> Option<SectionIndex> {
+        self.strtable.get(symbol_name)
+         .and_then(|idx| self.symbols.get(&idx))
+         .and_then(|sym| sym.get_section())
+    }
     /// Lookup this symbols ordinal index in the symbol table, if it has one
     pub fn index(&self, symbol_name: &str) -> Option<SymbolIndex> {
          self.strtable.get(symbol_name)
          .and_then(|idx| self.indexes.get(&idx).cloned())
     }
+    /// Insert the symbol `alias`, at the same place as the defined symbol `target`
+    pub fn alias(&mut self, alias: &str, target: &str, global: bool, weak: bool, hidden: bool) {
+        let (section, absolute_offset, segment_relative_offset) = {
+            let symbol = self.strtable.get(target).and_then(|idx| self.symbols.get(&idx)).expect("alias target is defined");
+            (symbol.section.expect("alias target has a section"), symbol.offset, symbol.segment_relative_offset)
+        };
+        self.insert(alias, SymbolType::Defined { section, absolute_offset, segment_relative_offset, global, weak, hidden });
+    }
+    /// Keep the definition `name` when the linker dead strips
+    pub fn no_dead_strip(&mut self, name: &str) {
+        if let Some(idx) = self.strtable.get(name) {
+            if let Some(symbol) = self.symbols.get_mut(&idx) {
+                symbol.no_dead_strip = true;
+            }
+        }
+    }
     /// Insert a new symbol into this objects symbol table
     pub fn insert(&mut self, symbol_name: &str, kind: SymbolType) {
         // mach-o requires _ prefixes on every symbol, we will allow this to be configurable later
@@ -321,9 +466,13 @@ impl SymbolTable {
             debug!("Inserting new symbol: {}", self.strtable.resolve(name_index).unwrap());
             // TODO: add code offset into symbol n_value
             let builder = match kind {
-                SymbolType::Undefined => SymbolBuilder::new(self.strtable_size).global(true).import(),
-                SymbolType::Defined { section, absolute_offset, global, segment_relative_offset } => {
-                    SymbolBuilder::new(self.strtable_size).global(global)
+                SymbolType::Undefined { weak } => SymbolBuilder::new(self.strtable_size).global(true).weak(weak).import(),
+                SymbolType::Absolute { value, global, hidden } => {
+                    SymbolBuilder::new(self.strtable_size).global(global).hidden(hidden).offset(value).absolute()
+                }
+                SymbolType::Common { size, align } => SymbolBuilder::new(self.strtable_size).global(true).offset(size).common(align),
+                SymbolType::Defined { section, absolute_offset, global, weak, hidden, segment_relative_offset } => {
+                    SymbolBuilder::new(self.strtable_size).global(global).weak(weak).hidden(hidden)
                         .offset(absolute_offset)
                         .relative_offset(segment_relative_offset)
                         .section(section)
@@ -342,36 +491,52 @@ impl SymbolTable {
 #[derive(Debug)]
 /// A Mach-o program segment
 struct SegmentBuilder {
-    /// The sections that belong to this program segment; currently only 2 (text + data)
+    /// The sections that belong to this program segment: text, data, cstrings, the coalesced text and data,
+    /// read-only data, the compact unwind entries, the constructor and destructor pointers, the thread-local variable
+    /// descriptors and their initial values, and zero-initialized data, then that of thread-local variables
     pub sections: [SectionBuilder; SegmentBuilder::NSECTIONS],
     /// A stupid offset value I need to refactor out
     pub offset: u64,
     size: u64,
+    /// The padding before the compact unwind entries, which are pointer aligned
+    unwind_padding: u64,
+    /// The padding before the constructor and destructor pointers, which are pointer aligned
+    function_pointers_padding: u64,
+    /// The padding before the thread-local variable descriptors, which are pointer aligned
+    thread_local_padding: u64,
+    /// The size of the zero-initialized data, which follows the data in memory, but isn't in the file
+    zerofill_size: u64,
 }
 
 impl SegmentBuilder {
-    pub const NSECTIONS: usize = 3;
+    pub const NSECTIONS: usize = 13;
     /// The size of this segment's _data_, in bytes
     pub fn size(&self) -> u64 {
         self.size
     }
+    /// The size of this segment in memory, including its zero-initialized data, in bytes
+    pub fn vmsize(&self) -> u64 {
+        self.size + self.zerofill_size
+    }
     /// The size of this segment's _load command_, including its associated sections, in bytes
     pub fn load_command_size(ctx: &Ctx) -> u64 {
-        Segment::size_with(&ctx) as u64 + (Self::NSECTIONS as u64 * Section::size_with(&ctx) as u64)
+        Segment::size_with(ctx) as u64 + (Self::NSECTIONS as u64 * Section::size_with(ctx) as u64)
     }
     fn _section_data_file_offset(ctx: &Ctx) -> u64 {
         // section data
         Header::size_with(&ctx.container) as u64 + Self::load_command_size(ctx)
     }
     // FIXME: this is in desperate need of refactoring, obviously
-    fn build_section(symtab: &mut SymbolTable, sectname: &'static str, segname: &'static str, offset: &mut u64, addr: &mut u64, symbol_offset: &mut u64, section: SectionIndex, definitions: &[Definition], alignment_exponent: u64, flags: Option<u32>) -> SectionBuilder {
+    fn build_section(symtab: &mut SymbolTable, sectname: &'static str, segname: &str, offset: &mut u64, addr: &mut u64, symbol_offset: &mut u64, section: SectionIndex, definitions: &[Definition], alignment_exponent: u64, flags: Option<u32>) -> SectionBuilder {
         let mut local_size = 0;
         let mut segment_relative_offset = 0;
         for def in definitions {
-            local_size += def.data.len() as u64;
-            symtab.insert(def.name, SymbolType::Defined { section, segment_relative_offset, absolute_offset: *symbol_offset, global: def.prop.global });
-            *symbol_offset += def.data.len() as u64;
-            segment_relative_offset += def.data.len() as u64;
+            local_size += def.data.len();
+            // a global definition in a coalesced section may be a COMDAT group member, which is weak in its place
+            let weak = def.prop.weak || (def.prop.global && flags.is_some_and(|flags| flags & SECTION_TYPE == S_COALESCED));
+            symtab.insert(def.name, SymbolType::Defined { section, segment_relative_offset, absolute_offset: *symbol_offset, global: def.prop.global, weak, hidden: def.prop.hidden });
+            *symbol_offset += def.data.len();
+            segment_relative_offset += def.data.len();
         }
         let mut section = SectionBuilder::new(sectname, segname, local_size).offset(*offset).addr(*addr).align(alignment_exponent);
         if let Some(flags) = flags {
@@ -381,26 +546,152 @@ impl SegmentBuilder {
         *addr += local_size;
         section
     }
-    /// Create a new program segment from an `artifact`, symbol table, and context
-    // FIXME: this is pub(crate) for now because we can't leak pub(crate) Definition
-    pub(crate) fn new(artifact: &Artifact, code: &[Definition], data: &[Definition], cstrings: &[Definition], symtab: &mut SymbolTable, ctx: &Ctx) -> Self {
+    /// Lay out the zero-initialized `definitions` in the zerofill section `sectname`, from the first address after
+    /// `addr` which all of them are aligned to; the initial value of a thread-local variable has a local symbol
+    fn build_zerofill(symtab: &mut SymbolTable, sectname: &'static str, segname: &str, section: SectionIndex, definitions: &[&Definition], addr: u64, flags: u32) -> SectionBuilder {
+        let align = definitions.iter().filter_map(|def| def.prop.zero_init).max().unwrap_or(1);
+        let zerofill_addr = addr.next_multiple_of(align);
+        let mut zerofill_size = 0u64;
+        for def in definitions {
+            let align = def.prop.zero_init.expect("zero-initialized data is aligned");
+            let segment_relative_offset = zerofill_size.next_multiple_of(align);
+            let absolute_offset = zerofill_addr + segment_relative_offset;
+            if flags == S_THREAD_LOCAL_ZEROFILL {
+                symtab.insert(&tlv_init(def.name), SymbolType::Defined { section, segment_relative_offset, absolute_offset, global: false, weak: false, hidden: false });
+            } else {
+                symtab.insert(def.name, SymbolType::Defined { section, segment_relative_offset, absolute_offset, global: def.prop.global, weak: def.prop.weak, hidden: def.prop.hidden });
+            }
+            zerofill_size = segment_relative_offset + def.data.len();
+        }
+        SectionBuilder::new(sectname, segname, zerofill_size).addr(zerofill_addr).align(u64::from(align.trailing_zeros())).flags(flags)
+    }
+    /// Create a new program segment from an `artifact`, the sections of its `definitions`, symbol table, and context
+    fn new(artifact: &Artifact, definitions: &SectionDefinitions, nunwind: usize, ninit: usize, nterm: usize, symtab: &mut SymbolTable, ctx: &Ctx) -> Self {
         let mut offset = Header::size_with(&ctx.container) as u64;
         let mut size = 0;
         let mut symbol_offset = 0;
-        let text = Self::build_section(symtab, "__text", "__TEXT", &mut offset, &mut size, &mut symbol_offset, CODE_SECTION_INDEX, &code, 4, Some(S_ATTR_PURE_INSTRUCTIONS | S_ATTR_SOME_INSTRUCTIONS));
-        let data = Self::build_section(symtab, "__data", "__DATA", &mut offset, &mut size, &mut symbol_offset, DATA_SECTION_INDEX, &data, 3, None);
-        let cstrings = Self::build_section(symtab, "__cstring", "__TEXT", &mut offset, &mut size, &mut symbol_offset, CSTRING_SECTION_INDEX, &cstrings, 0, Some(S_CSTRING_LITERALS));
-        for (ref import, _) in artifact.imports() {
-            symtab.insert(import, SymbolType::Undefined);
+        // the segment each section is linked into, unless the artifact assigns it to another
+        let segment = |sectname, standard| artifact.macho_segment(sectname).unwrap_or(standard);
+        let text = Self::build_section(symtab, "__text", segment("__text", "__TEXT"), &mut offset, &mut size, &mut symbol_offset, CODE_SECTION_INDEX, &definitions.code, 4, Some(S_ATTR_PURE_INSTRUCTIONS | S_ATTR_SOME_INSTRUCTIONS));
+        let data = Self::build_section(symtab, "__data", segment("__data", "__DATA"), &mut offset, &mut size, &mut symbol_offset, DATA_SECTION_INDEX, &definitions.data, 3, None);
+        let cstrings = Self::build_section(symtab, "__cstring", segment("__cstring", "__TEXT"), &mut offset, &mut size, &mut symbol_offset, CSTRING_SECTION_INDEX, &definitions.cstrings, 0, Some(S_CSTRING_LITERALS));
+        // weak definitions are placed in coalesced sections, so the linker keeps only one of each
+        let weak_text = Self::build_section(symtab, "__textcoal_nt", segment("__textcoal_nt", "__TEXT"), &mut offset, &mut size, &mut symbol_offset, WEAK_CODE_SECTION_INDEX, &definitions.weak_code, 4, Some(S_COALESCED | S_ATTR_PURE_INSTRUCTIONS | S_ATTR_SOME_INSTRUCTIONS));
+        let weak_data = Self::build_section(symtab, "__datacoal_nt", segment("__datacoal_nt", "__DATA"), &mut offset, &mut size, &mut symbol_offset, WEAK_DATA_SECTION_INDEX, &definitions.weak_data, 3, Some(S_COALESCED));
+        // read-only data may hold pointers the dynamic linker fixes up, so by default it is in __DATA, as clang does
+        let consts = Self::build_section(symtab, "__const", segment("__const", "__DATA"), &mut offset, &mut size, &mut symbol_offset, CONST_SECTION_INDEX, &definitions.consts, 3, None);
+        // ld64 builds the image's __TEXT,__unwind_info from these, and doesn't keep the section itself
+        let unwind_padding = if nunwind == 0 { 0 } else { (8 - size % 8) % 8 };
+        offset += unwind_padding;
+        size += unwind_padding;
+        let unwind_size = nunwind as u64 * COMPACT_UNWIND_ENTRY_SIZE;
+        let unwind = SectionBuilder::new("__compact_unwind", segment("__compact_unwind", "__LD"), unwind_size)
+            .offset(offset).addr(size).align(3).flags(S_REGULAR | S_ATTR_DEBUG);
+        offset += unwind_size;
+        size += unwind_size;
+        // dyld calls the constructors in order, and the destructors in reverse
+        let pointer_size = ctx.size() as u64;
+        let function_pointers_padding = if ninit + nterm == 0 { 0 } else { (pointer_size - size % pointer_size) % pointer_size };
+        offset += function_pointers_padding;
+        size += function_pointers_padding;
+        let mut function_pointers = |sectname, flags, n| {
+            let section_size = n as u64 * pointer_size;
+            let section = SectionBuilder::new(sectname, segment(sectname, "__DATA"), section_size)
+                .offset(offset).addr(size).align(u64::from(pointer_size.trailing_zeros())).flags(flags);
+            offset += section_size;
+            size += section_size;
+            section
+        };
+        let init = function_pointers("__mod_init_func", S_MOD_INIT_FUNC_POINTERS, ninit);
+        let term = function_pointers("__mod_term_func", S_MOD_TERM_FUNC_POINTERS, nterm);
+        // a thread-local variable's symbol is its descriptor, of the thunk dyld binds, a key, and its initial value
+        let nthread_locals = definitions.thread_data.len() + definitions.thread_bss.len();
+        let thread_local_padding = if nthread_locals == 0 { 0 } else { (pointer_size - size % pointer_size) % pointer_size };
+        offset += thread_local_padding;
+        size += thread_local_padding;
+        let descriptor_size = 3 * pointer_size;
+        for (i, def) in definitions.thread_data.iter().chain(&definitions.thread_bss).enumerate() {
+            let segment_relative_offset = i as u64 * descriptor_size;
+            symtab.insert(def.name, SymbolType::Defined {
+                section: THREAD_VARS_SECTION_INDEX,
+                segment_relative_offset,
+                absolute_offset: size + segment_relative_offset,
+                global: def.prop.global,
+                weak: false,
+                hidden: def.prop.hidden,
+            });
+        }
+        if nthread_locals != 0 {
+            symtab.insert(TLV_BOOTSTRAP, SymbolType::Undefined { weak: false });
+        }
+        let thread_vars_size = nthread_locals as u64 * descriptor_size;
+        let thread_vars = SectionBuilder::new("__thread_vars", segment("__thread_vars", "__DATA"), thread_vars_size)
+            .offset(offset).addr(size).align(u64::from(pointer_size.trailing_zeros())).flags(S_THREAD_LOCAL_VARIABLES);
+        offset += thread_vars_size;
+        size += thread_vars_size;
+        let mut thread_data_size = 0;
+        for def in &definitions.thread_data {
+            symtab.insert(&tlv_init(def.name), SymbolType::Defined {
+                section: THREAD_DATA_SECTION_INDEX,
+                segment_relative_offset: thread_data_size,
+                absolute_offset: size + thread_data_size,
+                global: false,
+                weak: false,
+                hidden: false,
+            });
+            thread_data_size += def.data.len();
+        }
+        let thread_data = SectionBuilder::new("__thread_data", segment("__thread_data", "__DATA"), thread_data_size)
+            .offset(offset).addr(size).align(u64::from(pointer_size.trailing_zeros())).flags(S_THREAD_LOCAL_REGULAR);
+        offset += thread_data_size;
+        size += thread_data_size;
+        // zero-initialized data is last, as it takes up no space in the file, except common data, which the linker allocates
+        for def in definitions.zerofill.iter().filter(|def| def.prop.common) {
+            let align = def.prop.zero_init.expect("common data is aligned");
+            symtab.insert(def.name, SymbolType::Common { size: def.data.len(), align });
+        }
+        let zerofill = definitions.zerofill.iter().filter(|def| !def.prop.common).collect::<Vec<_>>();
+        let bss = Self::build_zerofill(symtab, "__bss", segment("__bss", "__DATA"), BSS_SECTION_INDEX, &zerofill, size, S_ZEROFILL);
+        let thread_bss = definitions.thread_bss.iter().collect::<Vec<_>>();
+        let thread_bss = Self::build_zerofill(symtab, "__thread_bss", segment("__thread_bss", "__DATA"), THREAD_BSS_SECTION_INDEX, &thread_bss, bss.addr + bss.size, S_THREAD_LOCAL_ZEROFILL);
+        let zerofill_size = thread_bss.addr + thread_bss.size - size;
+        for (import, _) in artifact.imports() {
+            symtab.insert(import, SymbolType::Undefined { weak: artifact.is_weak_import(import) });
+        }
+        for abs in artifact.absolutes() {
+            if let Decl::Absolute(abs_decl) = *abs.decl {
+                symtab.insert(abs.name, SymbolType::Absolute {
+                    value: abs_decl.value(),
+                    global: abs_decl.is_global(),
+                    hidden: abs_decl.is_hidden(),
+                });
+            }
+        }
+        for (alias, target) in artifact.aliases() {
+            let (global, weak) = match *alias.decl {
+                Decl::Function(f) => (f.scope() != Scope::Local, f.is_weak()),
+                Decl::Data(d) => (d.scope() != Scope::Local, d.is_weak()),
+                Decl::CString(c) => (c.is_global(), false),
+                _ => unreachable!("only definitions have aliases"),
+            };
+            let hidden = artifact.alias_visibility(alias.name) == Visibility::Hidden;
+            symtab.alias(alias.name, target.name, global, weak, hidden);
+        }
+        for name in artifact.no_dead_strip() {
+            symtab.no_dead_strip(name);
         }
         // FIXME re add assert
         //assert_eq!(offset, Header::size_with(&ctx.container) + Self::load_command_size(ctx));
         debug!("Segment Size: {} Symtable LoadCommand Offset: {}", size, offset);
-        let sections = [text, data, cstrings];
+        let sections = [text, data, cstrings, weak_text, weak_data, consts, unwind, init, term, thread_vars, thread_data, bss, thread_bss];
         SegmentBuilder {
             size,
             sections,
             offset,
+            unwind_padding,
+            function_pointers_padding,
+            thread_local_padding,
+            zerofill_size,
         }
     }
 }
@@ -416,29 +707,69 @@ struct Mach<'a> {
     code: ArtifactCode<'a>,
     data: ArtifactData<'a>,
     cstrings: Vec<Definition<'a>>,
+    weak_code: Vec<Definition<'a>>,
+    weak_data: Vec<Definition<'a>>,
+    consts: Vec<Definition<'a>>,
+    /// The initial values of the thread-local variables which are not zero-initialized
+    thread_data: Vec<Definition<'a>>,
+    /// The length and compact unwind encoding of each function which has one
+    unwind: Vec<(u64, u32)>,
+    /// The number of constructors and destructors, whose pointers are relocated
+    function_pointers: usize,
+    /// The load commands which follow the symbol table's: the `LC_UUID` of the build id, if there is one, then the
+    /// `LC_LINKER_OPTION` of each linker directive
+    load_commands: Vec<Vec<u8>>,
+    addends: Addends<'a>,
     _p: ::std::marker::PhantomData<&'a ()>,
 }
 
 impl<'a> Mach<'a> {
-    pub fn new(artifact: &'a Artifact) -> Self {
+    pub fn new(artifact: &'a Artifact) -> Result<Self, Error> {
         let ctx = make_ctx(&artifact.target);
         // FIXME: I believe we can avoid this partition by refactoring SegmentBuilder::new
-        let (mut code, mut data, mut cstrings) = (Vec::new(), Vec::new(), Vec::new());
+        let mut definitions = SectionDefinitions::default();
+        // Mach-O has no groups, but the linker also keeps one of the weak definitions of a name
+        let grouped = artifact.comdats().map(|(member, _, _)| member).collect::<HashSet<_>>();
         for def in artifact.definitions() {
-            if def.prop.function {
-                code.push(def);
-            } else if def.prop.cstring {
-                cstrings.push(def)
+            if def.prop.tls {
+                if def.prop.zero_init.is_some() { definitions.thread_bss.push(def) } else { definitions.thread_data.push(def) }
+            } else if def.prop.zero_init.is_some() {
+                definitions.zerofill.push(def);
+            } else if def.prop.weak || (def.prop.global && grouped.contains(def.name)) {
+                if def.prop.function { definitions.weak_code.push(def) } else { definitions.weak_data.push(def) }
+            } else if def.prop.function {
+                definitions.code.push(def);
+            // the linker merges identical literals in __cstring, so strings which are kept intact are constants
+            } else if def.prop.cstring && !def.prop.no_merge {
+                definitions.cstrings.push(def)
+            } else if def.prop.writable {
+                definitions.data.push(def);
             } else {
-                data.push(def);
+                definitions.consts.push(def);
             }
         }
 
+        let lengths = artifact.definitions().map(|def| (def.name, def.data.len())).collect::<HashMap<_, _>>();
+        let unwind = artifact.compact_unwind().map(|(function, encoding)| (lengths[function.name], encoding)).collect::<Vec<_>>();
+
         let mut symtab = SymbolTable::new();
-        let segment = SegmentBuilder::new(&artifact, &code, &data, &cstrings, &mut symtab, &ctx);
-        let relocations = build_relocations(&artifact, &symtab);
+        let (init, term) = (function_array(artifact.constructors()), function_array(artifact.destructors()));
+        let segment = SegmentBuilder::new(artifact, &definitions, unwind.len(), init.len(), term.len(), &mut symtab, &ctx);
+        let thread_locals = definitions.thread_data.iter().chain(&definitions.thread_bss).map(|def| def.name).collect::<Vec<_>>();
+        let mut addends = HashMap::new();
+        let mut relocations = build_relocations(artifact, &symtab, &init, &term, &thread_locals, &mut addends)?;
+        let SectionDefinitions { code, data, cstrings, weak_code, weak_data, consts, thread_data, .. } = definitions;
+        build_differences(artifact, &symtab, &mut relocations, &mut addends)?;
+
+        let mut load_commands = Vec::new();
+        if let Some(id) = artifact.gnu_buildid()? {
+            load_commands.push(uuid(&id, &ctx)?);
+        }
+        for directive in artifact.directives() {
+            load_commands.push(linker_option(directive, &ctx)?);
+        }
 
-        Mach {
+        Ok(Mach {
             ctx,
             architecture: artifact.target.architecture,
             symtab,
@@ -448,16 +779,25 @@ impl<'a> Mach<'a> {
             code,
             data,
             cstrings,
-        }
+            weak_code,
+            weak_data,
+            consts,
+            thread_data,
+            unwind,
+            function_pointers: init.len() + term.len(),
+            load_commands,
+            addends,
+        })
     }
     fn header(&self, sizeofcmds: u64) -> Header {
         let mut header = Header::new(&self.ctx);
         header.filetype = MH_OBJECT;
         // safe to divide up the sections into sub-sections via symbols for dead code stripping
         header.flags = MH_SUBSECTIONS_VIA_SYMBOLS;
-        header.cputype = CpuType::from(self.architecture).0;
-        header.cpusubtype = 3;
-        header.ncmds = 2;
+        let cpu = CpuType::new(self.architecture).expect("the cpu type is checked before the object is built");
+        header.cputype = cpu.0;
+        header.cpusubtype = cpu.1;
+        header.ncmds = 2 + self.load_commands.len();
         header.sizeofcmds = sizeofcmds as u32;
         header
     }
@@ -467,7 +807,8 @@ impl<'a> Mach<'a> {
         // construct symtab command
         let mut symtab_load_command = SymtabCommand::new();
         let segment_load_command_size = SegmentBuilder::load_command_size(&self.ctx);
-        let sizeof_load_commands = segment_load_command_size + symtab_load_command.cmdsize as u64;
+        let load_commands_size = self.load_commands.iter().map(|command| command.len() as u64).sum::<u64>();
+        let sizeof_load_commands = segment_load_command_size + symtab_load_command.cmdsize as u64 + load_commands_size;
         let symtable_offset = self.segment.offset + sizeof_load_commands;
         let strtable_offset = symtable_offset + (self.symtab.len() as u64 * Nlist::size_with(&self.ctx) as u64);
         let relocation_offset_start = strtable_offset + self.symtab.sizeof_strtable();
@@ -479,11 +820,10 @@ impl<'a> Mach<'a> {
         // marshall the sections into something we can actually write
         let mut raw_sections = Cursor::new(Vec::<u8>::new());
         let mut relocation_offset = relocation_offset_start;
-        let mut section_offset = first_section_offset;
-        for (idx, section) in self.segment.sections.into_iter().cloned().enumerate() {
+        for (idx, section) in self.segment.sections.iter().cloned().enumerate() {
             let mut section: Section = section.create();
-            section.offset = section_offset as u32;
-            section_offset += section.size;
+            // the contents are laid out as they are in memory, except zero-initialized data, which has none
+            section.offset = if section.flags == S_ZEROFILL || section.flags == S_THREAD_LOCAL_ZEROFILL { 0 } else { (first_section_offset + section.addr) as u32 };
             debug!("{}: Setting nrelocs", idx);
             // relocations are tied to segment/sections
             // TODO: move this also into SegmentBuilder
@@ -505,12 +845,12 @@ impl<'a> Mach<'a> {
         segment_load_command.initprot = 7;
         segment_load_command.maxprot = 7;
         segment_load_command.filesize = self.segment.size();
-        segment_load_command.vmsize = segment_load_command.filesize;
+        segment_load_command.vmsize = self.segment.vmsize();
         segment_load_command.fileoff = first_section_offset;
         debug!("Segment: {:#?}", segment_load_command);
 
         debug!("Symtable Offset: {:#?}", symtable_offset);
-        assert_eq!(symtable_offset, self.segment.offset + segment_load_command.cmdsize as u64 + symtab_load_command.cmdsize as u64);
+        assert_eq!(symtable_offset, self.segment.offset + segment_load_command.cmdsize as u64 + symtab_load_command.cmdsize as u64 + load_commands_size);
         symtab_load_command.nsyms = self.symtab.len() as u32;
         symtab_load_command.symoff = symtable_offset as u32;
         symtab_load_command.stroff = strtable_offset as u32;
@@ -522,7 +862,7 @@ impl<'a> Mach<'a> {
         // write header
         //////////////////////////////
         file.iowrite_with(header, self.ctx)?;
-        debug!("SEEK: after header: {}", file.seek(Current(0))?);
+        debug!("SEEK: after header: {}", file.stream_position()?);
 
         //////////////////////////////
         // write load commands
@@ -530,31 +870,84 @@ impl<'a> Mach<'a> {
         file.iowrite_with(segment_load_command, self.ctx)?;
         file.write_all(&raw_sections)?;
         file.iowrite_with(symtab_load_command, self.ctx.le)?;
-        debug!("SEEK: after load commands: {}", file.seek(Current(0))?);
+        for command in &self.load_commands {
+            file.write_all(command)?;
+        }
+        debug!("SEEK: after load commands: {}", file.stream_position()?);
 
         //////////////////////////////
         // write code
         //////////////////////////////
         for code in self.code {
-            file.write_all(code.data)?;
+            write_definition(&code, &self.addends, &mut file)?;
         }
-        debug!("SEEK: after code: {}", file.seek(Current(0))?);
+        debug!("SEEK: after code: {}", file.stream_position()?);
 
         //////////////////////////////
         // write data
         //////////////////////////////
         for data in self.data {
-            file.write_all(data.data)?;
+            write_definition(&data, &self.addends, &mut file)?;
         }
-        debug!("SEEK: after data: {}", file.seek(Current(0))?);
+        debug!("SEEK: after data: {}", file.stream_position()?);
 
         //////////////////////////////
         // write cstrings
         //////////////////////////////
         for cstring in self.cstrings {
-            file.write_all(cstring.data)?;
+            write_definition(&cstring, &self.addends, &mut file)?;
         }
-        debug!("SEEK: after cstrings: {}", file.seek(Current(0))?);
+        debug!("SEEK: after cstrings: {}", file.stream_position()?);
+
+        //////////////////////////////
+        // write weak code and data
+        //////////////////////////////
+        for def in self.weak_code.into_iter().chain(self.weak_data) {
+            write_definition(&def, &self.addends, &mut file)?;
+        }
+        debug!("SEEK: after weak definitions: {}", file.stream_position()?);
+
+        //////////////////////////////
+        // write read-only data
+        //////////////////////////////
+        for def in self.consts {
+            write_definition(&def, &self.addends, &mut file)?;
+        }
+        debug!("SEEK: after read-only data: {}", file.stream_position()?);
+
+        //////////////////////////////
+        // write compact unwind entries
+        //////////////////////////////
+        file.write_all(&vec![0; self.segment.unwind_padding as usize])?;
+        for (length, encoding) in self.unwind {
+            // the function's address is relocated
+            file.iowrite_with(0u64, self.ctx.le)?;
+            file.iowrite_with(length as u32, self.ctx.le)?;
+            file.iowrite_with(encoding, self.ctx.le)?;
+            // no personality function or language specific data
+            file.iowrite_with(0u64, self.ctx.le)?;
+            file.iowrite_with(0u64, self.ctx.le)?;
+        }
+        debug!("SEEK: after compact unwind: {}", file.stream_position()?);
+
+        //////////////////////////////
+        // write constructors and destructors
+        //////////////////////////////
+        // the pointers are relocated
+        let function_pointers_size = self.segment.function_pointers_padding as usize + self.function_pointers * self.ctx.size();
+        file.write_all(&vec![0; function_pointers_size])?;
+        debug!("SEEK: after constructors and destructors: {}", file.stream_position()?);
+
+        //////////////////////////////
+        // write thread-local variables
+        //////////////////////////////
+        // the thunk and initial value of each descriptor are relocated, and its key is zero
+        let thread_vars_size = self.segment.thread_local_padding + self.segment.sections[THREAD_VARS_SECTION_INDEX].size;
+        file.write_all(&vec![0; thread_vars_size as usize])?;
+        for def in self.thread_data {
+            write_definition(&def, &self.addends, &mut file)?;
+        }
+        debug!("SEEK: after thread-local variables: {}", file.stream_position()?);
 
         //////////////////////////////
         // write symtable
@@ -564,7 +957,7 @@ impl<'a> Mach<'a> {
             debug!("{}: {:?}", idx, symbol);
             file.iowrite_with(symbol, self.ctx)?;
         }
-        debug!("SEEK: after symtable: {}", file.seek(Current(0))?);
+        debug!("SEEK: after symtable: {}", file.stream_position()?);
 
         //////////////////////////////
         // write strtable
@@ -578,7 +971,7 @@ impl<'a> Mach<'a> {
             file.write_all(string.as_bytes())?;
             file.iowrite(0u8)?;
         }
-        debug!("SEEK: after strtable: {}", file.seek(Current(0))?);
+        debug!("SEEK: after strtable: {}", file.stream_position()?);
 
         //////////////////////////////
         // write relocations
@@ -590,7 +983,7 @@ impl<'a> Mach<'a> {
                 file.iowrite_with(reloc, self.ctx.le)?;
             }
         }
-        debug!("SEEK: after relocations: {}", file.seek(Current(0))?);
+        debug!("SEEK: after relocations: {}", file.stream_position()?);
 
         file.iowrite(0u8)?;
 
@@ -598,51 +991,415 @@ impl<'a> Mach<'a> {
     }
 }
 
+/// The `LC_UUID` load command of the build id `id`, whose first 16 bytes are the UUID, padded with zeros if it is
+/// shorter
+fn uuid(id: &[u8], ctx: &Ctx) -> Result<Vec<u8>, Error> {
+    let mut command = vec![0; 24];
+    command.pwrite_with(LC_UUID, 0, ctx.le)?;
+    command.pwrite_with(24u32, 4, ctx.le)?;
+    let len = id.len().min(16);
+    command[8..8 + len].copy_from_slice(&id[..len]);
+    Ok(command)
+}
+
+/// The `LC_LINKER_OPTION` load command of `directive`: its strings, null terminated and padded to the size of a
+/// pointer, follow the command and its count of strings; a library is linked with as `-l<name>`
+fn linker_option(directive: &LinkerDirective, ctx: &Ctx) -> Result<Vec<u8>, Error> {
+    let strings = match *directive {
+        LinkerDirective::Library(ref name) => vec![format!("-l{}", name)],
+        LinkerDirective::Option(ref name, ref value) => vec![name.clone(), value.clone()],
+    };
+    let mut command = vec![0; 12];
+    for string in &strings {
+        command.extend_from_slice(string.as_bytes());
+        command.push(0);
+    }
+    let size = command.len().next_multiple_of(ctx.size());
+    command.resize(size, 0);
+    let cmdsize = command.len() as u32;
+    command.pwrite_with(LC_LINKER_OPTION, 0, ctx.le)?;
+    command.pwrite_with(cmdsize, 4, ctx.le)?;
+    command.pwrite_with(strings.len() as u32, 8, ctx.le)?;
+    Ok(command)
+}
+
 // FIXME: this should actually return a runtime error if we encounter a from.decl to.decl pair which we don't explicitly match on
-fn build_relocations(artifact: &Artifact, symtab: &SymbolTable) -> Relocations {
-    use goblin::mach::relocation::{X86_64_RELOC_BRANCH, X86_64_RELOC_SIGNED, X86_64_RELOC_UNSIGNED, X86_64_RELOC_GOT_LOAD};
-    let mut text_relocations = Vec::new();
-    let mut data_relocations = Vec::new();
+fn build_relocations<'a>(artifact: &'a Artifact, symtab: &SymbolTable, init: &[Binding], term: &[Binding], thread_locals: &[&str], addends: &mut Addends<'a>) -> Result<Relocations, Error> {
+    use goblin::mach::relocation::{X86_64_RELOC_BRANCH, X86_64_RELOC_SIGNED, X86_64_RELOC_UNSIGNED, X86_64_RELOC_GOT_LOAD, X86_64_RELOC_TLV};
+    let mut relocations = vec![Vec::new(); SegmentBuilder::NSECTIONS];
     debug!("Generating relocations");
     for link in artifact.links() {
         debug!("Import links for: from {} to {} at {:#x} with {:?}", link.from.name, link.to.name, link.at, link.to.decl);
+        if artifact.target.architecture == Architecture::Aarch64 {
+            for (offset, reloc, absolute) in arm64_relocations(&link)? {
+                let mut builder = relocation(symtab, &link, offset, reloc)?;
+                if absolute {
+                    builder = builder.absolute();
+                    // only a pointer is 8 bytes, the others are instructions
+                    if reloc != ARM64_RELOC_UNSIGNED { builder = builder.four_bytes(); }
+                }
+                let section = symtab.section(link.from.name).expect("relocation has a section");
+                relocations[section].push(builder.create());
+            }
+            continue;
+        }
+        if is_i386(artifact.target.architecture) {
+            let section = symtab.section(link.from.name).expect("relocation has a section");
+            match i386_relocation(&link) {
+                // the field holds the target's offset from the field when the symbol's address is zero, which the
+                // linker adds the address to
+                Some(addend) => {
+                    let address = symtab.address(link.from.name).expect("relocation has a section") + link.at;
+                    addends.entry(link.from.name).or_default().push((link.at, (addend - address as i64) as i32));
+                    relocations[section].push(relocation(symtab, &link, 0, GENERIC_RELOC_VANILLA)?.create());
+                },
+                None => relocations[section].push(relocation(symtab, &link, 0, GENERIC_RELOC_VANILLA)?.absolute().four_bytes().create()),
+            }
+            continue;
+        }
         let (absolute, reloc) = match (link.from.decl, link.to.decl) {
+            // a branch refers to the function's symbol, which the linker routes through a stub if it must
+            _ if link.reloc.is_plt() => (false, X86_64_RELOC_BRANCH),
+            // the `movq _sym@TLVP(%rip), %rdi` of the address of the variable's descriptor
+            _ if link.reloc.is_tls() => (false, X86_64_RELOC_TLV),
             // NB: we currenetly deduce the meaning of our relocation from from decls -> to decl relocations
             // e.g., global static data references, are constructed from Data -> Data links
             // various static function pointers in the .data section
-            (&Decl::Data {..}, &Decl::Function {..}) => (true, X86_64_RELOC_UNSIGNED),
-            (&Decl::Data {..}, &Decl::FunctionImport {..}) => (true, X86_64_RELOC_UNSIGNED),
+            (&Decl::Data(_), &Decl::Function(_)) => (true, X86_64_RELOC_UNSIGNED),
+            (&Decl::Data(_), &Decl::FunctionImport) => (true, X86_64_RELOC_UNSIGNED),
             // anything else is just a regular relocation/callq
-            (_, &Decl::Function {..}) => (false, X86_64_RELOC_BRANCH),
+            (_, &Decl::Function(_)) => (false, X86_64_RELOC_BRANCH),
             // we are a relocation in the data section to another object in the data section, e.g., a static reference
-            (&Decl::Data {..}, &Decl::Data {..}) => (true, X86_64_RELOC_UNSIGNED),
-            (_, &Decl::Data {..}) => (false, X86_64_RELOC_SIGNED),
+            (&Decl::Data(_), &Decl::Data(_)) => (true, X86_64_RELOC_UNSIGNED),
+            (_, &Decl::Data(_)) => (false, X86_64_RELOC_SIGNED),
             // TODO: we will also need to specify relocations from Data to Cstrings, e.g., char * STR = "a global static string";
-            (_, &Decl::CString {..}) => (false, X86_64_RELOC_SIGNED),
+            (_, &Decl::CString(_)) => (false, X86_64_RELOC_SIGNED),
             (_, &Decl::FunctionImport) => (false, X86_64_RELOC_BRANCH),
             (_, &Decl::DataImport) => (false, X86_64_RELOC_GOT_LOAD),
+            // the linker fills in the fixed value of the symbol directly
+            (_, &Decl::Absolute(_)) => (true, X86_64_RELOC_UNSIGNED),
+            (_, &Decl::DebugSection(_)) | (_, &Decl::Section(_)) => (true, X86_64_RELOC_UNSIGNED),
         };
-        match (symtab.offset(link.from.name), symtab.index(link.to.name)) {
-            (Some(base_offset), Some(to_symbol_index)) => {
-                debug!("{} offset: {}", link.to.name, base_offset + link.at);
-                let builder = RelocationBuilder::new(to_symbol_index, base_offset + link.at, reloc);
-                // NB: we currently associate absolute relocations with data relocations; this may prove
-                // too fragile for future additions; needs analysis
-                if absolute {
-                    data_relocations.push(builder.absolute().create());
-                } else {
-                    text_relocations.push(builder.create());
-                }
+        let mut builder = relocation(symtab, &link, 0, reloc)?;
+        if absolute {
+            builder = builder.absolute();
+        }
+        // relocations belong to the section of the symbol they are relative to
+        let section = symtab.section(link.from.name).expect("relocation has a section");
+        relocations[section].push(builder.create());
+    }
+    for (i, (function, _)) in artifact.compact_unwind().enumerate() {
+        match symtab.index(function.name) {
+            Some(symbol_index) => {
+                let builder = RelocationBuilder::new(symbol_index, i as u64 * COMPACT_UNWIND_ENTRY_SIZE, X86_64_RELOC_UNSIGNED);
+                relocations[UNWIND_SECTION_INDEX].push(builder.absolute().create());
             },
-            _ => error!("Import Relocation from {} to {} at {:#x} has a missing symbol. Dumping symtab {:?}", link.from.name, link.to.name, link.at, symtab)
+            None => return Err(format_err!("Compact unwind entry for {} has a missing symbol", function.name)),
         }
     }
-    vec![text_relocations, data_relocations]
+    // the pointers are absolute, which is relocation type 0 on every architecture
+    let pointer_size = make_ctx(&artifact.target).size() as u64;
+    for &(section, functions) in &[(INIT_SECTION_INDEX, init), (TERM_SECTION_INDEX, term)] {
+        for (i, function) in functions.iter().enumerate() {
+            match symtab.index(function.name) {
+                Some(symbol_index) => {
+                    let builder = RelocationBuilder::new(symbol_index, i as u64 * pointer_size, X86_64_RELOC_UNSIGNED).absolute();
+                    relocations[section].push(if pointer_size == 4 { builder.four_bytes() } else { builder }.create());
+                },
+                None => return Err(format_err!("Constructor or destructor {} has a missing symbol", function.name)),
+            }
+        }
+    }
+    let descriptor_size = 3 * pointer_size;
+    for (i, name) in thread_locals.iter().enumerate() {
+        // the thunk is the first pointer of the descriptor, and the initial value the third
+        for &(offset, ref symbol) in &[(0, TLV_BOOTSTRAP.to_string()), (2 * pointer_size, tlv_init(name))] {
+            match symtab.index(symbol) {
+                Some(symbol_index) => {
+                    let builder = RelocationBuilder::new(symbol_index, i as u64 * descriptor_size + offset, X86_64_RELOC_UNSIGNED).absolute();
+                    relocations[THREAD_VARS_SECTION_INDEX].push(if pointer_size == 4 { builder.four_bytes() } else { builder }.create());
+                },
+                None => return Err(format_err!("Thread-local variable {} has a missing symbol {}", name, symbol)),
+            }
+        }
+    }
+    Ok(relocations)
 }
 
-pub fn to_bytes(artifact: &Artifact) -> Result<Vec<u8>, Error> {
-    let mach = Mach::new(&artifact);
-    let mut buffer = Cursor::new(Vec::new());
-    mach.write(&mut buffer)?;
-    Ok(buffer.into_inner())
+/// The functions of a constructor or destructor array, in the order they are laid out: those with a priority first, in
+/// increasing order of priority, then those without, and otherwise in the order they were added
+fn function_array<'a, I: Iterator<Item = (Binding<'a>, Option<u16>)>>(functions: I) -> Vec<Binding<'a>> {
+    let mut functions = functions.collect::<Vec<_>>();
+    functions.sort_by_key(|&(_, priority)| (priority.is_none(), priority));
+    functions.into_iter().map(|(function, _)| function).collect()
+}
+
+/// The symbol of the initial value of the thread-local variable `name`, which its descriptor refers to
+fn tlv_init(name: &str) -> String {
+    format!("{}$tlv$init", name)
+}
+
+/// Begin the relocation `reloc` of the field `offset` bytes after the field of `link`
+fn relocation(symtab: &SymbolTable, link: &LinkAndDecl, offset: u64, reloc: RelocType) -> Result<RelocationBuilder, Error> {
+    match (symtab.offset(link.from.name), symtab.section(link.from.name), symtab.index(link.to.name)) {
+        (Some(base_offset), Some(_), Some(to_symbol_index)) => {
+            debug!("{} offset: {}", link.to.name, base_offset + link.at + offset);
+            Ok(RelocationBuilder::new(to_symbol_index, base_offset + link.at + offset, reloc))
+        },
+        _ => Err(format_err!("Relocation from {} to {} at {:#x} has a missing symbol", link.from.name, link.to.name, link.at)),
+    }
+}
+
+/// The ARM64 relocations of `link`, each with its offset from the field, and whether it is absolute. An address is
+/// the page of an `adrp` at the field, and the offset into the page of the `add` or `ldr` which follows it, which
+/// are relocated in pairs; through the GOT for an import.
+fn arm64_relocations(link: &LinkAndDecl) -> Result<Vec<(u64, RelocType, bool)>, Error> {
+    let unsupported = || Error::from(ArtifactError::unsupported_relocation(link, "aarch64 Mach-O".to_string()));
+    match link.reloc {
+        Reloc::Auto | Reloc::Plt { addend: 0 } => (),
+        // the `adrp` and `ldr` of the address of the variable's descriptor
+        Reloc::Tls { .. } if matches!(*link.from.decl, Decl::Function(_)) => {
+            return Ok(vec![(0, ARM64_RELOC_TLVP_LOAD_PAGE21, false), (4, ARM64_RELOC_TLVP_LOAD_PAGEOFF12, true)]);
+        },
+        _ => return Err(unsupported()),
+    }
+    Ok(match (link.from.decl, link.to.decl) {
+        // the linker routes a call through a stub if it must
+        (&Decl::Function(_), &Decl::Function(_)) | (&Decl::Function(_), &Decl::FunctionImport) => vec![(0, ARM64_RELOC_BRANCH26, false)],
+        _ if link.reloc.is_plt() => return Err(unsupported()),
+        (&Decl::Function(_), &Decl::Data(_)) | (&Decl::Function(_), &Decl::CString(_)) => {
+            vec![(0, ARM64_RELOC_PAGE21, false), (4, ARM64_RELOC_PAGEOFF12, true)]
+        },
+        (&Decl::Function(_), &Decl::DataImport) => vec![(0, ARM64_RELOC_GOT_LOAD_PAGE21, false), (4, ARM64_RELOC_GOT_LOAD_PAGEOFF12, true)],
+        // a pointer, which the dynamic linker binds for an import
+        (&Decl::Data(_), _) => vec![(0, ARM64_RELOC_UNSIGNED, true)],
+        _ => return Err(unsupported()),
+    })
+}
+
+/// The addend of the i386 relocation of `link`, if it is relative to the field, as it would be in ELF: only a call
+/// is, since i386 has no PC relative addressing of data. Anything else is a 4 byte absolute address.
+fn i386_relocation(link: &LinkAndDecl) -> Option<i64> {
+    match (link.reloc, link.from.decl, link.to.decl) {
+        (Reloc::Plt { addend }, _, _) => Some(i64::from(addend)),
+        (Reloc::Auto, &Decl::Function(_), &Decl::Function(_)) | (Reloc::Auto, &Decl::Function(_), &Decl::FunctionImport) => Some(-4),
+        _ => None,
+    }
+}
+
+/// Is `architecture` 32-bit x86, whose relocations are the generic ones?
+fn is_i386(architecture: Architecture) -> bool {
+    matches!(architecture, Architecture::I386 | Architecture::I586 | Architecture::I686)
+}
+
+/// Add the relocation pair of each symbol difference to `relocations`, and the value of each field, which holds
+/// the difference's addend, to `addends`
+fn build_differences<'a>(artifact: &'a Artifact, symtab: &SymbolTable, relocations: &mut Relocations, addends: &mut Addends<'a>) -> Result<(), Error> {
+    use goblin::mach::relocation::{GENERIC_RELOC_SECTDIFF, GENERIC_RELOC_PAIR, X86_64_RELOC_SUBTRACTOR, X86_64_RELOC_UNSIGNED};
+    for difference in artifact.differences() {
+        debug!("Difference in {} at {:#x}: {} - {}", difference.from.name, difference.at, difference.to.name, difference.minus.name);
+        let (from, minus, to) = (difference.from.name, difference.minus.name, difference.to.name);
+        let (base_offset, section) = match (symtab.offset(from), symtab.section(from)) {
+            (Some(base_offset), Some(section)) => (base_offset, section),
+            _ => return Err(format_err!("Difference in {} has a missing symbol", from)),
+        };
+        let offset = base_offset + difference.at;
+        if artifact.target.architecture == Architecture::X86_64 {
+            // the linker subtracts the first symbol from the second, and adds the field
+            match (symtab.index(minus), symtab.index(to)) {
+                (Some(minus), Some(to)) => {
+                    relocations[section].push(RelocationBuilder::new(minus, offset, X86_64_RELOC_SUBTRACTOR).absolute().four_bytes().create());
+                    relocations[section].push(RelocationBuilder::new(to, offset, X86_64_RELOC_UNSIGNED).absolute().four_bytes().create());
+                },
+                _ => return Err(format_err!("Difference of {} and {} has a missing symbol", to, minus)),
+            }
+            addends.entry(from).or_default().push((difference.at, 0));
+        } else {
+            // the field holds the difference of the two addresses, which the linker adjusts as they move
+            let (to_address, minus_address) = match (symtab.address(to), symtab.address(minus)) {
+                (Some(to_address), Some(minus_address)) => (to_address, minus_address),
+                _ => return Err(format_err!("Difference of {} and {} has a missing symbol", to, minus)),
+            };
+            if offset >= 1 << 24 {
+                return Err(format_err!("Difference at {:#x} in {} is beyond the reach of a scattered relocation", difference.at, from));
+            }
+            let r_type = if is_global(difference.to.decl) { GENERIC_RELOC_SECTDIFF } else { GENERIC_RELOC_LOCAL_SECTDIFF };
+            relocations[section].push(scattered(offset, r_type, to_address));
+            relocations[section].push(scattered(0, GENERIC_RELOC_PAIR, minus_address));
+            addends.entry(from).or_default().push((difference.at, to_address.wrapping_sub(minus_address) as i32));
+        }
+    }
+    Ok(())
+}
+
+/// Write the contents of `def` to `sink`, with the fields in `addends` filled in
+fn write_definition<W: Write>(def: &Definition, addends: &Addends, sink: &mut W) -> Result<(), Error> {
+    match addends.get(def.name) {
+        Some(fields) => {
+            let mut bytes = Vec::with_capacity(def.data.len() as usize);
+            def.data.write_to(&mut bytes)?;
+            for &(at, value) in fields {
+                bytes.pwrite_with(value, at as usize, scroll::LE)?;
+            }
+            sink.write_all(&bytes)?;
+        },
+        None => def.data.write_to(sink)?,
+    }
+    Ok(())
+}
+
+/// Is `decl` a definition which is visible outside of this object?
+fn is_global(decl: &Decl) -> bool {
+    match *decl {
+        Decl::Function(f) => f.is_global(),
+        Decl::Data(d) => d.is_global(),
+        Decl::CString(c) => c.is_global(),
+        _ => false,
+    }
+}
+
+/// Is `decl` a thread-local variable, whose symbol is its descriptor?
+fn is_thread_local(decl: &Decl) -> bool {
+    match *decl {
+        Decl::Data(d) => d.is_thread_local(),
+        _ => false,
+    }
+}
+
+/// Is `decl` a weak definition, which is placed in a coalesced section?
+fn is_weak(decl: &Decl) -> bool {
+    match *decl {
+        Decl::Function(f) => f.is_weak(),
+        Decl::Data(d) => d.is_weak(),
+        _ => false,
+    }
+}
+
+pub fn write<T: Write + Seek>(artifact: &Artifact, sink: T) -> Result<(), Error> {
+    if CpuType::new(artifact.target.architecture).is_none() {
+        return Err(ArtifactError::UnsupportedArchitecture(artifact.target.architecture.to_string(), "Mach-O".to_string()).into());
+    }
+    // TODO: 32-bit ARM has its own relocations, e.g. ARM_RELOC_BR24 for a call, as do PowerPC and SPARC
+    match artifact.target.architecture {
+        Architecture::X86_64 | Architecture::Aarch64 => (),
+        architecture if is_i386(architecture) => (),
+        architecture => if let Some(link) = artifact.links().next() {
+            return Err(format_err!("Mach-O {} relocations are not yet supported: from {} to {}", architecture, link.from.name, link.to.name));
+        },
+    }
+    // mach has no relocation which is resolved to the size of a symbol
+    if let Some(link) = artifact.links().find(|link| link.reloc.is_size()) {
+        return Err(format_err!("Mach-O does not support relocating {} to the size of {}", link.from.name, link.to.name));
+    }
+    // the symbol table is keyed by the symbols' names
+    if let Some((name, symbol)) = artifact.symbol_names().next() {
+        return Err(format_err!("Mach-O does not yet support emitting {} as the symbol {}", name, symbol));
+    }
+    // the definitions are all placed in the standard sections
+    if let Some((name, section)) = artifact.placements().next() {
+        return Err(format_err!("Mach-O does not yet support placing {} in {}", name, section));
+    }
+    // nor one to the offset of a GOT entry from the GOT base, which it only addresses PC relative
+    if let Some(link) = artifact.links().find(|link| link.reloc.is_got_offset()) {
+        return Err(format_err!("Mach-O does not support relocating {} to the GOT offset of {}", link.from.name, link.to.name));
+    }
+    if let Some(link) = artifact.links().find(|link| link.reloc.is_got()) {
+        return Err(format_err!("Mach-O does not yet support relocating {} to the GOT entry of {}", link.from.name, link.to.name));
+    }
+    if let Some(link) = artifact.links().find(|link| link.reloc.is_got_base()) {
+        return Err(format_err!("Mach-O does not support relocating {} to the GOT base", link.from.name));
+    }
+    if let Some(link) = artifact.links().find(|link| link.reloc.is_data_relative()) {
+        return Err(format_err!("Mach-O does not support relocating {} to the address of {} relative to the GOT base", link.from.name, link.to.name));
+    }
+    // TODO: the addend of an unsigned or signed relocation is stored in place
+    if let Some(link) = artifact.links().find(|link| link.reloc.is_absolute() || link.reloc.is_relative()) {
+        return Err(format_err!("Mach-O does not yet support sized absolute or relative relocations from {} to {}", link.from.name, link.to.name));
+    }
+    // TODO: the offset within a section is the symbol's offset from the start of the section
+    if let Some(link) = artifact.links().find(|link| link.reloc.is_section_offset()) {
+        return Err(format_err!("Mach-O does not yet support writing the section offset of {} in {}", link.to.name, link.from.name));
+    }
+    // only a PE/COFF loader fills in an import address table, or reads an export table
+    if let Some((name, _)) = artifact.dll_imports().next() {
+        return Err(format_err!("Mach-O does not support importing {} from a DLL", name));
+    }
+    if let Some(name) = artifact.dll_exports().next() {
+        return Err(format_err!("Mach-O does not support exporting {} from a DLL", name));
+    }
+    // a private extern symbol is hidden, but there is nothing between it and a default one
+    if let Some((alias, target)) = artifact.aliases().find(|(alias, _)| artifact.alias_visibility(alias.name) == Visibility::Protected) {
+        return Err(format_err!("Mach-O does not support the protected alias {} of {}", alias.name, target.name));
+    }
+    // code loads the address of a thread-local variable's descriptor and calls its thunk, which has no offset from the
+    // thread pointer or the module's TLS block
+    if let Some(link) = artifact.links().find(|link| match link.reloc {
+        Reloc::Tls { model, .. } => model != TlsModel::GeneralDynamic && model != TlsModel::InitialExec,
+        _ => false,
+    }) {
+        return Err(format_err!("Mach-O only locates thread-local variables through their descriptors, not from {} to {} with {:?}", link.from.name, link.to.name, link.reloc));
+    }
+    // TODO: i386 loads the address of the descriptor with GENERIC_RELOC_TLV
+    if let Some(link) = artifact.links().find(|link| link.reloc.is_tls() && is_i386(artifact.target.architecture)) {
+        return Err(format_err!("Mach-O i386 does not yet support TLS relocations from {} to {}", link.from.name, link.to.name));
+    }
+    // TODO: the initial value of a thread-local variable is relocated at its $tlv$init symbol, not its descriptor
+    if let Some(link) = artifact.links().find(|link| is_thread_local(link.from.decl)) {
+        return Err(format_err!("Mach-O does not yet support relocations in the thread-local variable {} to {}", link.from.name, link.to.name));
+    }
+    if let Some(difference) = artifact.differences().find(|difference| is_thread_local(difference.from.decl)) {
+        return Err(format_err!("Mach-O does not yet support symbol differences in the thread-local variable {}", difference.from.name));
+    }
+    // the descriptors are not in a coalesced section
+    let grouped = artifact.comdats().map(|(member, _, _)| member).collect::<HashSet<_>>();
+    if let Some(def) = artifact.definitions().find(|def| def.prop.tls && (def.prop.weak || grouped.contains(def.name))) {
+        return Err(format_err!("Mach-O does not support the weak thread-local variable {}", def.name));
+    }
+    // Mach-O has no indirect functions; dyld binds each symbol to one address
+    if let Some(def) = artifact.definitions().find(|def| def.prop.ifunc) {
+        return Err(format_err!("Mach-O does not support the indirect function {}", def.name));
+    }
+    // TODO: Thumb functions are marked with N_ARM_THUMB_DEF
+    if let Some(def) = artifact.definitions().find(|def| def.prop.thumb) {
+        return Err(format_err!("Mach-O Thumb functions are not yet supported: {}", def.name));
+    }
+    // TODO: custom sections need a segment, and flags for their permissions and contents
+    if let Some(def) = artifact.definitions().find(|def| def.prop.section) {
+        return Err(format_err!("Mach-O custom sections are not yet supported: {}", def.name));
+    }
+    // TODO: place debug sections in the __DWARF segment
+    if let Some(def) = artifact.definitions().find(|def| def.prop.debug) {
+        return Err(format_err!("Mach-O debug sections are not yet supported: {}", def.name));
+    }
+    // N_WEAK_DEF is only meaningful for definitions in coalesced sections
+    if let Some(abs) = artifact.absolutes().find(|abs| match *abs.decl { Decl::Absolute(abs) => abs.is_weak(), _ => false }) {
+        return Err(format_err!("Mach-O does not support weak absolute symbols: {}", abs.name));
+    }
+    // nor for zero-initialized data, which is in a zerofill section
+    if let Some(def) = artifact.definitions().find(|def| def.prop.weak && def.prop.zero_init.is_some()) {
+        return Err(format_err!("Mach-O does not support the weak zero-initialized data object {}", def.name));
+    }
+    // nor for an alias in a section which is not coalesced
+    if let Some((alias, target)) = artifact.aliases().find(|(alias, target)| is_weak(alias.decl) && !is_weak(target.decl)) {
+        return Err(format_err!("Mach-O does not support the weak alias {} of {}, which is not weak", alias.name, target.name));
+    }
+    if let Some((function, _)) = artifact.compact_unwind().next() {
+        if artifact.target.architecture != Architecture::X86_64 {
+            return Err(format_err!("Mach-O compact unwind entries are only supported for x86-64: {}", function.name));
+        }
+    }
+    if let Some((_, leader, selection)) = artifact.comdats().find(|&(_, _, selection)| selection != ComdatSelection::Any) {
+        return Err(format_err!("Mach-O does not support the COMDAT selection {:?} of the group {}", selection, leader));
+    }
+    // a symbol's n_sect is a byte, where 0 is NO_SECT
+    artifact.check_section_count(SegmentBuilder::NSECTIONS, 255)?;
+    if let Some(difference) = artifact.differences().next() {
+        match artifact.target.architecture {
+            Architecture::X86_64 | Architecture::I386 | Architecture::I586 | Architecture::I686 => (),
+            _ => return Err(format_err!("Mach-O symbol differences are only supported for x86: {} - {}", difference.to.name, difference.minus.name)),
+        }
+    }
+    let mach = Mach::new(artifact)?;
+    mach.write(sink)?;
+    Ok(())
 }
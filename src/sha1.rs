@@ -0,0 +1,113 @@
+//! SHA-1, as `ld --build-id=sha1` hashes its output, for build ids computed from an artifact's contents
+
+use std::io;
+
+/// A SHA-1 hash of everything written to it, which is streamed so file backed contents aren't held in memory
+pub struct Sha1 {
+    state: [u32; 5],
+    block: Vec<u8>,
+    len: u64,
+}
+
+impl Sha1 {
+    pub fn new() -> Self {
+        Sha1 {
+            state: [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476, 0xc3d2_e1f0],
+            block: Vec::with_capacity(64),
+            len: 0,
+        }
+    }
+    pub fn update(&mut self, mut bytes: &[u8]) {
+        self.len += bytes.len() as u64;
+        while !bytes.is_empty() {
+            let take = (64 - self.block.len()).min(bytes.len());
+            self.block.extend_from_slice(&bytes[..take]);
+            bytes = &bytes[take..];
+            if self.block.len() == 64 {
+                let block = ::std::mem::replace(&mut self.block, Vec::with_capacity(64));
+                self.compress(&block);
+            }
+        }
+    }
+    /// The 20 byte digest of everything written
+    pub fn finish(mut self) -> Vec<u8> {
+        let bits = self.len.wrapping_mul(8);
+        let mut padding = vec![0x80];
+        padding.extend(vec![0; (119 - self.block.len()) % 64]);
+        padding.extend_from_slice(&bits.to_be_bytes());
+        let len = self.len;
+        self.update(&padding);
+        self.len = len;
+        debug_assert!(self.block.is_empty());
+        self.state.iter().flat_map(|word| word.to_be_bytes().to_vec()).collect()
+    }
+    fn compress(&mut self, block: &[u8]) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = self.state;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ed9_eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1b_bcdc),
+                _ => (b ^ c ^ d, 0xca62_c1d6),
+            };
+            let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        for (state, word) in self.state.iter_mut().zip(&[a, b, c, d, e]) {
+            *state = state.wrapping_add(*word);
+        }
+    }
+}
+
+impl io::Write for Sha1 {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.update(bytes);
+        Ok(bytes.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sha1;
+
+    fn hex(bytes: &[u8]) -> String {
+        let mut sha1 = Sha1::new();
+        sha1.update(bytes);
+        sha1.finish().iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn known_answers() {
+        assert_eq!(hex(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(hex(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"), "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+        // the padding of 55 bytes fits in their block, that of 56 takes another, and 64 fill a block exactly
+        assert_eq!(hex(&[b'a'; 55]), "c1c8bbdc22796e28c0e15163d20899b65621d65a");
+        assert_eq!(hex(&[b'a'; 56]), "c2db330f6083854c99d4b5bfb6e8f29f201be699");
+        assert_eq!(hex(&[b'a'; 64]), "0098ba824b5c16427bd7a1122a5a442a25ec644d");
+    }
+
+    #[test]
+    fn updates_split_across_blocks_hash_as_one() {
+        let mut sha1 = Sha1::new();
+        for chunk in vec![b'a'; 1_000_000].chunks(1000 - 7) {
+            sha1.update(chunk);
+        }
+        let digest = sha1.finish().iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+        assert_eq!(digest, "34aa973cd4c4daa4f61eeb2bdbad27316534016f");
+    }
+}
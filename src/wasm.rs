@@ -0,0 +1,447 @@
+//! The WebAssembly backend, which writes an artifact as a relocatable object file for `wasm-ld`, following the
+//! [tool conventions](https://github.com/WebAssembly/tool-conventions/blob/main/Linking.md): a module with a
+//! `linking` custom section, which has the symbol table and the data segments' names, and a `reloc.` custom section
+//! for each section with relocations.
+//!
+//! Each function is its body, in the code section, and each data object or string is a data segment of its own,
+//! so the linker can discard those nothing refers to.
+
+use failure::Error;
+use target_lexicon::Architecture;
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use {Artifact, Decl, ImportKind, Reloc, WasmSignature, WasmType};
+use artifact::{ArtifactError, Definition, LinkAndDecl};
+
+const MAGIC: &[u8] = b"\0asm";
+const VERSION: u32 = 1;
+
+const SECTION_CUSTOM: u8 = 0;
+const SECTION_TYPE: u8 = 1;
+const SECTION_IMPORT: u8 = 2;
+const SECTION_FUNCTION: u8 = 3;
+const SECTION_CODE: u8 = 10;
+const SECTION_DATA: u8 = 11;
+
+const EXTERNAL_FUNCTION: u8 = 0;
+const EXTERNAL_TABLE: u8 = 1;
+const EXTERNAL_MEMORY: u8 = 2;
+const TYPE_FUNC: u8 = 0x60;
+const TYPE_FUNCREF: u8 = 0x70;
+const OPCODE_I32_CONST: u8 = 0x41;
+const OPCODE_END: u8 = 0x0b;
+
+/// The version of the `linking` section
+const LINKING_VERSION: u32 = 2;
+const WASM_SEGMENT_INFO: u8 = 5;
+const WASM_INIT_FUNCS: u8 = 6;
+const WASM_SYMBOL_TABLE: u8 = 8;
+
+const SYMTAB_FUNCTION: u8 = 0;
+const SYMTAB_DATA: u8 = 1;
+const SYMTAB_TABLE: u8 = 5;
+
+const WASM_SYMBOL_BINDING_WEAK: u32 = 0x1;
+const WASM_SYMBOL_BINDING_LOCAL: u32 = 0x2;
+const WASM_SYMBOL_VISIBILITY_HIDDEN: u32 = 0x4;
+const WASM_SYMBOL_UNDEFINED: u32 = 0x10;
+const WASM_SYMBOL_NO_STRIP: u32 = 0x80;
+
+/// The segment holds null-terminated strings, which the linker may merge
+const WASM_SEG_FLAG_STRINGS: u32 = 0x1;
+
+const R_WASM_FUNCTION_INDEX_LEB: u8 = 0;
+const R_WASM_TABLE_INDEX_SLEB: u8 = 1;
+const R_WASM_TABLE_INDEX_I32: u8 = 2;
+const R_WASM_MEMORY_ADDR_LEB: u8 = 3;
+const R_WASM_MEMORY_ADDR_SLEB: u8 = 4;
+const R_WASM_MEMORY_ADDR_I32: u8 = 5;
+const R_WASM_TYPE_INDEX_LEB: u8 = 6;
+const R_WASM_FUNCTION_OFFSET_I32: u8 = 8;
+const R_WASM_SECTION_OFFSET_I32: u8 = 9;
+
+/// The module every import is from, which `wasm-ld` resolves against the other objects it links
+const IMPORT_MODULE: &str = "env";
+/// The linear memory, which every object imports, and its data segments are placed in
+const LINEAR_MEMORY: &str = "__linear_memory";
+/// The table of the functions whose addresses are taken, which the linker builds
+const INDIRECT_FUNCTION_TABLE: &str = "__indirect_function_table";
+
+/// Does the relocation `reloc` carry an addend?
+fn has_addend(reloc: u8) -> bool {
+    match reloc {
+        R_WASM_MEMORY_ADDR_LEB | R_WASM_MEMORY_ADDR_SLEB | R_WASM_MEMORY_ADDR_I32 | R_WASM_FUNCTION_OFFSET_I32 |
+        R_WASM_SECTION_OFFSET_I32 => true,
+        // the 64-bit, PC relative, TLS and location relative memory addresses, and the 64-bit function offset
+        11 | 14 | 15 | 16 | 17 | 21 | 22 | 23 | 25 => true,
+        _ => false,
+    }
+}
+
+/// Does the relocation `reloc` refer to an entry of the function table, which the object must then import?
+fn uses_table(reloc: u8) -> bool {
+    match reloc {
+        R_WASM_TABLE_INDEX_SLEB | R_WASM_TABLE_INDEX_I32 => true,
+        // the 64-bit and relative table indices
+        18 | 19 | 24 => true,
+        _ => false,
+    }
+}
+
+fn is_function(decl: &Decl) -> bool {
+    matches!(*decl, Decl::Function(_) | Decl::FunctionImport)
+}
+
+fn is_function_import(kind: &ImportKind) -> bool {
+    match *kind {
+        ImportKind::Function => true,
+        ImportKind::Data => false,
+    }
+}
+
+fn is_data(decl: &Decl) -> bool {
+    matches!(*decl, Decl::Data(_) | Decl::CString(_) | Decl::DataImport)
+}
+
+/// The relocation of `link`, and its addend
+fn relocation(link: &LinkAndDecl) -> Result<(u8, i32), Error> {
+    let unsupported = || ArtifactError::unsupported_relocation(link, "wasm32 WebAssembly".to_string());
+    Ok(match (link.reloc, link.from.decl) {
+        (Reloc::Raw { reloc, addend, .. }, _) if reloc <= u32::from(u8::MAX) => (reloc as u8, addend),
+        // a call, which the function's body encodes as a 5 byte LEB of the function's index
+        (Reloc::Auto, &Decl::Function(_)) if is_function(link.to.decl) => (R_WASM_FUNCTION_INDEX_LEB, 0),
+        // the address of data as the operand of an `i32.const`
+        (Reloc::Auto, &Decl::Function(_)) if is_data(link.to.decl) => (R_WASM_MEMORY_ADDR_SLEB, 0),
+        // a pointer to a function is its index in the function table
+        (Reloc::Auto, &Decl::Data(_)) | (Reloc::Absolute { size: 4, addend: 0 }, &Decl::Data(_)) if is_function(link.to.decl) => {
+            (R_WASM_TABLE_INDEX_I32, 0)
+        },
+        (Reloc::Auto, &Decl::Data(_)) if is_data(link.to.decl) => (R_WASM_MEMORY_ADDR_I32, 0),
+        (Reloc::Absolute { size: 4, addend }, &Decl::Data(_)) if is_data(link.to.decl) => (R_WASM_MEMORY_ADDR_I32, addend),
+        _ => return Err(unsupported().into()),
+    })
+}
+
+/// Append `value` to `bytes` as an unsigned LEB128
+fn uleb(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// Append `value` to `bytes` as a signed LEB128
+fn sleb(bytes: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        // the sign bit of the last byte is the sign of the value
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn name(bytes: &mut Vec<u8>, name: &str) {
+    uleb(bytes, name.len() as u64);
+    bytes.extend_from_slice(name.as_bytes());
+}
+
+/// Append `contents` to `bytes` prefixed by their size
+fn sized(bytes: &mut Vec<u8>, contents: &[u8]) {
+    uleb(bytes, contents.len() as u64);
+    bytes.extend_from_slice(contents);
+}
+
+fn value_type(ty: WasmType) -> u8 {
+    match ty {
+        WasmType::I32 => 0x7f,
+        WasmType::I64 => 0x7e,
+        WasmType::F32 => 0x7d,
+        WasmType::F64 => 0x7c,
+    }
+}
+
+/// The alignment of the data segment of `def`, as a power of two
+fn alignment(def: &Definition, artifact: &Artifact) -> u64 {
+    let align = match def.prop.zero_init {
+        Some(align) => align,
+        None if def.prop.cstring => 1,
+        None => artifact.data_align.unwrap_or(if def.prop.writable { 8 } else { 1 }),
+    };
+    u64::from(align.trailing_zeros())
+}
+
+/// The symbols of a WebAssembly object, and the index spaces they refer to
+#[derive(Default)]
+struct Symbols<'a> {
+    /// The symbol table, in order
+    table: Vec<u8>,
+    count: u32,
+    indices: HashMap<&'a str, u32>,
+    /// The signatures of the module, each once, in the order they are first used
+    types: Vec<&'a WasmSignature>,
+}
+
+impl<'a> Symbols<'a> {
+    fn push(&mut self, symbol: &'a str, kind: u8, flags: u32) {
+        self.table.push(kind);
+        uleb(&mut self.table, u64::from(flags));
+        self.indices.insert(symbol, self.count);
+        self.count += 1;
+    }
+    /// The index of the type of the function `function`, which must have a signature
+    fn type_index(&mut self, artifact: &'a Artifact, function: &str) -> Result<u32, Error> {
+        let signature = artifact.wasm_signature(function).ok_or_else(|| format_err!("WebAssembly function {} has no signature", function))?;
+        Ok(match self.types.iter().position(|&ty| ty == signature) {
+            Some(index) => index as u32,
+            None => {
+                self.types.push(signature);
+                self.types.len() as u32 - 1
+            },
+        })
+    }
+}
+
+/// Append the section `id` with `contents` to `module`, and return its index among the module's sections
+fn section(module: &mut Vec<u8>, sections: &mut u32, id: u8, contents: &[u8]) -> u32 {
+    module.push(id);
+    sized(module, contents);
+    *sections += 1;
+    *sections - 1
+}
+
+/// Append a relocation section for the section `target`, with the relocations `relocs` of its contents
+fn reloc_section(module: &mut Vec<u8>, sections: &mut u32, target: (&str, u32), mut relocs: Vec<(u8, u64, u32, i32)>) {
+    if relocs.is_empty() {
+        return;
+    }
+    relocs.sort_by_key(|&(_, offset, _, _)| offset);
+    let mut contents = Vec::new();
+    name(&mut contents, &format!("reloc.{}", target.0));
+    uleb(&mut contents, u64::from(target.1));
+    uleb(&mut contents, relocs.len() as u64);
+    for (reloc, offset, index, addend) in relocs {
+        contents.push(reloc);
+        uleb(&mut contents, offset);
+        uleb(&mut contents, u64::from(index));
+        if has_addend(reloc) {
+            sleb(&mut contents, i64::from(addend));
+        }
+    }
+    section(module, sections, SECTION_CUSTOM, &contents);
+}
+
+pub fn write<W: Write>(artifact: &Artifact, mut sink: W) -> Result<(), Error> {
+    if artifact.target.architecture != Architecture::Wasm32 {
+        return Err(ArtifactError::UnsupportedArchitecture(artifact.target.architecture.to_string(), "WebAssembly".to_string()).into());
+    }
+    if let Some(abs) = artifact.absolutes().next() {
+        return Err(format_err!("WebAssembly has no absolute symbols: {}", abs.name));
+    }
+    // TODO: thread-local data is in segments flagged WASM_SEG_FLAG_TLS, addressed relative to __tls_base
+    if let Some(def) = artifact.definitions().find(|def| def.prop.tls) {
+        return Err(format_err!("WebAssembly thread-local data is not yet supported: {}", def.name));
+    }
+    // TODO: debug sections are custom sections, whose relocations are offsets
+    if let Some(def) = artifact.definitions().find(|def| def.prop.debug || def.prop.section) {
+        return Err(format_err!("WebAssembly custom sections are not yet supported: {}", def.name));
+    }
+    if let Some(def) = artifact.definitions().find(|def| def.prop.ifunc || def.prop.thumb || def.prop.common) {
+        return Err(format_err!("WebAssembly does not support the definition {}", def.name));
+    }
+    // TODO: an alias is another symbol of the same function or segment
+    if let Some((alias, target)) = artifact.aliases().next() {
+        return Err(format_err!("WebAssembly aliases are not yet supported: {} of {}", alias.name, target.name));
+    }
+    // TODO: the linking section has a subsection of COMDAT groups
+    if let Some((member, leader, _)) = artifact.comdats().next() {
+        return Err(format_err!("WebAssembly COMDAT groups are not yet supported: {} of {}", member, leader));
+    }
+    if let Some(difference) = artifact.differences().next() {
+        return Err(format_err!("WebAssembly has no symbol differences: {} - {}", difference.to.name, difference.minus.name));
+    }
+    // a module has no finalizers; a constructor registers what is run at exit with `atexit`
+    if let Some((destructor, _)) = artifact.destructors().next() {
+        return Err(format_err!("WebAssembly has no destructors: {}", destructor.name));
+    }
+    if let Some((name, symbol)) = artifact.symbol_names().next() {
+        return Err(format_err!("WebAssembly does not yet support emitting {} as the symbol {}", name, symbol));
+    }
+    if let Some((name, _)) = artifact.dll_imports().next() {
+        return Err(format_err!("WebAssembly does not support importing {} from a DLL", name));
+    }
+    if let Some(name) = artifact.dll_exports().next() {
+        return Err(format_err!("WebAssembly does not support exporting {} from a DLL", name));
+    }
+    let links = artifact.links().map(|link| relocation(&link).map(|reloc| (link, reloc))).collect::<Result<Vec<_>, _>>()?;
+
+    let mut symbols = Symbols::default();
+    let (functions, data): (Vec<_>, Vec<_>) = artifact.definitions().partition(|def| def.prop.function);
+    let flags = |def: &Definition| {
+        let binding = if !def.prop.global { WASM_SYMBOL_BINDING_LOCAL } else if def.prop.weak { WASM_SYMBOL_BINDING_WEAK } else { 0 };
+        let visibility = if def.prop.hidden { WASM_SYMBOL_VISIBILITY_HIDDEN } else { 0 };
+        binding | visibility | if artifact.is_no_dead_strip(def.name) { WASM_SYMBOL_NO_STRIP } else { 0 }
+    };
+
+    // the imported functions come first in the function index space, and are named by their imports
+    let mut imports = Vec::new();
+    let mut nimports = 0;
+    name(&mut imports, IMPORT_MODULE);
+    name(&mut imports, LINEAR_MEMORY);
+    imports.push(EXTERNAL_MEMORY);
+    // no maximum, and at least no pages
+    imports.extend_from_slice(&[0, 0]);
+    nimports += 1;
+    let mut nfunctions = 0;
+    for (import, _) in artifact.imports().filter(|&(_, kind)| is_function_import(kind)) {
+        let ty = symbols.type_index(artifact, import)?;
+        name(&mut imports, IMPORT_MODULE);
+        name(&mut imports, import);
+        imports.push(EXTERNAL_FUNCTION);
+        uleb(&mut imports, u64::from(ty));
+        nimports += 1;
+        let weak = if artifact.is_weak_import(import) { WASM_SYMBOL_BINDING_WEAK } else { 0 };
+        symbols.push(import, SYMTAB_FUNCTION, WASM_SYMBOL_UNDEFINED | weak);
+        uleb(&mut symbols.table, nfunctions);
+        nfunctions += 1;
+    }
+
+    // the function section declares the type of each body in the code section
+    let (mut function_section, mut code) = (Vec::new(), Vec::new());
+    uleb(&mut function_section, functions.len() as u64);
+    uleb(&mut code, functions.len() as u64);
+    let mut bodies = HashMap::new();
+    for def in &functions {
+        let ty = symbols.type_index(artifact, def.name)?;
+        uleb(&mut function_section, u64::from(ty));
+        uleb(&mut code, def.data.len());
+        bodies.insert(def.name, code.len() as u64);
+        def.data.write_to(&mut code)?;
+        symbols.push(def.name, SYMTAB_FUNCTION, flags(def));
+        uleb(&mut symbols.table, nfunctions);
+        name(&mut symbols.table, def.name);
+        nfunctions += 1;
+    }
+
+    // each data object is a segment, at its own offset in the object's memory
+    let (mut data_section, mut segment_info) = (Vec::new(), Vec::new());
+    uleb(&mut data_section, data.len() as u64);
+    uleb(&mut segment_info, data.len() as u64);
+    let mut segments = HashMap::new();
+    let mut address = 0u64;
+    for (index, def) in data.iter().enumerate() {
+        let align = alignment(def, artifact);
+        address = address.next_multiple_of(1 << align);
+        // an active segment of the first memory
+        data_section.push(0);
+        data_section.push(OPCODE_I32_CONST);
+        sleb(&mut data_section, address as i32 as i64);
+        data_section.push(OPCODE_END);
+        uleb(&mut data_section, def.data.len());
+        segments.insert(def.name, data_section.len() as u64);
+        def.data.write_to(&mut data_section)?;
+        address += def.data.len();
+
+        let prefix = if def.prop.cstring { ".rodata.str" } else if def.prop.zero_init.is_some() { ".bss" } else if def.prop.writable { ".data" } else { ".rodata" };
+        name(&mut segment_info, &format!("{}.{}", prefix, def.name));
+        uleb(&mut segment_info, align);
+        uleb(&mut segment_info, u64::from(if def.prop.cstring { WASM_SEG_FLAG_STRINGS } else { 0 }));
+        symbols.push(def.name, SYMTAB_DATA, flags(def));
+        name(&mut symbols.table, def.name);
+        // its segment, its offset in the segment, and its size
+        uleb(&mut symbols.table, index as u64);
+        uleb(&mut symbols.table, 0);
+        uleb(&mut symbols.table, def.data.len());
+    }
+    for (import, _) in artifact.imports().filter(|&(_, kind)| !is_function_import(kind)) {
+        let weak = if artifact.is_weak_import(import) { WASM_SYMBOL_BINDING_WEAK } else { 0 };
+        symbols.push(import, SYMTAB_DATA, WASM_SYMBOL_UNDEFINED | weak);
+        name(&mut symbols.table, import);
+    }
+    // a pointer to a function is an index into the table the linker builds, which the object refers to as an import
+    if links.iter().any(|&(_, (reloc, _))| uses_table(reloc)) {
+        name(&mut imports, IMPORT_MODULE);
+        name(&mut imports, INDIRECT_FUNCTION_TABLE);
+        imports.push(EXTERNAL_TABLE);
+        imports.extend_from_slice(&[TYPE_FUNCREF, 0, 0]);
+        nimports += 1;
+        symbols.push(INDIRECT_FUNCTION_TABLE, SYMTAB_TABLE, WASM_SYMBOL_UNDEFINED | WASM_SYMBOL_NO_STRIP);
+        uleb(&mut symbols.table, 0);
+    }
+
+    let (mut code_relocs, mut data_relocs) = (Vec::new(), Vec::new());
+    for (link, (reloc, addend)) in links {
+        let index = if reloc == R_WASM_TYPE_INDEX_LEB {
+            symbols.type_index(artifact, link.to.name)?
+        } else {
+            symbols.indices[link.to.name]
+        };
+        match (bodies.get(link.from.name), segments.get(link.from.name)) {
+            (Some(&body), _) => code_relocs.push((reloc, body + link.at, index, addend)),
+            (_, Some(&segment)) => data_relocs.push((reloc, segment + link.at, index, addend)),
+            _ => unreachable!("only definitions have relocations"),
+        }
+    }
+
+    let mut module = Vec::new();
+    module.extend_from_slice(MAGIC);
+    module.extend_from_slice(&VERSION.to_le_bytes());
+    let mut sections = 0;
+    let mut types = Vec::new();
+    uleb(&mut types, symbols.types.len() as u64);
+    for signature in &symbols.types {
+        types.push(TYPE_FUNC);
+        uleb(&mut types, signature.params.len() as u64);
+        types.extend(signature.params.iter().map(|&ty| value_type(ty)));
+        uleb(&mut types, signature.results.len() as u64);
+        types.extend(signature.results.iter().map(|&ty| value_type(ty)));
+    }
+    section(&mut module, &mut sections, SECTION_TYPE, &types);
+    let mut import_section = Vec::new();
+    uleb(&mut import_section, nimports);
+    import_section.extend_from_slice(&imports);
+    section(&mut module, &mut sections, SECTION_IMPORT, &import_section);
+    section(&mut module, &mut sections, SECTION_FUNCTION, &function_section);
+    let code_index = section(&mut module, &mut sections, SECTION_CODE, &code);
+    let data_index = section(&mut module, &mut sections, SECTION_DATA, &data_section);
+
+    let mut linking = Vec::new();
+    name(&mut linking, "linking");
+    uleb(&mut linking, u64::from(LINKING_VERSION));
+    let mut symbol_table = Vec::new();
+    uleb(&mut symbol_table, u64::from(symbols.count));
+    symbol_table.extend_from_slice(&symbols.table);
+    linking.push(WASM_SYMBOL_TABLE);
+    sized(&mut linking, &symbol_table);
+    if !data.is_empty() {
+        linking.push(WASM_SEGMENT_INFO);
+        sized(&mut linking, &segment_info);
+    }
+    let constructors = artifact.constructors().collect::<Vec<_>>();
+    if !constructors.is_empty() {
+        let mut init_funcs = Vec::new();
+        uleb(&mut init_funcs, constructors.len() as u64);
+        for (constructor, priority) in constructors {
+            // without a priority, a constructor runs after those with one
+            uleb(&mut init_funcs, u64::from(priority.unwrap_or(u16::MAX)));
+            uleb(&mut init_funcs, u64::from(symbols.indices[constructor.name]));
+        }
+        linking.push(WASM_INIT_FUNCS);
+        sized(&mut linking, &init_funcs);
+    }
+    section(&mut module, &mut sections, SECTION_CUSTOM, &linking);
+    reloc_section(&mut module, &mut sections, ("CODE", code_index), code_relocs);
+    reloc_section(&mut module, &mut sections, ("DATA", data_index), data_relocs);
+    sink.write_all(&module)?;
+    Ok(())
+}
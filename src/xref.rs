@@ -0,0 +1,96 @@
+//! A cross-reference report of an artifact: for every symbol, where it is referred to, and whether it is resolved,
+//! for diagnosing e.g. why a symbol is undefined without emitting the object or running the linker
+
+use std::collections::HashMap;
+use std::fmt;
+
+use Artifact;
+
+/// Whether a symbol is resolved in the artifact
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Defined by the artifact, including absolute symbols and aliases
+    Defined,
+    /// Imported, i.e. resolved by the linker or dynamic loader from somewhere else
+    Imported,
+    /// Declared but never defined, so the artifact can't be emitted
+    Unresolved,
+}
+
+/// A place which refers to a symbol: the field `at` bytes into the definition `from`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Site {
+    /// The definition containing the reference; each definition is emitted in its own section
+    pub from: String,
+    /// The offset of the field in `from`
+    pub at: u64,
+}
+
+/// A symbol, how it is resolved, and every place which refers to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Xref {
+    pub name: String,
+    pub resolution: Resolution,
+    /// The references to the symbol, in the order their relocations were added
+    pub sites: Vec<Site>,
+}
+
+/// The cross-references of every declaration in an artifact, in the order they were declared
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct XrefReport {
+    pub symbols: Vec<Xref>,
+}
+
+impl XrefReport {
+    /// Get the cross-references of the symbol `name`, if it is declared
+    pub fn get(&self, name: &str) -> Option<&Xref> {
+        self.symbols.iter().find(|xref| xref.name == name)
+    }
+    /// Get the symbols which are declared but never defined, and so must be before the artifact can be emitted
+    pub fn unresolved<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Xref> + 'a> {
+        Box::new(self.symbols.iter().filter(|xref| xref.resolution == Resolution::Unresolved))
+    }
+}
+
+impl fmt::Display for XrefReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for xref in &self.symbols {
+            writeln!(f, "{} ({:?})", xref.name, xref.resolution)?;
+            for site in &xref.sites {
+                writeln!(f, "    {}+{:#x}", site.from, site.at)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Build the cross-reference report of `artifact` from its declarations and relocations; nothing is emitted,
+/// so this works for an artifact which is missing definitions, unlike [emit](../struct.Artifact.html#method.emit).
+pub fn xref_report(artifact: &Artifact) -> XrefReport {
+    let mut symbols = Vec::new();
+    let mut index = HashMap::new();
+    for (binding, defined) in artifact.declared() {
+        let resolution = if binding.decl.is_import() {
+            Resolution::Imported
+        } else if defined || binding.decl.is_absolute() {
+            Resolution::Defined
+        } else {
+            Resolution::Unresolved
+        };
+        index.insert(binding.name, symbols.len());
+        symbols.push(Xref { name: binding.name.to_string(), resolution, sites: Vec::new() });
+    }
+    {
+        let mut refer = |to: &str, from: &str, at: u64| {
+            symbols[index[to]].sites.push(Site { from: from.to_string(), at });
+        };
+        for link in artifact.links() {
+            refer(link.to.name, link.from.name, link.at);
+        }
+        for difference in artifact.differences() {
+            refer(difference.to.name, difference.from.name, difference.at);
+            refer(difference.minus.name, difference.from.name, difference.at);
+        }
+    }
+    XrefReport { symbols }
+}
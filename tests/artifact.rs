@@ -3,6 +3,7 @@ extern crate faerie;
 extern crate target_lexicon;
 #[cfg(test)]
 extern crate goblin;
+extern crate failure;
 
 use faerie::*;
 use std::str::FromStr;
@@ -18,10 +19,7 @@ fn duplicate_declarations_are_ok() {
 
     obj.declare(
         "str.0",
-        faerie::Decl::Data {
-            global: false,
-            writable: false,
-        },
+        faerie::Decl::data(),
     ).expect("declare should be compatible");
 
     obj.define("str.0", b"hello world\0".to_vec()).expect(
@@ -34,26 +32,20 @@ fn duplicate_declarations_are_ok() {
             ("str.0", faerie::Decl::DataImport),
             (
                 "str.0",
-                faerie::Decl::Data {
-                    global: true,
-                    writable: false,
-                }
+                faerie::Decl::data().global().into()
             ),
             ("str.0", faerie::Decl::DataImport),
             ("str.0", faerie::Decl::DataImport),
             (
                 "str.0",
-                faerie::Decl::Data {
-                    global: true,
-                    writable: false,
-                }
+                faerie::Decl::data().global().into()
             ),
 
             ("f", faerie::Decl::FunctionImport),
-            ("f", faerie::Decl::Function { global: true }),
+            ("f", faerie::Decl::function().global().into()),
             ("f", faerie::Decl::FunctionImport),
             ("f", faerie::Decl::FunctionImport),
-            ("f", faerie::Decl::Function { global: true }),
+            ("f", faerie::Decl::function().global().into()),
         ].into_iter(),
     ).expect("multiple declarations are ok");
 }
@@ -70,10 +62,7 @@ fn multiple_different_declarations_are_not_ok() {
     assert!(
         obj.declare(
             "f",
-            faerie::Decl::Data {
-                global: false,
-                writable: false,
-            },
+            faerie::Decl::data(),
         ).is_err()
     );
 }
@@ -85,10 +74,10 @@ fn multiple_different_conflicting_declarations_are_not_ok_and_do_not_overwrite()
         obj.declarations(
             vec![
                 ("f", faerie::Decl::FunctionImport),
-                ("f", faerie::Decl::Function { global: true }),
+                ("f", faerie::Decl::function().global().into()),
                 ("f", faerie::Decl::FunctionImport),
                 ("f", faerie::Decl::FunctionImport),
-                ("f", faerie::Decl::Function { global: false }),
+                ("f", faerie::Decl::function().into()),
             ].into_iter(),
         ).is_err()
     ); // multiple conflicting declarations are not ok
@@ -116,7 +105,7 @@ fn import_declarations_work_with_redeclarations() {
             ("f", faerie::Decl::FunctionImport),
             ("d", faerie::Decl::DataImport),
             ("d", faerie::Decl::DataImport),
-            ("f", faerie::Decl::Function { global: true }),
+            ("f", faerie::Decl::function().global().into()),
             ("f", faerie::Decl::FunctionImport),
         ].into_iter(),
     ).expect("can declare");
@@ -139,8 +128,8 @@ fn reject_duplicate_definitions() {
     let mut obj = Artifact::new(triple!("x86_64"), "t.o".into());
     obj.declarations(
         vec![
-            ("f", faerie::Decl::Function { global: true }),
-            ("g", faerie::Decl::Function { global: false }),
+            ("f", faerie::Decl::function().global().into()),
+            ("g", faerie::Decl::function().into()),
         ].into_iter(),
     ).expect("can declare");
 
@@ -159,8 +148,8 @@ fn undefined_symbols() {
     let mut obj = Artifact::new(triple!("x86_64"), "t.o".into());
     obj.declarations(
         vec![
-            ("f", faerie::Decl::Function { global: true }),
-            ("g", faerie::Decl::Function { global: false }),
+            ("f", faerie::Decl::function().global().into()),
+            ("g", faerie::Decl::function().into()),
         ].into_iter(),
     ).expect("can declare");
     assert_eq!(
@@ -203,3 +192,511 @@ fn vary_output_formats() {
     }
     */
 }
+
+#[test]
+fn exported_symbols_match_the_symbol_table() {
+    use target_lexicon::BinaryFormat;
+    use goblin::Object;
+
+    let mut obj = Artifact::new(triple!("x86_64"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3]).expect("can declare and define f");
+    obj.declare_with("g", Decl::function(), vec![0xc3]).expect("can declare and define g");
+    obj.declare_with("h", Decl::data().weak(), vec![0; 8]).expect("can declare and define h");
+    obj.declare_with("s", Decl::cstring().global(), b"s\0".to_vec()).expect("can declare and define s");
+    obj.declare("VERSION", Decl::absolute(2).global()).expect("can declare VERSION");
+    obj.declare("LIMIT", Decl::absolute(8)).expect("can declare LIMIT");
+    obj.import("puts", ImportKind::Function).expect("can import puts");
+
+    assert_eq!(obj.exported_symbols(BinaryFormat::Elf), vec!["f", "h", "s", "VERSION"]);
+    assert_eq!(obj.exported_symbols(BinaryFormat::Macho), vec!["_f", "_h", "_s", "_VERSION"]);
+
+    // they are exactly the global and weak symbols which are defined in the emitted objects
+    let elf = obj.emit_as(BinaryFormat::Elf).unwrap();
+    let mut defined = match Object::parse(&elf).unwrap() {
+        Object::Elf(elf) => elf.syms.iter()
+            .filter(|sym| sym.st_bind() != goblin::elf::sym::STB_LOCAL && sym.st_shndx != 0)
+            .map(|sym| elf.strtab[sym.st_name].to_string())
+            .collect::<Vec<_>>(),
+        _ => panic!("emitted as ELF but didn't parse as ELF"),
+    };
+    defined.sort();
+    let mut exported = obj.exported_symbols(BinaryFormat::Elf);
+    exported.sort();
+    assert_eq!(defined, exported);
+}
+
+#[test]
+fn flat_binary_applies_relocations_in_place() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    // lea rax, [rip + msg]; call helper; ret
+    obj.declare_with("main", Decl::function().global(), vec![0x48, 0x8d, 0x05, 0, 0, 0, 0, 0xe8, 0, 0, 0, 0, 0xc3])
+        .expect("can declare and define main");
+    obj.declare_with("helper", Decl::function(), vec![0xc3]).expect("can declare and define helper");
+    obj.declare_with("msg", Decl::data(), b"hi\0".to_vec()).expect("can declare and define msg");
+    obj.declare_with("table", Decl::data().global().writable(), vec![0; 16]).expect("can declare and define table");
+    obj.declare("port", Decl::absolute(0x3f8).global()).expect("can declare port");
+    obj.link(Link { from: "main", to: "msg", at: 3 }).expect("can link from main to msg");
+    obj.link(Link { from: "main", to: "helper", at: 8 }).expect("can link from main to helper");
+    obj.link(Link { from: "table", to: "main", at: 0 }).expect("can link from table to main");
+    obj.link(Link { from: "table", to: "port", at: 8 }).expect("can link from table to port");
+
+    let bytes = obj.to_flat_binary(0x1000).expect("can emit flat binary");
+    // helper at 0x1000, main at 0x1010, msg at 0x101d, and table at 0x1020
+    let mut expected = vec![0xc3];
+    expected.resize(0x10, 0);
+    expected.extend_from_slice(&[0x48, 0x8d, 0x05, 0x06, 0, 0, 0, 0xe8, 0xe4, 0xff, 0xff, 0xff, 0xc3]);
+    expected.extend_from_slice(b"hi\0");
+    expected.extend_from_slice(&[0x10, 0x10, 0, 0, 0, 0, 0, 0, 0xf8, 0x03, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(bytes, expected);
+
+    // a 32-bit pointer to main cannot hold its address
+    let mut far = obj.clone();
+    far.link_with(Link { from: "table", to: "main", at: 0 }, Reloc::Absolute { size: 4, addend: 0 }).expect("can link");
+    assert!(far.to_flat_binary(0x1_0000_0000).is_err());
+    // there is no linker to resolve an import
+    obj.import("puts", ImportKind::Function).expect("can import puts");
+    obj.link(Link { from: "main", to: "puts", at: 8 }).expect("can link from main to puts");
+    assert!(obj.to_flat_binary(0x1000).is_err());
+}
+
+#[test]
+fn flat_binary_writes_narrow_fields_without_touching_the_bytes_after_them() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("table", Decl::data(), vec![0xaa; 8]).expect("can declare and define table");
+    obj.declare_with("end", Decl::data(), vec![0xbb; 3]).expect("can declare and define end");
+    obj.link_with(Link { from: "table", to: "table", at: 2 }, Reloc::Absolute { size: 2, addend: 0 }).expect("can link");
+    obj.link_with(Link { from: "table", to: "end", at: 5 }, Reloc::Relative { size: 1, addend: 0 }).expect("can link");
+    // the fields at the end of a definition fit in it, though 4 bytes would not
+    obj.link_with(Link { from: "end", to: "table", at: 1 }, Reloc::Absolute { size: 2, addend: 1 }).expect("can link");
+
+    // table at 0x108, then end at 0x110
+    let bytes = obj.to_flat_binary(0x108).expect("can emit flat binary");
+    assert_eq!(bytes, vec![0xaa, 0xaa, 0x08, 0x01, 0xaa, 0x03, 0xaa, 0xaa, 0xbb, 0x09, 0x01]);
+
+    // the address doesn't fit in 2 bytes, nor the distance in 1
+    assert!(obj.to_flat_binary(0x1_0000).is_err());
+    let mut far = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    far.declare_with("table", Decl::data(), vec![0xaa; 8]).expect("can declare and define table");
+    far.declare_with("end", Decl::data(), vec![0; 0x80]).expect("can declare and define end");
+    far.link_with(Link { from: "end", to: "table", at: 0x7f }, Reloc::Relative { size: 1, addend: 0 }).expect("can link");
+    assert!(far.to_flat_binary(0x1000).is_err());
+}
+
+#[test]
+fn metadata_is_kept_but_not_emitted() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0xc3]).expect("can declare and define main");
+    obj.declare_with("table", Decl::data(), vec![0; 8]).expect("can declare and define table");
+    let plain = obj.emit().expect("can emit elf file");
+
+    obj.set_metadata("table", b"table.rs:3".to_vec()).expect("can attach metadata to table");
+    obj.set_metadata("main", b"main.rs:1".to_vec()).expect("can attach metadata to main");
+    obj.set_metadata("main", b"main.rs:2".to_vec()).expect("can replace the metadata of main");
+    assert!(obj.set_metadata("missing", Vec::new()).is_err());
+
+    assert_eq!(obj.emit().expect("can emit elf file"), plain);
+    assert_eq!(obj.metadata("main"), Some(&b"main.rs:2"[..]));
+    assert_eq!(obj.metadata("missing"), None);
+    assert_eq!(obj.metadata_entries().collect::<Vec<_>>(), vec![("table", &b"table.rs:3"[..]), ("main", &b"main.rs:2"[..])]);
+}
+
+#[test]
+fn emit_errors_name_what_is_wrong() {
+    use faerie::artifact::ArtifactError;
+
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare("helper", Decl::function()).expect("can declare helper");
+    obj.declare_with("main", Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xc3]).expect("can declare and define main");
+    obj.link(Link { from: "main", to: "helper", at: 1 }).expect("can link from main to helper");
+    let err = obj.emit().expect_err("helper is not defined");
+    match err.downcast_ref::<ArtifactError>() {
+        Some(ArtifactError::UndefinedSymbols(names)) => assert_eq!(names, &["helper"]),
+        _ => panic!("unexpected error: {}", err),
+    }
+
+    obj.define("helper", vec![0xc3]).expect("can define helper");
+    obj.declare_with(".debug_str", Decl::debug_section(), b"main\0".to_vec()).expect("can declare and define .debug_str");
+    obj.link(Link { from: "main", to: ".debug_str", at: 1 }).expect("can link from main to .debug_str");
+    let err = obj.emit().expect_err("code cannot refer to a debug section");
+    match err.downcast_ref::<ArtifactError>() {
+        Some(&ArtifactError::UnsupportedRelocation { ref from, ref to, ref to_decl, at, reloc: Reloc::Auto, ref target, .. }) => {
+            assert_eq!((from.as_str(), to.as_str(), at), ("main", ".debug_str", 1));
+            assert!(to_decl.is_debug_section());
+            assert_eq!(target, "x86_64 ELF");
+        },
+        _ => panic!("unexpected error: {}", err),
+    }
+
+    let mut obj = Artifact::new(triple!("wasm32-unknown-unknown"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0x0b]).expect("can declare and define main");
+    let err = obj.emit_as(target_lexicon::BinaryFormat::Elf).expect_err("wasm32 does not exist in ELF");
+    match err.downcast_ref::<ArtifactError>() {
+        Some(&ArtifactError::UnsupportedArchitecture(..)) => (),
+        _ => panic!("unexpected error: {}", err),
+    }
+    assert!(obj.emit_as(target_lexicon::BinaryFormat::Macho).is_err());
+    // the format is checked first, so this is not reported as an undefined symbol
+    obj.declare("helper", Decl::function()).expect("can declare helper");
+    let err = obj.emit_as(target_lexicon::BinaryFormat::Coff).expect_err("there is no COFF backend");
+    match err.downcast_ref::<ArtifactError>() {
+        Some(ArtifactError::UnsupportedBinaryFormat(format)) => assert_eq!(format, "coff"),
+        _ => panic!("unexpected error: {}", err),
+    }
+    assert!(obj.write_to(Vec::new(), target_lexicon::BinaryFormat::Coff).is_err());
+}
+
+#[test]
+fn custom_backend_writes_its_own_format() {
+    use faerie::backend::{self, Sink};
+    use faerie::ObjectBackend;
+
+    /// A listing of the definitions, imports and links, one per line
+    #[derive(Debug)]
+    struct Listing;
+    impl ObjectBackend for Listing {
+        fn write(&self, artifact: &Artifact, sink: &mut dyn Sink) -> Result<(), failure::Error> {
+            for def in artifact.definitions() {
+                writeln!(sink, "def {} {}", def.name, def.data.len())?;
+            }
+            for (name, _) in artifact.imports() {
+                writeln!(sink, "import {}", name)?;
+            }
+            for link in artifact.links() {
+                writeln!(sink, "link {}+{} -> {}", link.from.name, link.at, link.to.name)?;
+            }
+            Ok(())
+        }
+    }
+
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xc3]).expect("can declare and define main");
+    obj.import("puts", ImportKind::Function).expect("can import puts");
+    obj.link(Link { from: "main", to: "puts", at: 1 }).expect("can link from main to puts");
+
+    let listing = obj.emit_with(&Listing).expect("can emit listing");
+    assert_eq!(String::from_utf8(listing).expect("listing is utf8"), "def main 6\nimport puts\nlink main+1 -> puts\n");
+    // the built-in formats are backends too
+    assert_eq!(obj.emit_with(&backend::Elf).expect("can emit elf file"), obj.emit().expect("can emit elf file"));
+
+    obj.declare("helper", Decl::function()).expect("can declare helper");
+    assert!(obj.emit_with(&Listing).is_err());
+}
+
+#[test]
+fn xref_report_lists_references_and_resolution() {
+    let mut obj = Artifact::new(triple!("x86_64"), "t.o".into());
+    obj.declarations(vec![
+        ("main", Decl::function().global().into()),
+        ("helper", Decl::function().into()),
+        ("puts", Decl::FunctionImport),
+        ("missing", Decl::data().global().into()),
+    ].into_iter()).expect("declarations");
+    obj.define("main", vec![0xe8, 0, 0, 0, 0, 0xe8, 0, 0, 0, 0, 0x48, 0x8b, 0x05, 0, 0, 0, 0, 0xc3]).expect("define main");
+    obj.define("helper", vec![0xe8, 0, 0, 0, 0, 0xc3]).expect("define helper");
+    obj.link(Link { from: "main", to: "helper", at: 1 }).expect("link main to helper");
+    obj.link(Link { from: "main", to: "puts", at: 6 }).expect("link main to puts");
+    obj.link(Link { from: "main", to: "missing", at: 13 }).expect("link main to missing");
+    obj.link(Link { from: "helper", to: "puts", at: 1 }).expect("link helper to puts");
+
+    let report = xref_report(&obj);
+    let names: Vec<&str> = report.symbols.iter().map(|xref| xref.name.as_str()).collect();
+    assert_eq!(names, ["main", "helper", "puts", "missing"]);
+
+    let puts = report.get("puts").expect("puts");
+    assert_eq!(puts.resolution, xref::Resolution::Imported);
+    assert_eq!(puts.sites, [xref::Site { from: "main".into(), at: 6 }, xref::Site { from: "helper".into(), at: 1 }]);
+    assert_eq!(report.get("helper").unwrap().resolution, xref::Resolution::Defined);
+    assert!(report.get("main").unwrap().sites.is_empty());
+
+    let unresolved: Vec<&str> = report.unresolved().map(|xref| xref.name.as_str()).collect();
+    assert_eq!(unresolved, ["missing"]);
+    assert_eq!(report.get("missing").unwrap().sites, [xref::Site { from: "main".into(), at: 13 }]);
+}
+
+#[test]
+fn objects_can_be_written_to_any_sink() {
+    use std::io::{BufWriter, Cursor, Write};
+    use target_lexicon::BinaryFormat;
+
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("blob", Decl::data().global(), vec![0xab; 4096]).expect("can define blob");
+    let bytes = obj.emit().expect("can emit");
+
+    // a buffered, seekable sink is streamed into
+    let mut buffered = BufWriter::new(Cursor::new(Vec::new()));
+    obj.write(&mut buffered).expect("can write to a buffered sink");
+    buffered.flush().expect("can flush");
+    assert_eq!(buffered.into_inner().expect("is flushed").into_inner(), bytes);
+
+    // and one which can't seek is written all at once
+    let mut unseekable = Vec::new();
+    obj.write_to(&mut unseekable, BinaryFormat::Elf).expect("can write to a sink which can't seek");
+    assert_eq!(unseekable, bytes);
+}
+
+#[test]
+fn spilled_function_bodies_are_streamed_into_the_object() {
+    use target_lexicon::BinaryFormat;
+
+    let spill = std::env::temp_dir().join(format!("faerie-{}-spill", std::process::id()));
+    let _ = std::fs::remove_file(&spill);
+    let artifact = |spill: Option<std::path::PathBuf>| {
+        let mut builder = ArtifactBuilder::new(triple!("x86_64-unknown-linux-gnu")).name("spill.o".into());
+        if let Some(spill) = spill {
+            builder = builder.spill(spill);
+        }
+        let mut obj = builder.finish();
+        obj.declare_with("f", Decl::function().global(), vec![0x90; 4096]).expect("can define f");
+        obj.declare_with("g", Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xc3]).expect("can define g");
+        obj.declare_with("d", Decl::data().global(), vec![0xab; 16]).expect("can define d");
+        obj.link(Link { from: "g", to: "f", at: 1 }).expect("can link g to f");
+        obj
+    };
+    let spilled = artifact(Some(spill.clone()));
+    // only the bodies of the functions were appended to the file
+    let contents = std::fs::read(&spill).expect("spill file exists");
+    assert_eq!(contents.len(), 4096 + 6);
+    assert_eq!(&contents[4096..], &[0xe8, 0, 0, 0, 0, 0xc3]);
+    let in_memory = artifact(None);
+    for &format in &[BinaryFormat::Elf, BinaryFormat::Macho] {
+        assert_eq!(spilled.emit_as(format).expect("can emit spilled"), in_memory.emit_as(format).expect("can emit in memory"));
+    }
+    std::fs::remove_file(&spill).expect("can remove spill file");
+}
+
+#[test]
+fn equal_artifacts_emit_identical_bytes() {
+    use target_lexicon::BinaryFormat;
+
+    // every artifact, and every map within it, is seeded with its own random hash keys
+    fn artifact() -> Artifact {
+        let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "same.o".into());
+        for i in 0..32 {
+            let (function, data, import, string) = (format!("f{}", i), format!("d{}", i), format!("i{}", i), format!("s{}", i));
+            let decl = if i % 2 == 0 { Decl::function().global() } else { Decl::function() };
+            obj.declare_with(&function, decl, vec![0xe8, 0, 0, 0, 0, 0xc3]).expect("can declare and define function");
+            obj.declare_with(&data, Decl::data().writable(), vec![0; 8]).expect("can declare and define data");
+            obj.declare_with(&string, Decl::cstring(), format!("string {}\0", i).into_bytes()).expect("can declare and define string");
+            obj.import(&import, if i % 2 == 0 { ImportKind::Function } else { ImportKind::Data }).expect("can import");
+            obj.link(Link { from: &function, to: if i % 2 == 0 { &import } else { "f0" }, at: 1 }).expect("can link from function");
+            obj.link(Link { from: &data, to: &function, at: 0 }).expect("can link from data");
+            obj.define_alias(format!("a{}", i), &function, Scope::Global).expect("can alias function");
+        }
+        obj.set_comdat("d0", "f0", ComdatSelection::Any).expect("can group d0");
+        obj
+    }
+    let (first, second) = (artifact(), artifact());
+    for &format in &[BinaryFormat::Elf, BinaryFormat::Macho] {
+        let bytes = first.emit_as(format).expect("can emit");
+        assert_eq!(bytes, first.emit_as(format).expect("can emit again"), "{} differs when emitted again", format);
+        assert_eq!(bytes, second.emit_as(format).expect("can emit the same artifact"), "{} differs for the same artifact", format);
+    }
+}
+
+#[test]
+fn strict_auto_rejects_links_faerie_would_guess() {
+    fn artifact(strict: bool, call: Reloc) -> Artifact {
+        let mut obj = ArtifactBuilder::new(triple!("x86_64-unknown-linux-gnu")).name("strict.o".into()).strict_auto(strict).finish();
+        obj.declare_with("main", Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0x48, 0x8d, 0x05, 0, 0, 0, 0, 0xc3]).expect("can define main");
+        obj.declare_with("helper", Decl::function(), vec![0xc3]).expect("can define helper");
+        obj.declare_with("handlers", Decl::data(), vec![0; 8]).expect("can define handlers");
+        obj.link(Link { from: "handlers", to: "helper", at: 0 }).expect("can link handlers to helper");
+        obj.link(Link { from: "main", to: "handlers", at: 8 }).expect("can link main to handlers");
+        obj.link_with(Link { from: "main", to: "helper", at: 1 }, call).expect("can link main to helper");
+        obj
+    }
+    let helper = Decl::function().into();
+    assert_eq!(AutoRelocation::classify(&Decl::data().into(), &helper), AutoRelocation::Unambiguous);
+    assert!(matches!(AutoRelocation::classify(&Decl::function().into(), &helper), AutoRelocation::Ambiguous(_)));
+    assert_eq!(AutoRelocation::classify(&Decl::function().into(), &Decl::debug_section().into()), AutoRelocation::Unsupported);
+
+    // a call is indistinguishable from taking the function's address, so faerie guesses, unless it is strict
+    assert!(artifact(false, Reloc::Auto).emit().is_ok());
+    let err = artifact(true, Reloc::Auto).emit().expect_err("strict artifact doesn't guess");
+    assert!(err.to_string().contains("Reloc::Plt"), "the error suggests the relocation to link with: {}", err);
+    // the pointer and the load of data are unambiguous
+    assert!(artifact(true, Reloc::Plt { addend: -4 }).emit().is_ok());
+}
+
+#[test]
+fn emit_formats_emits_each_format_as_emit_as_does() {
+    use target_lexicon::BinaryFormat;
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "multi.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xc3]).expect("can define main");
+    obj.import("exit", ImportKind::Function).expect("can import exit");
+    obj.link(Link { from: "main", to: "exit", at: 1 }).expect("can link main to exit");
+
+    let formats = [BinaryFormat::Elf, BinaryFormat::Macho, BinaryFormat::Wasm];
+    let emitted = obj.emit_formats(&formats);
+    assert_eq!(emitted.iter().map(|&(format, _)| format).collect::<Vec<_>>(), formats);
+    for (format, bytes) in emitted {
+        match format {
+            // one format which can't be emitted doesn't prevent the others
+            BinaryFormat::Wasm => assert!(bytes.is_err()),
+            _ => assert_eq!(bytes.expect("can emit"), obj.emit_as(format).expect("can emit"), "{} differs", format),
+        }
+    }
+    // nor can any format be emitted with an undefined declaration
+    obj.declare("missing", Decl::function()).expect("can declare missing");
+    assert!(obj.emit_formats(&formats).into_iter().all(|(_, bytes)| bytes.is_err()));
+}
+
+#[test]
+fn bss_declarations_take_no_space_in_any_format() {
+    use target_lexicon::BinaryFormat;
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "bss.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0x8b, 0x05, 0, 0, 0, 0, 0xc3]).expect("can define main");
+    obj.declare_bss("buffer", Decl::data().global(), 64 << 20, 4096).expect("can declare buffer");
+    obj.link(Link { from: "main", to: "buffer", at: 2 }).expect("can link main to buffer");
+    assert!(obj.declare_bss("misaligned", Decl::data(), 8, 3).is_err());
+    // it is defined by its size alone
+    assert!(obj.define("buffer", vec![0; 8]).is_err());
+
+    for &format in &[BinaryFormat::Elf, BinaryFormat::Macho] {
+        let bytes = obj.emit_as(format).expect("can emit");
+        assert!(bytes.len() < 4096, "the {} object is {} bytes", format, bytes.len());
+    }
+}
+
+#[test]
+fn dll_imports_and_exports_are_left_to_a_coff_backend() {
+    use faerie::artifact::ArtifactError;
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("run", Decl::function().global(), vec![0xc3]).expect("can declare and define run");
+    obj.declare_with("counter", Decl::data().global().writable(), vec![0; 4]).expect("can declare and define counter");
+    obj.declare_with("helper", Decl::function(), vec![0xc3]).expect("can declare and define helper");
+    obj.import("GetLastError", ImportKind::Function).expect("can import GetLastError");
+    obj.import("_environ", ImportKind::Data).expect("can import _environ");
+    assert!(obj.emit().is_ok());
+
+    obj.set_dll_import("GetLastError", true).expect("can import GetLastError from a DLL");
+    obj.set_dll_import("_environ", true).expect("can import _environ from a DLL");
+    obj.set_dll_import("_environ", false).expect("can import _environ statically");
+    obj.set_dll_export("run").expect("can export run");
+    obj.set_dll_export("counter").expect("can export counter");
+    obj.set_no_dead_strip("counter", true).expect("can keep counter");
+    obj.set_no_dead_strip("helper", true).expect("can keep helper");
+    match obj.set_dll_export("helper") {
+        Err(ArtifactError::NotExportable(name)) => assert_eq!(name, "helper"),
+        result => panic!("unexpected result: {:?}", result),
+    }
+    assert!(obj.set_dll_import("run", true).is_err());
+
+    assert_eq!(obj.dll_imports().collect::<Vec<_>>(), vec![("GetLastError", "__imp_GetLastError".to_string())]);
+    assert_eq!(obj.dll_exports().collect::<Vec<_>>(), vec!["run", "counter"]);
+    assert_eq!(obj.linker_directives(), " /EXPORT:run /EXPORT:counter,DATA /INCLUDE:counter");
+    // neither ELF nor Mach-O objects have DLL linkage
+    assert!(obj.emit().is_err());
+    assert!(obj.emit_as(target_lexicon::BinaryFormat::Macho).is_err());
+}
+
+#[test]
+fn declarations_definitions_and_links_can_be_read_back() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.import("puts", ImportKind::Function).expect("can import puts");
+    obj.import("helper", ImportKind::Function).expect("can import helper");
+    obj.declare_with("main", Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xe8, 0, 0, 0, 0, 0xc3]).expect("can define main");
+    obj.link(Link { from: "main", to: "puts", at: 1 }).expect("can link main to puts");
+    obj.link(Link { from: "main", to: "helper", at: 6 }).expect("can link main to helper");
+    assert!(!obj.is_import_satisfied("helper"));
+    obj.declare_with("helper", Decl::function(), vec![0xc3]).expect("can define helper");
+
+    assert!(obj.is_import_satisfied("helper"));
+    assert!(!obj.is_import_satisfied("puts"));
+    assert!(!obj.is_import_satisfied("missing"));
+    assert_eq!(obj.declaration("helper").map(|binding| binding.decl.is_import()), Some(false));
+    assert_eq!(obj.declaration("puts").map(|binding| binding.decl.is_import()), Some(true));
+    assert!(obj.declaration("missing").is_none());
+    assert_eq!(
+        obj.declared().map(|(binding, defined)| (binding.name, defined)).collect::<Vec<_>>(),
+        vec![("puts", false), ("helper", true), ("main", true)]
+    );
+
+    assert_eq!(obj.definition("helper").map(|def| def.data.clone()), Some(DataSource::Bytes(vec![0xc3])));
+    assert!(obj.definition("puts").is_none());
+    assert_eq!(obj.links_from("main").map(|link| (link.to.name, link.at)).collect::<Vec<_>>(), vec![("puts", 1), ("helper", 6)]);
+    assert_eq!(obj.links_to("helper").map(|link| (link.from.name, link.at)).collect::<Vec<_>>(), vec![("main", 6)]);
+    assert_eq!(obj.links_from("helper").count(), 0);
+}
+
+#[test]
+fn relocations_outside_of_their_definition_are_rejected() {
+    use faerie::artifact::ArtifactError;
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xc3]).expect("can declare and define main");
+    obj.declare_with("table", Decl::data(), vec![0; 12]).expect("can declare and define table");
+    // a call's 4 byte field can't start 3 bytes before the end of main
+    obj.link(Link { from: "main", to: "main", at: 3 }).expect("can link from main to itself");
+    let err = obj.emit().expect_err("the field is outside of main");
+    match err.downcast_ref::<ArtifactError>() {
+        Some(&ArtifactError::RelocationOutOfBounds { ref from, ref to, at, size, len }) => {
+            assert_eq!((from.as_str(), to.as_str(), at, size, len), ("main", "main", 3, 4, 6));
+        },
+        _ => panic!("unexpected error: {}", err),
+    }
+    assert!(err.to_string().contains("at 0x3"), "{}", err);
+
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xc3]).expect("can declare and define main");
+    obj.declare_with("table", Decl::data(), vec![0; 12]).expect("can declare and define table");
+    obj.link_with(Link { from: "table", to: "main", at: 4 }, Reloc::Absolute { size: 8, addend: 0 }).expect("can link from table to main");
+    obj.link_with(Link { from: "table", to: "main", at: 11 }, Reloc::Absolute { size: 1, addend: 255 }).expect("can link from table to main");
+    assert!(obj.emit().is_ok());
+    obj.link_with(Link { from: "table", to: "main", at: 10 }, Reloc::Absolute { size: 1, addend: 256 }).expect("can link from table to main");
+    let err = obj.emit().expect_err("the addend doesn't fit in a byte");
+    match err.downcast_ref::<ArtifactError>() {
+        Some(&ArtifactError::AddendOverflow { ref from, at, addend, size, .. }) => {
+            assert_eq!((from.as_str(), at, addend, size), ("table", 10, 256, 1));
+        },
+        _ => panic!("unexpected error: {}", err),
+    }
+    obj.link_with(Link { from: "table", to: "main", at: 8 }, Reloc::Absolute { size: 8, addend: 0 }).expect("can link from table to main");
+    assert!(obj.emit_formats(&[target_lexicon::BinaryFormat::Elf, target_lexicon::BinaryFormat::Macho]).iter().all(|(_, bytes)| bytes.is_err()));
+}
+
+#[test]
+fn definitions_can_be_replaced_and_removed() {
+    use faerie::artifact::ArtifactError;
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3]).expect("can declare and define f");
+    obj.declare_with("g", Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xc3]).expect("can declare and define g");
+    obj.declare("s", Decl::cstring()).expect("can declare s");
+    obj.define("s", b"hi\0".to_vec()).expect("can define s");
+    obj.declare("h", Decl::function().global()).expect("can declare h");
+    obj.link(Link { from: "g", to: "f", at: 1 }).expect("can link from g to f");
+
+    obj.redefine("f", vec![0x90, 0xc3]).expect("can redefine f");
+    assert_eq!(obj.definition("f").expect("f is defined").data.len(), 2);
+    match obj.redefine("s", b"hi".to_vec()) {
+        Err(ArtifactError::UnterminatedString(name)) => assert_eq!(name, "s"),
+        result => panic!("unexpected result: {:?}", result),
+    }
+    assert_eq!(obj.definition("s").expect("s is still defined").data.len(), 3);
+    match obj.redefine("h", vec![0xc3]) {
+        Err(ArtifactError::NotDefined(name)) => assert_eq!(name, "h"),
+        result => panic!("unexpected result: {:?}", result),
+    }
+    match obj.remove_definition("i") {
+        Err(ArtifactError::Undeclared(name)) => assert_eq!(name, "i"),
+        result => panic!("unexpected result: {:?}", result),
+    }
+    obj.define("h", vec![0xc3]).expect("can define h");
+
+    // the link from g fits in its new contents until it is shrunk
+    obj.redefine("g", vec![0x90, 0xe8, 0, 0, 0, 0, 0xc3]).expect("can redefine g");
+    assert!(obj.emit().is_ok());
+    obj.redefine("g", vec![0xc3]).expect("can redefine g");
+    assert!(obj.emit().is_err());
+
+    obj.remove_definition("g").expect("can remove g");
+    assert!(obj.definition("g").is_none());
+    assert_eq!(obj.links_from("g").count(), 0);
+    assert_eq!(obj.undefined_symbols(), vec!["g".to_string()]);
+    assert!(obj.emit().is_err());
+    obj.define("g", vec![0xe9, 0, 0, 0, 0]).expect("can define g again");
+    obj.link(Link { from: "g", to: "f", at: 1 }).expect("can link from g to f");
+    obj.remove_definition("f").expect("can remove f");
+    assert_eq!(obj.links_to("f").count(), 1);
+    obj.define("f", vec![0xc3]).expect("can define f again");
+    assert!(obj.emit().is_ok());
+}
@@ -1,20 +1,29 @@
 extern crate faerie;
 extern crate scroll;
 extern crate goblin;
+extern crate failure;
 #[macro_use]
 extern crate target_lexicon;
 
 use std::str::FromStr;
 
-use faerie::{Artifact, Decl, Link};
+use faerie::{Artifact, ArtifactBuilder, Decl, Link, Reloc, ImportKind, Scope, IsaLevel, Strip, TlsModel, ComdatSelection};
+use faerie::{Archive, ArchiveFormat, LinkerDirective, RelocModel, SymbolKind, Visibility};
 use goblin::elf::*;
 
+/// The path of the file `name` of the test `test` in the temporary directory, which no other test or concurrent run
+/// of the tests writes to
+fn temp_path(test: &str, name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("faerie-{}-{}-{}", std::process::id(), test, name));
+    path.to_str().expect("utf8 path").to_string()
+}
+
 #[test]
 // This test is for a known bug (issue #31).
 fn file_name_is_same_as_symbol_name_issue_31() {
     const NAME: &str = "a";
     let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "a".into());
-    obj.declare(NAME, Decl::Function { global: true }).expect("can declare");
+    obj.declare(NAME, Decl::function().global()).expect("can declare");
     obj.define(NAME, vec![1, 2, 3, 4]).expect("can define");
     println!("\n{:#?}", obj);
     let bytes = obj.emit().expect("can emit elf file");
@@ -22,10 +31,11 @@ fn file_name_is_same_as_symbol_name_issue_31() {
     println!("{:?}", bytes);
 
     // Presently, the following expect fails, `bytes` is not a valid Elf:
-    let elf = goblin::Object::parse(&bytes).expect("can parse elf file");
+    let elf = goblin::Object::parse(bytes).expect("can parse elf file");
     match elf {
         goblin::Object::Elf(elf) => {
-            assert_eq!(elf.syms.len(), 4);
+            // nothing is relocated against the function's section, so it has no section symbol
+            assert_eq!(elf.syms.len(), 3);
             let syms =  elf.syms.iter().collect::<Vec<_>>();
             let sym = syms.iter().find(|sym| {
                 sym.st_shndx == section_header::SHN_ABS as usize
@@ -51,8 +61,8 @@ fn file_name_is_same_as_symbol_name_issue_31() {
 fn link_symbol_pair_panic_issue_30() {
     let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
 
-    obj.declare("a", Decl::Function { global: true }).expect("can declare a");
-    obj.declare_with("b", Decl::Function { global: true }, vec![1, 2, 3, 4]).expect("can declare and define b");
+    obj.declare("a", Decl::function().global()).expect("can declare a");
+    obj.declare_with("b", Decl::function().global(), vec![1, 2, 3, 4]).expect("can declare and define b");
 
 
     obj.link(Link {
@@ -67,3 +77,3896 @@ fn link_symbol_pair_panic_issue_30() {
     // and return an error describing them:
     assert!(obj.emit().is_err());
 }
+
+#[test]
+fn absolute_symbol_relocation() {
+    const REGISTER: u64 = 0xfee0_0020;
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare("APIC_EOI", Decl::absolute(REGISTER).global()).expect("can declare absolute");
+    // movabs eax, [APIC_EOI]; ret
+    obj.declare_with("eoi", Decl::function().global(), vec![0x48, 0xa1, 0, 0, 0, 0, 0, 0, 0, 0, 0xc3])
+        .expect("can declare and define eoi");
+    obj.link(Link { from: "eoi", to: "APIC_EOI", at: 2 }).expect("can link from eoi to APIC_EOI");
+    assert!(obj.define("APIC_EOI", vec![0]).is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    let syms = elf.syms.iter().collect::<Vec<_>>();
+    let (abs_idx, abs) = syms.iter().enumerate().find(|&(_, sym)| &elf.strtab[sym.st_name] == "APIC_EOI")
+        .expect("There should be an APIC_EOI symbol");
+    assert_eq!(abs.st_shndx, section_header::SHN_ABS as usize);
+    assert_eq!(abs.st_value, REGISTER);
+    assert_eq!(abs.st_bind(), sym::STB_GLOBAL);
+
+    assert_eq!(elf.shdr_relocs.len(), 1);
+    let relocs = &elf.shdr_relocs[0].1;
+    assert_eq!(relocs.len(), 1);
+    assert_eq!(relocs[0].r_type, reloc::R_X86_64_64);
+    assert_eq!(relocs[0].r_sym, abs_idx);
+    assert_eq!(relocs[0].r_offset, 2);
+    assert_eq!(relocs[0].r_addend, Some(0));
+}
+
+#[test]
+fn file_backed_definition_is_streamed() {
+    use faerie::DataSource;
+    use std::io::Write;
+
+    let path = std::path::PathBuf::from(temp_path("file-backed", "data.bin"));
+    {
+        let mut file = std::fs::File::create(&path).expect("can create data file");
+        file.write_all(b"garbageTABLE-CONTENTSgarbage").expect("can write data file");
+    }
+
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare("table", Decl::data().global()).expect("can declare table");
+    obj.define_from("table", DataSource::File { path: path.clone(), offset: 7, size: 14 }).expect("can define table");
+    obj.declare("short", Decl::data().global()).expect("can declare short");
+    obj.define_from("short", DataSource::File { path: path.clone(), offset: 7, size: 1000 }).expect("can define short");
+    // the file is only read during emission, so the short read is only detected then
+    assert!(obj.emit().is_err());
+
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare("table", Decl::data().global()).expect("can declare table");
+    obj.define_from("table", DataSource::File { path: path.clone(), offset: 7, size: 14 }).expect("can define table");
+    let bytes = obj.emit().expect("can emit elf file");
+    std::fs::remove_file(&path).expect("can remove data file");
+
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    let section = elf.section_headers.iter().find(|shdr| &elf.shdr_strtab[shdr.sh_name] == ".data.table")
+        .expect("There should be a .data.table section");
+    let start = section.sh_offset as usize;
+    assert_eq!(&bytes[start..start + section.sh_size as usize], b"TABLE-CONTENTS");
+}
+
+#[test]
+fn fill_patterns_are_expanded_when_written() {
+    use faerie::DataSource;
+
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declarations(vec![
+        ("flash", Decl::data().global().into()),
+        ("poison", Decl::data().global().writable().into()),
+        ("empty", Decl::data().global().into()),
+        ("bss", Decl::data().global().zero_init(8).into()),
+    ].into_iter()).expect("can declare");
+    // only the pattern is held until the object is written
+    obj.define_filled("flash", vec![0xff], 1 << 20).expect("can define flash");
+    obj.define_filled("poison", vec![0xde, 0xad, 0xbe, 0xef], 6).expect("can define poison");
+    assert!(obj.define_filled("empty", Vec::new(), 4).is_err(), "an empty pattern can't fill anything");
+    obj.define_filled("empty", Vec::new(), 0).expect("can define nothing with an empty pattern");
+    obj.define_from("bss", DataSource::Fill { pattern: vec![0, 0], size: 64 }).expect("can define bss with zeros");
+    let bytes = obj.emit().expect("can emit elf file");
+
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let contents = |name: &str| {
+        let section = elf.section_headers.iter().find(|shdr| &elf.shdr_strtab[shdr.sh_name] == name).expect("has section");
+        &bytes[section.sh_offset as usize..(section.sh_offset + section.sh_size) as usize]
+    };
+    let flash = contents(".data.flash");
+    assert_eq!(flash.len(), 1 << 20);
+    assert!(flash.iter().all(|&byte| byte == 0xff));
+    // the last repetition is truncated
+    assert_eq!(contents(".data.poison"), &[0xde, 0xad, 0xbe, 0xef, 0xde, 0xad]);
+    let bss = elf.section_headers.iter().find(|shdr| &elf.shdr_strtab[shdr.sh_name] == ".bss").expect("has .bss");
+    assert_eq!(bss.sh_type, section_header::SHT_NOBITS);
+    assert_eq!(bss.sh_size, 64);
+}
+
+#[test]
+fn strtab_shares_symbol_names_with_section_names() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    let names = (0..32).map(|i| format!("_ZN4core3fmt9Formatter{}write_str17h{:016x}E", i, i)).collect::<Vec<_>>();
+    for name in &names {
+        // jmp _ZN4core3fmt5writeE
+        obj.declare_with(name, Decl::function().global(), vec![0xe9, 0, 0, 0, 0]).expect("can declare and define");
+    }
+    obj.declare("_ZN4core3fmt5writeE", Decl::FunctionImport).expect("can import");
+    obj.link(Link { from: &names[0], to: "_ZN4core3fmt5writeE", at: 1 }).expect("can link");
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+
+    // every name still resolves to what it was before merging
+    for name in &names {
+        assert!(elf.syms.iter().any(|sym| &elf.strtab[sym.st_name] == name.as_str()));
+        let section_name = format!(".text.{}", name);
+        assert!(elf.section_headers.iter().any(|shdr| elf.shdr_strtab[shdr.sh_name] == *section_name));
+    }
+    assert!(elf.section_headers.iter().any(|shdr| &elf.shdr_strtab[shdr.sh_name] == ".note.GNU-stack"));
+
+    // the unmerged table would store each symbol name a second time on its own
+    let strtab = &elf.section_headers[1];
+    let unmerged = ["", ".strtab", ".symtab", "t.o", "_ZN4core3fmt5writeE", ".note.GNU-stack"].iter()
+        .map(|name| name.len() + 1)
+        .chain(names.iter().map(|name| name.len() + 1 + format!(".text.{}", name).len() + 1))
+        .chain(Some(format!(".reloc.{}", names[0]).len() + 1))
+        .sum::<usize>();
+    let merged = unmerged - names.iter().map(|name| name.len() + 1).sum::<usize>();
+    assert_eq!(strtab.sh_size as usize, merged);
+}
+
+#[test]
+fn cold_functions_are_placed_in_text_unlikely() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("hot", Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xc3]).expect("can declare and define hot");
+    obj.declare_with("oops", Decl::function().cold(), vec![0xc3]).expect("can declare and define oops");
+    obj.declare_with("panic", Decl::function().global().noreturn(), vec![0x0f, 0x0b]).expect("can declare and define panic");
+    obj.link(Link { from: "hot", to: "oops", at: 1 }).expect("can link from hot to oops");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    let section_names = elf.section_headers.iter().map(|shdr| &elf.shdr_strtab[shdr.sh_name]).collect::<Vec<_>>();
+    assert!(section_names.contains(&".text.hot"));
+    assert!(section_names.contains(&".text.unlikely.oops"));
+    assert!(section_names.contains(&".text.unlikely.panic"));
+
+    // the symbols are still placed in their respective sections
+    for sym in elf.syms.iter().filter(|sym| sym.st_type() == sym::STT_FUNC) {
+        let name = &elf.strtab[sym.st_name];
+        let section = &elf.shdr_strtab[elf.section_headers[sym.st_shndx].sh_name];
+        assert!(section.ends_with(&format!(".{}", name)));
+    }
+}
+
+#[test]
+fn buildid_note_is_well_formed() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    // an odd length definition, so the note has to be realigned
+    obj.declare_with("main", Decl::function().global(), vec![0x31, 0xc0, 0xc3]).expect("can declare and define main");
+    obj.set_buildid_note("Go", b"abc/defg".to_vec()).expect("can set go build id");
+    // setting it again replaces the old id
+    obj.set_buildid_note("Go", b"fingerprint".to_vec()).expect("can reset go build id");
+    obj.add_note(".note.runtime", "Rust", 1, vec![1, 2, 3, 4, 5]).expect("can add note");
+    obj.add_note(".note.runtime", "Rust", 2, vec![]).expect("can add second note");
+    assert!(obj.set_buildid_note("", vec![1]).is_err());
+    assert!(obj.add_note(".note.runtime", "Ru\0st", 1, vec![]).is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    let section = elf.section_headers.iter()
+        .find(|shdr| &elf.shdr_strtab[shdr.sh_name] == ".note.go.buildid")
+        .expect("has a go build id section");
+    assert_eq!(section.sh_type, section_header::SHT_NOTE);
+    assert_eq!(section.sh_offset % 4, 0);
+
+    let notes = elf.iter_note_sections(&bytes, Some(".note.go.buildid")).expect("has notes")
+        .collect::<Result<Vec<_>, _>>().expect("can parse notes");
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].name, "Go");
+    assert_eq!(notes[0].n_type, faerie::NT_GO_BUILD_ID);
+    assert_eq!(notes[0].desc, b"fingerprint");
+
+    let notes = elf.iter_note_sections(&bytes, Some(".note.runtime")).expect("has notes")
+        .collect::<Result<Vec<_>, _>>().expect("can parse notes");
+    assert_eq!(notes.iter().map(|note| (note.name, note.n_type, note.desc)).collect::<Vec<_>>(),
+               vec![("Rust", 1, &[1u8, 2, 3, 4, 5][..]), ("Rust", 2, &[][..])]);
+}
+
+#[test]
+fn content_buildid_is_reproducible() {
+    fn buildid(code: Vec<u8>, link: bool) -> Vec<u8> {
+        let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+        obj.set_content_buildid().expect("can set content build id");
+        obj.declare_with("main", Decl::function().global(), code).expect("can declare and define main");
+        obj.declare_with("table", Decl::data(), vec![0; 8]).expect("can declare and define table");
+        obj.declare_with(".debug_str", Decl::debug_section(), b"faerie\0".to_vec()).expect("can define debug section");
+        if link {
+            obj.link(Link { from: "table", to: "main", at: 0 }).expect("can link table to main");
+        }
+        obj.add_note(".note.runtime", "Rust", 1, vec![1, 2, 3]).expect("can add note");
+        let bytes = obj.emit().expect("can emit elf file");
+        let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+            goblin::Object::Elf(elf) => elf,
+            _ => panic!("Elf file not parsed as elf file"),
+        };
+        let notes = elf.iter_note_sections(&bytes, Some(".note.gnu.build-id")).expect("has a build id")
+            .collect::<Result<Vec<_>, _>>().expect("can parse notes");
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].name, "GNU");
+        assert_eq!(notes[0].n_type, faerie::NT_GNU_BUILD_ID);
+        notes[0].desc.to_vec()
+    }
+    let id = buildid(vec![0x31, 0xc0, 0xc3], true);
+    // the SHA-1 of the encoding set_content_buildid documents, for main, table and the note
+    assert_eq!(id, vec![
+        0x84, 0x38, 0xcb, 0x81, 0xf4, 0x5e, 0xc9, 0x72, 0x90, 0x33,
+        0xe0, 0xe6, 0x33, 0x60, 0x4c, 0x9d, 0x9e, 0xe1, 0x2e, 0xe6,
+    ]);
+    assert_eq!(buildid(vec![0x31, 0xc0, 0xc3], true), id);
+    // relocations haven't been applied to the contents, so they don't change the id
+    assert_eq!(buildid(vec![0x31, 0xc0, 0xc3], false), id);
+    assert_ne!(buildid(vec![0x90, 0x31, 0xc0, 0xc3], true), id);
+}
+
+#[test]
+fn package_note_holds_null_terminated_json() {
+    const METADATA: &str = r#"{"type":"deb","name":"hello","version":"1.0-1"}"#;
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.set_package_note("{}").expect("can set package metadata");
+    obj.set_package_note(METADATA).expect("can reset package metadata");
+    assert!(obj.set_package_note("{\"name\":\"a\0b\"}").is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    let notes = elf.iter_note_sections(&bytes, Some(".note.package")).expect("has notes")
+        .collect::<Result<Vec<_>, _>>().expect("can parse notes");
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].name, "FDO");
+    assert_eq!(notes[0].n_type, faerie::NT_FDO_PACKAGING_METADATA);
+    assert_eq!(notes[0].desc, format!("{}\0", METADATA).as_bytes());
+}
+
+#[test]
+fn empty_sections_are_emitted() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0xc3]).expect("can declare and define main");
+    obj.add_empty_section(".note.marker");
+    obj.add_empty_section(".note.marker");
+    obj.executable_stack = true;
+    obj.mark_non_executable_stack();
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    let section = |name: &str| {
+        let sections = elf.section_headers.iter().filter(|shdr| &elf.shdr_strtab[shdr.sh_name] == name).collect::<Vec<_>>();
+        assert_eq!(sections.len(), 1, "{} is emitted once", name);
+        sections[0].clone()
+    };
+    for &name in &[".note.marker", ".note.GNU-stack"] {
+        let shdr = section(name);
+        assert_eq!(shdr.sh_type, section_header::SHT_PROGBITS);
+        assert_eq!(shdr.sh_size, 0);
+        assert_eq!(shdr.sh_flags, 0);
+    }
+
+    obj.executable_stack = true;
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    let stack = elf.section_headers.iter().find(|shdr| &elf.shdr_strtab[shdr.sh_name] == ".note.GNU-stack").expect("has .note.GNU-stack");
+    assert_eq!(stack.sh_flags, section_header::SHF_EXECINSTR as u64);
+}
+
+#[test]
+fn size_relocation_resolves_against_the_sized_symbol() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("table", Decl::data(), vec![0; 24]).expect("can declare and define table");
+    // table_len: .quad table@SIZE - 8; .long table@SIZE
+    obj.declare_with("table_len", Decl::data().global(), vec![0; 12]).expect("can declare and define table_len");
+    obj.link_with(Link { from: "table_len", to: "table", at: 0 }, Reloc::Size { size: 8, addend: -8 })
+        .expect("can link to the size of table");
+    obj.link_with(Link { from: "table_len", to: "table", at: 8 }, Reloc::Size { size: 4, addend: 0 })
+        .expect("can link to the size of table");
+
+    obj.import("extern_table", ImportKind::Data).expect("can import extern_table");
+    assert!(obj.link_with(Link { from: "table_len", to: "extern_table", at: 0 }, Reloc::Size { size: 8, addend: 0 }).is_err());
+    assert!(obj.link_with(Link { from: "table_len", to: "table", at: 0 }, Reloc::Size { size: 2, addend: 0 }).is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    let table_idx = elf.syms.iter().position(|sym| &elf.strtab[sym.st_name] == "table")
+        .expect("There should be a table symbol");
+    assert_eq!(elf.syms.get(table_idx).unwrap().st_size, 24);
+
+    assert_eq!(elf.shdr_relocs.len(), 1);
+    let relocs = &elf.shdr_relocs[0].1;
+    assert_eq!(relocs.len(), 2);
+    assert_eq!((relocs[0].r_type, relocs[0].r_sym, relocs[0].r_offset, relocs[0].r_addend),
+               (reloc::R_X86_64_SIZE64, table_idx, 0, Some(-8)));
+    assert_eq!((relocs[1].r_type, relocs[1].r_sym, relocs[1].r_offset, relocs[1].r_addend),
+               (reloc::R_X86_64_SIZE32, table_idx, 8, Some(0)));
+
+    assert!(obj.emit_as(target_lexicon::BinaryFormat::Macho).is_err());
+}
+
+#[test]
+fn nobits_sections_have_a_size_but_no_contents() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0x31, 0xc0, 0xc3]).expect("can declare and define main");
+    obj.declare_with("table", Decl::data().writable(), vec![0x5a; 24]).expect("can declare and define table");
+    obj.declare_with(".debug_info", Decl::debug_section(), vec![0; 16]).expect("can declare and define .debug_info");
+
+    // the debug file keeps the layout of the loaded sections, as NOBITS
+    let (_, bytes) = obj.split_debug(target_lexicon::BinaryFormat::Elf, "t.debug").expect("can split debug info");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    let section = |name| elf.section_headers.iter().find(|shdr| &elf.shdr_strtab[shdr.sh_name] == name).expect("has section");
+    for &(name, size) in &[(".text.main", 3), (".data.table", 24)] {
+        let shdr = section(name);
+        assert_eq!(shdr.sh_type, section_header::SHT_NOBITS);
+        // the size in memory, though nothing is stored in the file
+        assert_eq!(shdr.sh_size, size);
+        assert!(shdr.sh_offset <= bytes.len() as u64);
+    }
+    assert!(!bytes.windows(24).any(|window| window == &[0x5a; 24][..]));
+    assert!(!bytes.windows(3).any(|window| window == &[0x31, 0xc0, 0xc3][..]));
+}
+
+#[test]
+fn split_debug_partitions_debug_sections() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0x31, 0xc0, 0xc3]).expect("can declare and define main");
+    obj.declare_with(".debug_info", Decl::debug_section(), vec![0; 16]).expect("can declare and define .debug_info");
+    obj.declare_with(".debug_abbrev", Decl::debug_section(), vec![0; 4]).expect("can declare and define .debug_abbrev");
+    // DW_AT_low_pc
+    obj.link(Link { from: ".debug_info", to: "main", at: 8 }).expect("can link from .debug_info to main");
+    // debug_abbrev_offset
+    obj.link_with(Link { from: ".debug_info", to: ".debug_abbrev", at: 0 }, Reloc::Raw { reloc: reloc::R_X86_64_32, addend: 0, size: None })
+        .expect("can link from .debug_info to .debug_abbrev");
+
+    let (stripped_bytes, debug_bytes) = obj.split_debug(target_lexicon::BinaryFormat::Elf, "t.debug").expect("can split debug info");
+    let parse = |bytes| match goblin::Object::parse(bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    let section = |elf: &Elf, name| elf.section_headers.iter().find(|shdr| &elf.shdr_strtab[shdr.sh_name] == name).cloned();
+    let has_main = |elf: &Elf| elf.syms.iter().any(|sym| &elf.strtab[sym.st_name] == "main");
+
+    let debug = parse(&debug_bytes);
+    assert_eq!(section(&debug, ".debug_info").expect("has .debug_info").sh_type, section_header::SHT_PROGBITS);
+    assert!(section(&debug, ".debug_abbrev").is_some());
+    assert_eq!(section(&debug, ".text.main").expect("has .text.main").sh_type, section_header::SHT_NOBITS);
+    assert!(has_main(&debug));
+    assert_eq!(debug.shdr_relocs.len(), 1);
+    assert_eq!(debug.shdr_relocs[0].1.len(), 2);
+
+    let stripped = parse(&stripped_bytes);
+    assert!(section(&stripped, ".debug_info").is_none());
+    assert!(section(&stripped, ".debug_abbrev").is_none());
+    assert_eq!(section(&stripped, ".text.main").expect("has .text.main").sh_type, section_header::SHT_PROGBITS);
+    assert!(has_main(&stripped));
+    assert!(stripped.shdr_relocs.is_empty());
+
+    // the debuglink is the null-terminated file name, padded to 4 bytes, and then the CRC-32 of the debug file
+    let debuglink = section(&stripped, ".gnu_debuglink").expect("has .gnu_debuglink");
+    let debuglink = &stripped_bytes[debuglink.sh_offset as usize..(debuglink.sh_offset + debuglink.sh_size) as usize];
+    assert_eq!(&debuglink[..8], b"t.debug\0");
+    let mut crc = !0u32;
+    for &byte in &debug_bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    assert_eq!(debuglink[8..], u32::to_le_bytes(!crc));
+}
+
+#[test]
+fn weak_definitions_have_weak_binding() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("local_fn", Decl::function(), vec![0xc3]).expect("can declare and define local_fn");
+    obj.declare_with("inline_fn", Decl::function().weak(), vec![0xc3]).expect("can declare and define inline_fn");
+    obj.declare_with("guard", Decl::data().weak(), vec![0; 8]).expect("can declare and define guard");
+    obj.declare_with("main", Decl::function().global(), vec![0xc3]).expect("can declare and define main");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    let binding = |name| elf.syms.iter().find(|sym| &elf.strtab[sym.st_name] == name).expect("has symbol").st_bind();
+    assert_eq!(binding("local_fn"), sym::STB_LOCAL);
+    assert_eq!(binding("inline_fn"), sym::STB_WEAK);
+    assert_eq!(binding("guard"), sym::STB_WEAK);
+    assert_eq!(binding("main"), sym::STB_GLOBAL);
+    // weak symbols are not local, so come after the symtab's local delimiter
+    let symtab = &elf.section_headers[2];
+    for (i, sym) in elf.syms.iter().enumerate() {
+        assert_eq!(sym.st_bind() == sym::STB_LOCAL, i < symtab.sh_info as usize);
+    }
+}
+
+#[test]
+fn batch_resolves_sibling_absolute_imports() {
+    const BASE: u64 = 0x4000_1000;
+    let mut board = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "board.o".into());
+    board.declare("UART_BASE", Decl::absolute(BASE).global()).expect("can declare absolute");
+    board.declare("PRIVATE", Decl::absolute(1)).expect("can declare local absolute");
+
+    let mut driver = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "driver.o".into());
+    driver.import("UART_BASE", ImportKind::Data).expect("can import UART_BASE");
+    driver.import("PRIVATE", ImportKind::Data).expect("can import PRIVATE");
+    driver.declare_with("uart_table", Decl::data().global(), vec![0; 16]).expect("can declare and define uart_table");
+    driver.link(Link { from: "uart_table", to: "UART_BASE", at: 8 }).expect("can link from uart_table to UART_BASE");
+
+    let mut other = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "other.o".into());
+    other.declare("UART_BASE", Decl::absolute(BASE + 1).global()).expect("can declare absolute");
+    let mut conflicting = vec![board.clone(), driver.clone(), other];
+    assert!(Artifact::resolve_batch(&mut conflicting).is_err());
+
+    let mut batch = vec![board, driver];
+    assert_eq!(Artifact::resolve_batch(&mut batch).expect("can resolve batch"), 1);
+    let driver = &batch[1];
+    // local absolutes are not visible to siblings
+    assert_eq!(driver.imports().map(|(name, _)| name).collect::<Vec<_>>(), vec!["PRIVATE"]);
+
+    let bytes = driver.emit().expect("can emit elf file");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    assert!(elf.shdr_relocs.is_empty());
+    let uart_base = elf.syms.iter().find(|sym| &elf.strtab[sym.st_name] == "UART_BASE").expect("has UART_BASE");
+    assert_eq!(uart_base.st_shndx, section_header::SHN_ABS as usize);
+    assert_eq!(uart_base.st_bind(), sym::STB_LOCAL);
+    let table = elf.section_headers.iter().find(|shdr| &elf.shdr_strtab[shdr.sh_name] == ".data.uart_table").expect("has uart_table");
+    let table = &bytes[table.sh_offset as usize..(table.sh_offset + table.sh_size) as usize];
+    assert_eq!(table[..8], [0; 8]);
+    assert_eq!(table[8..], u64::to_le_bytes(BASE));
+}
+
+#[test]
+fn debug_sections_are_not_allocated() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0xc3]).expect("can declare and define main");
+    obj.declare_with(".debug_info", Decl::debug_section(), vec![0; 16]).expect("can declare and define .debug_info");
+    obj.declare_with(".debug_str", Decl::debug_section().strings(), b"main\0int\0".to_vec()).expect("can declare and define .debug_str");
+    obj.link(Link { from: ".debug_info", to: "main", at: 8 }).expect("can link from .debug_info to main");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    let section = |name| elf.section_headers.iter().find(|shdr| &elf.shdr_strtab[shdr.sh_name] == name).expect("has section");
+    let info = section(".debug_info");
+    assert_eq!(info.sh_type, section_header::SHT_PROGBITS);
+    assert_eq!(info.sh_flags, 0);
+    assert_eq!(info.sh_entsize, 0);
+    let strings = section(".debug_str");
+    assert_eq!(strings.sh_type, section_header::SHT_PROGBITS);
+    assert_eq!(strings.sh_flags, u64::from(section_header::SHF_MERGE | section_header::SHF_STRINGS));
+    assert_eq!(strings.sh_entsize, 1);
+    assert_eq!(section(".text.main").sh_flags & u64::from(section_header::SHF_ALLOC), u64::from(section_header::SHF_ALLOC));
+}
+
+#[test]
+fn legacy_ctors_are_emitted_in_reverse() {
+    fn constructors(legacy_ctors: bool) -> Vec<(String, u32, Vec<String>)> {
+        let mut obj = faerie::ArtifactBuilder::new(triple!("x86_64-unknown-unknown-unknown-elf"))
+            .name("t.o".into())
+            .legacy_ctors(legacy_ctors)
+            .finish();
+        for name in &["first", "second", "early"] {
+            obj.declare_with(name, Decl::function(), vec![0xc3]).expect("can declare and define function");
+        }
+        obj.declare_with("data", Decl::data(), vec![0; 8]).expect("can declare and define data");
+        assert!(obj.add_constructor("data", None).is_err());
+        assert!(obj.add_constructor("missing", None).is_err());
+        obj.add_constructor("first", None).expect("can add constructor");
+        obj.add_constructor("second", None).expect("can add constructor");
+        obj.add_constructor("early", Some(200)).expect("can add constructor");
+
+        let bytes = obj.emit().expect("can emit elf file");
+        let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+            goblin::Object::Elf(elf) => elf,
+            _ => panic!("Elf file not parsed as elf file"),
+        };
+        let syms = elf.syms.iter().collect::<Vec<_>>();
+        elf.shdr_relocs.iter().map(|&(idx, ref relocs)| {
+            let array = &elf.section_headers[elf.section_headers[idx].sh_info as usize];
+            let functions = relocs.iter().map(|reloc| {
+                assert_eq!(reloc.r_type, reloc::R_X86_64_64);
+                // each pointer refers to the section symbol of its function
+                let shdr = &elf.section_headers[syms[reloc.r_sym].st_shndx];
+                elf.shdr_strtab[shdr.sh_name].to_string()
+            }).collect();
+            (elf.shdr_strtab[array.sh_name].to_string(), array.sh_type, functions)
+        }).collect()
+    }
+    let text = |names: &[&str]| names.iter().map(|name| format!(".text.{}", name)).collect::<Vec<_>>();
+    assert_eq!(constructors(false), vec![
+        (".init_array".to_string(), section_header::SHT_INIT_ARRAY, text(&["first", "second"])),
+        (".init_array.00200".to_string(), section_header::SHT_INIT_ARRAY, text(&["early"])),
+    ]);
+    // .ctors is run from the end, and its priority sections are sorted by name
+    assert_eq!(constructors(true), vec![
+        (".ctors".to_string(), section_header::SHT_PROGBITS, text(&["second", "first"])),
+        (".ctors.65335".to_string(), section_header::SHT_PROGBITS, text(&["early"])),
+    ]);
+}
+
+#[test]
+fn absolute_symbols_have_binding_and_visibility() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare("ABI_VERSION", Decl::absolute(0xffff_ffff_0000_0002).global()).expect("can declare absolute");
+    obj.declare("PAGE_SIZE", Decl::absolute(0x1000).weak()).expect("can declare absolute");
+    obj.declare("BUILD_ANCHOR", Decl::absolute(0x10).global().hidden()).expect("can declare absolute");
+    obj.declare_with("table", Decl::data().global(), vec![0; 8]).expect("can declare and define table");
+    obj.link(Link { from: "table", to: "ABI_VERSION", at: 0 }).expect("can link from table to ABI_VERSION");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    let syms = elf.syms.iter().collect::<Vec<_>>();
+    let sym = |name| syms.iter().position(|sym| &elf.strtab[sym.st_name] == name).expect("has symbol");
+    for &(name, bind, other) in &[("ABI_VERSION", sym::STB_GLOBAL, 0), ("PAGE_SIZE", sym::STB_WEAK, 0), ("BUILD_ANCHOR", sym::STB_GLOBAL, 2)] {
+        let abs = &syms[sym(name)];
+        assert_eq!(abs.st_shndx, section_header::SHN_ABS as usize);
+        assert_eq!(abs.st_bind(), bind);
+        assert_eq!(abs.st_other, other);
+    }
+    assert_eq!(syms[sym("ABI_VERSION")].st_value, 0xffff_ffff_0000_0002);
+    let relocs = elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter()).collect::<Vec<_>>();
+    assert_eq!(relocs.len(), 1);
+    assert_eq!(relocs[0].r_type, reloc::R_X86_64_64);
+    assert_eq!(relocs[0].r_sym, sym("ABI_VERSION"));
+}
+
+#[test]
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn partial_link_preserves_symbols_and_relocations() {
+    use std::process::Command;
+    fn ld(args: &[&str]) -> Option<bool> {
+        Command::new("ld").args(args).status().ok().map(|status| status.success())
+    }
+    let [a_path, b_path, partial, exe] = ["a.o", "b.o", "partial.o", "exe"].map(|name| temp_path("partial", name));
+
+    // _start calls the local helper, then the weak hook, and exits with its result
+    let mut a = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "a.o".into());
+    a.declare_with("_start", Decl::function().global(), vec![
+        0xe8, 0, 0, 0, 0,               // call helper
+        0xe8, 0, 0, 0, 0,               // call hook
+        0x48, 0x89, 0xc7,               // mov rdi, rax
+        0xb8, 60, 0, 0, 0,              // mov eax, SYS_exit
+        0x0f, 0x05,                     // syscall
+    ]).expect("can declare and define _start");
+    a.declare_with("helper", Decl::function(), vec![0xc3]).expect("can declare and define helper");
+    a.declare_with("hook", Decl::function().weak(), vec![0xb8, 1, 0, 0, 0, 0xc3]).expect("can declare and define hook");
+    a.link(Link { from: "_start", to: "helper", at: 1 }).expect("can link from _start to helper");
+    a.link(Link { from: "_start", to: "hook", at: 6 }).expect("can link from _start to hook");
+    // hook overrides the weak definition, and loads a local with the same name as a's helper
+    let mut b = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "b.o".into());
+    b.declare_with("hook", Decl::function().global(), vec![0x48, 0x8b, 0x05, 0, 0, 0, 0, 0xc3]).expect("can declare and define hook");
+    b.declare_with("helper", Decl::data(), vec![42, 0, 0, 0, 0, 0, 0, 0]).expect("can declare and define helper");
+    b.link(Link { from: "hook", to: "helper", at: 3 }).expect("can link from hook to helper");
+    std::fs::write(&a_path, a.emit().expect("can emit elf file")).expect("can write a.o");
+    std::fs::write(&b_path, b.emit().expect("can emit elf file")).expect("can write b.o");
+
+    match ld(&["-r", &a_path, &b_path, "-o", &partial]) {
+        Some(linked) => assert!(linked, "ld -r failed"),
+        None => return eprintln!("ld is not available, skipping"),
+    }
+    assert_eq!(ld(&[&partial, "-o", &exe]), Some(true), "ld failed on the partially linked object");
+    let status = Command::new(&exe).status().expect("can run the linked executable");
+    for file in &[a_path, b_path, partial, exe] {
+        let _ = std::fs::remove_file(file);
+    }
+    assert_eq!(status.code(), Some(42));
+}
+
+#[test]
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn archive_members_are_pulled_in_by_their_indexed_symbols() {
+    use std::process::Command;
+    let [main_path, lib, exe] = ["main.o", "libanswer.a", "archive"].map(|name| temp_path("archive", name));
+
+    // _start exits with the answer, which is only defined by a member of the archive
+    let mut main = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "main.o".into());
+    main.declare_with("_start", Decl::function().global(), vec![
+        0xe8, 0, 0, 0, 0,               // call answer
+        0x48, 0x89, 0xc7,               // mov rdi, rax
+        0xb8, 60, 0, 0, 0,              // mov eax, SYS_exit
+        0x0f, 0x05,                     // syscall
+    ]).expect("can declare and define _start");
+    main.import("answer", ImportKind::Function).expect("can import answer");
+    main.link(Link { from: "_start", to: "answer", at: 1 }).expect("can link from _start to answer");
+    let mut answer = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "answer.o".into());
+    answer.declare_with("answer", Decl::function().global(), vec![0xb8, 42, 0, 0, 0, 0xc3]).expect("can declare and define answer");
+    answer.declare_with("helper", Decl::function(), vec![0xc3]).expect("can declare and define helper");
+    let mut unused = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "a-rather-long-member-name.o".into());
+    unused.declare_with("unused", Decl::data().global(), vec![1; 3]).expect("can declare and define unused");
+
+    let mut archive = Archive::new(ArchiveFormat::Gnu);
+    archive.add_artifact(&unused).expect("can add unused");
+    archive.add_object("answer.o", answer.emit().expect("can emit answer")).expect("can add answer from its bytes");
+    assert!(archive.add_object("garbage.o", vec![0; 16]).is_err());
+    assert_eq!(archive.members().collect::<Vec<_>>(), ["a-rather-long-member-name.o", "answer.o"]);
+    let bytes = archive.to_bytes().expect("can write archive");
+    assert_eq!(bytes, archive.to_bytes().expect("can write archive again"));
+
+    // it is the archive GNU ar writes deterministically of the same objects
+    let members = std::path::PathBuf::from(temp_path("archive", "members"));
+    std::fs::create_dir_all(&members).expect("can create the members directory");
+    std::fs::write(members.join("a-rather-long-member-name.o"), unused.emit().expect("can emit unused")).expect("can write unused");
+    std::fs::write(members.join("answer.o"), answer.emit().expect("can emit answer")).expect("can write answer");
+    let ar = Command::new("ar").args(["rcsD", "ref.a", "a-rather-long-member-name.o", "answer.o"]).current_dir(&members).status();
+    if ar.is_ok_and(|status| status.success()) {
+        assert_eq!(std::fs::read(members.join("ref.a")).expect("can read the reference archive"), bytes);
+    }
+    let _ = std::fs::remove_dir_all(&members);
+
+    std::fs::write(&main_path, main.emit().expect("can emit main")).expect("can write main.o");
+    std::fs::write(&lib, &bytes).expect("can write the archive");
+    match Command::new("ld").args([&main_path, &lib, "-o", &exe]).status() {
+        Ok(status) => assert!(status.success(), "ld failed"),
+        Err(_) => return eprintln!("ld is not available, skipping"),
+    }
+    let status = Command::new(&exe).status().expect("can run the linked executable");
+    // only the member defining an undefined symbol is linked
+    let linked = std::fs::read(&exe).expect("can read the linked executable");
+    let elf = Elf::parse(&linked).expect("can parse the linked executable");
+    assert!(elf.syms.iter().any(|sym| &elf.strtab[sym.st_name] == "answer"));
+    assert!(!elf.syms.iter().any(|sym| &elf.strtab[sym.st_name] == "unused"));
+    for file in &[main_path, lib, exe] {
+        let _ = std::fs::remove_file(file);
+    }
+    assert_eq!(status.code(), Some(42));
+}
+
+#[test]
+fn static_imports_are_hidden() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xe9, 0, 0, 0, 0]).expect("can declare and define main");
+    obj.import("puts", ImportKind::Function).expect("can import puts");
+    obj.import("runtime_init", ImportKind::Function).expect("can import runtime_init");
+    obj.set_dynamic_import("runtime_init", false).expect("can make runtime_init static");
+    obj.set_dynamic_import("puts", false).expect("can make puts static");
+    obj.set_dynamic_import("puts", true).expect("can make puts dynamic again");
+    assert!(obj.set_dynamic_import("main", false).is_err());
+    assert!(obj.set_dynamic_import("missing", false).is_err());
+    obj.link(Link { from: "main", to: "runtime_init", at: 1 }).expect("can link from main to runtime_init");
+    obj.link(Link { from: "main", to: "puts", at: 6 }).expect("can link from main to puts");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    for &(name, other) in &[("puts", 0), ("runtime_init", 2)] {
+        let import = elf.syms.iter().find(|sym| &elf.strtab[sym.st_name] == name).expect("has import");
+        assert_eq!(import.st_shndx, section_header::SHN_UNDEF as usize);
+        assert_eq!(import.st_bind(), sym::STB_GLOBAL);
+        assert_eq!(import.st_other, other);
+    }
+}
+
+#[test]
+fn relocated_read_only_data_is_placed_in_data_rel_ro() {
+    fn sections(relro: bool) -> Vec<(String, u64)> {
+        let mut obj = faerie::ArtifactBuilder::new(triple!("x86_64-unknown-unknown-unknown-elf"))
+            .name("t.o".into())
+            .relro(relro)
+            .finish();
+        obj.declare_with("handler", Decl::function(), vec![0xc3]).expect("can declare and define handler");
+        obj.declare_with("table", Decl::data(), vec![0; 8]).expect("can declare and define table");
+        obj.declare_with("constants", Decl::data(), vec![1, 2, 3, 4]).expect("can declare and define constants");
+        obj.declare_with("state", Decl::data().writable(), vec![0; 8]).expect("can declare and define state");
+        obj.link(Link { from: "table", to: "handler", at: 0 }).expect("can link from table to handler");
+        obj.link(Link { from: "state", to: "handler", at: 0 }).expect("can link from state to handler");
+
+        let bytes = obj.emit().expect("can emit elf file");
+        let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+            goblin::Object::Elf(elf) => elf,
+            _ => panic!("Elf file not parsed as elf file"),
+        };
+        let mut sections = elf.section_headers.iter()
+            .map(|shdr| (elf.shdr_strtab[shdr.sh_name].to_string(), shdr.sh_flags))
+            .filter(|(name, _)| name.starts_with(".data"))
+            .collect::<Vec<_>>();
+        sections.sort();
+        sections
+    }
+    let read_only = u64::from(section_header::SHF_ALLOC);
+    let writable = u64::from(section_header::SHF_ALLOC | section_header::SHF_WRITE);
+    assert_eq!(sections(false), vec![
+        (".data.constants".to_string(), read_only),
+        (".data.state".to_string(), writable),
+        (".data.table".to_string(), read_only),
+    ]);
+    // the relocated table is only writable until the dynamic linker has relocated it
+    assert_eq!(sections(true), vec![
+        (".data.constants".to_string(), read_only),
+        (".data.rel.ro.table".to_string(), writable),
+        (".data.state".to_string(), writable),
+    ]);
+}
+
+#[test]
+fn debug_index_refers_to_offsets_in_debug_info() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0xc3]).expect("can declare and define main");
+    obj.declare_with(".debug_info", Decl::debug_section(), vec![0; 64]).expect("can declare and define .debug_info");
+    obj.declare_with(".debug_names", Decl::debug_section(), vec![0; 40]).expect("can declare and define .debug_names");
+    obj.declare_with(".gdb_index", Decl::debug_section(), vec![0; 48]).expect("can declare and define .gdb_index");
+    // the offsets of the two compilation units in .debug_info
+    for (i, &offset) in [0, 32].iter().enumerate() {
+        let at = 8 + 4 * i as u64;
+        obj.link_with(Link { from: ".debug_names", to: ".debug_info", at }, Reloc::Absolute { size: 4, addend: offset })
+            .expect("can link from .debug_names to .debug_info");
+    }
+    obj.link_with(Link { from: ".gdb_index", to: ".debug_info", at: 24 }, Reloc::Absolute { size: 8, addend: 32 })
+        .expect("can link from .gdb_index to .debug_info");
+    obj.link_with(Link { from: ".gdb_index", to: "main", at: 32 }, Reloc::Absolute { size: 8, addend: 0 })
+        .expect("can link from .gdb_index to main");
+    assert!(obj.link_with(Link { from: ".gdb_index", to: "main", at: 40 }, Reloc::Absolute { size: 3, addend: 0 }).is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    let syms = elf.syms.iter().collect::<Vec<_>>();
+    let relocs = |name| {
+        let (_, ref relocs) = *elf.shdr_relocs.iter()
+            .find(|&&(idx, _)| &elf.shdr_strtab[elf.section_headers[elf.section_headers[idx].sh_info as usize].sh_name] == name)
+            .expect("has relocations");
+        relocs.iter().map(|reloc| {
+            // relocations are against the section symbols of their targets
+            let target = &elf.shdr_strtab[elf.section_headers[syms[reloc.r_sym].st_shndx].sh_name];
+            (reloc.r_offset, reloc.r_type, target.to_string(), reloc.r_addend.expect("rela"))
+        }).collect::<Vec<_>>()
+    };
+    assert_eq!(relocs(".debug_names"), vec![
+        (8, reloc::R_X86_64_32, ".debug_info".to_string(), 0),
+        (12, reloc::R_X86_64_32, ".debug_info".to_string(), 32),
+    ]);
+    assert_eq!(relocs(".gdb_index"), vec![
+        (24, reloc::R_X86_64_64, ".debug_info".to_string(), 32),
+        (32, reloc::R_X86_64_64, ".text.main".to_string(), 0),
+    ]);
+}
+
+#[test]
+fn debug_addr_table_holds_the_only_address_relocations() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0xc3]).expect("can declare and define main");
+    obj.declare_with("counter", Decl::data().writable(), vec![0; 8]).expect("can declare and define counter");
+    obj.declare_with(".debug_info", Decl::debug_section(), vec![0; 32]).expect("can declare and define .debug_info");
+    assert_eq!(obj.debug_addr("main").expect("can add the address of main"), 0);
+    assert_eq!(obj.debug_addr("counter").expect("can add the address of counter"), 1);
+    assert_eq!(obj.debug_addr("main").expect("can add the address of main again"), 0);
+    assert!(obj.debug_addr(".debug_info").is_err());
+    assert!(obj.debug_addr("missing").is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    let (idx, shdr) = elf.section_headers.iter().enumerate()
+        .find(|&(_, shdr)| &elf.shdr_strtab[shdr.sh_name] == ".debug_addr").expect("has .debug_addr");
+    let offset = shdr.sh_offset as usize;
+    // unit_length, version 5, address_size 8, segment_selector_size 0, then two addresses
+    assert_eq!(shdr.sh_size, 8 + 2 * 8);
+    assert_eq!(bytes[offset..offset + 8], [20, 0, 0, 0, 5, 0, 8, 0]);
+
+    let syms = elf.syms.iter().collect::<Vec<_>>();
+    // all of the relocations are in .debug_addr
+    let (_, ref debug_addr_relocs) = *elf.shdr_relocs.iter().find(|&&(shndx, _)| elf.section_headers[shndx].sh_info as usize == idx)
+        .expect(".debug_addr has relocations");
+    assert_eq!(debug_addr_relocs.len(), elf.shdr_relocs.iter().map(|(_, relocs)| relocs.len()).sum::<usize>());
+    let relocs = debug_addr_relocs.iter().map(|reloc| {
+        let target = &elf.shdr_strtab[elf.section_headers[syms[reloc.r_sym].st_shndx].sh_name];
+        (reloc.r_offset, reloc.r_type, target.to_string())
+    }).collect::<Vec<_>>();
+    assert_eq!(relocs, vec![
+        (8, reloc::R_X86_64_64, ".text.main".to_string()),
+        (16, reloc::R_X86_64_64, ".data.counter".to_string()),
+    ]);
+}
+
+#[test]
+fn debug_line_relocations_are_address_or_offset_sized() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0x90, 0xc3]).expect("can declare and define main");
+    obj.declare_with(".debug_line_str", Decl::debug_section().strings(), b"/tmp\0a.c\0".to_vec())
+        .expect("can declare and define .debug_line_str");
+    // the DW_FORM_line_strp of the directory and file in the header, then DW_LNE_set_address in the program,
+    // each followed by bytes the relocations must not overwrite
+    obj.declare_with(".debug_line", Decl::debug_section(), vec![0xaa; 32]).expect("can declare and define .debug_line");
+    obj.link(Link { from: ".debug_line", to: ".debug_line_str", at: 4 }).expect("can link to the directory");
+    obj.link_with(Link { from: ".debug_line", to: ".debug_line_str", at: 12 }, Reloc::Absolute { size: 4, addend: 5 })
+        .expect("can link to the file name");
+    obj.link(Link { from: ".debug_line", to: "main", at: 20 }).expect("can link to main");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    let syms = elf.syms.iter().collect::<Vec<_>>();
+    assert_eq!(elf.shdr_relocs.len(), 1);
+    let relocs = elf.shdr_relocs[0].1.iter().map(|reloc| {
+        let target = &elf.shdr_strtab[elf.section_headers[syms[reloc.r_sym].st_shndx].sh_name];
+        (reloc.r_offset, reloc.r_type, target.to_string(), reloc.r_addend.expect("rela"))
+    }).collect::<Vec<_>>();
+    assert_eq!(relocs, vec![
+        // offsets into another debug section are 32-bit, as in 32-bit DWARF, and addresses are pointer sized
+        (4, reloc::R_X86_64_32, ".debug_line_str".to_string(), 0),
+        (12, reloc::R_X86_64_32, ".debug_line_str".to_string(), 5),
+        (20, reloc::R_X86_64_64, ".text.main".to_string(), 0),
+    ]);
+}
+
+#[test]
+fn raw_relocations_must_suit_their_target() {
+    let emit = |from: &str, to: &str, reloc: u32| {
+        let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+        obj.declare_with("main", Decl::function().global(), vec![0; 16]).expect("can declare and define main");
+        obj.declare_with("callee", Decl::function(), vec![0xc3]).expect("can declare and define callee");
+        obj.declare_with("table", Decl::data(), vec![0; 16]).expect("can declare and define table");
+        obj.declare_with(".debug_info", Decl::debug_section(), vec![0; 16]).expect("can declare and define .debug_info");
+        obj.link_with(Link { from, to, at: 4 }, Reloc::Raw { reloc, addend: 0, size: None }).expect("can link");
+        obj.emit()
+    };
+    assert!(emit("main", "callee", reloc::R_X86_64_PLT32).is_ok());
+    assert!(emit("main", "table", reloc::R_X86_64_PLT32).is_err());
+    assert!(emit("main", "table", reloc::R_X86_64_TPOFF32).is_err());
+    assert!(emit("table", "callee", reloc::R_X86_64_JUMP_SLOT).is_err());
+    assert!(emit("main", ".debug_info", reloc::R_X86_64_PC32).is_err());
+    assert!(emit(".debug_info", "main", reloc::R_X86_64_64).is_ok());
+    assert!(emit("main", ".debug_info", reloc::R_X86_64_SIZE32).is_err());
+
+    // a raw size relocation is against the sized symbol, not its section
+    let bytes = emit("main", "table", reloc::R_X86_64_SIZE32).expect("can emit elf file");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    let syms = elf.syms.iter().collect::<Vec<_>>();
+    let (_, ref relocs) = elf.shdr_relocs[0];
+    assert_eq!(&elf.strtab[syms[relocs.iter().next().expect("has a relocation").r_sym].st_name], "table");
+}
+
+#[test]
+fn too_many_sections_is_an_error() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    // null, .strtab, .symtab, the definitions and .note.GNU-stack
+    for i in 0..4 {
+        obj.declare_with(format!("f{}", i), Decl::function(), vec![0xc3]).expect("can declare and define");
+    }
+    obj.max_sections = Some(8);
+    assert!(obj.emit().is_ok());
+    obj.max_sections = Some(7);
+    let err = obj.emit().expect_err("more sections than the limit");
+    match err.downcast_ref::<faerie::artifact::ArtifactError>() {
+        Some(&faerie::artifact::ArtifactError::TooManySections { count: 8, limit: 7 }) => (),
+        _ => panic!("unexpected error {}", err),
+    }
+
+    // section indices from SHN_LORESERVE are reserved
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    for i in 0..section_header::SHN_LORESERVE as usize {
+        obj.declare_with(format!("f{}", i), Decl::function(), vec![0xc3]).expect("can declare and define");
+    }
+    assert!(obj.emit().is_err());
+}
+
+#[test]
+fn i386_pic_addresses_data_relative_to_the_got() {
+    // get: the GOT base in ebx, then the local static plus the global
+    let get = vec![
+        0xe8, 0, 0, 0, 0,               // call 1f
+        0x5b,                           // 1: pop ebx
+        0x81, 0xc3, 0, 0, 0, 0,         // add ebx, _GLOBAL_OFFSET_TABLE_ + (. - 1b)
+        0x8b, 0x83, 0, 0, 0, 0,         // mov eax, [ebx + counter@GOTOFF]
+        0x8b, 0x93, 0, 0, 0, 0,         // mov edx, [ebx + total@GOT]
+        0x03, 0x02,                     // add eax, [edx]
+        0xc3,                           // ret
+    ];
+    let mut obj = ArtifactBuilder::new(triple!("i686-unknown-linux-gnu")).name("t.o".into()).reloc_model(RelocModel::PicWithPlt).finish();
+    obj.declare_with("get", Decl::function().global(), get).expect("can declare and define get");
+    obj.declare_with("counter", Decl::data().writable(), vec![40, 0, 0, 0]).expect("can declare and define counter");
+    obj.declare_with("total", Decl::data().global().writable(), vec![2, 0, 0, 0]).expect("can declare and define total");
+    obj.link(Link { from: "get", to: "counter", at: 14 }).expect("can link from get to counter");
+    obj.link(Link { from: "get", to: "total", at: 20 }).expect("can link from get to total");
+    let mut wide = obj.clone();
+    wide.link_with(Link { from: "total", to: "counter", at: 0 }, Reloc::Absolute { size: 8, addend: 0 }).expect("can link");
+    assert!(wide.emit().is_err(), "i386 has no 8 byte relocations");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    let syms = elf.syms.iter().collect::<Vec<_>>();
+    let got = syms.iter().find(|sym| &elf.strtab[sym.st_name] == "_GLOBAL_OFFSET_TABLE_").expect("refers to the GOT base");
+    assert_eq!(got.st_shndx, section_header::SHN_UNDEF as usize);
+    assert_eq!(elf.shdr_relocs.len(), 1);
+    let (shndx, ref relocs) = elf.shdr_relocs[0];
+    assert_eq!(elf.section_headers[shndx].sh_type, section_header::SHT_REL);
+    let relocs = relocs.iter().map(|reloc| {
+        let sym = &syms[reloc.r_sym];
+        let target = if sym.st_type() == sym::STT_SECTION { &elf.shdr_strtab[elf.section_headers[sym.st_shndx].sh_name] } else { &elf.strtab[sym.st_name] };
+        (reloc.r_offset, reloc.r_type, target.to_string(), reloc.r_addend)
+    }).collect::<Vec<_>>();
+    assert_eq!(relocs, vec![
+        // a local is addressed by its offset from the GOT base, and a global through its GOT entry
+        (14, reloc::R_386_GOTOFF, ".data.counter".to_string(), None),
+        (20, reloc::R_386_GOT32X, "total".to_string(), None),
+    ]);
+}
+
+#[test]
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn i386_implicit_addends_link_and_run() {
+    use std::process::Command;
+    fn ld(args: &[&str]) -> Option<bool> {
+        Command::new("ld").args(args).status().ok().map(|status| status.success())
+    }
+    let [obj_path, exe, shared] = ["i386.o", "i386", "i386.so"].map(|name| temp_path("i386", name));
+
+    let mut obj = ArtifactBuilder::new(triple!("i686-unknown-linux-gnu")).name("i386.o".into()).reloc_model(RelocModel::PicWithPlt).finish();
+    obj.declare_with("_start", Decl::function().global(), vec![
+        0xe8, 0, 0, 0, 0,               // call get
+        0x89, 0xc3,                     // mov ebx, eax
+        0xb8, 1, 0, 0, 0,               // mov eax, SYS_exit
+        0xcd, 0x80,                     // int 0x80
+    ]).expect("can declare and define _start");
+    obj.declare_with("get", Decl::function().global(), vec![
+        0xe8, 0, 0, 0, 0, 0x5b, 0x81, 0xc3, 0xff, 0xff, 0xff, 0xff,
+        0x8b, 0x83, 0xff, 0xff, 0xff, 0xff, 0x8b, 0x93, 0xff, 0xff, 0xff, 0xff, 0x03, 0x02, 0xc3,
+    ]).expect("can declare and define get");
+    obj.declare_with("counter", Decl::data().writable(), vec![40, 0, 0, 0]).expect("can declare and define counter");
+    obj.declare_with("total", Decl::data().global().writable(), vec![2, 0, 0, 0]).expect("can declare and define total");
+    obj.import("_GLOBAL_OFFSET_TABLE_", ImportKind::Data).expect("can import the GOT base");
+    obj.link(Link { from: "_start", to: "get", at: 1 }).expect("can link from _start to get");
+    // the GOT base is relative to the popped return address, 3 bytes before the field
+    obj.link_with(Link { from: "get", to: "_GLOBAL_OFFSET_TABLE_", at: 8 }, Reloc::Raw { reloc: reloc::R_386_GOTPC, addend: 3, size: None })
+        .expect("can link from get to the GOT base");
+    obj.link(Link { from: "get", to: "counter", at: 14 }).expect("can link from get to counter");
+    obj.link(Link { from: "get", to: "total", at: 20 }).expect("can link from get to total");
+    std::fs::write(&obj_path, obj.emit().expect("can emit elf file")).expect("can write i386.o");
+
+    // the placeholder bytes are replaced by the addends, which the linker reads
+    match ld(&["-m", "elf_i386", &obj_path, "-o", &exe]) {
+        Some(linked) => assert!(linked, "ld failed"),
+        None => return eprintln!("ld is not available, skipping"),
+    }
+    // position independent code needs no text relocations in a shared library
+    assert_eq!(ld(&["-m", "elf_i386", "-shared", "-z", "text", &obj_path, "-o", &shared]), Some(true));
+    let status = Command::new(&exe).status();
+    for file in &[obj_path, exe, shared] {
+        let _ = std::fs::remove_file(file);
+    }
+    match status {
+        Ok(status) => assert_eq!(status.code(), Some(42)),
+        Err(err) => eprintln!("i386 executables can't be run, skipping: {}", err),
+    }
+}
+
+#[test]
+fn aliases_take_the_visibility_they_are_defined_with() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("foo", Decl::function().global(), vec![0xc3]).expect("can declare and define foo");
+    obj.declare_with("counter", Decl::data().global().writable(), vec![0; 4]).expect("can declare and define counter");
+    obj.define_alias_with("foo_hidden", "foo", Scope::Global, Visibility::Hidden).expect("can alias foo");
+    obj.define_alias_with("counter_protected", "counter", Scope::Weak, Visibility::Protected).expect("can alias counter");
+    obj.define_alias("counter_default", "counter", Scope::Global).expect("can alias counter");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let symbol = |name: &str| elf.syms.iter().find(|sym| &elf.strtab[sym.st_name] == name).expect("has symbol");
+    let (foo, foo_hidden) = (symbol("foo"), symbol("foo_hidden"));
+    assert_eq!(foo.st_other, 0);
+    assert_eq!(foo_hidden.st_other, 2); // STV_HIDDEN
+    assert_eq!(foo_hidden.st_bind(), sym::STB_GLOBAL);
+    assert_eq!((foo_hidden.st_shndx, foo_hidden.st_value, foo_hidden.st_size), (foo.st_shndx, foo.st_value, foo.st_size));
+    let (counter, counter_protected) = (symbol("counter"), symbol("counter_protected"));
+    assert_eq!(counter_protected.st_other, 3); // STV_PROTECTED
+    assert_eq!(counter_protected.st_bind(), sym::STB_WEAK);
+    assert_eq!(counter_protected.st_type(), sym::STT_OBJECT);
+    assert_eq!((counter_protected.st_shndx, counter_protected.st_value), (counter.st_shndx, counter.st_value));
+    assert_eq!(symbol("counter_default").st_other, 0);
+}
+
+#[test]
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn local_alias_is_not_preemptible() {
+    use std::process::Command;
+    let [a_path, b_path, lib] = ["alias-a.o", "alias-b.o", "alias.so"].map(|name| temp_path("alias", name));
+
+    // a defines foo, and points to it through its local alias
+    let mut a = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "alias-a.o".into());
+    a.declare_with("foo", Decl::function().global(), vec![0xb8, 1, 0, 0, 0, 0xc3]).expect("can declare and define foo");
+    a.define_alias("foo_local", "foo", Scope::Local).expect("can alias foo");
+    a.declare_with("local_ptr", Decl::data().global().writable(), vec![0; 8]).expect("can declare and define local_ptr");
+    a.link(Link { from: "local_ptr", to: "foo_local", at: 0 }).expect("can link from local_ptr to foo_local");
+    assert!(a.define_alias("foo_local", "foo", Scope::Global).is_err());
+    assert!(a.define_alias("nothing_local", "nothing", Scope::Local).is_err());
+    // b points to foo by its global name
+    let mut b = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "alias-b.o".into());
+    b.import("foo", ImportKind::Function).expect("can import foo");
+    b.declare_with("global_ptr", Decl::data().global().writable(), vec![0; 8]).expect("can declare and define global_ptr");
+    b.link(Link { from: "global_ptr", to: "foo", at: 0 }).expect("can link from global_ptr to foo");
+    assert!(b.define_alias("foo_local", "foo", Scope::Local).is_err());
+
+    let bytes = a.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let symbol = |name: &str| elf.syms.iter().find(|sym| &elf.strtab[sym.st_name] == name).expect("has symbol");
+    let (foo, foo_local) = (symbol("foo"), symbol("foo_local"));
+    assert_eq!(foo.st_bind(), sym::STB_GLOBAL);
+    assert_eq!(foo_local.st_bind(), sym::STB_LOCAL);
+    assert_eq!(foo_local.st_type(), sym::STT_FUNC);
+    assert_eq!((foo_local.st_shndx, foo_local.st_value, foo_local.st_size), (foo.st_shndx, foo.st_value, foo.st_size));
+    // the local symbols precede the global ones
+    let symtab = elf.section_headers.iter().find(|shdr| shdr.sh_type == section_header::SHT_SYMTAB).expect("has symtab");
+    for (i, sym) in elf.syms.iter().enumerate() {
+        assert_eq!(sym.st_bind() == sym::STB_LOCAL, i < symtab.sh_info as usize, "{}", &elf.strtab[sym.st_name]);
+    }
+    std::fs::write(&a_path, &bytes).expect("can write alias-a.o");
+    std::fs::write(&b_path, b.emit().expect("can emit elf file")).expect("can write alias-b.o");
+
+    match Command::new("ld").args(["-shared", &a_path, &b_path, "-o", &lib]).status() {
+        Ok(status) => assert!(status.success(), "ld -shared failed"),
+        Err(_) => return eprintln!("ld is not available, skipping"),
+    }
+    let bytes = std::fs::read(&lib).expect("can read the shared object");
+    for file in &[a_path, b_path, lib] {
+        let _ = std::fs::remove_file(file);
+    }
+    let so = Elf::parse(&bytes).expect("can parse the shared object");
+    let dynamic = |name: &str| so.dynsyms.iter().find(|sym| &so.dynstrtab[sym.st_name] == name);
+    assert!(dynamic("foo").is_some());
+    assert!(dynamic("foo_local").is_none());
+    let address = |name: &str| dynamic(name).expect("is exported").st_value;
+    let relocation = |at: u64| so.dynrelas.iter().find(|rela| rela.r_offset == at).expect("pointer is relocated");
+    // the pointer through the global name may be preempted, the one through the local alias is fixed
+    let global_ptr = relocation(address("global_ptr"));
+    assert_eq!(global_ptr.r_type, reloc::R_X86_64_64);
+    assert_eq!(&so.dynstrtab[so.dynsyms.get(global_ptr.r_sym).expect("has symbol").st_name], "foo");
+    let local_ptr = relocation(address("local_ptr"));
+    assert_eq!(local_ptr.r_type, reloc::R_X86_64_RELATIVE);
+    assert_eq!(local_ptr.r_addend, Some(address("foo") as i64));
+}
+
+#[test]
+fn gnu_properties_are_merged_into_one_note() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    // an odd length definition, so the note has to be realigned
+    obj.declare_with("main", Decl::function().global(), vec![0x31, 0xc0, 0xc3]).expect("can declare and define main");
+    obj.set_gnu_property(faerie::GNU_PROPERTY_X86_ISA_1_NEEDED, vec![1, 0, 0, 0]).expect("can set the ISA level");
+    obj.set_gnu_property(faerie::GNU_PROPERTY_X86_FEATURE_1_AND, vec![3, 0, 0, 0]).expect("can set the features");
+    // setting it again replaces the old value
+    obj.set_gnu_property(faerie::GNU_PROPERTY_X86_ISA_1_NEEDED, vec![2, 0, 0, 0]).expect("can reset the ISA level");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let section = elf.section_headers.iter()
+        .find(|shdr| &elf.shdr_strtab[shdr.sh_name] == ".note.gnu.property")
+        .expect("has a property section");
+    assert_eq!(section.sh_type, section_header::SHT_NOTE);
+    assert_eq!((section.sh_addralign, section.sh_offset % 8), (8, 0));
+    let notes = elf.iter_note_sections(&bytes, Some(".note.gnu.property")).expect("has notes")
+        .collect::<Result<Vec<_>, _>>().expect("can parse notes");
+    assert_eq!(notes.len(), 1);
+    assert_eq!((notes[0].name, notes[0].n_type), ("GNU", faerie::NT_GNU_PROPERTY_TYPE_0));
+    // sorted by kind, each padded to 8 bytes
+    assert_eq!(notes[0].desc, &[
+        0x02, 0, 0, 0xc0, 4, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0,
+        0x02, 0x80, 0, 0xc0, 4, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0,
+    ][..]);
+
+    #[cfg(target_os = "linux")]
+    {
+        let path = temp_path("properties", "properties.o");
+        std::fs::write(&path, &bytes).expect("can write the object");
+        let output = std::process::Command::new("readelf").arg("-n").arg(&path).output();
+        let _ = std::fs::remove_file(&path);
+        match output {
+            Ok(output) => {
+                let notes = String::from_utf8_lossy(&output.stdout);
+                assert!(notes.contains("x86 feature: IBT, SHSTK"), "{}", notes);
+                assert!(notes.contains("x86 ISA needed: x86-64-v2"), "{}", notes);
+            },
+            Err(_) => eprintln!("readelf is not available, skipping"),
+        }
+    }
+}
+
+#[test]
+fn min_isa_level_is_a_needed_property() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0x62, 0xf1, 0xfd, 0x48, 0x6f, 0xc1, 0xc3])
+        .expect("can declare and define main");
+    obj.set_min_isa_level(IsaLevel::V4).expect("can set the ISA level");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let notes = elf.iter_note_sections(&bytes, Some(".note.gnu.property")).expect("has notes")
+        .collect::<Result<Vec<_>, _>>().expect("can parse notes");
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].desc, &[0x02, 0x80, 0, 0xc0, 4, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0][..]);
+
+    let mut arm = Artifact::new(triple!("aarch64-unknown-linux-gnu"), "t.o".into());
+    assert!(arm.set_min_isa_level(IsaLevel::V2).is_err());
+
+    #[cfg(target_os = "linux")]
+    {
+        let path = temp_path("isa-level", "isa-level.o");
+        std::fs::write(&path, &bytes).expect("can write the object");
+        let output = std::process::Command::new("readelf").arg("-n").arg(&path).output();
+        let _ = std::fs::remove_file(&path);
+        match output {
+            Ok(output) => {
+                let notes = String::from_utf8_lossy(&output.stdout);
+                assert!(notes.contains("x86 ISA needed: x86-64-v4"), "{}", notes);
+            },
+            Err(_) => eprintln!("readelf is not available, skipping"),
+        }
+    }
+}
+
+#[test]
+fn control_flow_protection_is_a_program_property() {
+    let features = |triple: &str, branches: bool, returns: bool| {
+        let mut obj = Artifact::new(triple.parse().expect("valid triple"), "t.o".into());
+        obj.declare_with("run", Decl::function().global(), vec![0xc3]).expect("can declare and define run");
+        obj.set_control_flow_protection(branches, returns).expect("can set the control flow protection");
+        let bytes = obj.emit().expect("can emit elf file");
+        let elf = Elf::parse(&bytes).expect("can parse elf file");
+        let notes = elf.iter_note_sections(&bytes, Some(".note.gnu.property")).expect("has notes")
+            .collect::<Result<Vec<_>, _>>().expect("can parse notes");
+        assert_eq!((notes.len(), notes[0].n_type), (1, faerie::NT_GNU_PROPERTY_TYPE_0));
+        notes[0].desc.to_vec()
+    };
+    assert_eq!(features("x86_64-unknown-linux-gnu", true, true), vec![0x02, 0, 0, 0xc0, 4, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(features("i686-unknown-linux-gnu", true, false), vec![0x02, 0, 0, 0xc0, 4, 0, 0, 0, 1, 0, 0, 0]);
+    assert_eq!(features("aarch64-unknown-linux-gnu", false, true), vec![0, 0, 0, 0xc0, 4, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0]);
+
+    let mut wasm = Artifact::new(triple!("wasm32-unknown-unknown"), "t.o".into());
+    match wasm.set_control_flow_protection(true, true) {
+        Err(faerie::artifact::ArtifactError::NoControlFlowProtection(architecture)) => assert_eq!(architecture, "wasm32"),
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+#[test]
+fn custom_reloc_encoder_selects_relocations() {
+    use std::sync::Arc;
+    use faerie::artifact::LinkAndDecl;
+    use faerie::RelocEncoder;
+
+    const R_RISCV_64: u32 = 2;
+    const R_RISCV_CALL_PLT: u32 = 19;
+    const R_RISCV_PCREL_HI20: u32 = 23;
+
+    #[derive(Debug)]
+    struct Riscv;
+    impl RelocEncoder for Riscv {
+        fn encode(&self, _artifact: &Artifact, link: &LinkAndDecl) -> Result<(u32, i64), failure::Error> {
+            Ok(match (link.from.decl, link.to.decl) {
+                (&Decl::Function(_), &Decl::Function(_)) => (R_RISCV_CALL_PLT, 0),
+                (&Decl::Function(_), _) => (R_RISCV_PCREL_HI20, 0),
+                _ => (R_RISCV_64, 0),
+            })
+        }
+        fn pointer(&self, _artifact: &Artifact) -> u32 {
+            R_RISCV_64
+        }
+    }
+
+    let mut obj = ArtifactBuilder::new(triple!("riscv64-unknown-linux-gnu"))
+        .reloc_encoder(Arc::new(Riscv))
+        .finish();
+    obj.declare_with("callee", Decl::function().global(), vec![0x67, 0x80, 0, 0]).expect("can define callee");
+    // auipc ra, 0; jalr ra; auipc a0, 0
+    obj.declare_with("caller", Decl::function().global(), vec![0x97, 0, 0, 0, 0xe7, 0x80, 0, 0, 0x17, 5, 0, 0])
+        .expect("can define caller");
+    obj.declare_with("table", Decl::data(), vec![0; 8]).expect("can define table");
+    obj.link(Link { from: "caller", to: "callee", at: 0 }).expect("can link caller to callee");
+    obj.link(Link { from: "caller", to: "table", at: 8 }).expect("can link caller to table");
+    obj.link(Link { from: "table", to: "callee", at: 0 }).expect("can link table to callee");
+    obj.add_constructor("callee", None).expect("can add constructor");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    assert_eq!(elf.header.e_machine, header::EM_RISCV);
+    let mut relocs = elf.shdr_relocs.iter()
+        .flat_map(|(_, relocs)| relocs.iter().map(|reloc| (reloc.r_offset, reloc.r_type)))
+        .collect::<Vec<_>>();
+    relocs.sort();
+    assert_eq!(relocs, vec![(0, R_RISCV_64), (0, R_RISCV_64), (0, R_RISCV_CALL_PLT), (8, R_RISCV_PCREL_HI20)]);
+}
+
+#[test]
+fn architectures_without_an_encoder_are_unsupported() {
+    use std::sync::Arc;
+    use faerie::artifact::LinkAndDecl;
+    use faerie::RelocEncoder;
+
+    const R_PPC64_ADDR64: u32 = 38;
+
+    #[derive(Debug)]
+    struct Ppc64;
+    impl RelocEncoder for Ppc64 {
+        fn encode(&self, _artifact: &Artifact, _link: &LinkAndDecl) -> Result<(u32, i64), failure::Error> {
+            Ok((R_PPC64_ADDR64, 0))
+        }
+        fn pointer(&self, _artifact: &Artifact) -> u32 {
+            R_PPC64_ADDR64
+        }
+    }
+
+    let define = |obj: &mut Artifact| {
+        obj.declare_with("main", Decl::function().global(), vec![0x4e, 0x80, 0, 0x20]).expect("can define main");
+        obj.declare_with("table", Decl::data(), vec![0; 8]).expect("can define table");
+        obj.link(Link { from: "table", to: "main", at: 0 }).expect("can link table to main");
+    };
+    // faerie doesn't know the relocations of PowerPC, rather than relocating as on x86-64
+    let mut obj = Artifact::new(triple!("powerpc64le-unknown-linux-gnu"), "t.o".into());
+    define(&mut obj);
+    let err = obj.emit().expect_err("faerie has no PowerPC encoder");
+    match err.downcast_ref::<faerie::artifact::ArtifactError>() {
+        Some(faerie::artifact::ArtifactError::UnsupportedArchitecture(architecture, format)) => {
+            assert_eq!((architecture.as_str(), format.as_str()), ("powerpc64le", "ELF"));
+        },
+        _ => panic!("unexpected error: {}", err),
+    }
+
+    let mut obj = ArtifactBuilder::new(triple!("powerpc64le-unknown-linux-gnu")).reloc_encoder(Arc::new(Ppc64)).finish();
+    define(&mut obj);
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    assert_eq!(elf.header.e_machine, header::EM_PPC64);
+    let relocs = elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter().map(|reloc| reloc.r_type)).collect::<Vec<_>>();
+    assert_eq!(relocs, vec![R_PPC64_ADDR64]);
+}
+
+#[test]
+fn aarch64_links_are_calls_and_page_relative() {
+    const R_AARCH64_ABS64: u32 = 257;
+    const R_AARCH64_ADR_PREL_PG_HI21: u32 = 275;
+    const R_AARCH64_ADD_ABS_LO12_NC: u32 = 277;
+    const R_AARCH64_CALL26: u32 = 283;
+    const R_AARCH64_ADR_GOT_PAGE: u32 = 311;
+    const R_AARCH64_LD64_GOT_LO12_NC: u32 = 312;
+
+    let mut obj = Artifact::new(triple!("aarch64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("helper", Decl::function(), vec![0xc0, 0x03, 0x5f, 0xd6]).expect("can define helper");
+    obj.declare_with("main", Decl::function().global(), vec![
+        0x00, 0x00, 0x00, 0x94, // bl helper
+        0x00, 0x00, 0x00, 0x90, // adrp x0, counter
+        0x00, 0x00, 0x00, 0x91, // add x0, x0, :lo12:counter
+        0x01, 0x00, 0x00, 0x90, // adrp x1, :got:environ
+        0x21, 0x00, 0x40, 0xf9, // ldr x1, [x1, :got_lo12:environ]
+        0xc0, 0x03, 0x5f, 0xd6, // ret
+    ]).expect("can define main");
+    obj.declare_with("counter", Decl::data().writable(), vec![0; 8]).expect("can define counter");
+    obj.declare_with("table", Decl::data(), vec![0; 8]).expect("can define table");
+    obj.declare("environ", Decl::DataImport).expect("can import environ");
+    obj.link(Link { from: "main", to: "helper", at: 0 }).expect("can link main to helper");
+    obj.link(Link { from: "main", to: "counter", at: 4 }).expect("can link main to counter");
+    obj.link(Link { from: "main", to: "environ", at: 12 }).expect("can link main to environ");
+    obj.link(Link { from: "table", to: "main", at: 0 }).expect("can link table to main");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    assert_eq!(elf.header.e_machine, header::EM_AARCH64);
+    // a definition's section symbol has the name of its section
+    let symbol_name = |index: usize| {
+        let sym = elf.syms.get(index).expect("relocation has a symbol");
+        match &elf.strtab[sym.st_name] {
+            "" => &elf.shdr_strtab[elf.section_headers[sym.st_shndx].sh_name],
+            name => name,
+        }
+    };
+    let mut relocs = elf.shdr_relocs.iter()
+        .flat_map(|(_, relocs)| relocs.iter().map(|reloc| (reloc.r_offset, reloc.r_type, symbol_name(reloc.r_sym), reloc.r_addend)))
+        .collect::<Vec<_>>();
+    relocs.sort();
+    // as llvm-mc assembles the same instructions, but for the call, which it resolves within its one .text
+    assert_eq!(relocs, vec![
+        (0, R_AARCH64_ABS64, ".text.main", Some(0)),
+        (0, R_AARCH64_CALL26, ".text.helper", Some(0)),
+        (4, R_AARCH64_ADR_PREL_PG_HI21, ".data.counter", Some(0)),
+        (8, R_AARCH64_ADD_ABS_LO12_NC, ".data.counter", Some(0)),
+        (12, R_AARCH64_ADR_GOT_PAGE, "environ", Some(0)),
+        (16, R_AARCH64_LD64_GOT_LO12_NC, "environ", Some(0)),
+    ]);
+}
+
+#[test]
+fn absolute_32_bit_relocations_are_zero_or_sign_extended() {
+    let object = |reloc: Reloc| {
+        let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+        obj.declare_with("_start", Decl::function().global(), vec![0xc3]).expect("can declare and define _start");
+        obj.declare_with("target", Decl::data().global().writable(), vec![0; 8]).expect("can declare and define target");
+        obj.declare_with("table", Decl::data(), vec![0; 8]).expect("can declare and define table");
+        obj.link_with(Link { from: "table", to: "target", at: 4 }, reloc).expect("can link from table to target");
+        obj.emit().expect("can emit elf file")
+    };
+    let unsigned = object(Reloc::Absolute { size: 4, addend: 2 });
+    let signed = object(Reloc::SignedAbsolute { addend: -2 });
+    for &(bytes, r_type, addend) in &[(&unsigned, reloc::R_X86_64_32, 2), (&signed, reloc::R_X86_64_32S, -2)] {
+        let elf = Elf::parse(bytes).expect("can parse elf file");
+        let relocs = elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter()).collect::<Vec<_>>();
+        assert_eq!(relocs.len(), 1);
+        assert_eq!((relocs[0].r_offset, relocs[0].r_type, relocs[0].r_addend), (4, r_type, Some(addend)));
+    }
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::{Command, Stdio};
+        let [unsigned_path, signed_path, exe] = ["abs32.o", "abs32s.o", "abs32"].map(|name| temp_path("abs32", name));
+        std::fs::write(&unsigned_path, &unsigned).expect("can write abs32.o");
+        std::fs::write(&signed_path, &signed).expect("can write abs32s.o");
+        // between 2 and 4 GiB, the address fits zero extended, but not sign extended
+        let ld = |obj: &str| Command::new("ld").args(["-Tdata=0x90000000", obj, "-o", &exe]).stderr(Stdio::null())
+            .status().ok().map(|status| status.success());
+        let linked = (ld(&unsigned_path), ld(&signed_path));
+        for file in &[unsigned_path, signed_path, exe.clone()] {
+            let _ = std::fs::remove_file(file);
+        }
+        match linked {
+            (Some(unsigned), Some(signed)) => {
+                assert!(unsigned, "ld failed");
+                assert!(!signed, "ld should fail to sign extend the address");
+            },
+            _ => eprintln!("ld is not available, skipping"),
+        }
+    }
+}
+
+#[test]
+fn got_offset_relocations_refer_to_the_symbol() {
+    let object = |triple, size| {
+        let mut obj = ArtifactBuilder::new(triple).name("got.o".into()).reloc_model(RelocModel::PicWithPlt).finish();
+        let get = if size == 8 {
+            vec![0x48, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0xc3]  // movabs rax, counter@GOT; ret
+        } else {
+            vec![0x8b, 0x83, 0, 0, 0, 0, 0xc3]              // mov eax, [ebx + counter@GOT]; ret
+        };
+        obj.declare_with("get", Decl::function().global(), get).expect("can define get");
+        obj.declare_with("counter", Decl::data().global().writable(), vec![0; 4]).expect("can define counter");
+        obj.declare_with("debug", Decl::debug_section(), vec![0; 4]).expect("can define debug");
+        obj.link_with(Link { from: "get", to: "counter", at: 2 }, Reloc::GotOffset { size, addend: 0 })
+            .expect("can link from get to the GOT entry of counter");
+        assert!(obj.link_with(Link { from: "get", to: "debug", at: 2 }, Reloc::GotOffset { size, addend: 0 }).is_err());
+        assert!(obj.link_with(Link { from: "get", to: "counter", at: 2 }, Reloc::GotOffset { size: 2, addend: 0 }).is_err());
+        obj
+    };
+    for &(triple, size, r_type) in &[
+        ("i686-unknown-linux-gnu", 4, reloc::R_386_GOT32X),
+        ("x86_64-unknown-linux-gnu", 4, reloc::R_X86_64_GOT32),
+        ("x86_64-unknown-linux-gnu", 8, reloc::R_X86_64_GOT64),
+    ] {
+        let bytes = object(triple!(triple), size).emit().expect("can emit elf file");
+        let elf = Elf::parse(&bytes).expect("can parse elf file");
+        let relocs = elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter()).collect::<Vec<_>>();
+        assert_eq!(relocs.len(), 1);
+        assert_eq!((relocs[0].r_offset, relocs[0].r_type), (2, r_type));
+        // the GOT entry is the symbol's own, not its section's
+        let sym = elf.syms.get(relocs[0].r_sym).expect("relocation has a symbol");
+        assert_eq!(&elf.strtab[sym.st_name], "counter");
+    }
+    let mut wide = object(triple!("i686-unknown-linux-gnu"), 4);
+    wide.link_with(Link { from: "get", to: "counter", at: 2 }, Reloc::GotOffset { size: 8, addend: 0 }).expect("can link");
+    assert!(wide.emit().is_err());
+    assert!(object(triple!("x86_64-apple-darwin"), 4).emit().is_err());
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::Command;
+        let [obj_path, lib] = ["got.o", "libgot.so"].map(|name| temp_path("got", name));
+        let bytes = object(triple!("x86_64-unknown-linux-gnu"), 4).emit().expect("can emit elf file");
+        std::fs::write(&obj_path, bytes).expect("can write got.o");
+        let status = Command::new("ld").args(["-shared", &obj_path, "-o", &lib]).status();
+        let linked = std::fs::read(&lib);
+        for file in &[obj_path, lib] {
+            let _ = std::fs::remove_file(file);
+        }
+        match status {
+            Ok(status) => assert!(status.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        // the linker allocated a GOT entry for counter, which the dynamic linker fills in
+        let linked = linked.expect("can read the shared library");
+        let elf = Elf::parse(&linked).expect("can parse the shared library");
+        assert!(elf.dynrelas.iter().any(|reloc| {
+            reloc.r_type == reloc::R_X86_64_GLOB_DAT && &elf.dynstrtab[elf.dynsyms.get(reloc.r_sym).expect("dynamic symbol").st_name] == "counter"
+        }));
+    }
+}
+
+#[test]
+fn stripped_locals_still_link() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "strip.o".into());
+    obj.declare_with("_start", Decl::function().global(), vec![
+        0x8b, 0x3d, 0, 0, 0, 0,         // mov edi, [rip + value]
+        0x03, 0x3d, 0, 0, 0, 0,         // add edi, [rip + value_size]
+        0xb8, 60, 0, 0, 0,              // mov eax, SYS_exit
+        0x0f, 0x05,                     // syscall
+    ]).expect("can declare and define _start");
+    obj.declare_with("value", Decl::data(), vec![38, 0, 0, 0]).expect("can declare and define value");
+    obj.declare_with("value_size", Decl::data(), vec![0; 4]).expect("can declare and define value_size");
+    obj.declare_with("unused", Decl::function(), vec![0xc3]).expect("can declare and define unused");
+    obj.declare_with(".debug_str", Decl::debug_section(), b"strip\0".to_vec()).expect("can declare and define .debug_str");
+    obj.link(Link { from: "_start", to: "value", at: 2 }).expect("can link from _start to value");
+    obj.link(Link { from: "_start", to: "value_size", at: 8 }).expect("can link from _start to value_size");
+    // the size of value is relocated against its own local symbol
+    obj.link_with(Link { from: "value_size", to: "value", at: 0 }, Reloc::Size { size: 4, addend: 0 })
+        .expect("can link from value_size to the size of value");
+
+    let symbols = |bytes: &[u8]| {
+        let elf = Elf::parse(bytes).expect("can parse elf file");
+        for (_, relocs) in &elf.shdr_relocs {
+            for reloc in relocs.iter() {
+                assert!(reloc.r_sym < elf.syms.len(), "relocation refers to a symbol that was removed");
+            }
+        }
+        let locals = elf.syms.iter().take_while(|sym| sym.st_bind() == sym::STB_LOCAL).count();
+        let symtab = elf.section_headers.iter().find(|shdr| shdr.sh_type == section_header::SHT_SYMTAB).expect("has a symtab");
+        assert_eq!(symtab.sh_info as usize, locals);
+        let has_debug = elf.section_headers.iter().any(|shdr| &elf.shdr_strtab[shdr.sh_name] == ".debug_str");
+        let names = elf.syms.iter().map(|sym| (sym.st_type(), elf.strtab[sym.st_name].to_string()))
+            .filter(|&(typ, _)| typ != sym::STT_SECTION)
+            .collect::<Vec<_>>();
+        (names, elf.syms.iter().filter(|sym| sym.st_type() == sym::STT_SECTION).count(), has_debug)
+    };
+    let all = || vec![
+        (sym::STT_NOTYPE, String::new()), (sym::STT_FILE, "strip.o".to_string()), (sym::STT_OBJECT, "value".to_string()),
+        (sym::STT_OBJECT, "value_size".to_string()), (sym::STT_FUNC, "unused".to_string()), (sym::STT_FUNC, "_start".to_string()),
+    ];
+    let linkable = || vec![(sym::STT_NOTYPE, String::new()), (sym::STT_OBJECT, "value".to_string()), (sym::STT_FUNC, "_start".to_string())];
+    // only the sections of value and value_size are relocated against, so only they have a section symbol
+    let whole = obj.emit_stripped(target_lexicon::BinaryFormat::Elf, Strip::None).expect("can emit elf file");
+    assert_eq!(symbols(&whole), (all(), 2, true));
+    assert_eq!(symbols(&obj.emit_stripped(target_lexicon::BinaryFormat::Elf, Strip::Debug).expect("can emit elf file")), (all(), 2, false));
+    let discarded = obj.emit_stripped(target_lexicon::BinaryFormat::Elf, Strip::DiscardLocals).expect("can emit elf file");
+    assert_eq!(symbols(&discarded), (linkable(), 2, true));
+    let stripped = obj.emit_stripped(target_lexicon::BinaryFormat::Elf, Strip::All).expect("can emit elf file");
+    assert_eq!(symbols(&stripped), (linkable(), 2, false));
+    assert!(obj.emit_stripped(target_lexicon::BinaryFormat::Macho, Strip::All).is_err());
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::Command;
+        let [obj_path, exe] = ["strip.o", "strip"].map(|name| temp_path("strip", name));
+        std::fs::write(&obj_path, &stripped).expect("can write strip.o");
+        let linked = Command::new("ld").args([&obj_path, "-o", &exe]).status();
+        let status = Command::new(&exe).status();
+        for file in &[obj_path, exe] {
+            let _ = std::fs::remove_file(file);
+        }
+        match linked {
+            Ok(linked) => assert!(linked.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        // 38 + the size of value
+        assert_eq!(status.expect("can run the linked executable").code(), Some(42));
+    }
+}
+
+#[test]
+fn symbol_attributes_override_the_declared_ones() {
+    let mut obj = ArtifactBuilder::new(triple!("x86_64-unknown-linux-gnu"))
+        .name("t.o".into())
+        .function_sections(false)
+        .finish();
+    // exit(0)
+    obj.declare_with("_start", Decl::function().global(), vec![0x31, 0xff, 0xb8, 60, 0, 0, 0, 0x0f, 0x05]).expect("can declare and define _start");
+    obj.declare_with("kept", Decl::function().global(), vec![0xc3]).expect("can declare and define kept");
+    obj.declare_with("dropped", Decl::data().global(), vec![1]).expect("can declare and define dropped");
+    // a return and its padding
+    obj.declare_with("trampoline", Decl::data().global(), vec![0xc3, 0xcc, 0xcc, 0xcc]).expect("can declare and define trampoline");
+    obj.declare_with("counter", Decl::data().global().writable(), vec![0; 4]).expect("can declare and define counter");
+    obj.declare_with("slot", Decl::data().global().writable().common(8), vec![0; 8]).expect("can declare and define slot");
+    obj.import("puts", ImportKind::Function).expect("can import puts");
+    obj.set_symbol_size("trampoline", 1).expect("can set the size of trampoline");
+    obj.set_symbol_kind("trampoline", SymbolKind::Function).expect("can set the type of trampoline");
+    obj.set_symbol_other("counter", 2).expect("can set the st_other of counter");
+    obj.set_no_dead_strip("kept", true).expect("can keep kept");
+    obj.set_no_dead_strip("counter", true).expect("can keep counter");
+    obj.set_no_dead_strip("counter", false).expect("can drop counter");
+    assert!(obj.set_symbol_size("puts", 0).is_err());
+    assert!(obj.set_no_dead_strip("slot", true).is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let symbol = |name: &str| elf.syms.iter().find(|sym| &elf.strtab[sym.st_name] == name).expect("has symbol");
+    let trampoline = symbol("trampoline");
+    assert_eq!((trampoline.st_size, trampoline.st_type()), (1, sym::STT_FUNC));
+    assert_eq!(symbol("counter").st_other, 2);
+    const SHF_GNU_RETAIN: u64 = 0x20_0000;
+    let retained = |name: &str| elf.section_headers[symbol(name).st_shndx].sh_flags & SHF_GNU_RETAIN != 0;
+    assert!(retained("kept"));
+    assert!(!retained("dropped") && !retained("counter"));
+    // a function which is kept is in a section of its own, even without function sections
+    assert_ne!(symbol("kept").st_shndx, symbol("_start").st_shndx);
+    // which the GNU linker only honours in an object of the GNU ABI
+    assert_eq!(elf.header.e_ident[7], 3);
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::Command;
+        let [obj_path, exe] = ["retain.o", "retain"].map(|name| temp_path("weak", name));
+        std::fs::write(&obj_path, &bytes).expect("can write retain.o");
+        let linked = Command::new("ld").args([&obj_path, "--gc-sections", "-o", &exe]).status();
+        let exe_bytes = std::fs::read(&exe);
+        for file in &[obj_path, exe] {
+            let _ = std::fs::remove_file(file);
+        }
+        match linked {
+            Ok(linked) => assert!(linked.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        let exe_bytes = exe_bytes.expect("can read the linked executable");
+        let linked = Elf::parse(&exe_bytes).expect("can parse the linked executable");
+        let names = linked.syms.iter().map(|sym| &linked.strtab[sym.st_name]).collect::<Vec<_>>();
+        assert!(names.contains(&"kept"));
+        assert!(!names.contains(&"dropped"));
+    }
+}
+
+#[test]
+fn relative_relocation_addend_is_used_as_is() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![
+        0x66, 0x81, 0x3d, 0, 0, 0, 0, 0x2a, 0x00,   // cmp word [rip + value], 42
+        0x0f, 0x94, 0xc0,                           // sete al
+        0xc3,                                       // ret
+    ]).expect("can declare and define main");
+    obj.declare_with("value", Decl::data(), vec![42, 0]).expect("can declare and define value");
+    // the field is followed by the 2 byte immediate
+    obj.link_with(Link { from: "main", to: "value", at: 3 }, Reloc::Relative { size: 4, addend: -6 })
+        .expect("can link from main to value");
+    obj.declare_with("table", Decl::data(), vec![0; 8]).expect("can declare and define table");
+    obj.link_with(Link { from: "table", to: "value", at: 0 }, Reloc::Relative { size: 4, addend: -7 })
+        .expect("can link from table to value");
+    obj.link_with(Link { from: "table", to: "main", at: 4 }, Reloc::Relative { size: 4, addend: 3 })
+        .expect("can link from table to main");
+    assert!(obj.link_with(Link { from: "table", to: "main", at: 4 }, Reloc::Relative { size: 3, addend: 0 }).is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let mut relocs = elf.shdr_relocs.iter()
+        .flat_map(|(_, relocs)| relocs.iter().map(|reloc| (reloc.r_offset, reloc.r_type, reloc.r_addend)))
+        .collect::<Vec<_>>();
+    relocs.sort();
+    assert_eq!(relocs, vec![
+        (0, reloc::R_X86_64_PC32, Some(-7)),
+        (3, reloc::R_X86_64_PC32, Some(-6)),
+        (4, reloc::R_X86_64_PLT32, Some(3)),
+    ]);
+
+    // main is placed at 0x1000, then value at 0x100d, then table at 0x100f
+    let flat = obj.to_flat_binary(0x1000).expect("can emit flat binary");
+    assert_eq!(&flat[3..7], &(0x100d_i32 - 6 - 0x1003).to_le_bytes());
+    assert_eq!(&flat[0xf..0x13], &(0x100d_i32 - 7 - 0x100f).to_le_bytes());
+    assert_eq!(&flat[0x13..0x17], &(0x1000_i32 + 3 - 0x1013).to_le_bytes());
+    assert!(obj.emit_as(target_lexicon::BinaryFormat::Macho).is_err());
+}
+
+#[test]
+fn thirty_two_bit_targets_have_four_byte_pointers() {
+    for &(triple, pointer) in &[("i686-unknown-linux-gnu", reloc::R_386_32), ("armv7-unknown-linux-gnueabihf", reloc::R_ARM_ABS32)] {
+        let mut obj = Artifact::new(triple!(triple), "t.o".into());
+        obj.declare_with("main", Decl::function().global(), vec![0; 8]).expect("can declare and define main");
+        obj.declare_with("counter", Decl::data().global(), vec![0; 4]).expect("can declare and define counter");
+        obj.declare_with("table", Decl::data().writable(), vec![0; 8]).expect("can declare and define table");
+        obj.link(Link { from: "table", to: "main", at: 0 }).expect("can link from table to main");
+        obj.link(Link { from: "table", to: "counter", at: 4 }).expect("can link from table to counter");
+
+        let bytes = obj.emit().expect("can emit elf file");
+        let elf = Elf::parse(&bytes).expect("can parse elf file");
+        assert!(!elf.is_64, "{} is not 32-bit", triple);
+        let relocs = elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter().map(|reloc| (reloc.r_offset, reloc.r_type))).collect::<Vec<_>>();
+        assert_eq!(relocs, vec![(0, pointer), (4, pointer)], "{}", triple);
+        let counter = elf.syms.iter().find(|sym| &elf.strtab[sym.st_name] == "counter").expect("has counter");
+        assert_eq!(counter.st_size, 4);
+    }
+
+    // ELF has no 16-bit class
+    let mut obj = Artifact::new(triple!("msp430-none-elf"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0; 2]).expect("can declare and define main");
+    let err = obj.emit().expect_err("msp430 has 16-bit pointers");
+    match err.downcast_ref::<faerie::artifact::ArtifactError>() {
+        Some(faerie::artifact::ArtifactError::UnsupportedPointerWidth(_, 16, format)) => assert_eq!(format, "ELF"),
+        _ => panic!("unexpected error: {}", err),
+    }
+}
+
+#[test]
+fn explicit_relocations_take_their_width_and_indirection() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("_start", Decl::function().global(), vec![
+        0x48, 0x8b, 0x05, 0, 0, 0, 0,   // mov rax, [rip + counter@GOTPCREL]
+        0x8b, 0x00,                     // mov eax, [rax]
+        0x66, 0x03, 0x05, 0, 0, 0, 0,   // add ax, [rip + table]
+        0xeb, 0,                        // jmp short done
+    ]).expect("can declare and define _start");
+    // exit(eax)
+    obj.declare_with("done", Decl::function(), vec![0x89, 0xc7, 0xb8, 60, 0, 0, 0, 0x0f, 0x05]).expect("can declare and define done");
+    obj.declare_with("counter", Decl::data().global().writable(), vec![40, 0, 0, 0]).expect("can declare and define counter");
+    obj.declare_with("table", Decl::data(), vec![0; 2]).expect("can declare and define table");
+    obj.declare("two", Decl::absolute(2)).expect("can declare two");
+    obj.link_with(Link { from: "_start", to: "counter", at: 3 }, Reloc::Got { addend: -4 }).expect("can link from _start to counter");
+    obj.link(Link { from: "_start", to: "table", at: 12 }).expect("can link from _start to table");
+    obj.link_with(Link { from: "_start", to: "done", at: 17 }, Reloc::Relative { size: 1, addend: -1 }).expect("can link from _start to done");
+    obj.link_with(Link { from: "table", to: "two", at: 0 }, Reloc::Absolute { size: 2, addend: 0 }).expect("can link from table to two");
+    assert!(obj.link_with(Link { from: "table", to: "two", at: 0 }, Reloc::Absolute { size: 3, addend: 0 }).is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let mut relocs = elf.shdr_relocs.iter()
+        .flat_map(|(_, relocs)| relocs.iter().map(|reloc| (reloc.r_type, &elf.strtab[elf.syms.get(reloc.r_sym).expect("has symbol").st_name], reloc.r_addend)))
+        .collect::<Vec<_>>();
+    relocs.sort();
+    // the GOT entry is the symbol's own, rather than its section's
+    assert_eq!(relocs, vec![
+        (reloc::R_X86_64_PC32, "", Some(-4)),
+        (reloc::R_X86_64_GOTPCREL, "counter", Some(-4)),
+        (reloc::R_X86_64_16, "two", Some(0)),
+        (reloc::R_X86_64_PC8, "", Some(-1)),
+    ]);
+    assert!(obj.emit_as(target_lexicon::BinaryFormat::Macho).is_err());
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::Command;
+        let [obj_path, exe] = ["explicit.o", "explicit"].map(|name| temp_path("explicit", name));
+        std::fs::write(&obj_path, &bytes).expect("can write explicit.o");
+        let linked = Command::new("ld").args([&obj_path, "-o", &exe]).status();
+        let status = Command::new(&exe).status();
+        for file in &[obj_path, exe] {
+            let _ = std::fs::remove_file(file);
+        }
+        match linked {
+            Ok(linked) => assert!(linked.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        // counter + two
+        assert_eq!(status.expect("can run the linked executable").code(), Some(42));
+    }
+
+    let mut obj = Artifact::new(triple!("i686-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("f", Decl::function(), vec![0x8b, 0x05, 0, 0, 0, 0, 0xc3]).expect("can declare and define f");
+    obj.declare_with("counter", Decl::data().global(), vec![0; 4]).expect("can declare and define counter");
+    obj.link_with(Link { from: "f", to: "counter", at: 2 }, Reloc::Got { addend: -4 }).expect("can link from f to counter");
+    assert!(obj.emit().is_err());
+}
+
+#[test]
+fn jump_table_entries_are_offsets_from_the_table() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "switch.o".into());
+    obj.declare("local_table", Decl::data()).expect("can declare local_table");
+    obj.declare("switch", Decl::function().global()).expect("can declare switch");
+    obj.define("switch", vec![
+        0xb8, 1, 0, 0, 0, 0xc3,         // case0: mov eax, 1; ret
+        0xb8, 2, 0, 0, 0, 0xc3,         // case1: mov eax, 2; ret
+        0xb8, 3, 0, 0, 0, 0xc3,         // case2: mov eax, 3; ret
+    ]).expect("can define switch");
+    obj.declare("table", Decl::data()).expect("can declare table");
+    obj.define_jump_table("table", &[("switch", 0), ("switch", 12), ("switch", 6)]).expect("can define table");
+    // an entry may be resolved now when its label is in the table itself
+    obj.declare("both", Decl::data()).expect("can declare both");
+    assert!(obj.define_jump_table("both", &[("both", 0), ("absent", 0)]).is_err());
+    obj.import("elsewhere", ImportKind::Function).expect("can import elsewhere");
+    assert!(obj.define_jump_table("both", &[("elsewhere", 0)]).is_err());
+    obj.define("both", vec![0; 8]).expect("can define both");
+    assert!(obj.define_jump_table("local_table", &[("local_table", 0)]).is_ok());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let sections = elf.section_headers.iter()
+        .map(|header| (elf.shdr_strtab.get(header.sh_name).unwrap().unwrap(), header))
+        .collect::<Vec<_>>();
+    let (_, table) = sections.iter().find(|&&(name, _)| name == ".data.table").expect("has a section for table");
+    assert_eq!(table.sh_size, 12);
+    let mut relocs = elf.shdr_relocs.iter()
+        .flat_map(|(_, relocs)| relocs.iter().map(|reloc| (reloc.r_offset, reloc.r_type, reloc.r_addend)))
+        .collect::<Vec<_>>();
+    relocs.sort();
+    // the fields are relative to themselves, so their distances from the start of the table are added back
+    assert_eq!(relocs, vec![
+        (0, reloc::R_X86_64_PLT32, Some(0)),
+        (4, reloc::R_X86_64_PLT32, Some(4 + 12)),
+        (8, reloc::R_X86_64_PLT32, Some(8 + 6)),
+    ]);
+    assert!(sections.iter().all(|&(name, _)| name != ".reloc.local_table"));
+
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "inline.o".into());
+    obj.declare("inline", Decl::function().global()).expect("can declare inline");
+    obj.define("inline", vec![
+        0xb8, 1, 0, 0, 0, 0xc3,         // case0: mov eax, 1; ret
+        0xb8, 2, 0, 0, 0, 0xc3,         // case1: mov eax, 2; ret
+    ]).expect("can define inline");
+    obj.declare("table", Decl::function()).expect("can declare table");
+    obj.define_jump_table("table", &[("table", 8), ("table", 0)]).expect("can define a table of local labels");
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    assert!(elf.shdr_relocs.iter().all(|(_, relocs)| relocs.is_empty()));
+    let table = elf.section_headers.iter()
+        .find(|header| elf.shdr_strtab.get(header.sh_name).unwrap().unwrap() == ".text.table")
+        .expect("has a section for table");
+    let offset = table.sh_offset as usize;
+    assert_eq!(&bytes[offset..offset + 8], &[8, 0, 0, 0, 0, 0, 0, 0]);
+
+    // placed at 0x1000 after switch, the cross-section entries resolve to the offsets of the labels from the table
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "flat.o".into());
+    obj.declare_with("switch", Decl::function(), vec![0xc3; 18]).expect("can declare and define switch");
+    obj.declare("table", Decl::data()).expect("can declare table");
+    obj.define_jump_table("table", &[("switch", 0), ("switch", 12), ("switch", 6)]).expect("can define table");
+    let flat = obj.to_flat_binary(0x1000).expect("can emit flat binary");
+    assert_eq!(&flat[18..30], &[
+        (-18i32).to_le_bytes(), (12i32 - 18).to_le_bytes(), (6i32 - 18).to_le_bytes(),
+    ].concat()[..]);
+}
+
+#[test]
+fn plt_relocation_refers_to_a_defined_function() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "plt.o".into());
+    obj.declare_with("callee", Decl::function().global(), vec![0xc3]).expect("can declare and define callee");
+    obj.declare_with("caller", Decl::function().global(), vec![
+        0xe8, 0, 0, 0, 0,               // call callee
+        0xe9, 0, 0, 0, 0,               // jmp callee@PLT
+    ]).expect("can declare and define caller");
+    obj.declare_with("value", Decl::data(), vec![0; 4]).expect("can declare and define value");
+    obj.link(Link { from: "caller", to: "callee", at: 1 }).expect("can link from caller to callee");
+    obj.link_with(Link { from: "caller", to: "callee", at: 6 }, Reloc::Plt { addend: -4 })
+        .expect("can link from caller to the PLT entry of callee");
+    assert!(obj.link_with(Link { from: "caller", to: "value", at: 6 }, Reloc::Plt { addend: -4 }).is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let mut relocs = elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter().map(|reloc| {
+        let sym = elf.syms.get(reloc.r_sym).expect("relocation has a symbol");
+        (reloc.r_offset, reloc.r_type, sym.st_type(), elf.strtab[sym.st_name].to_string(), reloc.r_addend)
+    })).collect::<Vec<_>>();
+    relocs.sort();
+    assert_eq!(relocs, vec![
+        // the deduced call is resolved directly to the section of callee
+        (1, reloc::R_X86_64_PLT32, sym::STT_SECTION, String::new(), Some(-4)),
+        (6, reloc::R_X86_64_PLT32, sym::STT_FUNC, "callee".to_string(), Some(-4)),
+    ]);
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::Command;
+        let [obj_path, lib] = ["plt.o", "libplt.so"].map(|name| temp_path("plt", name));
+        std::fs::write(&obj_path, &bytes).expect("can write plt.o");
+        let status = Command::new("ld").args(["-shared", &obj_path, "-o", &lib]).status();
+        let linked = std::fs::read(&lib);
+        for file in &[obj_path, lib] {
+            let _ = std::fs::remove_file(file);
+        }
+        match status {
+            Ok(status) => assert!(status.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        // callee can be preempted, so it has a PLT entry
+        let linked = linked.expect("can read the shared library");
+        let elf = Elf::parse(&linked).expect("can parse the shared library");
+        assert!(elf.pltrelocs.iter().any(|reloc| {
+            reloc.r_type == reloc::R_X86_64_JUMP_SLOT && &elf.dynstrtab[elf.dynsyms.get(reloc.r_sym).expect("dynamic symbol").st_name] == "callee"
+        }));
+    }
+}
+
+#[test]
+fn sframe_section_describes_each_function() {
+    use faerie::{SFrameBase, SFrameRow};
+    let row = |offset, cfa_base, cfa_offset, fp_offset| SFrameRow { offset, cfa_base, cfa_offset, fp_offset, ra_offset: None };
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "sframe.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![
+        0x55,                           // push rbp
+        0x48, 0x89, 0xe5,               // mov rbp, rsp
+        0x5d,                           // pop rbp
+        0xc3,                           // ret
+    ]).expect("can declare and define f");
+    obj.declare("g", Decl::FunctionImport).expect("can declare g");
+    obj.set_sframe("f", vec![
+        row(0, SFrameBase::StackPointer, 8, None),
+        row(1, SFrameBase::StackPointer, 16, Some(-16)),
+        row(4, SFrameBase::FramePointer, 16, Some(-16)),
+        row(5, SFrameBase::StackPointer, 8, Some(-16)),
+    ]).expect("can set the SFrame rows of f");
+    assert!(obj.set_sframe("g", vec![row(0, SFrameBase::StackPointer, 8, None)]).is_err());
+    assert!(obj.set_sframe("f", vec![row(1, SFrameBase::StackPointer, 8, None)]).is_err());
+    assert!(obj.set_sframe("f", vec![SFrameRow { ra_offset: Some(-16), ..row(0, SFrameBase::StackPointer, 8, None) }]).is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let (shndx, section) = elf.section_headers.iter().enumerate()
+        .find(|&(_, shdr)| &elf.shdr_strtab[shdr.sh_name] == ".sframe")
+        .expect("has an .sframe section");
+    assert_eq!(section.sh_type, section_header::SHT_PROGBITS);
+    assert_eq!(section.sh_flags, u64::from(section_header::SHF_ALLOC));
+    assert_eq!(section.sh_addralign, 8);
+    // as GNU as emits it for the same CFI
+    let contents = &bytes[section.sh_offset as usize..(section.sh_offset + section.sh_size) as usize];
+    assert_eq!(contents, &[
+        0xe2, 0xde, 1, 0, 3, 0, 0xf8, 0, 1, 0, 0, 0, 4, 0, 0, 0, 15, 0, 0, 0, 0, 0, 0, 0, 17, 0, 0, 0,
+        0, 0, 0, 0, 6, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0, 0,
+        0, 3, 8, 1, 5, 16, 0xf0, 4, 4, 16, 0xf0, 5, 5, 8, 0xf0,
+    ][..]);
+    let relocs = elf.shdr_relocs.iter().find(|&&(idx, _)| elf.section_headers[idx].sh_info as usize == shndx)
+        .map(|(_, relocs)| relocs.iter().map(|reloc| (reloc.r_offset, reloc.r_type, reloc.r_addend)).collect::<Vec<_>>());
+    assert_eq!(relocs, Some(vec![(28, reloc::R_X86_64_PC32, Some(0))]));
+
+    #[cfg(target_os = "linux")]
+    {
+        let path = temp_path("sframe", "sframe.o");
+        std::fs::write(&path, &bytes).expect("can write the object");
+        let output = std::process::Command::new("readelf").arg("--sframe").arg(&path).output();
+        let _ = std::fs::remove_file(&path);
+        match output {
+            Ok(ref output) if output.status.success() && !output.stdout.is_empty() => {
+                let sframe = String::from_utf8_lossy(&output.stdout);
+                assert!(sframe.contains("Num FDEs: 1"), "{}", sframe);
+                assert!(sframe.contains("Num FREs: 4"), "{}", sframe);
+                assert!(sframe.contains("size = 6 bytes"), "{}", sframe);
+                let rows = sframe.lines().filter(|line| line.starts_with("    0000")).map(|line| line.split_whitespace().collect::<Vec<_>>())
+                    .collect::<Vec<_>>();
+                assert_eq!(rows, vec![
+                    vec!["0000000000000000", "sp+8", "u", "u"],
+                    vec!["0000000000000001", "sp+16", "c-16", "u"],
+                    vec!["0000000000000004", "fp+16", "c-16", "u"],
+                    vec!["0000000000000005", "sp+8", "c-16", "u"],
+                ]);
+            },
+            _ => eprintln!("readelf --sframe is not available, skipping"),
+        }
+    }
+}
+
+#[test]
+fn local_and_global_symbols_can_share_a_name() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("foo", Decl::function().global(), vec![0x31, 0xc0, 0xc3]).expect("can declare and define foo");
+    // the static foo of another unit
+    obj.declare_with("foo.static", Decl::function(), vec![0xb8, 1, 0, 0, 0, 0xc3]).expect("can declare and define foo.static");
+    obj.set_symbol_name("foo.static", "foo").expect("can emit foo.static as foo");
+    assert!(obj.set_symbol_name("foo", "bar").is_err());
+    assert!(obj.set_symbol_name("missing", "bar").is_err());
+    obj.declare_with("sizes", Decl::data(), vec![0; 8]).expect("can declare and define sizes");
+    obj.link_with(Link { from: "sizes", to: "foo", at: 0 }, Reloc::Size { size: 4, addend: 0 }).expect("can link to foo");
+    obj.link_with(Link { from: "sizes", to: "foo.static", at: 4 }, Reloc::Size { size: 4, addend: 0 })
+        .expect("can link to foo.static");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let foos = elf.syms.iter().filter(|sym| &elf.strtab[sym.st_name] == "foo")
+        .map(|sym| (sym.st_bind(), sym.st_size)).collect::<Vec<_>>();
+    assert_eq!(foos, vec![(sym::STB_LOCAL, 6), (sym::STB_GLOBAL, 3)]);
+    assert!(elf.syms.iter().all(|sym| &elf.strtab[sym.st_name] != "foo.static"));
+    let mut relocs = elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter().map(|reloc| {
+        let sym = elf.syms.get(reloc.r_sym).expect("relocation has a symbol");
+        (reloc.r_offset, elf.strtab[sym.st_name].to_string(), sym.st_bind())
+    })).collect::<Vec<_>>();
+    relocs.sort();
+    assert_eq!(relocs, vec![(0, "foo".to_string(), sym::STB_GLOBAL), (4, "foo".to_string(), sym::STB_LOCAL)]);
+    assert!(obj.emit_as(target_lexicon::BinaryFormat::Macho).is_err());
+}
+
+#[test]
+fn zero_initialized_data_takes_no_space_in_bss() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "bss.o".into());
+    obj.declare_with("_start", Decl::function().global(), vec![
+        0x8b, 0x3d, 0, 0, 0, 0,         // mov edi, [rip + buffer]
+        0x03, 0x3d, 0, 0, 0, 0,         // add edi, [rip + counter]
+        0x83, 0xc7, 42,                 // add edi, 42
+        0xb8, 60, 0, 0, 0,              // mov eax, SYS_exit
+        0x0f, 0x05,                     // syscall
+    ]).expect("can declare and define _start");
+    obj.declare("buffer", Decl::data().global().zero_init(64)).expect("can declare buffer");
+    obj.define_zeroed("buffer", 1 << 20).expect("can define buffer");
+    // zeros in memory are only recorded as their size
+    obj.declare_with("counter", Decl::data().zero_init(4), vec![0; 4]).expect("can declare and define counter");
+    obj.link(Link { from: "_start", to: "buffer", at: 2 }).expect("can link from _start to buffer");
+    obj.link(Link { from: "_start", to: "counter", at: 8 }).expect("can link from _start to counter");
+    assert!(obj.link(Link { from: "buffer", to: "counter", at: 0 }).is_err());
+    obj.declare("initialized", Decl::data().zero_init(4)).expect("can declare initialized");
+    assert!(obj.define("initialized", vec![1, 0, 0, 0]).is_err());
+    obj.define_zeroed("initialized", 4).expect("can define initialized");
+    assert!(obj.declare("misaligned", Decl::data().zero_init(3)).is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    assert!(bytes.len() < 4096, "the object is {} bytes", bytes.len());
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let (bss_index, bss) = elf.section_headers.iter().enumerate()
+        .find(|&(_, shdr)| &elf.shdr_strtab[shdr.sh_name] == ".bss").expect("has a .bss section");
+    assert_eq!(bss.sh_type, section_header::SHT_NOBITS);
+    assert_eq!(bss.sh_flags, u64::from(section_header::SHF_ALLOC | section_header::SHF_WRITE));
+    assert_eq!(bss.sh_addralign, 64);
+    // the locals come first, then buffer is aligned after them
+    assert_eq!(bss.sh_size, 64 + (1 << 20));
+    let symbol = |name: &str| elf.syms.iter().enumerate().find(|(_, sym)| &elf.strtab[sym.st_name] == name).expect("has the symbol");
+    let (counter_index, counter) = symbol("counter");
+    let (buffer_index, buffer) = symbol("buffer");
+    assert_eq!((counter.st_shndx, counter.st_value, counter.st_size), (bss_index, 0, 4));
+    assert_eq!((symbol("initialized").1.st_shndx, symbol("initialized").1.st_value), (bss_index, 4));
+    assert_eq!((buffer.st_shndx, buffer.st_value, buffer.st_size), (bss_index, 64, 1 << 20));
+    // .bss has no section symbol of its own, so the relocations are against the symbols
+    let relocs = elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter().map(|reloc| reloc.r_sym)).collect::<Vec<_>>();
+    assert_eq!(relocs, vec![buffer_index, counter_index]);
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::Command;
+        let [obj_path, exe] = ["bss.o", "bss"].map(|name| temp_path("bss", name));
+        std::fs::write(&obj_path, &bytes).expect("can write bss.o");
+        let linked = Command::new("ld").args([&obj_path, "-o", &exe]).status();
+        let status = Command::new(&exe).status();
+        for file in &[obj_path, exe] {
+            let _ = std::fs::remove_file(file);
+        }
+        match linked {
+            Ok(linked) => assert!(linked.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        assert_eq!(status.expect("can run the linked executable").code(), Some(42));
+    }
+}
+
+#[test]
+fn common_symbols_are_merged_by_the_linker() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "common.o".into());
+    obj.declare_with("_start", Decl::function().global(), vec![
+        0xc7, 0x05, 0, 0, 0, 0, 42, 0, 0, 0, // mov dword [rip + counter], 42
+        0x8b, 0x3d, 0, 0, 0, 0,         // mov edi, [rip + counter]
+        0x48, 0x8d, 0x05, 0, 0, 0, 0,   // lea rax, [rip + buffer]
+        0xb8, 60, 0, 0, 0,              // mov eax, SYS_exit
+        0x0f, 0x05,                     // syscall
+    ]).expect("can declare and define _start");
+    obj.declare_bss("counter", Decl::data().common(4), 4, 4).expect("can declare counter");
+    obj.declare("buffer", Decl::data().common(64)).expect("can declare buffer");
+    obj.define_zeroed("buffer", 256).expect("can define buffer");
+    // the field is followed by the 4 byte immediate
+    obj.link_with(Link { from: "_start", to: "counter", at: 2 }, Reloc::Relative { size: 4, addend: -8 }).expect("can link to counter");
+    obj.link(Link { from: "_start", to: "counter", at: 12 }).expect("can link to counter");
+    obj.link(Link { from: "_start", to: "buffer", at: 19 }).expect("can link to buffer");
+    // the linker places a common symbol, and the linker merges it by its global name
+    assert!(obj.link_with(Link { from: "_start", to: "buffer", at: 19 }, Reloc::SectionOffset { size: 4, addend: 0 }).is_err());
+    assert!(obj.define_alias("buffer_alias", "buffer", Scope::Global).is_err());
+    assert!(obj.declare("private", Decl::data().common(4).local()).is_err());
+    assert!(obj.declare("per_thread", Decl::data().common(4).thread_local()).is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    assert!(elf.section_headers.iter().all(|shdr| &elf.shdr_strtab[shdr.sh_name] != ".bss"));
+    let symbol = |name: &str| elf.syms.iter().enumerate().find(|(_, sym)| &elf.strtab[sym.st_name] == name).expect("has the symbol");
+    let (counter_index, counter) = symbol("counter");
+    let (buffer_index, buffer) = symbol("buffer");
+    // the value of a common symbol is its alignment
+    assert_eq!((counter.st_shndx, counter.st_value, counter.st_size), (section_header::SHN_COMMON as usize, 4, 4));
+    assert_eq!((buffer.st_shndx, buffer.st_value, buffer.st_size), (section_header::SHN_COMMON as usize, 64, 256));
+    assert_eq!((counter.st_bind(), counter.st_type()), (sym::STB_GLOBAL, sym::STT_OBJECT));
+    let relocs = elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter().map(|reloc| reloc.r_sym)).collect::<Vec<_>>();
+    assert_eq!(relocs, vec![counter_index, counter_index, buffer_index]);
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::Command;
+        // another object's tentative definition of counter is larger, and more aligned
+        let mut other = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "other.o".into());
+        other.declare_bss("counter", Decl::data().common(64), 8, 64).expect("can declare counter");
+        let other_bytes = other.emit().expect("can emit elf file");
+        let [obj_path, other_path, exe] = ["common.o", "other.o", "common"].map(|name| temp_path("common", name));
+        std::fs::write(&obj_path, &bytes).expect("can write common.o");
+        std::fs::write(&other_path, &other_bytes).expect("can write other.o");
+        let linked = Command::new("ld").args([&obj_path, &other_path, "-o", &exe]).status();
+        let linked_bytes = std::fs::read(&exe);
+        let status = Command::new(&exe).status();
+        for file in &[obj_path, other_path, exe] {
+            let _ = std::fs::remove_file(file);
+        }
+        match linked {
+            Ok(linked) => assert!(linked.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        let linked_bytes = linked_bytes.expect("can read the linked executable");
+        let linked = Elf::parse(&linked_bytes).expect("can parse the linked executable");
+        let counter = linked.syms.iter().find(|sym| &linked.strtab[sym.st_name] == "counter").expect("has counter");
+        assert_eq!((counter.st_size, counter.st_value % 64), (8, 0));
+        assert_eq!(status.expect("can run the linked executable").code(), Some(42));
+    }
+}
+
+#[test]
+fn raw_relocation_addend_fills_its_explicit_size() {
+    // a type the encoder doesn't know the field of, e.g. a vendor extension
+    const R_386_VENDOR16: u32 = 200;
+    let mut obj = Artifact::new(triple!("i686-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("table", Decl::data(), vec![0xff; 12]).expect("can declare and define table");
+    obj.declare("target", Decl::DataImport).expect("can declare target");
+    obj.link_with(Link { from: "table", to: "target", at: 0 }, Reloc::Raw { reloc: R_386_VENDOR16, addend: 0x0102, size: Some(2) })
+        .expect("can link a 2 byte field");
+    obj.link_with(Link { from: "table", to: "target", at: 4 }, Reloc::Raw { reloc: reloc::R_386_32, addend: 0x0304_0506, size: Some(4) })
+        .expect("can link a 4 byte field");
+    obj.link_with(Link { from: "table", to: "target", at: 8 }, Reloc::Raw { reloc: R_386_VENDOR16, addend: 0x0708, size: None })
+        .expect("can link a field of the default size");
+    assert!(obj.link_with(Link { from: "table", to: "target", at: 0 }, Reloc::Raw { reloc: R_386_VENDOR16, addend: 0, size: Some(3) }).is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let table = elf.section_headers.iter().find(|shdr| &elf.shdr_strtab[shdr.sh_name] == ".data.table").expect("has .data.table");
+    assert_eq!(&bytes[table.sh_offset as usize..][..12], &[2, 1, 0xff, 0xff, 6, 5, 4, 3, 8, 7, 0, 0]);
+    let (_, ref relocs) = elf.shdr_relocs[0];
+    assert_eq!(relocs.iter().map(|reloc| (reloc.r_offset, reloc.r_type)).collect::<Vec<_>>(),
+        vec![(0, R_386_VENDOR16), (4, reloc::R_386_32), (8, R_386_VENDOR16)]);
+}
+
+#[test]
+fn strings_can_be_placed_in_a_custom_merged_section() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0x48, 0x8d, 0x3d, 0, 0, 0, 0, 0xc3]).expect("can declare and define main");
+    obj.declare_with("hello", Decl::cstring(), b"hello\0".to_vec()).expect("can declare and define hello");
+    obj.declare_with("world", Decl::cstring(), b"world\0".to_vec()).expect("can declare and define world");
+    obj.declare_with("plain", Decl::cstring(), b"plain\0".to_vec()).expect("can declare and define plain");
+    obj.set_string_section("hello", ".my_strings").expect("can place hello in .my_strings");
+    obj.set_string_section("world", ".my_strings").expect("can place world in .my_strings");
+    obj.link(Link { from: "main", to: "world", at: 3 }).expect("can link from main to world");
+    assert!(obj.set_string_section("main", ".my_strings").is_err());
+    assert!(obj.set_string_section("hello", "").is_err());
+    obj.declare("unterminated", Decl::cstring()).expect("can declare unterminated");
+    assert!(obj.define("unterminated", b"oops".to_vec()).is_err());
+    obj.define("unterminated", b"fine\0".to_vec()).expect("can define unterminated");
+    assert!(obj.emit_as(target_lexicon::BinaryFormat::Macho).is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let merged = section_header::SHF_ALLOC | section_header::SHF_MERGE | section_header::SHF_STRINGS;
+    let strings = elf.section_headers.iter()
+        .filter(|shdr| shdr.sh_flags == u64::from(merged))
+        .map(|shdr| (&elf.shdr_strtab[shdr.sh_name], shdr.sh_entsize, &bytes[shdr.sh_offset as usize..][..shdr.sh_size as usize]))
+        .collect::<Vec<_>>();
+    assert_eq!(strings, vec![
+        (".my_strings", 1, &b"hello\0"[..]),
+        (".my_strings", 1, &b"world\0"[..]),
+        (".data.plain", 1, &b"plain\0"[..]),
+        (".data.unterminated", 1, &b"fine\0"[..]),
+    ]);
+}
+
+#[test]
+fn functions_and_data_can_be_placed_in_named_sections() {
+    use faerie::artifact::ArtifactError;
+    let mut obj = ArtifactBuilder::new(triple!("x86_64-unknown-linux-gnu")).name("placed.o".into()).function_sections(false).finish();
+    obj.declare_with("_start", Decl::function().global(), vec![
+        0xe8, 0, 0, 0, 0,               // call hot
+        0x03, 0x05, 0, 0, 0, 0,         // add eax, [rip + table + 4]
+        0x03, 0x05, 0, 0, 0, 0,         // add eax, [rip + counter]
+        0x89, 0xc7,                     // mov edi, eax
+        0xb8, 60, 0, 0, 0,              // mov eax, SYS_exit
+        0x0f, 0x05,                     // syscall
+    ]).expect("can declare and define _start");
+    obj.declare_with("hot", Decl::function(), vec![0xb8, 30, 0, 0, 0, 0xc3]).expect("can declare and define hot");
+    obj.declare_with("table", Decl::data(), vec![0, 0, 0, 0, 12, 0, 0, 0]).expect("can declare and define table");
+    obj.declare_bss("counter", Decl::data().writable(), 4, 4).expect("can declare counter");
+    obj.declare_bss("shared", Decl::data().global().common(8), 8, 8).expect("can declare shared");
+    obj.import("puts", ImportKind::Function).expect("can import puts");
+    obj.set_section("hot", ".text.hot").expect("can place hot in .text.hot");
+    obj.set_section("table", ".rodata.tables").expect("can place table in .rodata.tables");
+    obj.set_section("counter", ".bss.counters").expect("can place counter in .bss.counters");
+    for &name in &["shared", "puts"] {
+        match obj.set_section(name, ".data.hot") {
+            Err(ArtifactError::NotPlaceable(placed)) => assert_eq!(placed, name),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+    assert!(obj.set_section("hot", "").is_err());
+    assert!(obj.set_section("nothing", ".text.hot").is_err());
+    obj.link(Link { from: "_start", to: "hot", at: 1 }).expect("can link to hot");
+    obj.link_with(Link { from: "_start", to: "table", at: 7 }, Reloc::Relative { size: 4, addend: 0 }).expect("can link to table");
+    obj.link(Link { from: "_start", to: "counter", at: 13 }).expect("can link to counter");
+    assert!(obj.emit_as(target_lexicon::BinaryFormat::Macho).is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let section = |name: &str| elf.section_headers.iter().position(|shdr| &elf.shdr_strtab[shdr.sh_name] == name)
+        .unwrap_or_else(|| panic!("has {}", name));
+    let symbol = |name: &str| elf.syms.iter().find(|sym| &elf.strtab[sym.st_name] == name).expect("has symbol");
+    let (hot, tables, counters) = (section(".text.hot"), section(".rodata.tables"), section(".bss.counters"));
+    assert_eq!(elf.section_headers[hot].sh_flags, u64::from(section_header::SHF_ALLOC | section_header::SHF_EXECINSTR));
+    assert_eq!(elf.section_headers[tables].sh_flags & u64::from(section_header::SHF_WRITE | section_header::SHF_EXECINSTR), 0);
+    assert_eq!(elf.section_headers[counters].sh_type, section_header::SHT_NOBITS);
+    assert_eq!((symbol("hot").st_shndx, symbol("table").st_shndx, symbol("counter").st_shndx), (hot, tables, counters));
+    // _start isn't placed, so it is still in .text
+    assert_eq!(symbol("_start").st_shndx, section(".text"));
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::Command;
+        let [obj_path, exe] = ["placed.o", "placed"].map(|name| temp_path("placed", name));
+        std::fs::write(&obj_path, &bytes).expect("can write placed.o");
+        let linked = Command::new("ld").args([&obj_path, "-o", &exe]).status();
+        let status = Command::new(&exe).status();
+        for file in &[obj_path, exe] {
+            let _ = std::fs::remove_file(file);
+        }
+        match linked {
+            Ok(linked) => assert!(linked.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        assert_eq!(status.expect("can run the linked executable").code(), Some(42));
+    }
+}
+
+#[test]
+fn weak_imports_are_weak_undefined_symbols() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "weak.o".into());
+    obj.declare_with("_start", Decl::function().global(), vec![
+        0xbf, 0, 0, 0, 0,               // mov edi, hook
+        0x83, 0xc7, 42,                 // add edi, 42
+        0xb8, 60, 0, 0, 0,              // mov eax, SYS_exit
+        0x0f, 0x05,                     // syscall
+    ]).expect("can declare and define _start");
+    obj.import("hook", ImportKind::Function).expect("can import hook");
+    obj.link_with(Link { from: "_start", to: "hook", at: 1 }, Reloc::Absolute { size: 4, addend: 0 }).expect("can link from _start to hook");
+    assert!(obj.set_weak_import("_start", true).is_err());
+    let hook = |bytes: &[u8]| {
+        let elf = Elf::parse(bytes).expect("can parse elf file");
+        let sym = elf.syms.iter().find(|sym| &elf.strtab[sym.st_name] == "hook").expect("has hook");
+        (sym.st_bind(), sym.st_type(), sym.st_shndx)
+    };
+    assert_eq!(hook(&obj.emit().expect("can emit elf file")), (sym::STB_GLOBAL, sym::STT_NOTYPE, 0));
+    obj.set_weak_import("hook", true).expect("can make hook weak");
+    let bytes = obj.emit().expect("can emit elf file");
+    assert_eq!(hook(&bytes), (sym::STB_WEAK, sym::STT_NOTYPE, 0));
+
+    // a Mach-O weak reference
+    let mut mach = Artifact::new(triple!("x86_64-apple-darwin"), "weak.o".into());
+    mach.import("hook", ImportKind::Function).expect("can import hook");
+    mach.set_weak_import("hook", true).expect("can make hook weak");
+    let mach = mach.emit().expect("can emit mach file");
+    let mach = goblin::mach::MachO::parse(&mach, 0).expect("can parse mach file");
+    let (_, nlist) = mach.symbols().map(|symbol| symbol.expect("can parse symbol")).find(|&(name, _)| name == "_hook").expect("has _hook");
+    assert_eq!(nlist.n_desc, 0x40);
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::Command;
+        let [obj_path, exe] = ["weak.o", "weak"].map(|name| temp_path("weak-imports", name));
+        std::fs::write(&obj_path, &bytes).expect("can write weak.o");
+        // nothing defines hook, so its address is zero
+        let linked = Command::new("ld").args([&obj_path, "-o", &exe]).status();
+        let status = Command::new(&exe).status();
+        for file in &[obj_path, exe] {
+            let _ = std::fs::remove_file(file);
+        }
+        match linked {
+            Ok(linked) => assert!(linked.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        assert_eq!(status.expect("can run the linked executable").code(), Some(42));
+    }
+}
+
+#[test]
+fn thread_local_data_is_placed_in_tls_sections() {
+    use goblin::elf::section_header::{SHF_TLS, SHT_NOBITS};
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "tls.o".into());
+    obj.declarations(vec![
+        ("_start", Decl::function().global().into()),
+        ("counter", Decl::data().global().thread_local().into()),
+        ("scratch", Decl::data().thread_local().zero_init(8).into()),
+    ].into_iter()).expect("can declare");
+    obj.define("_start", vec![
+        0x48, 0x8b, 0x05, 0, 0, 0, 0,   // mov rax, [rip + counter@gottpoff]
+        0x64, 0x8b, 0x38,               // mov edi, fs:[rax]
+        0x66, 0x48, 0x8d, 0x3d, 0, 0, 0, 0, // data16 lea rdi, [rip + scratch@tlsgd]
+        0x66, 0x66, 0x48, 0xe8, 0, 0, 0, 0, // data16 data16 rex.W call __tls_get_addr@plt
+        0xb8, 60, 0, 0, 0,              // mov eax, SYS_exit
+        0x0f, 0x05,                     // syscall
+    ]).expect("can define _start");
+    obj.define("counter", vec![42, 0, 0, 0]).expect("can define counter");
+    obj.define_zeroed("scratch", 8).expect("can define scratch");
+    obj.link_with(Link { from: "_start", to: "counter", at: 3 }, Reloc::Tls { model: TlsModel::InitialExec, addend: 0 }).expect("can link to counter");
+    obj.link_with(Link { from: "_start", to: "scratch", at: 14 }, Reloc::Tls { model: TlsModel::GeneralDynamic, addend: 0 }).expect("can link to scratch");
+    // thread-local data has no address of its own, and other data has no TLS offset
+    assert!(obj.link(Link { from: "_start", to: "counter", at: 3 }).is_err());
+    obj.declare("start_addr", Decl::data()).expect("can declare start_addr");
+    assert!(obj.link_with(Link { from: "start_addr", to: "_start", at: 0 }, Reloc::Tls { model: TlsModel::LocalExec, addend: 0 }).is_err());
+    obj.define("start_addr", vec![0; 8]).expect("can define start_addr");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let section = |name: &str| elf.section_headers.iter().find(|shdr| &elf.shdr_strtab[shdr.sh_name] == name).expect("has section");
+    assert_ne!(section(".tdata.counter").sh_flags & u64::from(SHF_TLS), 0);
+    let tbss = section(".tbss");
+    assert_eq!((tbss.sh_type, tbss.sh_size), (SHT_NOBITS, 8));
+    assert_ne!(tbss.sh_flags & u64::from(SHF_TLS), 0);
+    let symbol = |name: &str| elf.syms.iter().position(|sym| &elf.strtab[sym.st_name] == name).expect("has symbol");
+    for name in &["counter", "scratch"] {
+        assert_eq!(elf.syms.get(symbol(name)).unwrap().st_type(), sym::STT_TLS);
+    }
+    let tls_get_addr = elf.syms.get(symbol("__tls_get_addr")).unwrap();
+    assert_eq!(tls_get_addr.st_shndx, 0);
+    let relocs = elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter())
+        .map(|reloc| (reloc.r_offset, reloc.r_type, reloc.r_sym, reloc.r_addend))
+        .collect::<Vec<_>>();
+    assert_eq!(relocs, [
+        (3, reloc::R_X86_64_GOTTPOFF, symbol("counter"), Some(-4)),
+        (14, reloc::R_X86_64_TLSGD, symbol("scratch"), Some(-4)),
+        (22, reloc::R_X86_64_PLT32, symbol("__tls_get_addr"), Some(-4)),
+    ]);
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::Command;
+        let [obj_path, exe] = ["tls.o", "tls"].map(|name| temp_path("tls", name));
+        std::fs::write(&obj_path, &bytes).expect("can write tls.o");
+        // a static link relaxes both accesses to offsets from the thread pointer, so nothing defines __tls_get_addr
+        let linked = Command::new("ld").args([&obj_path, "-o", &exe]).status();
+        let linked_bytes = std::fs::read(&exe);
+        for file in &[obj_path, exe] {
+            let _ = std::fs::remove_file(file);
+        }
+        match linked {
+            Ok(linked) => assert!(linked.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        let linked_bytes = linked_bytes.expect("can read the linked executable");
+        let linked = Elf::parse(&linked_bytes).expect("can parse the linked executable");
+        let tls = linked.program_headers.iter().find(|phdr| phdr.p_type == goblin::elf::program_header::PT_TLS).expect("has a TLS segment");
+        // the template holds counter, followed by the zeroed scratch
+        assert_eq!((tls.p_filesz, tls.p_memsz), (4, 16));
+    }
+}
+
+#[test]
+fn avr_calls_and_loads_addresses_in_ldi_pairs() {
+    use std::sync::Arc;
+    use faerie::encoder::*;
+
+    // target-lexicon doesn't know AVR
+    let target = target_lexicon::Triple { binary_format: target_lexicon::BinaryFormat::Elf, ..Default::default() };
+    let mut obj = ArtifactBuilder::new(target)
+        .reloc_encoder(Arc::new(Avr { family: 5 }))
+        .finish();
+    obj.declarations(vec![
+        ("main", Decl::function().global().into()),
+        ("blink", Decl::function().into()),
+        ("counter", Decl::data().global().writable().into()),
+        ("handlers", Decl::data().global().into()),
+    ].into_iter()).expect("can declare");
+    obj.define("main", vec![
+        0x0e, 0x94, 0, 0,   // call blink
+        0x80, 0xe0,         // ldi r24, lo8(counter)
+        0x90, 0xe0,         // ldi r25, hi8(counter)
+        0x00, 0xc0,         // rjmp main
+    ]).expect("can define main");
+    obj.define("blink", vec![0x08, 0x95]).expect("can define blink");
+    obj.define("counter", vec![0, 0]).expect("can define counter");
+    obj.define("handlers", vec![0; 4]).expect("can define handlers");
+    obj.link(Link { from: "main", to: "blink", at: 0 }).expect("can link main to blink");
+    obj.link(Link { from: "main", to: "counter", at: 4 }).expect("can link main to counter");
+    obj.link_with(Link { from: "main", to: "main", at: 8 }, Reloc::Relative { size: 4, addend: 0 }).expect("can link main to itself");
+    obj.link(Link { from: "handlers", to: "blink", at: 0 }).expect("can link handlers to blink");
+    obj.link(Link { from: "handlers", to: "counter", at: 2 }).expect("can link handlers to counter");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    assert!(!elf.is_64);
+    assert_eq!((elf.header.e_machine, elf.header.e_flags), (header::EM_AVR, 5));
+    let mut relocs = elf.shdr_relocs.iter()
+        .flat_map(|(_, relocs)| relocs.iter().map(|reloc| (reloc.r_offset, reloc.r_type, reloc.r_addend)))
+        .collect::<Vec<_>>();
+    relocs.sort();
+    // code addresses are word addresses, but the addends stay in bytes
+    assert_eq!(relocs, [
+        (0, R_AVR_16_PM, Some(0)),
+        (0, R_AVR_CALL, Some(0)),
+        (2, R_AVR_16, Some(0)),
+        (4, R_AVR_LO8_LDI, Some(0)),
+        (6, R_AVR_HI8_LDI, Some(0)),
+        (8, R_AVR_13_PCREL, Some(0)),
+    ]);
+
+    // there is no PC relative addressing of data
+    assert!(obj.link_with(Link { from: "main", to: "counter", at: 4 }, Reloc::Relative { size: 4, addend: 0 }).is_ok());
+    assert!(obj.emit().is_err());
+}
+
+#[test]
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn shared_object_runs_constructors_from_init_array() {
+    use std::process::Command;
+    use goblin::elf::dyn::{DT_INIT_ARRAY, DT_INIT_ARRAYSZ, DT_FINI_ARRAY, DT_FINI_ARRAYSZ};
+    let [obj_path, lib] = ["init.o", "libinit.so"].map(|name| temp_path("init", name));
+
+    // the object only has .init_array and .fini_array, and the linker makes the dynamic entries which point at them
+    let mut obj = ArtifactBuilder::new(triple!("x86_64-unknown-linux-gnu")).library(true).finish();
+    obj.declare_with("setup", Decl::function(), vec![0xc3]).expect("can declare and define setup");
+    obj.declare_with("setup_more", Decl::function(), vec![0xc3]).expect("can declare and define setup_more");
+    obj.declare_with("teardown", Decl::function(), vec![0xc3]).expect("can declare and define teardown");
+    obj.add_constructor("setup", None).expect("can add setup");
+    obj.add_constructor("setup_more", None).expect("can add setup_more");
+    obj.add_destructor("teardown", None).expect("can add teardown");
+    std::fs::write(&obj_path, obj.emit().expect("can emit elf file")).expect("can write init.o");
+
+    let linked = Command::new("ld").args(["-shared", &obj_path, "-o", &lib]).status();
+    let bytes = std::fs::read(&lib);
+    for file in &[obj_path, lib] {
+        let _ = std::fs::remove_file(file);
+    }
+    match linked {
+        Ok(linked) => assert!(linked.success(), "ld -shared failed"),
+        Err(_) => return eprintln!("ld is not available, skipping"),
+    }
+    let bytes = bytes.expect("can read the shared object");
+    let elf = Elf::parse(&bytes).expect("can parse the shared object");
+    let dynamic = elf.dynamic.as_ref().expect("has a dynamic section");
+    let entry = |tag: u64| dynamic.dyns.iter().find(|dyn_| dyn_.d_tag == tag).map(|dyn_| dyn_.d_val);
+    let section = |name: &str| elf.section_headers.iter().find(|shdr| &elf.shdr_strtab[shdr.sh_name] == name).expect("has section");
+    let (init_array, fini_array) = (section(".init_array"), section(".fini_array"));
+    assert_eq!(entry(DT_INIT_ARRAY), Some(init_array.sh_addr));
+    assert_eq!(entry(DT_INIT_ARRAYSZ), Some(16));
+    assert_eq!(entry(DT_FINI_ARRAY), Some(fini_array.sh_addr));
+    assert_eq!(entry(DT_FINI_ARRAYSZ), Some(8));
+}
+
+#[test]
+fn custom_sections_have_the_declared_flags() {
+    use goblin::elf::section_header::{SHF_ALLOC, SHF_WRITE, SHF_EXECINSTR, SHF_MERGE, SHF_STRINGS};
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "sections.o".into());
+    obj.declarations(vec![
+        ("main", Decl::function().global().into()),
+        (".counters", Decl::section().writable().into()),
+        (".rodata.utf16", Decl::section().strings(2).into()),
+        (".trampolines", Decl::section().executable().into()),
+    ].into_iter()).expect("can declare");
+    obj.define("main", vec![0x8b, 0x05, 0, 0, 0, 0, 0xc3]).expect("can define main");
+    obj.define(".counters", vec![0; 16]).expect("can define .counters");
+    // a string of 2 byte characters must end with a 2 byte null
+    assert!(obj.define(".rodata.utf16", vec![b'h', 0, b'i', 0, 0]).is_err());
+    obj.define(".rodata.utf16", vec![b'h', 0, b'i', 0, 0, 0]).expect("can define .rodata.utf16");
+    obj.define(".trampolines", vec![0xc3]).expect("can define .trampolines");
+    obj.link(Link { from: "main", to: ".counters", at: 2 }).expect("can link main to .counters");
+    assert!(obj.declare(".empty_chars", Decl::section().strings(0)).is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let section = |name: &str| elf.section_headers.iter().position(|shdr| &elf.shdr_strtab[shdr.sh_name] == name).expect("has section");
+    let flags = |name: &str| elf.section_headers[section(name)].sh_flags;
+    assert_eq!(flags(".counters"), u64::from(SHF_ALLOC | SHF_WRITE));
+    assert_eq!(flags(".rodata.utf16"), u64::from(SHF_ALLOC | SHF_MERGE | SHF_STRINGS));
+    assert_eq!(flags(".trampolines"), u64::from(SHF_ALLOC | SHF_EXECINSTR));
+    let utf16 = &elf.section_headers[section(".rodata.utf16")];
+    assert_eq!((utf16.sh_entsize, utf16.sh_addralign), (2, 2));
+    // a custom section has no symbol of its own, so it is relocated against its section symbol
+    assert!(elf.syms.iter().all(|sym| &elf.strtab[sym.st_name] != ".counters"));
+    let relocs = elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter()).collect::<Vec<_>>();
+    assert_eq!(relocs.len(), 1);
+    let target = elf.syms.get(relocs[0].r_sym).expect("has the relocation's symbol");
+    assert_eq!((target.st_type(), target.st_shndx), (sym::STT_SECTION, section(".counters")));
+}
+
+#[test]
+fn section_offsets_are_written_without_relocations() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "offsets.o".into());
+    obj.declarations(vec![
+        ("table", Decl::data().into()),
+        ("first", Decl::data().zero_init(8).into()),
+        ("second", Decl::data().global().zero_init(8).into()),
+        ("message", Decl::cstring().into()),
+        ("external", Decl::DataImport),
+        ("replaceable", Decl::data().global().weak().into()),
+    ].into_iter()).expect("can declare");
+    obj.define("table", vec![0xff; 16]).expect("can define table");
+    obj.define_zeroed("first", 4).expect("can define first");
+    obj.define_zeroed("second", 16).expect("can define second");
+    obj.define("message", b"hello\0".to_vec()).expect("can define message");
+    obj.define("replaceable", vec![0; 4]).expect("can define replaceable");
+    obj.link_with(Link { from: "table", to: "second", at: 0 }, Reloc::SectionOffset { size: 4, addend: 0 }).expect("can link to second");
+    obj.link_with(Link { from: "table", to: "first", at: 4 }, Reloc::SectionOffset { size: 4, addend: 2 }).expect("can link to first");
+    // a string is alone in its section
+    obj.link_with(Link { from: "table", to: "message", at: 8 }, Reloc::SectionOffset { size: 8, addend: 1 }).expect("can link to message");
+    assert!(obj.link_with(Link { from: "table", to: "second", at: 0 }, Reloc::SectionOffset { size: 2, addend: 0 }).is_err());
+    // the offset of a symbol which is defined elsewhere isn't known
+    assert!(obj.link_with(Link { from: "table", to: "external", at: 0 }, Reloc::SectionOffset { size: 4, addend: 0 }).is_err());
+    assert!(obj.link_with(Link { from: "table", to: "replaceable", at: 0 }, Reloc::SectionOffset { size: 4, addend: 0 }).is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let second = elf.syms.iter().find(|sym| &elf.strtab[sym.st_name] == "second").expect("has second");
+    assert_eq!(second.st_value, 8);
+    let table = elf.section_headers.iter().find(|shdr| &elf.shdr_strtab[shdr.sh_name] == ".data.table").expect("has .data.table");
+    assert_eq!(&bytes[table.sh_offset as usize..][..16], &[8, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+    assert!(elf.shdr_relocs.iter().all(|(_, relocs)| relocs.is_empty()));
+}
+
+#[test]
+fn no_plt_calls_imported_functions_through_the_got() {
+    let mut obj = ArtifactBuilder::new(triple!("x86_64-unknown-linux-gnu")).name("noplt.o".into()).reloc_model(RelocModel::Pic).finish();
+    obj.declarations(vec![
+        ("_start", Decl::function().global().into()),
+        ("answer", Decl::FunctionImport),
+        ("helper", Decl::function().into()),
+    ].into_iter()).expect("can declare");
+    obj.define("_start", vec![
+        0xff, 0x15, 0, 0, 0, 0,         // call [rip + answer@GOTPCREL]
+        0xe8, 0, 0, 0, 0,               // call helper
+        0x89, 0xc7,                     // mov edi, eax
+        0xb8, 60, 0, 0, 0,              // mov eax, SYS_exit
+        0x0f, 0x05,                     // syscall
+    ]).expect("can define _start");
+    obj.define("helper", vec![0xc3]).expect("can define helper");
+    obj.link(Link { from: "_start", to: "answer", at: 2 }).expect("can link to answer");
+    obj.link(Link { from: "_start", to: "helper", at: 7 }).expect("can link to helper");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let relocs = elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter())
+        .map(|reloc| (reloc.r_offset, reloc.r_type, &elf.strtab[elf.syms.get(reloc.r_sym).expect("has symbol").st_name]))
+        .collect::<Vec<_>>();
+    // only the imported function is called through the GOT
+    assert_eq!(relocs[0], (2, reloc::R_X86_64_GOTPCRELX, "answer"));
+    assert_eq!(relocs[1].1, reloc::R_X86_64_PLT32);
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::Command;
+        let mut answer = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "answer.o".into());
+        answer.declare_with("answer", Decl::function().global(), vec![
+            0xb8, 42, 0, 0, 0,              // mov eax, 42
+            0xc3,                           // ret
+        ]).expect("can declare and define answer");
+        let [obj_path, answer_path, exe] = ["noplt.o", "answer.o", "noplt"].map(|name| temp_path("noplt", name));
+        std::fs::write(&obj_path, &bytes).expect("can write noplt.o");
+        std::fs::write(&answer_path, answer.emit().expect("can emit answer.o")).expect("can write answer.o");
+        let linked = Command::new("ld").args([&obj_path, &answer_path, "-o", &exe]).status();
+        let status = Command::new(&exe).status();
+        for file in &[obj_path, answer_path, exe] {
+            let _ = std::fs::remove_file(file);
+        }
+        match linked {
+            Ok(linked) => assert!(linked.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        assert_eq!(status.expect("can run the linked executable").code(), Some(42));
+    }
+}
+
+#[test]
+fn static_code_calls_directly_and_addresses_data_absolutely() {
+    let obj = |model: RelocModel| {
+        let mut obj = ArtifactBuilder::new(triple!("x86_64-unknown-linux-gnu")).name("static.o".into()).reloc_model(model).finish();
+        obj.declarations(vec![
+            ("_start", Decl::function().global().into()),
+            ("answer", Decl::FunctionImport),
+            ("offset", Decl::DataImport),
+        ].into_iter()).expect("can declare");
+        obj.define("_start", vec![
+            0xe8, 0, 0, 0, 0,               // call answer
+            0x03, 0x04, 0x25, 0, 0, 0, 0,   // add eax, [offset]
+            0x89, 0xc7,                     // mov edi, eax
+            0xb8, 60, 0, 0, 0,              // mov eax, SYS_exit
+            0x0f, 0x05,                     // syscall
+        ]).expect("can define _start");
+        obj.link(Link { from: "_start", to: "answer", at: 1 }).expect("can link to answer");
+        obj.link(Link { from: "_start", to: "offset", at: 8 }).expect("can link to offset");
+        obj.emit().expect("can emit elf file")
+    };
+    let relocs = |bytes: &[u8]| {
+        let elf = Elf::parse(bytes).expect("can parse elf file");
+        elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter()).map(|reloc| (reloc.r_type, reloc.r_addend)).collect::<Vec<_>>()
+    };
+    assert_eq!(relocs(&obj(RelocModel::PicWithPlt)), vec![(reloc::R_X86_64_PLT32, Some(-4)), (reloc::R_X86_64_GOTPCREL, Some(-4))]);
+    assert_eq!(relocs(&obj(RelocModel::Pic)), vec![(reloc::R_X86_64_GOTPCRELX, Some(-4)), (reloc::R_X86_64_GOTPCREL, Some(-4))]);
+    let bytes = obj(RelocModel::Static);
+    assert_eq!(relocs(&bytes), vec![(reloc::R_X86_64_PC32, Some(-4)), (reloc::R_X86_64_32S, Some(0))]);
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::Command;
+        let mut answer = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "answer.o".into());
+        answer.declare_with("answer", Decl::function().global(), vec![
+            0xb8, 40, 0, 0, 0,              // mov eax, 40
+            0xc3,                           // ret
+        ]).expect("can declare and define answer");
+        answer.declare_with("offset", Decl::data().global(), vec![2, 0, 0, 0]).expect("can declare and define offset");
+        let [obj_path, answer_path, exe] = ["static.o", "answer.o", "static"].map(|name| temp_path("static", name));
+        std::fs::write(&obj_path, &bytes).expect("can write static.o");
+        std::fs::write(&answer_path, answer.emit().expect("can emit answer.o")).expect("can write answer.o");
+        let linked = Command::new("ld").args(["-static", &obj_path, &answer_path, "-o", &exe]).status();
+        let status = Command::new(&exe).status();
+        for file in &[obj_path, answer_path, exe] {
+            let _ = std::fs::remove_file(file);
+        }
+        match linked {
+            Ok(linked) => assert!(linked.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        assert_eq!(status.expect("can run the linked executable").code(), Some(42));
+    }
+}
+
+#[test]
+fn i386_reloc_model_decides_how_calls_and_data_are_addressed() {
+    let relocs = |model: RelocModel| {
+        let mut obj = ArtifactBuilder::new(triple!("i686-unknown-linux-gnu")).name("model.o".into()).reloc_model(model).finish();
+        obj.declarations(vec![
+            ("get", Decl::function().global().into()),
+            ("answer", Decl::FunctionImport),
+            ("offset", Decl::DataImport),
+        ].into_iter()).expect("can declare");
+        obj.define("get", vec![
+            0xe8, 0, 0, 0, 0,               // call answer, or call [ebx + answer@GOT]
+            0x8b, 0x15, 0, 0, 0, 0,         // mov edx, [offset], or mov edx, [ebx + offset@GOT]
+            0xc3,                           // ret
+        ]).expect("can define get");
+        obj.link(Link { from: "get", to: "answer", at: 1 }).expect("can link to answer");
+        obj.link(Link { from: "get", to: "offset", at: 7 }).expect("can link to offset");
+        let bytes = obj.emit().expect("can emit elf file");
+        let elf = Elf::parse(&bytes).expect("can parse elf file");
+        elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter()).map(|reloc| reloc.r_type).collect::<Vec<_>>()
+    };
+    assert_eq!(relocs(RelocModel::Static), vec![reloc::R_386_PC32, reloc::R_386_32]);
+    assert_eq!(relocs(RelocModel::PicWithPlt), vec![reloc::R_386_PLT32, reloc::R_386_GOT32X]);
+    assert_eq!(relocs(RelocModel::Pic), vec![reloc::R_386_GOT32X, reloc::R_386_GOT32X]);
+}
+
+#[test]
+#[allow(deprecated)]
+fn pic_and_no_plt_set_the_reloc_model() {
+    let model = |builder: ArtifactBuilder| builder.finish().reloc_model;
+    let builder = || ArtifactBuilder::new(triple!("x86_64-unknown-linux-gnu"));
+    assert_eq!(model(builder()), RelocModel::PicWithPlt);
+    assert_eq!(model(builder().pic(false)), RelocModel::Static);
+    assert_eq!(model(builder().pic(false).pic(true)), RelocModel::PicWithPlt);
+    assert_eq!(model(builder().no_plt(true)), RelocModel::Pic);
+    assert_eq!(model(builder().no_plt(true).pic(true)), RelocModel::Pic);
+    assert_eq!(model(builder().no_plt(true).no_plt(false)), RelocModel::PicWithPlt);
+    assert_eq!(model(builder().pic(false).no_plt(false)), RelocModel::Static);
+    assert_eq!(model(builder().no_plt(true).pic(false)), RelocModel::Static);
+}
+
+#[test]
+fn line_entries_point_at_a_function_and_its_line_program() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "lines.o".into());
+    obj.declare_with("_start", Decl::function().global(), vec![
+        0xbf, 0, 0, 0, 0,               // mov edi, 0
+        0xb8, 60, 0, 0, 0,              // mov eax, SYS_exit
+        0x0f, 0x05,                     // syscall
+    ]).expect("can declare and define _start");
+    obj.declare_with("table", Decl::data(), vec![0; 4]).expect("can declare and define table");
+    assert!(obj.define_line_entry("_start.lines", "_start", 0x30).is_err());
+    obj.declare_with(".debug_line", Decl::debug_section(), vec![0; 0x40]).expect("can declare and define .debug_line");
+    obj.define_line_entry("_start.lines", "_start", 0x30).expect("can add the line entry of _start");
+    assert!(obj.define_line_entry("table.lines", "table", 0).is_err());
+    assert!(obj.define_line_entry("missing.lines", "missing", 0).is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let name = |sym: usize| {
+        let sym = elf.syms.get(sym).expect("has symbol");
+        match sym.st_type() {
+            sym::STT_SECTION => &elf.shdr_strtab[elf.section_headers[sym.st_shndx].sh_name],
+            _ => &elf.strtab[sym.st_name],
+        }
+    };
+    let entry = elf.syms.iter().find(|sym| &elf.strtab[sym.st_name] == "_start.lines").expect("has the entry");
+    assert_eq!((entry.st_bind(), entry.st_size), (sym::STB_LOCAL, 12));
+    let relocs = elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter())
+        .map(|reloc| (reloc.r_offset, reloc.r_type, name(reloc.r_sym), reloc.r_addend))
+        .collect::<Vec<_>>();
+    assert_eq!(relocs, vec![
+        (0, reloc::R_X86_64_64, ".text._start", Some(0)),
+        (8, reloc::R_X86_64_32, ".debug_line", Some(0x30)),
+    ]);
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::Command;
+        use scroll::Pread;
+        let [obj_path, exe] = ["lines.o", "lines"].map(|name| temp_path("lines", name));
+        std::fs::write(&obj_path, &bytes).expect("can write lines.o");
+        let linked = Command::new("ld").args([&obj_path, "-o", &exe]).status();
+        let linked_bytes = std::fs::read(&exe);
+        for file in &[obj_path, exe] {
+            let _ = std::fs::remove_file(file);
+        }
+        match linked {
+            Ok(linked) => assert!(linked.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        let bytes = linked_bytes.expect("can read the linked executable");
+        let exe = Elf::parse(&bytes).expect("can parse the linked executable");
+        let symbol = |name: &str| exe.syms.iter().find(|sym| &exe.strtab[sym.st_name] == name).expect("has symbol");
+        let entry = symbol("_start.lines");
+        let section = &exe.section_headers[entry.st_shndx];
+        let at = (section.sh_offset + entry.st_value - section.sh_addr) as usize;
+        // the only line program is at the start of the linked .debug_line
+        assert_eq!(bytes.pread_with::<u64>(at, scroll::LE).expect("has the address"), symbol("_start").st_value);
+        assert_eq!(bytes.pread_with::<u32>(at + 8, scroll::LE).expect("has the offset"), 0x30);
+    }
+}
+
+#[test]
+fn only_relocated_sections_have_section_symbols() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    for i in 0..100 {
+        // mov eax, [rip + d]; ret
+        obj.declare_with(format!("f{}", i), Decl::function().global(), vec![0x8b, 0x05, 0, 0, 0, 0, 0xc3]).expect("can declare and define a function");
+        obj.declare_with(format!("d{}", i), Decl::data(), vec![0; 8]).expect("can declare and define a data object");
+    }
+    let section_symbols = |bytes: &[u8]| {
+        let elf = Elf::parse(bytes).expect("can parse elf file");
+        elf.syms.iter().filter(|sym| sym.st_type() == sym::STT_SECTION)
+            .map(|sym| elf.shdr_strtab[elf.section_headers[sym.st_shndx].sh_name].to_string())
+            .collect::<Vec<_>>()
+    };
+    let unrelocated = obj.emit().expect("can emit elf file");
+    assert_eq!(section_symbols(&unrelocated), Vec::<String>::new());
+    assert_eq!(Elf::parse(&unrelocated).expect("can parse elf file").syms.len(), 2 + 200);
+
+    obj.link(Link { from: "f0", to: "d1", at: 2 }).expect("can link from f0 to d1");
+    assert_eq!(section_symbols(&obj.emit().expect("can emit elf file")), vec![".data.d1"]);
+}
+
+#[test]
+fn no_merge_strings_are_not_mergeable() {
+    use goblin::elf::section_header::{SHF_MERGE, SHF_STRINGS};
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.declarations(vec![
+        ("merged", Decl::cstring().into()),
+        ("intact", Decl::cstring().no_merge().into()),
+        (".debug_str", Decl::debug_section().strings().into()),
+        (".debug_names_str", Decl::debug_section().strings().no_merge().into()),
+        (".rodata.names", Decl::section().strings(2).no_merge().into()),
+    ].into_iter()).expect("can declare");
+    obj.define("merged", b"hello\0".to_vec()).expect("can define merged");
+    obj.define("intact", b"hello\0".to_vec()).expect("can define intact");
+    obj.define(".debug_str", b"main\0".to_vec()).expect("can define .debug_str");
+    obj.define(".debug_names_str", b"main\0".to_vec()).expect("can define .debug_names_str");
+    // a table of strings which is kept intact must still be made of them
+    assert!(obj.define(".rodata.names", vec![b'a', 0, 0]).is_err());
+    obj.define(".rodata.names", vec![b'a', 0, 0, 0]).expect("can define .rodata.names");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let merge = |name: &str| {
+        let section = elf.section_headers.iter().find(|shdr| &elf.shdr_strtab[shdr.sh_name] == name).expect("has section");
+        section.sh_flags & u64::from(SHF_MERGE | SHF_STRINGS)
+    };
+    assert_eq!(merge(".data.merged"), u64::from(SHF_MERGE | SHF_STRINGS));
+    assert_eq!(merge(".debug_str"), u64::from(SHF_MERGE | SHF_STRINGS));
+    assert_eq!(merge(".data.intact"), 0);
+    assert_eq!(merge(".debug_names_str"), 0);
+    assert_eq!(merge(".rodata.names"), 0);
+}
+
+#[test]
+fn debug_offsets_are_32_bit_on_every_architecture() {
+    let debug_info = |target: &str| {
+        let mut obj = Artifact::new(target_lexicon::Triple::from_str(target).expect("is a triple"), "t.o".into());
+        obj.declare_with("main", Decl::function().global(), vec![0xc3]).expect("can declare and define main");
+        obj.declare_with(".debug_abbrev", Decl::debug_section(), vec![0; 8]).expect("can declare and define .debug_abbrev");
+        obj.declare_with(".debug_line", Decl::debug_section(), vec![0; 8]).expect("can declare and define .debug_line");
+        // DW_AT_stmt_list, then DW_AT_low_pc, after the DW_FORM_sec_offset of the abbreviations in the header
+        obj.declare_with(".debug_info", Decl::debug_section(), vec![0xaa; 24]).expect("can declare and define .debug_info");
+        obj.link_with(Link { from: ".debug_info", to: ".debug_abbrev", at: 6 }, Reloc::Absolute { size: 4, addend: 0 })
+            .expect("can link to the abbreviations");
+        obj.link_with(Link { from: ".debug_info", to: ".debug_line", at: 12 }, Reloc::Absolute { size: 4, addend: 4 })
+            .expect("can link to the line program");
+        obj.link(Link { from: ".debug_info", to: "main", at: 16 }).expect("can link to main");
+        obj
+    };
+    let relocs = |target: &str| {
+        let bytes = debug_info(target).emit().expect("can emit elf file");
+        let elf = Elf::parse(&bytes).expect("can parse elf file");
+        let info = elf.section_headers.iter().find(|shdr| &elf.shdr_strtab[shdr.sh_name] == ".debug_info").expect("has .debug_info");
+        let contents = bytes[info.sh_offset as usize..][..24].to_vec();
+        let relocs = elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter()).map(|reloc| {
+            let target = elf.syms.get(reloc.r_sym).expect("has symbol");
+            (reloc.r_offset, reloc.r_type, elf.shdr_strtab[elf.section_headers[target.st_shndx].sh_name].to_string(), reloc.r_addend)
+        }).collect::<Vec<_>>();
+        (relocs, contents)
+    };
+    const R_AARCH64_ABS64: u32 = 257;
+    const R_AARCH64_ABS32: u32 = 258;
+    let (aarch64, _) = relocs("aarch64-unknown-linux-gnu");
+    assert_eq!(aarch64, vec![
+        (6, R_AARCH64_ABS32, ".debug_abbrev".to_string(), Some(0)),
+        (12, R_AARCH64_ABS32, ".debug_line".to_string(), Some(4)),
+        (16, R_AARCH64_ABS64, ".text.main".to_string(), Some(0)),
+    ]);
+    // i386 writes the addends in place of the fields, without touching the bytes around them
+    let (i386, contents) = relocs("i686-unknown-linux-gnu");
+    assert_eq!(i386, vec![
+        (6, reloc::R_386_32, ".debug_abbrev".to_string(), None),
+        (12, reloc::R_386_32, ".debug_line".to_string(), None),
+        (16, reloc::R_386_32, ".text.main".to_string(), None),
+    ]);
+    assert_eq!(contents, vec![
+        0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0, 0, 0, 0, 0xaa, 0xaa,
+        4, 0, 0, 0, 0, 0, 0, 0, 0xaa, 0xaa, 0xaa, 0xaa,
+    ]);
+    // there is no COFF backend to emit section relative relocations for
+    let err = debug_info("x86_64-pc-windows-msvc").emit().expect_err("COFF is not supported");
+    match err.downcast_ref::<faerie::artifact::ArtifactError>() {
+        Some(faerie::artifact::ArtifactError::UnsupportedBinaryFormat(format)) => assert_eq!(format, "coff"),
+        _ => panic!("unexpected error: {}", err),
+    }
+}
+
+#[test]
+fn static_ifuncs_are_resolved_through_irelative_relocations() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "ifunc.o".into());
+    obj.declarations(vec![
+        ("_start", Decl::function().global().into()),
+        ("answer", Decl::function().global().ifunc().into()),
+        ("answer_impl", Decl::function().into()),
+        // the linker defines these around the IRELATIVE relocations of a static executable
+        ("__rela_iplt_start", Decl::DataImport),
+        ("__rela_iplt_end", Decl::DataImport),
+    ].into_iter()).expect("can declare");
+    // apply each IRELATIVE, as the C runtime's startup code does, then call through the resolved GOT entry
+    obj.define("_start", vec![
+        0x48, 0x8b, 0x1d, 0, 0, 0, 0,   // mov rbx, [rip + __rela_iplt_start@GOTPCREL]
+        0x4c, 0x8b, 0x25, 0, 0, 0, 0,   // mov r12, [rip + __rela_iplt_end@GOTPCREL]
+        0x4c, 0x39, 0xe3,               // loop: cmp rbx, r12
+        0x73, 0x10,                     // jae done
+        0x4c, 0x8b, 0x2b,               // mov r13, [rbx]           ; r_offset
+        0xff, 0x53, 0x10,               // call [rbx + 16]          ; r_addend, the resolver
+        0x49, 0x89, 0x45, 0x00,         // mov [r13], rax
+        0x48, 0x83, 0xc3, 0x18,         // add rbx, 24
+        0xeb, 0xeb,                     // jmp loop
+        0xe8, 0, 0, 0, 0,               // done: call answer
+        0x89, 0xc7,                     // mov edi, eax
+        0xb8, 60, 0, 0, 0,              // mov eax, SYS_exit
+        0x0f, 0x05,                     // syscall
+    ]).expect("can define _start");
+    obj.define("answer", vec![
+        0x48, 0x8d, 0x05, 0, 0, 0, 0,   // lea rax, [rip + answer_impl]
+        0xc3,                           // ret
+    ]).expect("can define answer");
+    obj.define("answer_impl", vec![0xb8, 42, 0, 0, 0, 0xc3]).expect("can define answer_impl");
+    obj.link(Link { from: "_start", to: "__rela_iplt_start", at: 3 }).expect("can link to __rela_iplt_start");
+    obj.link(Link { from: "_start", to: "__rela_iplt_end", at: 10 }).expect("can link to __rela_iplt_end");
+    obj.link(Link { from: "_start", to: "answer", at: 36 }).expect("can link to answer");
+    obj.link(Link { from: "answer", to: "answer_impl", at: 3 }).expect("can link to answer_impl");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let (index, answer) = elf.syms.iter().enumerate().find(|(_, sym)| &elf.strtab[sym.st_name] == "answer").expect("has answer");
+    assert_eq!(answer.st_type(), sym::STT_GNU_IFUNC);
+    // the call refers to the ifunc itself, not its section, so the linker knows to resolve it
+    let call = elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter()).find(|reloc| reloc.r_offset == 36).expect("call is relocated");
+    assert_eq!((call.r_type, call.r_sym), (reloc::R_X86_64_PLT32, index));
+    assert!(obj.emit_as(target_lexicon::BinaryFormat::Macho).is_err());
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::Command;
+        use scroll::Pread;
+        let [obj_path, exe] = ["ifunc.o", "ifunc"].map(|name| temp_path("ifunc", name));
+        std::fs::write(&obj_path, &bytes).expect("can write ifunc.o");
+        let linked = Command::new("ld").args(["-static", &obj_path, "-o", &exe]).status();
+        let linked_bytes = std::fs::read(&exe);
+        let status = Command::new(&exe).status();
+        for file in &[obj_path, exe] {
+            let _ = std::fs::remove_file(file);
+        }
+        match linked {
+            Ok(linked) => assert!(linked.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        let bytes = linked_bytes.expect("can read the linked executable");
+        let exe = Elf::parse(&bytes).expect("can parse the linked executable");
+        let symbol = |name: &str| exe.syms.iter().find(|sym| &exe.strtab[sym.st_name] == name).expect("has symbol").st_value;
+        // the table is the one relocation the resolver of answer is called for; it applies to no section, so it is read by hand
+        let (start, end) = (symbol("__rela_iplt_start"), symbol("__rela_iplt_end"));
+        assert_eq!(end - start, 24);
+        let table = exe.section_headers.iter().find(|shdr| shdr.sh_addr == start && shdr.sh_type == section_header::SHT_RELA)
+            .expect("the table is a RELA section");
+        let info = bytes.pread_with::<u64>(table.sh_offset as usize + 8, scroll::LE).expect("has r_info");
+        let addend = bytes.pread_with::<u64>(table.sh_offset as usize + 16, scroll::LE).expect("has r_addend");
+        assert_eq!(info as u32, reloc::R_X86_64_IRELATIVE);
+        assert_eq!(addend, symbol("answer"));
+        assert_eq!(status.expect("can run the linked executable").code(), Some(42));
+    }
+}
+
+#[test]
+fn function_and_data_alignment_can_be_overridden() {
+    fn alignments(builder: ArtifactBuilder) -> Vec<(String, u64)> {
+        let mut obj = builder.name("align.o".into()).finish();
+        obj.declarations(vec![
+            ("function", Decl::function().global().into()),
+            ("writable", Decl::data().global().writable().into()),
+            ("read_only", Decl::data().global().into()),
+            ("string", Decl::cstring().global().into()),
+        ].into_iter()).expect("can declare");
+        obj.define("function", vec![0xc3]).expect("can define function");
+        obj.define("writable", vec![1, 2, 3, 4]).expect("can define writable");
+        obj.define("read_only", vec![1, 2, 3, 4]).expect("can define read_only");
+        obj.define("string", b"faerie\0".to_vec()).expect("can define string");
+        let bytes = obj.emit().expect("can emit elf file");
+        let elf = Elf::parse(&bytes).expect("can parse elf file");
+        elf.section_headers.iter()
+            .map(|shdr| (elf.shdr_strtab[shdr.sh_name].to_string(), shdr.sh_addralign))
+            .filter(|(name, _)| name.starts_with(".text") || name.starts_with(".data") || name.starts_with(".rodata"))
+            .collect()
+    }
+    let target = triple!("x86_64-unknown-linux-gnu");
+    assert_eq!(alignments(ArtifactBuilder::new(target.clone())), vec![
+        (".data.read_only".to_string(), 1),
+        (".data.string".to_string(), 1),
+        (".data.writable".to_string(), 8),
+        (".text.function".to_string(), 16),
+    ]);
+    assert_eq!(alignments(ArtifactBuilder::new(target.clone()).function_align(64).data_align(32)), vec![
+        (".data.read_only".to_string(), 32),
+        // strings keep their own alignment
+        (".data.string".to_string(), 1),
+        (".data.writable".to_string(), 32),
+        (".text.function".to_string(), 64),
+    ]);
+
+    let mut obj = ArtifactBuilder::new(target).function_align(48).finish();
+    obj.declare_with("function", Decl::function(), vec![0xc3]).expect("can declare and define function");
+    let err = obj.emit().expect_err("alignment must be a power of two");
+    assert_eq!(err.to_string(), "Invalid alignment of functions: 48 is not a power of two");
+}
+
+#[test]
+fn functions_can_share_text_without_function_sections() {
+    let mut obj = ArtifactBuilder::new(triple!("x86_64-unknown-linux-gnu"))
+        .name("text.o".into())
+        .function_sections(false)
+        .finish();
+    obj.declarations(vec![
+        ("_start", Decl::function().global().into()),
+        ("helper", Decl::function().into()),
+    ].into_iter()).expect("can declare");
+    obj.define("_start", vec![
+        0xe8, 0, 0, 0, 0,               // call helper
+        0x89, 0xc7,                     // mov edi, eax
+        0xb8, 60, 0, 0, 0,              // mov eax, SYS_exit
+        0x0f, 0x05,                     // syscall
+    ]).expect("can define _start");
+    obj.define("helper", vec![
+        0xb8, 42, 0, 0, 0,              // mov eax, 42
+        0xc3,                           // ret
+    ]).expect("can define helper");
+    obj.link(Link { from: "_start", to: "helper", at: 1 }).expect("can link to helper");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let texts = elf.section_headers.iter().enumerate()
+        .filter(|(_, shdr)| elf.shdr_strtab[shdr.sh_name].starts_with(".text"))
+        .collect::<Vec<_>>();
+    assert_eq!(texts.len(), 1, "the functions share one section");
+    let (text_idx, text) = texts[0];
+    assert_eq!(&elf.shdr_strtab[text.sh_name], ".text");
+    assert_eq!(text.sh_addralign, 16);
+    let value = |name: &str| {
+        let sym = elf.syms.iter().find(|sym| &elf.strtab[sym.st_name] == name).expect("has symbol");
+        assert_eq!(sym.st_shndx, text_idx);
+        sym.st_value
+    };
+    let (start, helper) = (value("_start"), value("helper"));
+    assert_ne!(start, helper);
+    assert_eq!(start % 16, 0);
+    assert_eq!(helper % 16, 0);
+    assert!(start.max(helper) + 6 <= text.sh_size);
+    // the relocation is at the field's offset in the shared section, against the function's own symbol
+    let relocs = elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter())
+        .map(|reloc| (reloc.r_offset, &elf.strtab[elf.syms.get(reloc.r_sym).expect("has symbol").st_name]))
+        .collect::<Vec<_>>();
+    assert_eq!(relocs, vec![(start + 1, "helper")]);
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::Command;
+        let [obj_path, exe] = ["text.o", "text"].map(|name| temp_path("text", name));
+        std::fs::write(&obj_path, &bytes).expect("can write text.o");
+        let linked = Command::new("ld").args([&obj_path, "-o", &exe]).status();
+        let status = Command::new(&exe).status();
+        for file in &[obj_path, exe] {
+            let _ = std::fs::remove_file(file);
+        }
+        match linked {
+            Ok(linked) => assert!(linked.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        assert_eq!(status.expect("can run the linked executable").code(), Some(42));
+    }
+}
+
+#[test]
+fn symbol_zero_is_the_null_symbol() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "null.o".into());
+    obj.declarations(vec![
+        ("function", Decl::function().global().into()),
+        ("local", Decl::data().into()),
+    ].into_iter()).expect("can declare");
+    obj.define("function", vec![0xc3]).expect("can define function");
+    obj.define("local", vec![1, 2, 3, 4]).expect("can define local");
+    obj.link(Link { from: "local", to: "function", at: 0 }).expect("can link to function");
+    for &strip in &[Strip::None, Strip::All] {
+        let bytes = obj.emit_stripped(target_lexicon::BinaryFormat::Elf, strip).expect("can emit elf file");
+        let elf = Elf::parse(&bytes).expect("can parse elf file");
+        let symtab = elf.section_headers.iter().find(|shdr| shdr.sh_type == section_header::SHT_SYMTAB).expect("has .symtab");
+        let null = &bytes[symtab.sh_offset as usize..(symtab.sh_offset + symtab.sh_entsize) as usize];
+        assert!(null.iter().all(|&byte| byte == 0), "symbol 0 is all zeros when stripped {:?}", strip);
+        let files = elf.syms.iter().enumerate()
+            .filter(|(_, sym)| sym.st_type() == sym::STT_FILE)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        // the file symbol is a local no relocation refers to, so it is discarded along with the others
+        assert_eq!(files, if strip == Strip::None { vec![1] } else { vec![] });
+    }
+}
+
+#[test]
+fn comdat_groups_collapse_duplicate_definitions() {
+    fn object(name: &str, caller: &str) -> Vec<u8> {
+        let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), name.into());
+        obj.declarations(vec![
+            (caller, Decl::function().global().into()),
+            ("inline_fn", Decl::function().global().into()),
+            ("inline_data", Decl::data().into()),
+            ("import", Decl::DataImport),
+        ].into_iter()).expect("can declare");
+        obj.define(caller, vec![
+            0xe8, 0, 0, 0, 0,               // call inline_fn
+            0x89, 0xc7,                     // mov edi, eax
+            0xb8, 60, 0, 0, 0,              // mov eax, SYS_exit
+            0x0f, 0x05,                     // syscall
+        ]).expect("can define caller");
+        obj.define("inline_fn", vec![
+            0x8b, 0x05, 0, 0, 0, 0,         // mov eax, [rip + inline_data]
+            0xc3,                           // ret
+        ]).expect("can define inline_fn");
+        obj.define("inline_data", vec![42, 0, 0, 0]).expect("can define inline_data");
+        obj.link(Link { from: caller, to: "inline_fn", at: 1 }).expect("can link to inline_fn");
+        obj.link(Link { from: "inline_fn", to: "inline_data", at: 2 }).expect("can link to inline_data");
+        obj.set_comdat("inline_data", "inline_fn", ComdatSelection::Any).expect("can group inline_data");
+        assert!(obj.set_comdat("import", "inline_fn", ComdatSelection::Any).is_err(), "imports have no section");
+        assert!(obj.set_comdat("inline_fn", "inline_data", ComdatSelection::Any).is_err(), "the leader must be global");
+        assert!(obj.set_comdat("inline_data", "inline_fn", ComdatSelection::ExactMatch).is_err(), "the group is selected as a whole");
+        obj.emit().expect("can emit elf file")
+    }
+    // ELF groups have no other selection
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "exact.o".into());
+    obj.declare_with("generic", Decl::function().global(), vec![0xc3]).expect("can declare and define generic");
+    obj.set_comdat("generic", "generic", ComdatSelection::ExactMatch).expect("can group generic");
+    assert_eq!(obj.comdats().collect::<Vec<_>>(), [("generic", "generic", ComdatSelection::ExactMatch)]);
+    assert!(obj.emit().is_err());
+
+    let bytes = object("main.o", "_start");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let groups = elf.section_headers.iter().filter(|shdr| shdr.sh_type == section_header::SHT_GROUP).collect::<Vec<_>>();
+    assert_eq!(groups.len(), 1);
+    let group = groups[0];
+    assert_eq!(&elf.strtab[elf.syms.get(group.sh_info as usize).expect("has signature").st_name], "inline_fn");
+    let words = bytes[group.sh_offset as usize..(group.sh_offset + group.sh_size) as usize].chunks(4)
+        .map(|word| u32::from(word[0]) | u32::from(word[1]) << 8 | u32::from(word[2]) << 16 | u32::from(word[3]) << 24)
+        .collect::<Vec<_>>();
+    assert_eq!(words[0], 1, "GRP_COMDAT");
+    let mut members = words[1..].iter().map(|&shndx| {
+        let shdr = &elf.section_headers[shndx as usize];
+        assert_ne!(shdr.sh_flags & u64::from(section_header::SHF_GROUP), 0);
+        elf.shdr_strtab[shdr.sh_name].to_string()
+    }).collect::<Vec<_>>();
+    members.sort();
+    assert_eq!(members, vec![".data.inline_data", ".reloc.inline_fn", ".text.inline_fn"]);
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::Command;
+        let [main_path, other_path, exe] = ["main.o", "other.o", "comdat"].map(|name| temp_path("comdat", name));
+        std::fs::write(&main_path, &bytes).expect("can write main.o");
+        std::fs::write(&other_path, object("other.o", "other")).expect("can write other.o");
+        let linked = Command::new("ld").args([&main_path, &other_path, "-o", &exe]).status();
+        let status = Command::new(&exe).status();
+        for file in &[main_path, other_path, exe] {
+            let _ = std::fs::remove_file(file);
+        }
+        match linked {
+            Ok(linked) => assert!(linked.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        assert_eq!(status.expect("can run the linked executable").code(), Some(42));
+    }
+}
+
+#[test]
+fn inline_addends_are_written_in_place_as_well() {
+    use scroll::Pread;
+    let mut obj = ArtifactBuilder::new(triple!("x86_64-unknown-linux-gnu")).name("inline.o".into()).inline_addends(true).finish();
+    obj.declarations(vec![
+        ("_start", Decl::function().global().into()),
+        ("helper", Decl::function().into()),
+        ("value", Decl::data().into()),
+        ("table", Decl::data().writable().into()),
+    ].into_iter()).expect("can declare");
+    obj.define("_start", vec![
+        0xe8, 0, 0, 0, 0,               // call helper
+        0x89, 0xc7,                     // mov edi, eax
+        0xb8, 60, 0, 0, 0,              // mov eax, SYS_exit
+        0x0f, 0x05,                     // syscall
+    ]).expect("can define _start");
+    obj.define("helper", vec![
+        0x8b, 0x05, 0, 0, 0, 0,         // mov eax, [rip + value]
+        0xc3,                           // ret
+    ]).expect("can define helper");
+    obj.define("value", vec![42, 0, 0, 0]).expect("can define value");
+    obj.define("table", vec![0; 8]).expect("can define table");
+    obj.link(Link { from: "_start", to: "helper", at: 1 }).expect("can link to helper");
+    obj.link(Link { from: "helper", to: "value", at: 2 }).expect("can link to value");
+    obj.link_with(Link { from: "table", to: "helper", at: 0 }, Reloc::Absolute { size: 8, addend: 3 }).expect("can link to helper");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let section = |name: &str| elf.section_headers.iter().find(|shdr| &elf.shdr_strtab[shdr.sh_name] == name).expect("has section").sh_offset as usize;
+    let relocs = elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter()).collect::<Vec<_>>();
+    assert!(relocs.iter().all(|reloc| reloc.r_addend.is_some()), "the relocations still carry their addend");
+    // each field holds the addend of its relocation, of the field's size
+    assert_eq!(bytes.pread_with::<i32>(section(".text._start") + 1, scroll::LE).expect("has field"), -4);
+    assert_eq!(bytes.pread_with::<i32>(section(".text.helper") + 2, scroll::LE).expect("has field"), -4);
+    assert_eq!(bytes.pread_with::<u64>(section(".data.table"), scroll::LE).expect("has field"), 3);
+
+    // the fields are overwritten by standard linkers
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::Command;
+        let [obj_path, exe] = ["inline.o", "inline"].map(|name| temp_path("inline", name));
+        std::fs::write(&obj_path, &bytes).expect("can write inline.o");
+        let linked = Command::new("ld").args([&obj_path, "-o", &exe]).status();
+        let status = Command::new(&exe).status();
+        for file in &[obj_path, exe] {
+            let _ = std::fs::remove_file(file);
+        }
+        match linked {
+            Ok(linked) => assert!(linked.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        assert_eq!(status.expect("can run the linked executable").code(), Some(42));
+    }
+}
+
+#[test]
+fn arm_and_thumb_functions_interwork() {
+    let mut obj = Artifact::new(triple!("armv7-unknown-linux-gnueabihf"), "interwork.o".into());
+    obj.declarations(vec![
+        ("arm", Decl::function().global().into()),
+        ("thumb", Decl::function().global().thumb().into()),
+        ("counter", Decl::data().writable().into()),
+        ("table", Decl::data().into()),
+    ].into_iter()).expect("can declare");
+    obj.define("arm", vec![
+        0xfe, 0xff, 0xff, 0xeb, // bl thumb
+        0x00, 0x00, 0x00, 0xe3, // movw r0, #:lower16:counter
+        0x00, 0x00, 0x40, 0xe3, // movt r0, #:upper16:counter
+        0x1e, 0xff, 0x2f, 0xe1, // bx lr
+    ]).expect("can define arm");
+    obj.define("thumb", vec![
+        0xff, 0xf7, 0xfe, 0xff, // bl arm
+        0x70, 0x47,             // bx lr
+        0x00, 0xbf,             // nop
+    ]).expect("can define thumb");
+    obj.define("counter", vec![0; 4]).expect("can define counter");
+    obj.define("table", vec![4, 0, 0, 0]).expect("can define table");
+    obj.link(Link { from: "arm", to: "thumb", at: 0 }).expect("can link arm to thumb");
+    obj.link(Link { from: "arm", to: "counter", at: 4 }).expect("can link arm to counter");
+    obj.link(Link { from: "thumb", to: "arm", at: 0 }).expect("can link thumb to arm");
+    obj.link_with(Link { from: "table", to: "thumb", at: 0 }, Reloc::Absolute { size: 4, addend: 4 }).expect("can link table to thumb");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    assert!(!elf.is_64);
+    assert_eq!((elf.header.e_machine, elf.header.e_flags), (header::EM_ARM, 0x0500_0000));
+    assert!(elf.section_headers.iter().all(|shdr| shdr.sh_type != section_header::SHT_RELA));
+    let symbol = |name: &str| elf.syms.iter().find(|sym| &elf.strtab[sym.st_name] == name).expect("has symbol");
+    // the linker tells Thumb code from ARM code by the low bit of its symbol
+    assert_eq!(symbol("thumb").st_value, 1);
+    assert_eq!(symbol("arm").st_value, 0);
+    let symbol_name = |index: usize| {
+        let sym = elf.syms.get(index).expect("relocation has a symbol");
+        match &elf.strtab[sym.st_name] {
+            "" => &elf.shdr_strtab[elf.section_headers[sym.st_shndx].sh_name],
+            name => name,
+        }
+    };
+    let section = |name: &str| elf.section_headers.iter().find(|shdr| &elf.shdr_strtab[shdr.sh_name] == name).expect("has section").sh_offset as usize;
+    let mut relocs = elf.shdr_relocs.iter()
+        .flat_map(|(shndx, relocs)| {
+            let name = &elf.shdr_strtab[elf.section_headers[*shndx].sh_name];
+            relocs.iter().map(move |reloc| (name, reloc.r_offset, reloc.r_type, symbol_name(reloc.r_sym)))
+        })
+        .collect::<Vec<_>>();
+    relocs.sort();
+    // each call is made by the caller's kind of `bl`, to the callee's own symbol
+    assert_eq!(relocs, vec![
+        (".reloc.arm", 0, reloc::R_ARM_CALL, "thumb"),
+        (".reloc.arm", 4, reloc::R_ARM_MOVW_ABS_NC, ".data.counter"),
+        (".reloc.arm", 8, reloc::R_ARM_MOVT_ABS, ".data.counter"),
+        (".reloc.table", 0, reloc::R_ARM_ABS32, "thumb"),
+        (".reloc.thumb", 0, reloc::R_ARM_THM_PC22, "arm"),
+    ]);
+    // the addends of the instructions are the ones encoded in them; only those of data are written in place
+    assert_eq!(&bytes[section(".text.arm")..][..4], &[0xfe, 0xff, 0xff, 0xeb]);
+    assert_eq!(&bytes[section(".text.thumb")..][..4], &[0xff, 0xf7, 0xfe, 0xff]);
+    assert_eq!(&bytes[section(".data.table")..][..4], &[4, 0, 0, 0]);
+
+    // every function is Thumb code on a Thumb-only target
+    let mut obj = Artifact::new(triple!("thumbv7m-none-eabi"), "thumb.o".into());
+    obj.declare_with("reset", Decl::function().global(), vec![0xfe, 0xe7]).expect("can define reset");
+    let bytes = obj.emit_as(target_lexicon::BinaryFormat::Elf).expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    assert_eq!(elf.syms.iter().find(|sym| &elf.strtab[sym.st_name] == "reset").expect("has reset").st_value, 1);
+}
+
+#[test]
+fn tm_clone_table_pairs_functions_with_their_clones() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "tm.o".into());
+    obj.declarations(vec![
+        ("deposit", Decl::function().global().into()),
+        ("_ZGTt7deposit", Decl::function().into()),
+        ("withdraw", Decl::FunctionImport),
+        ("_ZGTt8withdraw", Decl::FunctionImport),
+        ("balance", Decl::data().writable().into()),
+    ].into_iter()).expect("can declare");
+    obj.define("deposit", vec![0xc3]).expect("can define deposit");
+    obj.define("_ZGTt7deposit", vec![0xc3]).expect("can define its clone");
+    obj.define("balance", vec![0; 8]).expect("can define balance");
+    obj.add_tm_clone("deposit", "_ZGTt7deposit").expect("can add clone of deposit");
+    obj.add_tm_clone("withdraw", "_ZGTt8withdraw").expect("can add clone of withdraw");
+    assert!(obj.add_tm_clone("balance", "deposit").is_err(), "only functions have clones");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let (shndx, table) = elf.section_headers.iter().enumerate()
+        .find(|&(_, shdr)| &elf.shdr_strtab[shdr.sh_name] == ".tm_clone_table")
+        .expect("has .tm_clone_table");
+    assert_eq!((table.sh_type, table.sh_flags, table.sh_size), (section_header::SHT_PROGBITS, u64::from(section_header::SHF_ALLOC | section_header::SHF_WRITE), 32));
+    // each entry is a pointer to the function, then one to its clone
+    let symbol_name = |index: usize| {
+        let sym = elf.syms.get(index).expect("relocation has a symbol");
+        match &elf.strtab[sym.st_name] {
+            "" => &elf.shdr_strtab[elf.section_headers[sym.st_shndx].sh_name],
+            name => name,
+        }
+    };
+    let (_, relocs) = elf.shdr_relocs.iter().find(|&&(index, _)| elf.section_headers[index].sh_info as usize == shndx).expect("table is relocated");
+    let relocs = relocs.iter().map(|reloc| (reloc.r_offset, reloc.r_type, symbol_name(reloc.r_sym))).collect::<Vec<_>>();
+    assert_eq!(relocs, vec![
+        (0, reloc::R_X86_64_64, ".text.deposit"),
+        (8, reloc::R_X86_64_64, ".text._ZGTt7deposit"),
+        (16, reloc::R_X86_64_64, "withdraw"),
+        (24, reloc::R_X86_64_64, "_ZGTt8withdraw"),
+    ]);
+    // the delimiters are local, so they don't clash with the runtime's own around the linked table
+    let delimiter = |name: &str| {
+        let sym = elf.syms.iter().find(|sym| &elf.strtab[sym.st_name] == name).expect("has delimiter");
+        (sym.st_bind(), sym.st_shndx, sym.st_value)
+    };
+    assert_eq!(delimiter("__TMC_LIST__"), (sym::STB_LOCAL, shndx, 0));
+    assert_eq!(delimiter("__TMC_END__"), (sym::STB_LOCAL, shndx, 32));
+}
+
+#[test]
+fn i386_pic_prologue_adds_the_got_base_relative_to_its_thunk() {
+    let mut obj = ArtifactBuilder::new(triple!("i686-unknown-linux-gnu")).name("prologue.o".into()).reloc_model(RelocModel::PicWithPlt).finish();
+    obj.declare_with("_start", Decl::function().global(), vec![
+        0xe8, 0, 0, 0, 0,               // call get
+        0x89, 0xc3,                     // mov ebx, eax
+        0xb8, 1, 0, 0, 0,               // mov eax, SYS_exit
+        0xcd, 0x80,                     // int 0x80
+    ]).expect("can define _start");
+    obj.declare_with("get", Decl::function().global(), vec![
+        0x53,                           // push ebx
+        0xe8, 0, 0, 0, 0,               // call __x86.get_pc_thunk.bx
+        0x81, 0xc3, 0, 0, 0, 0,         // add ebx, _GLOBAL_OFFSET_TABLE_
+        0x8b, 0x83, 0, 0, 0, 0,         // mov eax, [ebx + counter@GOTOFF]
+        0x5b,                           // pop ebx
+        0xc3,                           // ret
+    ]).expect("can define get");
+    obj.declare_with("__x86.get_pc_thunk.bx", Decl::function(), vec![
+        0x8b, 0x1c, 0x24,               // mov ebx, [esp]
+        0xc3,                           // ret
+    ]).expect("can define the thunk");
+    obj.declare_with("counter", Decl::data().writable(), vec![42, 0, 0, 0]).expect("can define counter");
+    obj.link(Link { from: "_start", to: "get", at: 1 }).expect("can link _start to get");
+    obj.link(Link { from: "get", to: "__x86.get_pc_thunk.bx", at: 2 }).expect("can link get to the thunk");
+    // the thunk returns the address of the `add`, 2 bytes before its immediate
+    obj.link_got_base("get", 8, 6).expect("can link get to the GOT base");
+    obj.link(Link { from: "get", to: "counter", at: 14 }).expect("can link get to counter");
+    let mut other = obj.clone();
+    other.import("environ", ImportKind::Data).expect("can import environ");
+    assert!(other.link_with(Link { from: "get", to: "environ", at: 8 }, Reloc::GotBase { addend: 2 }).is_err(), "only the GOT base is relocated to");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let symbol_name = |index: usize| {
+        let sym = elf.syms.get(index).expect("relocation has a symbol");
+        match &elf.strtab[sym.st_name] {
+            "" => &elf.shdr_strtab[elf.section_headers[sym.st_shndx].sh_name],
+            name => name,
+        }
+    };
+    let (shndx, ref relocs) = *elf.shdr_relocs.iter()
+        .find(|&&(index, _)| &elf.shdr_strtab[elf.section_headers[elf.section_headers[index].sh_info as usize].sh_name] == ".text.get")
+        .expect("get is relocated");
+    let relocs = relocs.iter().map(|reloc| (reloc.r_offset, reloc.r_type, symbol_name(reloc.r_sym))).collect::<Vec<_>>();
+    assert_eq!(relocs, vec![
+        (2, reloc::R_386_PLT32, ".text.__x86.get_pc_thunk.bx"),
+        (8, reloc::R_386_GOTPC, "_GLOBAL_OFFSET_TABLE_"),
+        (14, reloc::R_386_GOTOFF, ".data.counter"),
+    ]);
+    // the addend is written in place: the immediate's distance from the address the thunk returns
+    let get = elf.section_headers[elf.section_headers[shndx].sh_info as usize].sh_offset as usize;
+    assert_eq!(&bytes[get + 8..get + 12], &[2, 0, 0, 0]);
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::Command;
+        let [obj_path, exe] = ["prologue.o", "prologue"].map(|name| temp_path("prologue", name));
+        std::fs::write(&obj_path, &bytes).expect("can write prologue.o");
+        let linked = Command::new("ld").args(["-m", "elf_i386", &obj_path, "-o", &exe]).status();
+        let status = Command::new(&exe).status();
+        for file in &[obj_path, exe] {
+            let _ = std::fs::remove_file(file);
+        }
+        match linked {
+            Ok(linked) => assert!(linked.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        match status {
+            Ok(status) => assert_eq!(status.code(), Some(42)),
+            Err(err) => eprintln!("i386 executables can't be run, skipping: {}", err),
+        }
+    }
+}
+
+#[test]
+fn link_order_sections_are_linked_to_their_definition() {
+    use goblin::elf::section_header::{SHF_ALLOC, SHF_LINK_ORDER};
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "order.o".into());
+    obj.declarations(vec![
+        ("_start", Decl::function().global().into()),
+        ("exit", Decl::function().into()),
+        (".exidx", Decl::section().into()),
+    ].into_iter()).expect("can declare");
+    obj.define("_start", vec![0xe8, 0, 0, 0, 0]).expect("can define _start");
+    obj.define("exit", vec![0x31, 0xff, 0xb8, 60, 0, 0, 0, 0x0f, 0x05]).expect("can define exit");
+    obj.define(".exidx", vec![0; 8]).expect("can define .exidx");
+    obj.link(Link { from: "_start", to: "exit", at: 1 }).expect("can link _start to exit");
+    obj.link_with(Link { from: ".exidx", to: "exit", at: 0 }, Reloc::Relative { size: 4, addend: 0 }).expect("can link .exidx to exit");
+    obj.set_link_order(".exidx", "exit").expect("can order .exidx by exit");
+    // only a custom section is ordered, by a definition with an address
+    assert!(obj.set_link_order("exit", "_start").is_err());
+    assert!(obj.set_link_order(".exidx", ".exidx").is_err());
+    assert!(obj.set_link_order(".exidx", "missing").is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let section = |name: &str| elf.section_headers.iter().position(|shdr| &elf.shdr_strtab[shdr.sh_name] == name).expect("has section");
+    let exidx = &elf.section_headers[section(".exidx")];
+    assert_eq!(exidx.sh_flags, u64::from(SHF_ALLOC | SHF_LINK_ORDER));
+    assert_eq!(exidx.sh_link as usize, section(".text.exit"));
+    assert_eq!(elf.section_headers[section(".text._start")].sh_link, 0);
+
+    // the linker places the section by the one it is linked to
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::Command;
+        let [obj_path, exe] = ["order.o", "order"].map(|name| temp_path("order", name));
+        std::fs::write(&obj_path, &bytes).expect("can write order.o");
+        let linked = Command::new("ld").args([&obj_path, "-o", &exe]).status();
+        let status = Command::new(&exe).status();
+        for file in &[obj_path, exe] {
+            let _ = std::fs::remove_file(file);
+        }
+        match linked {
+            Ok(linked) => assert!(linked.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        assert_eq!(status.expect("can run the linked executable").code(), Some(0));
+    }
+}
+
+#[test]
+fn local_exec_tls_offsets_locate_a_field_of_thread_local_data() {
+    use scroll::Pread;
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "field.o".into());
+    obj.declarations(vec![
+        ("_start", Decl::function().global().into()),
+        ("state", Decl::data().writable().thread_local().into()),
+    ].into_iter()).expect("can declare");
+    obj.define("_start", vec![
+        0x64, 0x8b, 0x3c, 0x25, 0, 0, 0, 0, // mov edi, fs:[state@tpoff + 16]
+        0xb8, 60, 0, 0, 0,              // mov eax, SYS_exit
+        0x0f, 0x05,                     // syscall
+    ]).expect("can define _start");
+    let mut state = vec![0; 24];
+    state[16] = 42;
+    obj.define("state", state).expect("can define state");
+    obj.link_with(Link { from: "_start", to: "state", at: 4 }, Reloc::Tls { model: TlsModel::LocalExec, addend: 16 }).expect("can link to a field of state");
+    // the other models locate the object through the GOT, so only its start
+    for &model in &[TlsModel::GeneralDynamic, TlsModel::InitialExec] {
+        assert!(obj.link_with(Link { from: "_start", to: "state", at: 4 }, Reloc::Tls { model, addend: 16 }).is_err());
+    }
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let relocs = elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter())
+        .map(|reloc| (reloc.r_offset, reloc.r_type, &elf.strtab[elf.syms.get(reloc.r_sym).expect("has symbol").st_name], reloc.r_addend))
+        .collect::<Vec<_>>();
+    assert_eq!(relocs, [(4, reloc::R_X86_64_TPOFF32, "state", Some(16))]);
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::Command;
+        let [obj_path, exe] = ["field.o", "field"].map(|name| temp_path("field", name));
+        std::fs::write(&obj_path, &bytes).expect("can write field.o");
+        let linked = Command::new("ld").args([&obj_path, "-o", &exe]).status();
+        let linked_bytes = std::fs::read(&exe);
+        for file in &[obj_path, exe] {
+            let _ = std::fs::remove_file(file);
+        }
+        match linked {
+            Ok(linked) => assert!(linked.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        let linked_bytes = linked_bytes.expect("can read the linked executable");
+        let mov = linked_bytes.windows(4).position(|bytes| bytes == [0x64, 0x8b, 0x3c, 0x25]).expect("has the mov");
+        // state is the whole TLS block, which ends at the thread pointer, so its field is 8 bytes before it
+        assert_eq!(linked_bytes.pread_with::<i32>(mov + 4, scroll::LE).expect("has the displacement"), -24 + 16);
+    }
+}
+
+#[test]
+fn local_dynamic_tls_accesses_share_the_block_of_the_module() {
+    use scroll::Pread;
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "module.o".into());
+    obj.declarations(vec![
+        ("_start", Decl::function().global().into()),
+        ("first", Decl::data().writable().thread_local().into()),
+        ("second", Decl::data().writable().thread_local().into()),
+    ].into_iter()).expect("can declare");
+    obj.define("_start", vec![
+        0x48, 0x8d, 0x3d, 0, 0, 0, 0,   // lea rdi, [rip + first@tlsld]
+        0xe8, 0, 0, 0, 0,               // call __tls_get_addr@plt
+        0x8b, 0xb8, 0, 0, 0, 0,         // mov edi, [rax + first@dtpoff]
+        0x03, 0xb8, 0, 0, 0, 0,         // add edi, [rax + second@dtpoff + 4]
+        0xb8, 60, 0, 0, 0,              // mov eax, SYS_exit
+        0x0f, 0x05,                     // syscall
+    ]).expect("can define _start");
+    obj.define("first", vec![40, 0, 0, 0]).expect("can define first");
+    obj.define("second", vec![0, 0, 0, 0, 2, 0, 0, 0]).expect("can define second");
+    obj.link_with(Link { from: "_start", to: "first", at: 3 }, Reloc::Tls { model: TlsModel::LocalDynamic, addend: 0 }).expect("can link to the block");
+    obj.link_with(Link { from: "_start", to: "first", at: 14 }, Reloc::Tls { model: TlsModel::LocalDynamicOffset, addend: 0 }).expect("can link to first");
+    obj.link_with(Link { from: "_start", to: "second", at: 20 }, Reloc::Tls { model: TlsModel::LocalDynamicOffset, addend: 4 }).expect("can link to a field of second");
+    // the block is located by the module, not an object in it, and an import is in another module's block
+    assert!(obj.link_with(Link { from: "_start", to: "first", at: 3 }, Reloc::Tls { model: TlsModel::LocalDynamic, addend: 4 }).is_err());
+    obj.import("elsewhere", ImportKind::Data).expect("can import elsewhere");
+    assert!(obj.link_with(Link { from: "_start", to: "elsewhere", at: 14 }, Reloc::Tls { model: TlsModel::LocalDynamicOffset, addend: 0 }).is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let relocs = elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter())
+        .map(|reloc| (reloc.r_offset, reloc.r_type, &elf.strtab[elf.syms.get(reloc.r_sym).expect("has symbol").st_name], reloc.r_addend))
+        .collect::<Vec<_>>();
+    assert_eq!(relocs, [
+        (3, reloc::R_X86_64_TLSLD, "first", Some(-4)),
+        (8, reloc::R_X86_64_PLT32, "__tls_get_addr", Some(-4)),
+        (14, reloc::R_X86_64_DTPOFF32, "first", Some(0)),
+        (20, reloc::R_X86_64_DTPOFF32, "second", Some(4)),
+    ]);
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use std::process::Command;
+        let [obj_path, exe] = ["module.o", "module"].map(|name| temp_path("module", name));
+        std::fs::write(&obj_path, &bytes).expect("can write module.o");
+        let linked = Command::new("ld").args([&obj_path, "-o", &exe]).status();
+        let linked_bytes = std::fs::read(&exe);
+        for file in &[obj_path, exe] {
+            let _ = std::fs::remove_file(file);
+        }
+        match linked {
+            Ok(linked) => assert!(linked.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        let linked_bytes = linked_bytes.expect("can read the linked executable");
+        // a static link relaxes the call to loading the thread pointer, which the block ends at
+        let start = linked_bytes.windows(8).position(|bytes| bytes == [0x66, 0x66, 0x66, 0x64, 0x48, 0x8b, 0x04, 0x25])
+            .expect("has the relaxed sequence");
+        let displacement = |at: usize| linked_bytes.pread_with::<i32>(start + at, scroll::LE).expect("has the displacement");
+        let linked = Elf::parse(&linked_bytes).expect("can parse the linked executable");
+        let tls = linked.program_headers.iter().find(|phdr| phdr.p_type == goblin::elf::program_header::PT_TLS).expect("has a TLS segment");
+        let value = |name: &str| linked.syms.iter().find(|sym| &linked.strtab[sym.st_name] == name).expect("has symbol").st_value;
+        // the linked value of a thread-local symbol is its offset in the block
+        assert_eq!(displacement(14), value("first") as i32 - tls.p_memsz as i32);
+        assert_eq!(displacement(20), value("second") as i32 + 4 - tls.p_memsz as i32);
+    }
+}
+
+#[test]
+fn unwind_sections_hold_call_frame_information() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "unwind.o".into());
+    obj.declare_with("_start", Decl::function().global(), vec![
+        0xb8, 60, 0, 0, 0,              // mov eax, SYS_exit
+        0xbf, 42, 0, 0, 0,              // mov edi, 42
+        0x0f, 0x05,                     // syscall
+    ]).expect("can declare and define _start");
+    obj.declare_with("other", Decl::function().global(), vec![0xc3]).expect("can declare and define other");
+    let cie = [
+        0x14, 0, 0, 0,                  // length
+        0, 0, 0, 0,                     // CIE id
+        1, b'z', b'R', 0,               // version, augmentation
+        1, 0x78, 16,                    // code alignment 1, data alignment -8, return address in rip
+        1, 0x1b,                        // FDE pointers are DW_EH_PE_pcrel | DW_EH_PE_sdata4
+        0x0c, 7, 8,                     // DW_CFA_def_cfa rsp+8
+        0x90, 1,                        // DW_CFA_offset rip, cfa-8
+        0, 0,                           // padding
+    ];
+    let fde = |cie_pointer: u8, size: u8| vec![
+        0x14, 0, 0, 0,                  // length
+        cie_pointer, 0, 0, 0,           // the offset back to the CIE
+        0, 0, 0, 0,                     // the initial location, relative to the field
+        size, 0, 0, 0,                  // the size of the function
+        0,                              // no augmentation data
+        0, 0, 0, 0, 0, 0, 0,            // padding
+    ];
+    let mut eh_frame = cie.to_vec();
+    eh_frame.extend(fde(28, 12));
+    eh_frame.extend(fde(52, 1));
+    obj.declare_with(".eh_frame", Decl::section().unwind(), eh_frame).expect("can declare and define .eh_frame");
+    obj.link(Link { from: ".eh_frame", to: "_start", at: 32 }).expect("can link to _start");
+    obj.link_with(Link { from: ".eh_frame", to: "other", at: 56 }, Reloc::Relative { size: 4, addend: 0 }).expect("can link to other");
+    assert!(obj.declare(".eh_frame.rw", Decl::section().unwind().writable()).is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let eh_frame = elf.section_headers.iter().find(|shdr| &elf.shdr_strtab[shdr.sh_name] == ".eh_frame").expect("has .eh_frame");
+    assert_eq!(eh_frame.sh_type, 0x7000_0001);
+    assert_eq!(eh_frame.sh_flags, u64::from(section_header::SHF_ALLOC));
+    assert_eq!(eh_frame.sh_addralign, 8);
+    // the initial locations are the functions themselves, not their PLT entries, in their own sections
+    let relocs = elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter()).map(|reloc| {
+        let sym = elf.syms.get(reloc.r_sym).expect("has symbol");
+        assert_eq!(sym.st_type(), sym::STT_SECTION);
+        (reloc.r_offset, reloc.r_type, &elf.shdr_strtab[elf.section_headers[sym.st_shndx].sh_name], reloc.r_addend)
+    }).collect::<Vec<_>>();
+    assert_eq!(relocs, [(32, reloc::R_X86_64_PC32, ".text._start", Some(0)), (56, reloc::R_X86_64_PC32, ".text.other", Some(0))]);
+
+    // a data relative pointer is relative to the GOT base, which x86-64 only has a 64-bit relocation for
+    let mut datarel = Artifact::new(triple!("i686-unknown-linux-gnu"), "datarel.o".into());
+    datarel.declare_with(".eh_frame", Decl::section().unwind(), vec![0; 8]).expect("can declare and define .eh_frame");
+    datarel.declare_with("lsda", Decl::data(), vec![0; 4]).expect("can declare and define lsda");
+    datarel.link_with(Link { from: ".eh_frame", to: "lsda", at: 4 }, Reloc::DataRelative { size: 4, addend: 0 }).expect("can link to lsda");
+    let bytes = datarel.emit().expect("can emit i386 elf file");
+    let elf = Elf::parse(&bytes).expect("can parse i386 elf file");
+    let rel = elf.shdr_relocs.iter().flat_map(|(_, relocs)| relocs.iter()).next().expect("has relocation");
+    assert_eq!((rel.r_offset, rel.r_type), (4, reloc::R_386_GOTOFF));
+    let mut datarel = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "datarel.o".into());
+    datarel.declare_with(".eh_frame", Decl::section().unwind(), vec![0; 8]).expect("can declare and define .eh_frame");
+    datarel.declare_with("lsda", Decl::data(), vec![0; 4]).expect("can declare and define lsda");
+    datarel.link_with(Link { from: ".eh_frame", to: "lsda", at: 0 }, Reloc::DataRelative { size: 4, addend: 0 }).expect("can link to lsda");
+    assert!(datarel.emit().is_err());
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        use scroll::Pread;
+        use std::process::Command;
+        let [obj_path, exe] = ["unwind.o", "unwind"].map(|name| temp_path("unwind", name));
+        std::fs::write(&obj_path, obj.emit().expect("can emit elf file")).expect("can write unwind.o");
+        let linked = Command::new("ld").args(["--eh-frame-hdr", &obj_path, "-o", &exe]).status();
+        let linked_bytes = std::fs::read(&exe);
+        for file in &[obj_path, exe] {
+            let _ = std::fs::remove_file(file);
+        }
+        match linked {
+            Ok(linked) => assert!(linked.success(), "ld failed"),
+            Err(_) => return eprintln!("ld is not available, skipping"),
+        }
+        // the linker parsed both FDEs into the binary search table of .eh_frame_hdr
+        let linked_bytes = linked_bytes.expect("can read the linked executable");
+        let linked = Elf::parse(&linked_bytes).expect("can parse the linked executable");
+        let hdr = linked.section_headers.iter().find(|shdr| &linked.shdr_strtab[shdr.sh_name] == ".eh_frame_hdr").expect("has .eh_frame_hdr");
+        let fde_count = linked_bytes.pread_with::<u32>(hdr.sh_offset as usize + 8, scroll::LE).expect("has the FDE count");
+        assert_eq!(fde_count, 2);
+    }
+}
+
+#[test]
+fn custom_sections_take_the_attributes_they_are_declared_with() {
+    const SHF_GNU_RETAIN: u64 = 0x20_0000;
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("init", Decl::function(), vec![0xc3]).expect("can declare and define init");
+    obj.declare_with(".init_array", Decl::section().writable().elf_type(section_header::SHT_INIT_ARRAY), vec![0; 8]).expect("can declare and define .init_array");
+    obj.link(Link { from: ".init_array", to: "init", at: 0 }).expect("can link to init");
+    obj.declare_with(".rodata.cst8", Decl::section().merge(8), vec![1; 16]).expect("can declare and define .rodata.cst8");
+    obj.declare_with(".comment.tool", Decl::section().not_allocated(), b"faerie\0".to_vec()).expect("can declare and define .comment.tool");
+    obj.declare_with(".lbss", Decl::section().writable().no_bits(), vec![0; 64]).expect("can declare and define .lbss");
+    obj.declare_with(".retained", Decl::section().elf_flags(SHF_GNU_RETAIN), vec![2; 4]).expect("can declare and define .retained");
+    // a section without bits has none to define
+    obj.declare(".nonzero", Decl::section().no_bits()).expect("can declare .nonzero");
+    assert!(obj.define(".nonzero", vec![1]).is_err());
+    obj.define(".nonzero", vec![0]).expect("can define .nonzero");
+    for &decl in &[
+        Decl::section().not_allocated().writable(),
+        Decl::section().not_allocated().no_bits(),
+        Decl::section().merge(4).strings(1),
+        Decl::section().merge(0),
+        Decl::section().unwind().no_bits(),
+    ] {
+        assert!(obj.declare(".invalid", decl).is_err());
+    }
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let section = |name: &str| elf.section_headers.iter().find(|shdr| &elf.shdr_strtab[shdr.sh_name] == name).expect("has section").clone();
+    let (alloc, write, merge) = (u64::from(section_header::SHF_ALLOC), u64::from(section_header::SHF_WRITE), u64::from(section_header::SHF_MERGE));
+    let init_array = section(".init_array");
+    assert_eq!((init_array.sh_type, init_array.sh_flags), (section_header::SHT_INIT_ARRAY, alloc | write));
+    let constants = section(".rodata.cst8");
+    assert_eq!((constants.sh_type, constants.sh_flags, constants.sh_entsize), (section_header::SHT_PROGBITS, alloc | merge, 8));
+    let comment = section(".comment.tool");
+    assert_eq!((comment.sh_type, comment.sh_flags, comment.sh_size), (section_header::SHT_PROGBITS, 0, 7));
+    let lbss = section(".lbss");
+    assert_eq!((lbss.sh_type, lbss.sh_flags, lbss.sh_size), (section_header::SHT_NOBITS, alloc | write, 64));
+    let retained = section(".retained");
+    assert_eq!(retained.sh_flags, alloc | SHF_GNU_RETAIN);
+    assert_eq!(&bytes[retained.sh_offset as usize..][..4], &[2; 4]);
+}
+
+#[test]
+fn pooled_strings_and_constants_are_defined_once_and_mergeable() {
+    use goblin::elf::section_header::{SHF_ALLOC, SHF_MERGE, SHF_STRINGS};
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0x48, 0x8d, 0x3d, 0, 0, 0, 0, 0xf2, 0x0f, 0x10, 0x05, 0, 0, 0, 0, 0xc3]).expect("can define main");
+    // a name of the pool which is already declared is skipped
+    obj.declare_with(".Lstr.0", Decl::cstring(), b"taken\0".to_vec()).expect("can define .Lstr.0");
+    let hello = obj.define_cstring(b"hello\0".to_vec()).expect("can pool hello");
+    assert_eq!(obj.define_cstring(b"hello\0".to_vec()).expect("can pool hello again"), hello);
+    let world = obj.define_cstring(b"world\0".to_vec()).expect("can pool world");
+    assert_ne!(hello, world);
+    assert!(obj.define_cstring(b"unterminated".to_vec()).is_err());
+    let one = obj.define_const(1f64.to_bits().to_le_bytes().to_vec()).expect("can pool 1.0");
+    assert_eq!(obj.define_const(1f64.to_bits().to_le_bytes().to_vec()).expect("can pool 1.0 again"), one);
+    let triple = obj.define_const(vec![1, 2, 3]).expect("can pool a 3 byte constant");
+    obj.link(Link { from: "main", to: &hello, at: 3 }).expect("can link main to hello");
+    obj.link(Link { from: "main", to: &one, at: 11 }).expect("can link main to 1.0");
+    assert!(obj.undefined_symbols().is_empty());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let section = |name: &str| {
+        let section = elf.section_headers.iter().find(|shdr| elf.shdr_strtab[shdr.sh_name] == format!(".data.{}", name)).expect("has section");
+        (section.sh_flags, section.sh_entsize, section.sh_addralign, &bytes[section.sh_offset as usize..][..section.sh_size as usize])
+    };
+    let strings = u64::from(SHF_ALLOC | SHF_MERGE | SHF_STRINGS);
+    assert_eq!(section(&hello), (strings, 1, 1, &b"hello\0"[..]));
+    assert_eq!(section(&world), (strings, 1, 1, &b"world\0"[..]));
+    assert_eq!(section(&one).0, u64::from(SHF_ALLOC | SHF_MERGE));
+    assert_eq!((section(&one).1, section(&one).2), (8, 8));
+    assert_eq!(section(&triple).0, u64::from(SHF_ALLOC));
+    assert_eq!(elf.section_headers.iter().filter(|shdr| elf.shdr_strtab[shdr.sh_name].starts_with(".data.")).count(), 5);
+}
+
+#[test]
+fn hidden_definitions_have_hidden_visibility() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("helper", Decl::function().global().hidden(), vec![0xc3]).expect("can declare and define helper");
+    obj.declare_with("state", Decl::data().global().writable().hidden(), vec![1; 8]).expect("can declare and define state");
+    obj.declare_bss("buffer", Decl::data().global().hidden(), 64, 8).expect("can declare buffer");
+    obj.declare_bss("scratch", Decl::data().global().thread_local().hidden(), 16, 8).expect("can declare scratch");
+    obj.declare_with("visible", Decl::data().global(), vec![1; 8]).expect("can declare and define visible");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let symbol = |name: &str| elf.syms.iter().find(|sym| &elf.strtab[sym.st_name] == name).expect("has the symbol");
+    for name in &["helper", "state", "buffer", "scratch"] {
+        assert_eq!(symbol(name).st_other, 2, "{} is STV_HIDDEN", name);
+    }
+    assert_eq!(symbol("visible").st_other, 0);
+    assert_eq!(symbol("scratch").st_type(), sym::STT_TLS);
+}
+
+#[test]
+fn debug_section_relative_relocations_are_against_the_section() {
+    use faerie::artifact::ArtifactError;
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0xc3]).expect("can declare and define main");
+    obj.declare_with(".debug_info", Decl::debug_section(), vec![0; 32]).expect("can declare and define .debug_info");
+    obj.declare_with(".debug_line", Decl::debug_section(), vec![0; 16]).expect("can declare and define .debug_line");
+    obj.declare_with(".debug_str", Decl::debug_section().strings(), b"t.c\0main\0".to_vec()).expect("can declare and define .debug_str");
+    // DW_AT_stmt_list, DW_AT_name and a 64-bit DWARF DW_FORM_strp
+    obj.link_with(Link { from: ".debug_info", to: ".debug_line", at: 8 }, Reloc::DebugSecRel { size: 4, addend: 0 })
+        .expect("can link from .debug_info to .debug_line");
+    obj.link_with(Link { from: ".debug_info", to: ".debug_str", at: 12 }, Reloc::DebugSecRel { size: 4, addend: 4 })
+        .expect("can link from .debug_info to .debug_str");
+    obj.link_with(Link { from: ".debug_info", to: ".debug_str", at: 16 }, Reloc::DebugSecRel { size: 8, addend: 0 })
+        .expect("can link from .debug_info to .debug_str");
+    assert!(obj.link_with(Link { from: ".debug_info", to: ".debug_str", at: 24 }, Reloc::DebugSecRel { size: 2, addend: 0 }).is_err());
+    match obj.link_with(Link { from: ".debug_info", to: "main", at: 24 }, Reloc::DebugSecRel { size: 8, addend: 0 }) {
+        Err(err) => match err.downcast_ref::<ArtifactError>() {
+            Some(ArtifactError::NotADebugSection(name)) => assert_eq!(name, "main"),
+            _ => panic!("unexpected error: {}", err),
+        },
+        Ok(()) => panic!("main is not a debug section"),
+    }
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let syms = elf.syms.iter().collect::<Vec<_>>();
+    let (_, ref relocs) = *elf.shdr_relocs.iter()
+        .find(|&&(idx, _)| &elf.shdr_strtab[elf.section_headers[elf.section_headers[idx].sh_info as usize].sh_name] == ".debug_info")
+        .expect("has relocations");
+    let relocs = relocs.iter().map(|reloc| {
+        let target = &elf.shdr_strtab[elf.section_headers[syms[reloc.r_sym].st_shndx].sh_name];
+        (reloc.r_offset, reloc.r_type, target, reloc.r_addend.expect("rela"))
+    }).collect::<Vec<_>>();
+    assert_eq!(relocs, vec![
+        (8, reloc::R_X86_64_32, ".debug_line", 0),
+        (12, reloc::R_X86_64_32, ".debug_str", 4),
+        (16, reloc::R_X86_64_64, ".debug_str", 0),
+    ]);
+}
+
+#[test]
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn versioned_symbols_are_bound_to_their_versions() {
+    use std::process::Command;
+    use faerie::artifact::ArtifactError;
+    let [obj_path, script, lib] = ["versioned.o", "versioned.map", "libversioned.so"].map(|name| temp_path("versioned", name));
+
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "versioned.o".into());
+    obj.declare_with("foo_v1", Decl::function().global(), vec![0xb8, 1, 0, 0, 0, 0xc3]).expect("can declare and define foo_v1");
+    obj.declare_with("foo", Decl::function().global(), vec![0xb8, 2, 0, 0, 0, 0xc3]).expect("can declare and define foo");
+    obj.declare_with("helper", Decl::function(), vec![0xc3]).expect("can declare and define helper");
+    obj.import("memcpy", ImportKind::Function).expect("can import memcpy");
+    obj.set_symbol_version("foo_v1", "foo@MYLIB_1.0").expect("can version foo_v1");
+    obj.set_symbol_version("foo", "foo@@MYLIB_2.0").expect("can version foo");
+    obj.set_symbol_version("memcpy", "memcpy@GLIBC_2.2.5").expect("can version memcpy");
+    for &(name, symbol) in &[("helper", "helper@MYLIB_1.0"), ("memcpy", "memcpy@@GLIBC_2.2.5"), ("foo", "foo"), ("foo", "@MYLIB_2.0"), ("foo", "foo@@")] {
+        match obj.set_symbol_version(name, symbol) {
+            Err(ArtifactError::InvalidSymbolVersion(..)) => (),
+            result => panic!("unexpected result for {}: {:?}", symbol, result),
+        }
+    }
+    assert!(obj.exported_symbols(target_lexicon::BinaryFormat::Elf).contains(&"foo@@MYLIB_2.0".to_string()));
+    assert!(obj.emit_as(target_lexicon::BinaryFormat::Macho).is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let names = elf.syms.iter().map(|sym| &elf.strtab[sym.st_name]).collect::<Vec<_>>();
+    for name in &["foo@MYLIB_1.0", "foo@@MYLIB_2.0", "memcpy@GLIBC_2.2.5", "helper"] {
+        assert!(names.contains(name), "{} is not in {:?}", name, names);
+    }
+    assert!(!names.contains(&"foo_v1"));
+
+    // the import is bound to the old memcpy of the C library
+    let libc = "/lib/x86_64-linux-gnu/libc.so.6";
+    if !std::path::Path::new(libc).exists() {
+        return eprintln!("{} is not available, skipping", libc);
+    }
+    std::fs::write(&obj_path, &bytes).expect("can write versioned.o");
+    std::fs::write(&script, "MYLIB_1.0 { global: foo; local: *; };\nMYLIB_2.0 { global: foo; } MYLIB_1.0;\n").expect("can write versioned.map");
+    let linked = Command::new("ld").args(["-shared", "--version-script", &script, &obj_path, libc, "-o", &lib]).status();
+    let output = Command::new("readelf").args(["--dyn-syms", "-W", &lib]).output();
+    for file in &[obj_path, script, lib] {
+        let _ = std::fs::remove_file(file);
+    }
+    match (linked, output) {
+        (Ok(status), Ok(output)) => {
+            assert!(status.success(), "ld -shared failed");
+            let symbols = String::from_utf8_lossy(&output.stdout);
+            assert!(symbols.contains(" foo@MYLIB_1.0"), "{}", symbols);
+            assert!(symbols.contains(" foo@@MYLIB_2.0"), "{}", symbols);
+            assert!(symbols.contains(" memcpy@GLIBC_2.2.5"), "{}", symbols);
+        },
+        _ => eprintln!("ld or readelf is not available, skipping"),
+    }
+}
+
+#[test]
+fn relocations_of_many_links_are_in_the_order_they_were_added() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    let count = 10_000;
+    let names = (0..count).map(|i| format!("f{}", i)).collect::<Vec<_>>();
+    for name in &names {
+        obj.declare_with(name, Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xc3]).expect("can declare and define the function");
+    }
+    for (i, name) in names.iter().enumerate() {
+        obj.link(Link { from: name, to: &names[(i + 1) % count], at: 1 }).expect("can link to the next function");
+    }
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let section = |idx: usize| &elf.shdr_strtab[elf.section_headers[idx].sh_name];
+    let relocs = elf.shdr_relocs.iter().map(|(idx, relocs)| {
+        let reloc = relocs.iter().next().expect("has a relocation");
+        // a function defined here is called through its section symbol
+        let target = section(elf.syms.get(reloc.r_sym).expect("has symbol").st_shndx);
+        (section(elf.section_headers[*idx].sh_info as usize).to_string(), target.to_string(), reloc.r_type, reloc.r_addend)
+    }).collect::<Vec<_>>();
+    assert_eq!(relocs.len(), count);
+    for (i, reloc) in relocs.iter().enumerate() {
+        let expected = (format!(".text.{}", names[i]), format!(".text.{}", names[(i + 1) % count]), reloc::R_X86_64_PLT32, Some(-4));
+        assert_eq!(*reloc, expected);
+    }
+}
+
+#[test]
+fn linker_directives_are_embedded() {
+    use faerie::artifact::ArtifactError;
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("_start", Decl::function().global(), vec![0xc3]).expect("can declare and define _start");
+    obj.add_linker_directive(LinkerDirective::Library("m".into())).expect("can link with libm");
+    obj.add_linker_directive(LinkerDirective::Option("lib".into(), "my util".into())).expect("can add an option");
+    obj.add_linker_directive(LinkerDirective::Library("my util".into())).expect("can link with my util");
+    obj.add_linker_directive(LinkerDirective::Library("m".into())).expect("can link with libm again");
+    match obj.add_linker_directive(LinkerDirective::Option("".into(), "value".into())) {
+        Err(ArtifactError::InvalidLinkerDirective(LinkerDirective::Option(name, _))) => assert_eq!(name, ""),
+        result => panic!("unexpected result: {:?}", result),
+    }
+    assert!(obj.add_linker_directive(LinkerDirective::Library("m\0".into())).is_err());
+    assert_eq!(obj.linker_directives(), " /DEFAULTLIB:m /DEFAULTLIB:\"my util\"");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => elf,
+        _ => panic!("Elf file not parsed as elf file"),
+    };
+    let section = |name: &str| elf.section_headers.iter().find(|shdr| &elf.shdr_strtab[shdr.sh_name] == name)
+        .unwrap_or_else(|| panic!("has {}", name)).clone();
+    let contents = |shdr: &section_header::SectionHeader| &bytes[shdr.sh_offset as usize..(shdr.sh_offset + shdr.sh_size) as usize];
+    let deplibs = section(".deplibs");
+    assert_eq!(deplibs.sh_type, 0x6fff_4c04);
+    assert_eq!(deplibs.sh_flags, (section_header::SHF_MERGE | section_header::SHF_STRINGS) as u64);
+    assert_eq!(deplibs.sh_entsize, 1);
+    assert_eq!(contents(&deplibs), b"m\0my util\0");
+    let options = section(".linker-options");
+    assert_eq!(options.sh_type, 0x6fff_4c01);
+    assert_eq!(options.sh_flags, 0x8000_0000);
+    assert_eq!(contents(&options), b"lib\0my util\0");
+}
+
+#[test]
+fn deterministic_output_does_not_depend_on_the_order_of_links() {
+    let links = [("f", "puts", 1), ("g", "f", 6), ("f", "g", 6), ("g", "puts", 1)];
+    let build = |order: &[usize], deterministic: bool| {
+        let mut obj = ArtifactBuilder::new(triple!("x86_64-unknown-linux-gnu"))
+            .name("t.o".into())
+            .function_sections(false)
+            .finish();
+        obj.set_deterministic(deterministic);
+        obj.set_content_buildid().expect("can compute the build id");
+        obj.import("puts", ImportKind::Function).expect("can import puts");
+        for name in &["f", "g"] {
+            obj.declare_with(name, Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xe8, 0, 0, 0, 0, 0xc3])
+                .expect("can declare and define the function");
+        }
+        for &i in order {
+            let (from, to, at) = links[i];
+            obj.link(Link { from, to, at }).expect("can link");
+        }
+        obj.emit().expect("can emit elf file")
+    };
+
+    let bytes = build(&[0, 1, 2, 3], true);
+    assert_eq!(bytes, build(&[3, 2, 1, 0], true));
+    assert_eq!(bytes, build(&[1, 3, 0, 2], true));
+    assert_ne!(build(&[0, 1, 2, 3], false), build(&[3, 2, 1, 0], false));
+
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    let (_, relocs) = elf.shdr_relocs.first().expect("has a relocation section");
+    let offsets = relocs.iter().map(|reloc| reloc.r_offset).collect::<Vec<_>>();
+    assert_eq!(offsets.len(), 4);
+    let mut sorted = offsets.clone();
+    sorted.sort();
+    assert_eq!(offsets, sorted);
+}
+
+#[test]
+fn riscv64_links_are_relaxable_calls_and_pc_relative() {
+    const R_RISCV_64: u32 = 2;
+    const R_RISCV_CALL_PLT: u32 = 19;
+    const R_RISCV_GOT_HI20: u32 = 20;
+    const R_RISCV_PCREL_HI20: u32 = 23;
+    const R_RISCV_PCREL_LO12_I: u32 = 24;
+    const R_RISCV_RELAX: u32 = 51;
+
+    let mut obj = Artifact::new(triple!("riscv64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("helper", Decl::function(), vec![0x67, 0x80, 0, 0]).expect("can define helper");
+    obj.declare_with("main", Decl::function().global(), vec![
+        0x97, 0x00, 0x00, 0x00, // auipc ra, %pcrel_hi(helper)
+        0xe7, 0x80, 0x00, 0x00, // jalr ra, ra
+        0x17, 0x05, 0x00, 0x00, // auipc a0, %pcrel_hi(counter)
+        0x13, 0x05, 0x05, 0x00, // addi a0, a0, %pcrel_lo(.Lpcrel_hi0)
+        0x97, 0x05, 0x00, 0x00, // auipc a1, %got_pcrel_hi(environ)
+        0x83, 0xb5, 0x05, 0x00, // ld a1, %pcrel_lo(.Lpcrel_hi1)(a1)
+        0x67, 0x80, 0x00, 0x00, // ret
+    ]).expect("can define main");
+    obj.declare_with("counter", Decl::data().writable(), vec![0; 8]).expect("can define counter");
+    obj.declare_with("table", Decl::data(), vec![0; 8]).expect("can define table");
+    obj.declare("environ", Decl::DataImport).expect("can import environ");
+    obj.link(Link { from: "main", to: "helper", at: 0 }).expect("can link main to helper");
+    obj.link(Link { from: "main", to: "counter", at: 8 }).expect("can link main to counter");
+    obj.link(Link { from: "main", to: "environ", at: 16 }).expect("can link main to environ");
+    obj.link(Link { from: "table", to: "main", at: 0 }).expect("can link table to main");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = Elf::parse(&bytes).expect("can parse elf file");
+    assert_eq!(elf.header.e_machine, header::EM_RISCV);
+    // compressed instructions, and the double precision floating point ABI
+    assert_eq!(elf.header.e_flags, 0x5);
+    let symbol = |index: usize| {
+        let sym = elf.syms.get(index).expect("relocation has a symbol");
+        match &elf.strtab[sym.st_name] {
+            "" if index == 0 => "",
+            "" => &elf.shdr_strtab[elf.section_headers[sym.st_shndx].sh_name],
+            name => name,
+        }
+    };
+    let relocs = elf.shdr_relocs.iter()
+        .flat_map(|(_, relocs)| relocs.iter().map(|reloc| (reloc.r_offset, reloc.r_type, symbol(reloc.r_sym), reloc.r_addend)))
+        .collect::<Vec<_>>();
+    // as gas assembles the same instructions, each relaxable one followed by its R_RISCV_RELAX
+    assert_eq!(relocs, vec![
+        (0, R_RISCV_CALL_PLT, "helper", Some(0)),
+        (0, R_RISCV_RELAX, "", Some(0)),
+        (8, R_RISCV_PCREL_HI20, "counter", Some(0)),
+        (8, R_RISCV_RELAX, "", Some(0)),
+        (12, R_RISCV_PCREL_LO12_I, ".Lpcrel_hi0", Some(0)),
+        (12, R_RISCV_RELAX, "", Some(0)),
+        (16, R_RISCV_GOT_HI20, "environ", Some(0)),
+        (16, R_RISCV_RELAX, "", Some(0)),
+        (20, R_RISCV_PCREL_LO12_I, ".Lpcrel_hi1", Some(0)),
+        (20, R_RISCV_RELAX, "", Some(0)),
+        (0, R_RISCV_64, ".text.main", Some(0)),
+    ]);
+    // each label is a local symbol at the auipc whose address the instruction after it completes
+    for &(label, value) in &[(".Lpcrel_hi0", 8), (".Lpcrel_hi1", 16)] {
+        let sym = elf.syms.iter().find(|sym| &elf.strtab[sym.st_name] == label).expect("has the label");
+        assert_eq!((sym.st_bind(), sym.st_type(), sym.st_value), (sym::STB_LOCAL, sym::STT_NOTYPE, value));
+        assert_eq!(&elf.shdr_strtab[elf.section_headers[sym.st_shndx].sh_name], ".text.main");
+    }
+}
@@ -0,0 +1,776 @@
+extern crate faerie;
+extern crate goblin;
+extern crate scroll;
+#[macro_use]
+extern crate target_lexicon;
+
+use std::str::FromStr;
+
+use faerie::{Artifact, Archive, ArchiveFormat, Decl, Link, LinkerDirective, Reloc, Scope, TlsModel, UnwindRegister, Visibility, x86_64_frame_unwind};
+use goblin::mach::*;
+use scroll::Pread;
+
+#[test]
+fn weak_definitions_are_coalesced() {
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xc3]).expect("can declare and define main");
+    obj.declare_with("inline_fn", Decl::function().weak(), vec![0xc3]).expect("can declare and define inline_fn");
+    obj.declare_with("guard", Decl::data().weak().writable(), vec![0; 8]).expect("can declare and define guard");
+    obj.link(Link { from: "main", to: "inline_fn", at: 1 }).expect("can link from main to inline_fn");
+
+    let bytes = obj.emit().expect("can emit mach file");
+    let mach = match goblin::Object::parse(&bytes).expect("can parse mach file") {
+        goblin::Object::Mach(Mach::Binary(mach)) => mach,
+        _ => panic!("Mach file not parsed as mach file"),
+    };
+    let sections = mach.segments.sections().flatten()
+        .map(|section| section.expect("can parse section").0)
+        .collect::<Vec<_>>();
+    let names = sections.iter().map(|section| section.name().expect("section has a name")).collect::<Vec<_>>();
+    let section_type = |name| sections[names.iter().position(|&n| n == name).expect("has section")].flags & constants::SECTION_TYPE;
+    assert_eq!(section_type("__textcoal_nt"), constants::S_COALESCED);
+    assert_eq!(section_type("__datacoal_nt"), constants::S_COALESCED);
+    assert_eq!(section_type("__text"), constants::S_REGULAR);
+
+    const N_WEAK_DEF: u16 = 0x80;
+    for symbol in mach.symbols() {
+        let (name, nlist) = symbol.expect("can parse symbol");
+        let section = names[nlist.n_sect - 1];
+        match name {
+            "_main" => {
+                assert_eq!(section, "__text");
+                assert_eq!(nlist.n_desc & N_WEAK_DEF, 0);
+            },
+            "_inline_fn" => {
+                assert_eq!(section, "__textcoal_nt");
+                assert_eq!(nlist.n_desc & N_WEAK_DEF, N_WEAK_DEF);
+                assert!(nlist.is_global());
+            },
+            "_guard" => {
+                assert_eq!(section, "__datacoal_nt");
+                assert_eq!(nlist.n_desc & N_WEAK_DEF, N_WEAK_DEF);
+                assert!(nlist.is_global());
+            },
+            _ => panic!("unexpected symbol {}", name),
+        }
+    }
+}
+
+#[test]
+fn const_section_can_be_assigned_to_data_const() {
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("handler", Decl::function().global(), vec![0xc3]).expect("can declare and define handler");
+    obj.declare_with("table", Decl::data().global(), vec![0; 8]).expect("can declare and define table");
+    obj.declare_with("counter", Decl::data().global().writable(), vec![0; 8]).expect("can declare and define counter");
+    obj.link(Link { from: "table", to: "handler", at: 0 }).expect("can link from table to handler");
+    obj.set_macho_segment("__const", "__DATA_CONST").expect("can assign __const to __DATA_CONST");
+    assert!(obj.set_macho_segment("__const", "__SEGMENT_NAME_TOO_LONG").is_err());
+
+    let bytes = obj.emit().expect("can emit mach file");
+    let mach = match goblin::Object::parse(&bytes).expect("can parse mach file") {
+        goblin::Object::Mach(Mach::Binary(mach)) => mach,
+        _ => panic!("Mach file not parsed as mach file"),
+    };
+    let sections = mach.segments.sections().flatten()
+        .map(|section| section.expect("can parse section").0)
+        .collect::<Vec<_>>();
+    let section = |name| sections.iter().find(|section| section.name().expect("section has a name") == name).expect("has section");
+    let table = section("__const");
+    assert_eq!(table.segname().expect("section has a segment name"), "__DATA_CONST");
+    assert_eq!(table.size, 8);
+    // the pointer to handler is relocated in place
+    assert_eq!(table.nreloc, 1);
+    assert_eq!(section("__data").segname().expect("section has a segment name"), "__DATA");
+    assert_eq!(section("__text").segname().expect("section has a segment name"), "__TEXT");
+
+    for symbol in mach.symbols() {
+        let (name, nlist) = symbol.expect("can parse symbol");
+        let section = sections[nlist.n_sect - 1].name().expect("section has a name");
+        match name {
+            "_handler" => assert_eq!(section, "__text"),
+            "_table" => assert_eq!(section, "__const"),
+            "_counter" => assert_eq!(section, "__data"),
+            _ => panic!("unexpected symbol {}", name),
+        }
+    }
+}
+
+#[test]
+fn compact_unwind_entries_refer_to_their_functions() {
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("leaf", Decl::function().global(), vec![0x55, 0x48, 0x89, 0xe5, 0x5d, 0xc3]).expect("can declare and define leaf");
+    obj.declare_with("main", Decl::function().global(), vec![
+        0x55,                           // push rbp
+        0x48, 0x89, 0xe5,               // mov rbp, rsp
+        0x41, 0x56,                     // push r14
+        0x53,                           // push rbx
+        0xe8, 0, 0, 0, 0,               // call leaf
+        0x5b,                           // pop rbx
+        0x41, 0x5e,                     // pop r14
+        0x5d,                           // pop rbp
+        0xc3,                           // ret
+    ]).expect("can declare and define main");
+    obj.link(Link { from: "main", to: "leaf", at: 8 }).expect("can link from main to leaf");
+    // the registers are found from the lowest address up: rbx at rbp - 24, then r14
+    let encoding = x86_64_frame_unwind(&[UnwindRegister::R14, UnwindRegister::Rbx]).expect("can encode the frame");
+    assert_eq!(encoding, 0x0102_0021);
+    assert_eq!(x86_64_frame_unwind(&[UnwindRegister::Rbx; 6]), None);
+    obj.set_compact_unwind("leaf", x86_64_frame_unwind(&[]).expect("can encode the frame")).expect("can set the unwind of leaf");
+    obj.set_compact_unwind("main", encoding).expect("can set the unwind of main");
+
+    let bytes = obj.emit().expect("can emit mach file");
+    let mach = match goblin::Object::parse(&bytes).expect("can parse mach file") {
+        goblin::Object::Mach(Mach::Binary(mach)) => mach,
+        _ => panic!("Mach file not parsed as mach file"),
+    };
+    let (unwind, contents) = mach.segments.sections().flatten()
+        .map(|section| section.expect("can parse section"))
+        .find(|(section, _)| section.name().expect("section has a name") == "__compact_unwind")
+        .expect("has __compact_unwind");
+    assert_eq!(unwind.segname().expect("section has a segment name"), "__LD");
+    assert_eq!(unwind.addr % 8, 0);
+    assert_eq!(contents.len(), 64);
+    let entries = contents.chunks(32).map(|entry| {
+        (entry.pread_with::<u32>(8, scroll::LE).unwrap(), entry.pread_with::<u32>(12, scroll::LE).unwrap())
+    }).collect::<Vec<_>>();
+    assert_eq!(entries, [(6, 0x0100_0000), (17, encoding)]);
+
+    // each entry's start is relocated to its function
+    let names = mach.symbols().map(|symbol| symbol.expect("can parse symbol").0).collect::<Vec<_>>();
+    let relocations = unwind.iter_relocations(&bytes, goblin::container::Ctx::default())
+        .map(|reloc| reloc.expect("can parse relocation"))
+        .map(|reloc| (reloc.r_address, names[reloc.r_symbolnum()], reloc.r_length()))
+        .collect::<Vec<_>>();
+    assert_eq!(relocations, [(0, "_leaf", 3), (32, "_main", 3)]);
+}
+
+#[test]
+fn constructors_and_destructors_are_function_pointers() {
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    for name in &["first", "second", "early", "cleanup"] {
+        obj.declare_with(*name, Decl::function(), vec![0xc3]).expect("can declare and define function");
+    }
+    obj.declare_with("data", Decl::data(), vec![1]).expect("can declare and define data");
+    assert!(obj.add_constructor("data", None).is_err());
+    obj.add_constructor("first", None).expect("can add constructor");
+    obj.add_constructor("second", None).expect("can add constructor");
+    obj.add_constructor("early", Some(200)).expect("can add constructor");
+    obj.add_destructor("cleanup", None).expect("can add destructor");
+
+    let bytes = obj.emit().expect("can emit mach file");
+    let mach = match goblin::Object::parse(&bytes).expect("can parse mach file") {
+        goblin::Object::Mach(Mach::Binary(mach)) => mach,
+        _ => panic!("Mach file not parsed as mach file"),
+    };
+    let names = mach.symbols().map(|symbol| symbol.expect("can parse symbol").0).collect::<Vec<_>>();
+    let sections = mach.segments.sections().flatten().map(|section| section.expect("can parse section")).collect::<Vec<_>>();
+    let pointers = |sectname: &str| {
+        let (section, contents) = sections.iter().find(|(section, _)| section.name().expect("section has a name") == sectname)
+            .expect("has section");
+        assert_eq!(section.segname().expect("section has a segment name"), "__DATA");
+        assert_eq!((section.addr % 8, contents.len() % 8), (0, 0));
+        let relocations = section.iter_relocations(&bytes, goblin::container::Ctx::default())
+            .map(|reloc| reloc.expect("can parse relocation"))
+            .map(|reloc| (reloc.r_address, names[reloc.r_symbolnum()], reloc.r_length()))
+            .collect::<Vec<_>>();
+        (section.flags & constants::SECTION_TYPE, relocations)
+    };
+    // the constructors with a priority are first, as the runtime has no priorities
+    assert_eq!(pointers("__mod_init_func"), (0x9, vec![(0, "_early", 3), (8, "_first", 3), (16, "_second", 3)]));
+    assert_eq!(pointers("__mod_term_func"), (0xa, vec![(0, "_cleanup", 3)]));
+}
+
+#[test]
+fn i386_symbol_differences_are_scattered() {
+    let mut obj = Artifact::new(triple!("i386-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function(), vec![0xc3]).expect("can declare and define f");
+    obj.declare_with("g", Decl::function().global(), vec![0x90, 0xc3]).expect("can declare and define g");
+    obj.declare_with("table", Decl::data(), vec![0; 8]).expect("can declare and define table");
+    obj.link_difference(Link { from: "table", to: "g", at: 0 }, "table").expect("can link g - table");
+    obj.link_difference(Link { from: "table", to: "f", at: 4 }, "table").expect("can link f - table");
+    assert!(obj.link_difference(Link { from: "table", to: "f", at: 0 }, "printf").is_err());
+
+    let bytes = obj.emit().expect("can emit mach file");
+    let mach = match goblin::Object::parse(&bytes).expect("can parse mach file") {
+        goblin::Object::Mach(Mach::Binary(mach)) => mach,
+        _ => panic!("Mach file not parsed as mach file"),
+    };
+    let (table, contents) = mach.segments.sections().flatten()
+        .map(|section| section.expect("can parse section"))
+        .find(|(section, _)| section.name().expect("section has a name") == "__const")
+        .expect("has __const");
+    // f is at 0, g at 1, and table at 3
+    assert_eq!(contents, &[0xfe, 0xff, 0xff, 0xff, 0xfd, 0xff, 0xff, 0xff]);
+    assert_eq!(table.addr, 3);
+    assert_eq!(table.nreloc, 4);
+    let relocations = (0..8).map(|i| bytes.pread_with::<u32>(table.reloff as usize + i * 4, scroll::LE).unwrap()).collect::<Vec<_>>();
+    // scattered, 4 bytes long: a section difference, or a local one as f is not external, and its pair
+    assert_eq!(relocations, [0xa200_0000, 1, 0xa100_0000, 3, 0xa400_0004, 0, 0xa100_0000, 3]);
+
+    let path = std::env::temp_dir().join(format!("faerie-scattered-{}.o", std::process::id()));
+    std::fs::write(&path, &bytes).expect("can write object");
+    let output = match std::process::Command::new("llvm-objdump").arg("--macho").arg("-r").arg(&path).output() {
+        Ok(output) => output,
+        Err(_) => {
+            eprintln!("llvm-objdump is not available; skipping the relocation listing");
+            let _ = std::fs::remove_file(&path);
+            return;
+        },
+    };
+    let _ = std::fs::remove_file(&path);
+    assert!(output.status.success());
+    let listing = String::from_utf8(output.stdout).expect("listing is utf8");
+    let listing = listing.lines().skip_while(|line| !line.starts_with("address")).skip(1)
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect::<Vec<_>>();
+    assert_eq!(listing, [
+        "00000000 False long n/a SECTDIF True 0x00000001",
+        "False long n/a PAIR True 0x00000003",
+        "00000004 False long n/a LOCSDIF True 0x00000000",
+        "False long n/a PAIR True 0x00000003",
+    ]);
+}
+
+#[test]
+fn i386_relocations_are_generic_and_four_bytes() {
+    let mut obj = Artifact::new(triple!("i686-apple-darwin"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![
+        0xe8, 0, 0, 0, 0,   // call helper
+        0xa1, 0, 0, 0, 0,   // mov eax, [counter]
+        0xc3,               // ret
+    ]).expect("can declare and define main");
+    obj.declare_with("helper", Decl::function(), vec![0xc3]).expect("can declare and define helper");
+    obj.declare_with("counter", Decl::data().global().writable(), vec![0; 4]).expect("can declare and define counter");
+    obj.declare_with("table", Decl::data().writable(), vec![0; 4]).expect("can declare and define table");
+    obj.link(Link { from: "main", to: "helper", at: 1 }).expect("can link from main to helper");
+    obj.link(Link { from: "main", to: "counter", at: 6 }).expect("can link from main to counter");
+    obj.link(Link { from: "table", to: "main", at: 0 }).expect("can link from table to main");
+
+    let bytes = obj.emit().expect("can emit mach file");
+    let mach = match goblin::Object::parse(&bytes).expect("can parse mach file") {
+        goblin::Object::Mach(Mach::Binary(mach)) => mach,
+        _ => panic!("Mach file not parsed as mach file"),
+    };
+    assert!(!mach.is_64);
+    assert_eq!((mach.header.cputype, mach.header.cpusubtype), (cputype::CPU_TYPE_X86, cputype::CPU_SUBTYPE_I386_ALL));
+    let (_, text) = mach.segments.sections().flatten()
+        .map(|section| section.expect("can parse section"))
+        .find(|(section, _)| section.name().expect("section has a name") == "__text")
+        .expect("has __text");
+    let main = mach.symbols().map(|symbol| symbol.expect("can parse symbol"))
+        .find(|&(name, _)| name == "_main").expect("has _main").1.n_value as usize;
+    // the call is relative to the end of its field
+    assert_eq!(&text[main + 1..main + 5], &(-(main as i32 + 5)).to_le_bytes());
+
+    let path = std::env::temp_dir().join(format!("faerie-i386-{}.o", std::process::id()));
+    std::fs::write(&path, &bytes).expect("can write object");
+    let output = match std::process::Command::new("llvm-objdump").arg("--macho").arg("-r").arg(&path).output() {
+        Ok(output) => output,
+        Err(_) => {
+            let _ = std::fs::remove_file(&path);
+            return eprintln!("llvm-objdump is not available; skipping the relocation listing");
+        },
+    };
+    let _ = std::fs::remove_file(&path);
+    assert!(output.status.success());
+    let listing = String::from_utf8(output.stdout).expect("listing is utf8");
+    let listing = listing.lines().filter(|line| line.starts_with('0'))
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect::<Vec<_>>();
+    // main follows helper
+    assert_eq!(listing, [
+        "00000002 True long True VANILLA False _helper",
+        "00000007 False long True VANILLA False _counter",
+        "00000000 False long True VANILLA False _main",
+    ]);
+
+    // 32-bit ARM has relocations of its own
+    let mut obj = Artifact::new(triple!("armv7-apple-ios"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0; 8]).expect("can declare and define main");
+    let bytes = obj.emit().expect("can emit mach file without relocations");
+    match goblin::Object::parse(&bytes).expect("can parse mach file") {
+        goblin::Object::Mach(Mach::Binary(mach)) => {
+            assert_eq!((mach.header.cputype, mach.header.cpusubtype), (cputype::CPU_TYPE_ARM, cputype::CPU_SUBTYPE_ARM_V7))
+        },
+        _ => panic!("Mach file not parsed as mach file"),
+    }
+    obj.declare_with("table", Decl::data(), vec![0; 4]).expect("can declare and define table");
+    obj.link(Link { from: "table", to: "main", at: 0 }).expect("can link from table to main");
+    assert!(obj.emit().is_err());
+}
+
+#[test]
+fn zero_initialized_data_is_zerofill() {
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0x8b, 0x05, 0, 0, 0, 0, 0xc3]).expect("can declare and define main");
+    obj.declare("buffer", Decl::data().global().zero_init(64)).expect("can declare buffer");
+    obj.define_zeroed("buffer", 1 << 20).expect("can define buffer");
+    obj.link(Link { from: "main", to: "buffer", at: 2 }).expect("can link from main to buffer");
+
+    let bytes = obj.emit().expect("can emit mach file");
+    assert!(bytes.len() < 4096, "the object is {} bytes", bytes.len());
+    let mach = match goblin::Object::parse(&bytes).expect("can parse mach file") {
+        goblin::Object::Mach(Mach::Binary(mach)) => mach,
+        _ => panic!("Mach file not parsed as mach file"),
+    };
+    let segment = &mach.segments[0];
+    assert_eq!(segment.vmsize, 64 + (1 << 20));
+    assert_eq!(segment.filesize, 7);
+    let sections = segment.sections().expect("can parse sections").into_iter().map(|(section, _)| section).collect::<Vec<_>>();
+    let bss = sections.iter().find(|section| section.name().expect("section has a name") == "__bss").expect("has __bss");
+    assert_eq!(bss.flags & constants::SECTION_TYPE, constants::S_ZEROFILL);
+    assert_eq!((bss.addr, bss.size, bss.offset, bss.align), (64, 1 << 20, 0, 6));
+    let (_, buffer) = mach.symbols().map(|symbol| symbol.expect("can parse symbol")).find(|&(name, _)| name == "_buffer").expect("has _buffer");
+    assert_eq!(sections[buffer.n_sect - 1].name().expect("section has a name"), "__bss");
+    assert_eq!(buffer.n_value, 64);
+}
+
+#[test]
+fn common_symbols_are_undefined_with_their_size() {
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0x8b, 0x05, 0, 0, 0, 0, 0xc3]).expect("can declare and define main");
+    obj.declare_bss("counter", Decl::data().common(4), 4, 4).expect("can declare counter");
+    obj.declare_bss("buffer", Decl::data().common(64), 256, 64).expect("can declare buffer");
+    obj.link(Link { from: "main", to: "counter", at: 2 }).expect("can link from main to counter");
+
+    let bytes = obj.emit().expect("can emit mach file");
+    let mach = match goblin::Object::parse(&bytes).expect("can parse mach file") {
+        goblin::Object::Mach(Mach::Binary(mach)) => mach,
+        _ => panic!("Mach file not parsed as mach file"),
+    };
+    // the linker allocates them, so __bss is empty
+    let sections = mach.segments[0].sections().expect("can parse sections").into_iter().map(|(section, _)| section).collect::<Vec<_>>();
+    let bss = sections.iter().find(|section| section.name().expect("section has a name") == "__bss").expect("has __bss");
+    assert_eq!(bss.size, 0);
+    let symbols = mach.symbols().map(|symbol| symbol.expect("can parse symbol")).collect::<Vec<_>>();
+    let symbol = |name| symbols.iter().position(|&(symbol, _)| symbol == name).expect("has the symbol");
+    // an undefined external symbol with a size is common, and the log2 of its alignment is in n_desc
+    for &(name, size, align) in &[("_counter", 4, 2), ("_buffer", 256, 6)] {
+        let (_, nlist) = &symbols[symbol(name)];
+        assert_eq!((nlist.n_type, nlist.n_sect, nlist.n_value, nlist.n_desc), (symbols::N_UNDF | symbols::N_EXT, 0, size, align << 8));
+    }
+    // the load of counter is relocated against its symbol
+    let text = sections.iter().find(|section| section.name().expect("section has a name") == "__text").expect("has __text");
+    let relocations = text.iter_relocations(&bytes, goblin::container::Ctx::default())
+        .map(|reloc| reloc.expect("can parse relocation"))
+        .map(|reloc| (reloc.r_address, symbols[reloc.r_symbolnum()].0, reloc.r_extern()))
+        .collect::<Vec<_>>();
+    assert_eq!(relocations, [(2, "_counter", 1)]);
+}
+
+#[test]
+fn comdat_members_are_weak_definitions() {
+    use faerie::ComdatSelection;
+    const N_WEAK_DEF: u16 = 0x80;
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("inline_fn", Decl::function().global(), vec![0x8b, 0x05, 0, 0, 0, 0, 0xc3]).expect("can declare and define inline_fn");
+    obj.declare_with("vtable", Decl::data().global(), vec![0; 8]).expect("can declare and define vtable");
+    obj.declare_with("inline_data", Decl::data(), vec![42, 0, 0, 0]).expect("can declare and define inline_data");
+    obj.link(Link { from: "inline_fn", to: "inline_data", at: 2 }).expect("can link from inline_fn to inline_data");
+    obj.set_comdat("vtable", "inline_fn", ComdatSelection::Any).expect("can group vtable");
+    obj.set_comdat("inline_data", "inline_fn", ComdatSelection::Any).expect("can group inline_data");
+
+    let bytes = obj.emit().expect("can emit mach file");
+    let mach = match goblin::Object::parse(&bytes).expect("can parse mach file") {
+        goblin::Object::Mach(Mach::Binary(mach)) => mach,
+        _ => panic!("Mach file not parsed as mach file"),
+    };
+    let sections = mach.segments[0].sections().expect("can parse sections").into_iter().map(|(section, _)| section).collect::<Vec<_>>();
+    let symbols = mach.symbols().map(|symbol| symbol.expect("can parse symbol")).collect::<Vec<_>>();
+    let placement = |name| {
+        let (_, nlist) = symbols.iter().find(|&&(symbol, _)| symbol == name).expect("has the symbol");
+        (sections[nlist.n_sect - 1].name().expect("section has a name"), nlist.n_desc & N_WEAK_DEF != 0)
+    };
+    // the global members are kept once by name, and the local one is only referred to from this object
+    assert_eq!(placement("_inline_fn"), ("__textcoal_nt", true));
+    assert_eq!(placement("_vtable"), ("__datacoal_nt", true));
+    assert_eq!(placement("_inline_data"), ("__const", false));
+
+    obj.declare_with("generic", Decl::function().global(), vec![0xc3]).expect("can declare and define generic");
+    obj.set_comdat("generic", "generic", ComdatSelection::SameSize).expect("can group generic");
+    assert!(obj.emit().is_err());
+}
+
+#[test]
+fn arm64_relocations_are_chosen_for_the_architecture() {
+    use goblin::mach::relocation::*;
+    let mut obj = Artifact::new(triple!("aarch64-apple-darwin"), "t.o".into());
+    obj.declarations(vec![
+        ("main", Decl::function().global().into()),
+        ("helper", Decl::function().into()),
+        ("counter", Decl::data().writable().into()),
+        ("handlers", Decl::data().global().into()),
+        ("printf", Decl::FunctionImport),
+        ("environ", Decl::DataImport),
+    ].into_iter()).expect("can declare");
+    obj.define("main", vec![
+        0x00, 0x00, 0x00, 0x94,         // bl helper
+        0x00, 0x00, 0x00, 0x90,         // adrp x0, counter@PAGE
+        0x00, 0x00, 0x00, 0x91,         // add x0, x0, counter@PAGEOFF
+        0x01, 0x00, 0x00, 0x90,         // adrp x1, environ@GOTPAGE
+        0x21, 0x00, 0x40, 0xf9,         // ldr x1, [x1, environ@GOTPAGEOFF]
+        0x00, 0x00, 0x00, 0x14,         // b printf
+    ]).expect("can define main");
+    obj.define("helper", vec![0xc0, 0x03, 0x5f, 0xd6]).expect("can define helper");
+    obj.define("counter", vec![0; 4]).expect("can define counter");
+    obj.define("handlers", vec![0; 8]).expect("can define handlers");
+    obj.link(Link { from: "main", to: "helper", at: 0 }).expect("can link from main to helper");
+    obj.link(Link { from: "main", to: "counter", at: 4 }).expect("can link from main to counter");
+    obj.link(Link { from: "main", to: "environ", at: 12 }).expect("can link from main to environ");
+    obj.link(Link { from: "main", to: "printf", at: 20 }).expect("can link from main to printf");
+    obj.link(Link { from: "handlers", to: "helper", at: 0 }).expect("can link from handlers to helper");
+
+    let bytes = obj.emit().expect("can emit mach file");
+    let mach = match goblin::Object::parse(&bytes).expect("can parse mach file") {
+        goblin::Object::Mach(Mach::Binary(mach)) => mach,
+        _ => panic!("Mach file not parsed as mach file"),
+    };
+    assert_eq!(mach.header.cputype, cputype::CPU_TYPE_ARM64);
+    let names = mach.symbols().map(|symbol| symbol.expect("can parse symbol").0).collect::<Vec<_>>();
+    let relocations = |name| {
+        let sections = mach.segments[0].sections().expect("can parse sections");
+        let (section, _) = sections.iter().find(|(section, _)| section.name().expect("section has a name") == name).expect("has section");
+        let mut relocations = section.iter_relocations(&bytes, goblin::container::Ctx::default())
+            .map(|reloc| reloc.expect("can parse relocation"))
+            .map(|reloc| (reloc.r_address, reloc.r_type(), reloc.is_pic(), reloc.r_length(), names[reloc.r_symbolnum()]))
+            .collect::<Vec<_>>();
+        relocations.sort();
+        relocations
+    };
+    // each address is a page and an offset into it, and the import's is loaded from the GOT;
+    // main follows the local helper
+    assert_eq!(relocations("__text"), [
+        (4, ARM64_RELOC_BRANCH26, true, 2, "_helper"),
+        (8, ARM64_RELOC_PAGE21, true, 2, "_counter"),
+        (12, ARM64_RELOC_PAGEOFF12, false, 2, "_counter"),
+        (16, ARM64_RELOC_GOT_LOAD_PAGE21, true, 2, "_environ"),
+        (20, ARM64_RELOC_GOT_LOAD_PAGEOFF12, false, 2, "_environ"),
+        (24, ARM64_RELOC_BRANCH26, true, 2, "_printf"),
+    ]);
+    let pointers = relocations("__const");
+    assert_eq!(pointers.iter().map(|&(_, r_type, pic, length, name)| (r_type, pic, length, name)).collect::<Vec<_>>(), [
+        (ARM64_RELOC_UNSIGNED, false, 3, "_helper"),
+    ]);
+}
+
+#[test]
+fn aliases_are_at_the_address_of_their_definition() {
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("helper", Decl::function(), vec![0x90]).expect("can declare and define helper");
+    obj.declare_with("foo", Decl::function().global(), vec![0xb8, 42, 0, 0, 0, 0xc3]).expect("can declare and define foo");
+    obj.define_alias("bar", "foo", Scope::Global).expect("can alias foo");
+    obj.define_alias("foo_local", "bar", Scope::Local).expect("can alias bar");
+    obj.define_alias_with("foo_hidden", "foo", Scope::Global, Visibility::Hidden).expect("can alias foo");
+    obj.import("printf", faerie::ImportKind::Function).expect("can import printf");
+    // an import has no definition to alias
+    assert!(obj.define_alias("print", "printf", Scope::Global).is_err());
+
+    let bytes = obj.emit().expect("can emit mach file");
+    let mach = match goblin::Object::parse(&bytes).expect("can parse mach file") {
+        goblin::Object::Mach(Mach::Binary(mach)) => mach,
+        _ => panic!("Mach file not parsed as mach file"),
+    };
+    let symbols = mach.symbols().map(|symbol| symbol.expect("can parse symbol")).collect::<Vec<_>>();
+    let symbol = |name: &str| &symbols.iter().find(|&&(n, _)| n == name).expect("has symbol").1;
+    let (foo, bar, foo_local) = (symbol("_foo"), symbol("_bar"), symbol("_foo_local"));
+    assert_ne!(foo.n_value, symbol("_helper").n_value);
+    assert_eq!((bar.n_sect, bar.n_value), (foo.n_sect, foo.n_value));
+    assert_eq!((foo_local.n_sect, foo_local.n_value), (foo.n_sect, foo.n_value));
+    assert!(bar.is_global());
+    assert!(!foo_local.is_global());
+    let foo_hidden = symbol("_foo_hidden");
+    assert_eq!((foo_hidden.n_sect, foo_hidden.n_value), (foo.n_sect, foo.n_value));
+    assert!(foo_hidden.is_global());
+    assert_eq!(foo_hidden.n_type & symbols::N_PEXT, symbols::N_PEXT);
+
+    // a private extern symbol is hidden, and there is nothing in between
+    obj.define_alias_with("foo_protected", "foo", Scope::Global, Visibility::Protected).expect("can alias foo");
+    assert!(obj.emit().is_err());
+}
+
+#[test]
+fn no_dead_strip_definitions_are_marked() {
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("init", Decl::function(), vec![0xc3]).expect("can declare and define init");
+    obj.declare_with("other", Decl::function().global(), vec![0xc3]).expect("can declare and define other");
+    obj.define_alias("init_alias", "init", Scope::Global).expect("can alias init");
+    obj.set_no_dead_strip("init_alias", true).expect("can keep init through its alias");
+
+    let bytes = obj.emit().expect("can emit mach file");
+    let mach = match goblin::Object::parse(&bytes).expect("can parse mach file") {
+        goblin::Object::Mach(Mach::Binary(mach)) => mach,
+        _ => panic!("Mach file not parsed as mach file"),
+    };
+    let symbols = mach.symbols().map(|symbol| symbol.expect("can parse symbol")).collect::<Vec<_>>();
+    let symbol = |name: &str| &symbols.iter().find(|&&(n, _)| n == name).expect("has symbol").1;
+    // N_NO_DEAD_STRIP
+    assert_eq!(symbol("_init").n_desc & 0x20, 0x20);
+    assert_eq!(symbol("_other").n_desc & 0x20, 0);
+}
+
+#[test]
+fn no_merge_strings_are_not_literals() {
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("merged", Decl::cstring(), b"hello\0".to_vec()).expect("can declare and define merged");
+    obj.declare_with("intact", Decl::cstring().no_merge(), b"hello\0".to_vec()).expect("can declare and define intact");
+
+    let bytes = obj.emit().expect("can emit mach file");
+    let mach = match goblin::Object::parse(&bytes).expect("can parse mach file") {
+        goblin::Object::Mach(Mach::Binary(mach)) => mach,
+        _ => panic!("Mach file not parsed as mach file"),
+    };
+    let sections = mach.segments.sections().flatten()
+        .map(|section| section.expect("can parse section").0)
+        .collect::<Vec<_>>();
+    let section = |name| sections.iter().find(|section| section.name().expect("section has a name") == name).expect("has section");
+    // the linker merges identical literals
+    assert_eq!(section("__cstring").flags & constants::SECTION_TYPE, constants::S_CSTRING_LITERALS);
+    assert_eq!((section("__cstring").size, section("__const").size), (6, 6));
+}
+
+#[test]
+fn bsd_archive_members_are_aligned_and_indexed() {
+    let mut first = Artifact::new(triple!("x86_64-apple-darwin"), "first.o".into());
+    first.declare_with("f", Decl::function().global(), vec![0xc3]).expect("can declare and define f");
+    first.declare_with("local", Decl::data(), vec![1]).expect("can declare and define local");
+    let mut second = Artifact::new(triple!("x86_64-apple-darwin"), "a-long-name-for-the-second.o".into());
+    second.declare_with("g", Decl::function().global(), vec![0xc3]).expect("can declare and define g");
+    second.declare_with("counter", Decl::data().global().writable(), vec![0; 5]).expect("can declare and define counter");
+
+    let mut archive = Archive::new(ArchiveFormat::Bsd);
+    archive.add_artifact(&first).expect("can add first");
+    archive.add_object("second.o", second.emit().expect("can emit second")).expect("can add second from its bytes");
+    let bytes = archive.to_bytes().expect("can write archive");
+
+    let parsed = goblin::archive::Archive::parse(&bytes).expect("can parse archive");
+    let mut members = parsed.members();
+    members.sort();
+    assert_eq!(members, ["first.o", "second.o"]);
+    assert_eq!(parsed.member_of_symbol("_f"), Some("first.o"));
+    assert_eq!(parsed.member_of_symbol("_g"), Some("second.o"));
+    assert_eq!(parsed.member_of_symbol("_counter"), Some("second.o"));
+    assert_eq!(parsed.member_of_symbol("_local"), None);
+    for name in parsed.members() {
+        let member = parsed.get(name).expect("has member");
+        assert_eq!(member.offset % 8, 0, "{} is not 8 byte aligned", name);
+    }
+    // the contents are padded with zeros to 8 bytes
+    let first_bytes = first.emit().expect("can emit first");
+    let extracted = parsed.extract("first.o", &bytes).expect("can extract first.o");
+    assert_eq!(&extracted[..first_bytes.len()], &first_bytes[..]);
+    assert!(extracted[first_bytes.len()..].iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn private_externs_and_weak_imports_are_marked() {
+    use goblin::mach::constants::S_ATTR_SOME_INSTRUCTIONS;
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("run", Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xc3]).expect("can declare and define run");
+    obj.declare_with("helper", Decl::function().global().hidden(), vec![0xc3]).expect("can declare and define helper");
+    obj.declare_with("state", Decl::data().global().writable().hidden(), vec![0; 8]).expect("can declare and define state");
+    obj.declare_bss("buffer", Decl::data().global().hidden(), 64, 8).expect("can declare buffer");
+    obj.declare("optional", Decl::FunctionImport).expect("can import optional");
+    obj.set_weak_import("optional", true).expect("can weakly import optional");
+    obj.link(Link { from: "run", to: "optional", at: 1 }).expect("can link run to optional");
+
+    let bytes = obj.emit().expect("can emit mach file");
+    let mach = match goblin::Object::parse(&bytes).expect("can parse mach file") {
+        goblin::Object::Mach(Mach::Binary(mach)) => mach,
+        _ => panic!("Mach file not parsed as mach file"),
+    };
+    // the linker dead strips each definition on its own
+    assert_eq!(mach.header.flags & header::MH_SUBSECTIONS_VIA_SYMBOLS, header::MH_SUBSECTIONS_VIA_SYMBOLS);
+    let text = mach.segments.sections().flatten().map(|section| section.expect("can parse section").0)
+        .find(|section| section.name().expect("has a name") == "__text").expect("has __text");
+    assert_eq!(text.flags & S_ATTR_SOME_INSTRUCTIONS, S_ATTR_SOME_INSTRUCTIONS);
+
+    let symbols = mach.symbols().map(|symbol| symbol.expect("can parse symbol")).collect::<Vec<_>>();
+    let symbol = |name: &str| &symbols.iter().find(|&&(n, _)| n == name).expect("has symbol").1;
+    for name in &["_helper", "_state", "_buffer"] {
+        assert!(symbol(name).is_global());
+        assert_eq!(symbol(name).n_type & symbols::N_PEXT, symbols::N_PEXT, "{} is a private extern", name);
+    }
+    assert_eq!(symbol("_run").n_type & symbols::N_PEXT, 0);
+    const N_WEAK_REF: u16 = 0x40;
+    assert_eq!(symbol("_optional").n_desc & N_WEAK_REF, N_WEAK_REF);
+    assert!(symbol("_optional").is_undefined());
+}
+
+#[test]
+fn linker_directives_are_linker_option_load_commands() {
+    use goblin::mach::load_command::LC_LINKER_OPTION;
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("_main", Decl::function().global(), vec![0xc3]).expect("can declare and define _main");
+    obj.add_linker_directive(LinkerDirective::Library("z".into())).expect("can link with libz");
+    obj.add_linker_directive(LinkerDirective::Option("-framework".into(), "Foundation".into())).expect("can link with Foundation");
+
+    let bytes = obj.emit().expect("can emit mach file");
+    let mach = match goblin::Object::parse(&bytes).expect("can parse mach file") {
+        goblin::Object::Mach(Mach::Binary(mach)) => mach,
+        _ => panic!("Mach file not parsed as mach file"),
+    };
+    assert_eq!(mach.header.ncmds, 4);
+    let options = mach.load_commands.iter().filter(|command| command.command.cmd() == LC_LINKER_OPTION).map(|command| {
+        let size = command.command.cmdsize();
+        assert_eq!(size % 8, 0, "the command is pointer aligned");
+        let count = bytes.pread_with::<u32>(command.offset + 8, scroll::LE).expect("has a count");
+        bytes[command.offset + 12..command.offset + size].split(|&b| b == 0)
+            .take(count as usize).map(|s| String::from_utf8(s.to_vec()).expect("utf8 option")).collect::<Vec<_>>()
+    }).collect::<Vec<_>>();
+    assert_eq!(options, vec![vec!["-lz".to_string()], vec!["-framework".to_string(), "Foundation".to_string()]]);
+    // the sections follow the load commands
+    let text = mach.segments.sections().flatten().map(|section| section.expect("can parse section").0)
+        .find(|section| section.name().expect("has a name") == "__text").expect("has __text");
+    assert_eq!(bytes[text.offset as usize], 0xc3);
+}
+
+#[test]
+fn build_id_is_the_uuid() {
+    use goblin::mach::load_command::CommandVariant;
+    let uuid = |obj: &Artifact| {
+        let bytes = obj.emit().expect("can emit mach file");
+        let mach = match goblin::Object::parse(&bytes).expect("can parse mach file") {
+            goblin::Object::Mach(Mach::Binary(mach)) => mach,
+            _ => panic!("Mach file not parsed as mach file"),
+        };
+        let uuids = mach.load_commands.iter().filter_map(|command| match command.command {
+            CommandVariant::Uuid(uuid) => Some(uuid.uuid),
+            _ => None,
+        }).collect::<Vec<_>>();
+        assert!(uuids.len() <= 1);
+        uuids.first().cloned()
+    };
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("_main", Decl::function().global(), vec![0xc3]).expect("can declare and define _main");
+    assert_eq!(uuid(&obj), None);
+
+    obj.set_buildid_note("GNU", (0..20).collect()).expect("can set the build id");
+    assert_eq!(uuid(&obj), Some([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]));
+    obj.set_buildid_note("GNU", vec![0xaa; 8]).expect("can set a short build id");
+    assert_eq!(uuid(&obj), Some([0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0, 0, 0, 0, 0, 0, 0, 0]));
+
+    // the UUID follows the contents
+    obj.set_content_buildid().expect("can compute the build id");
+    let first = uuid(&obj).expect("has a uuid");
+    assert_ne!(first, [0; 16]);
+    obj.declare_with("_helper", Decl::function(), vec![0xc3]).expect("can declare and define _helper");
+    assert_ne!(uuid(&obj), Some(first));
+}
+
+#[test]
+fn thread_local_variables_are_descriptors() {
+    use goblin::mach::relocation::*;
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declarations(vec![
+        ("main", Decl::function().global().into()),
+        ("counter", Decl::data().global().thread_local().into()),
+        ("scratch", Decl::data().thread_local().zero_init(8).into()),
+        ("errno", Decl::DataImport),
+    ].into_iter()).expect("can declare");
+    obj.define("main", vec![
+        0x48, 0x8b, 0x3d, 0, 0, 0, 0,   // movq _counter@TLVP(%rip), %rdi
+        0xff, 0x17,                     // callq *(%rdi)
+        0x48, 0x8b, 0x3d, 0, 0, 0, 0,   // movq _errno@TLVP(%rip), %rdi
+        0xff, 0x17,                     // callq *(%rdi)
+        0xc3,
+    ]).expect("can define main");
+    obj.define("counter", vec![1, 2, 3, 4]).expect("can define counter");
+    obj.define_zeroed("scratch", 16).expect("can define scratch");
+    let tlv = Reloc::Tls { model: TlsModel::GeneralDynamic, addend: 0 };
+    obj.link_with(Link { from: "main", to: "counter", at: 3 }, tlv).expect("can link from main to counter");
+    obj.link_with(Link { from: "main", to: "errno", at: 12 }, tlv).expect("can link from main to errno");
+
+    let bytes = obj.emit().expect("can emit mach file");
+    let mach = match goblin::Object::parse(&bytes).expect("can parse mach file") {
+        goblin::Object::Mach(Mach::Binary(mach)) => mach,
+        _ => panic!("Mach file not parsed as mach file"),
+    };
+    let sections = mach.segments[0].sections().expect("can parse sections");
+    let section = |name| sections.iter().find(|(section, _)| section.name().expect("section has a name") == name).expect("has section");
+    let (vars, descriptors) = section("__thread_vars");
+    assert_eq!((vars.flags & constants::SECTION_TYPE, vars.addr % 8), (constants::S_THREAD_LOCAL_VARIABLES, 0));
+    // the keys are zero, and the pointers are relocated
+    assert_eq!(*descriptors, &[0; 48][..]);
+    let (data, initial) = section("__thread_data");
+    assert_eq!(data.flags & constants::SECTION_TYPE, constants::S_THREAD_LOCAL_REGULAR);
+    assert_eq!(*initial, &[1, 2, 3, 4][..]);
+    let (bss, _) = section("__thread_bss");
+    assert_eq!(bss.flags & constants::SECTION_TYPE, constants::S_THREAD_LOCAL_ZEROFILL);
+    assert_eq!((bss.size, bss.offset, bss.addr % 8), (16, 0, 0));
+
+    // each variable's symbol is its descriptor, and its initial value has a local symbol
+    let symbols = mach.symbols().map(|symbol| symbol.expect("can parse symbol")).collect::<Vec<_>>();
+    let symbol = |name| symbols.iter().find(|&&(n, _)| n == name).map(|(_, nlist)| nlist).expect("has symbol");
+    let place = |name| {
+        let nlist = symbol(name);
+        (sections[nlist.n_sect - 1].0.name().expect("section has a name"), nlist.n_value, nlist.is_global())
+    };
+    assert_eq!(place("_counter"), ("__thread_vars", vars.addr, true));
+    assert_eq!(place("_scratch"), ("__thread_vars", vars.addr + 24, false));
+    assert_eq!(place("_counter$tlv$init"), ("__thread_data", data.addr, false));
+    assert_eq!(place("_scratch$tlv$init"), ("__thread_bss", bss.addr, false));
+    assert!(symbol("__tlv_bootstrap").is_undefined());
+
+    let names = symbols.iter().map(|&(name, _)| name).collect::<Vec<_>>();
+    let relocations = |section: &segment::Section| {
+        let mut relocations = section.iter_relocations(&bytes, goblin::container::Ctx::default())
+            .map(|reloc| reloc.expect("can parse relocation"))
+            .map(|reloc| (reloc.r_address, reloc.r_type(), reloc.is_pic(), reloc.r_length(), names[reloc.r_symbolnum()]))
+            .collect::<Vec<_>>();
+        relocations.sort();
+        relocations
+    };
+    assert_eq!(relocations(&section("__text").0), [
+        (3, X86_64_RELOC_TLV, true, 2, "_counter"),
+        (12, X86_64_RELOC_TLV, true, 2, "_errno"),
+    ]);
+    // the thunk and initial value of each descriptor
+    assert_eq!(relocations(vars), [
+        (0, X86_64_RELOC_UNSIGNED, false, 3, "__tlv_bootstrap"),
+        (16, X86_64_RELOC_UNSIGNED, false, 3, "_counter$tlv$init"),
+        (24, X86_64_RELOC_UNSIGNED, false, 3, "__tlv_bootstrap"),
+        (40, X86_64_RELOC_UNSIGNED, false, 3, "_scratch$tlv$init"),
+    ]);
+}
+
+#[test]
+fn arm64_thread_local_variables_are_loaded_through_their_descriptors() {
+    use goblin::mach::relocation::*;
+    let mut obj = Artifact::new(triple!("aarch64-apple-darwin"), "t.o".into());
+    obj.declarations(vec![
+        ("main", Decl::function().global().into()),
+        ("counter", Decl::data().global().thread_local().into()),
+    ].into_iter()).expect("can declare");
+    obj.define("main", vec![
+        0x00, 0x00, 0x00, 0x90,         // adrp x0, _counter@TLVPPAGE
+        0x00, 0x00, 0x40, 0xf9,         // ldr x0, [x0, _counter@TLVPPAGEOFF]
+        0x08, 0x00, 0x40, 0xf9,         // ldr x8, [x0]
+        0x00, 0x01, 0x3f, 0xd6,         // blr x8
+    ]).expect("can define main");
+    obj.define("counter", vec![0; 8]).expect("can define counter");
+    obj.link_with(Link { from: "main", to: "counter", at: 0 }, Reloc::Tls { model: TlsModel::InitialExec, addend: 0 }).expect("can link from main to counter");
+
+    let bytes = obj.emit().expect("can emit mach file");
+    let mach = match goblin::Object::parse(&bytes).expect("can parse mach file") {
+        goblin::Object::Mach(Mach::Binary(mach)) => mach,
+        _ => panic!("Mach file not parsed as mach file"),
+    };
+    let names = mach.symbols().map(|symbol| symbol.expect("can parse symbol").0).collect::<Vec<_>>();
+    let sections = mach.segments[0].sections().expect("can parse sections");
+    let (text, _) = sections.iter().find(|(section, _)| section.name().expect("section has a name") == "__text").expect("has __text");
+    let relocations = text.iter_relocations(&bytes, goblin::container::Ctx::default())
+        .map(|reloc| reloc.expect("can parse relocation"))
+        .map(|reloc| (reloc.r_address, reloc.r_type(), reloc.is_pic(), names[reloc.r_symbolnum()]))
+        .collect::<Vec<_>>();
+    assert_eq!(relocations, [
+        (0, ARM64_RELOC_TLVP_LOAD_PAGE21, true, "_counter"),
+        (4, ARM64_RELOC_TLVP_LOAD_PAGEOFF12, false, "_counter"),
+    ]);
+
+    // there is no offset from the thread pointer, only the descriptor
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0x8b, 0x04, 0x25, 0, 0, 0, 0, 0xc3]).expect("can declare and define main");
+    obj.declare_with("counter", Decl::data().thread_local(), vec![0; 4]).expect("can declare and define counter");
+    obj.link_with(Link { from: "main", to: "counter", at: 3 }, Reloc::Tls { model: TlsModel::LocalExec, addend: 0 }).expect("can link from main to counter");
+    assert!(obj.emit().is_err());
+}
@@ -0,0 +1,78 @@
+extern crate faerie;
+#[macro_use]
+extern crate target_lexicon;
+
+use std::str::FromStr;
+
+use faerie::{Artifact, Decl, Link, WasmSignature, WasmType};
+
+#[test]
+fn functions_and_data_are_linkable_by_wasm_ld() {
+    let mut obj = Artifact::new(triple!("wasm32-unknown-unknown"), "t.o".into());
+    obj.declare("print", Decl::FunctionImport).expect("can declare print");
+    obj.declare_with("add", Decl::function().global(), vec![
+        0x00,               // no locals
+        0x20, 0x00,         // local.get 0
+        0x20, 0x01,         // local.get 1
+        0x6a,               // i32.add
+        0x0b,               // end
+    ]).expect("can declare and define add");
+    obj.declare_with("main", Decl::function().global(), vec![
+        0x00,                                   // no locals
+        0x41, 0x02,                             // i32.const 2
+        0x41, 0x80, 0x80, 0x80, 0x80, 0x00,     // i32.const counter
+        0x28, 0x02, 0x00,                       // i32.load
+        0x10, 0x80, 0x80, 0x80, 0x80, 0x00,     // call add
+        0x10, 0x80, 0x80, 0x80, 0x80, 0x00,     // call print
+        0x0b,                                   // end
+    ]).expect("can declare and define main");
+    obj.declare_with("init", Decl::function(), vec![0x00, 0x0b]).expect("can declare and define init");
+    obj.declare_with("counter", Decl::data().global().writable(), vec![40, 0, 0, 0]).expect("can declare and define counter");
+    obj.declare_with("message", Decl::cstring(), b"hi\0".to_vec()).expect("can declare and define message");
+    obj.declare_with("callbacks", Decl::data(), vec![0; 8]).expect("can declare and define callbacks");
+    let i32_binary = WasmSignature { params: vec![WasmType::I32, WasmType::I32], results: vec![WasmType::I32] };
+    obj.set_wasm_signature("add", i32_binary).expect("can set the signature of add");
+    obj.set_wasm_signature("main", WasmSignature { params: vec![], results: vec![WasmType::I32] }).expect("can set the signature of main");
+    obj.set_wasm_signature("print", WasmSignature { params: vec![WasmType::I32], results: vec![WasmType::I32] }).expect("can set the signature of print");
+    assert!(obj.set_wasm_signature("counter", WasmSignature::default()).is_err());
+    obj.link(Link { from: "main", to: "counter", at: 4 }).expect("can link from main to counter");
+    obj.link(Link { from: "main", to: "add", at: 13 }).expect("can link from main to add");
+    obj.link(Link { from: "main", to: "print", at: 19 }).expect("can link from main to print");
+    obj.link(Link { from: "callbacks", to: "add", at: 0 }).expect("can link from callbacks to add");
+    obj.link(Link { from: "callbacks", to: "message", at: 4 }).expect("can link from callbacks to message");
+    obj.add_constructor("init", None).expect("can add init as a constructor");
+    assert!(obj.emit().is_err(), "init has no signature");
+    obj.set_wasm_signature("init", WasmSignature::default()).expect("can set the signature of init");
+
+    let bytes = obj.emit().expect("can emit wasm file");
+    assert_eq!(&bytes[..8], b"\0asm\x01\0\0\0");
+    assert!(obj.emit_as(target_lexicon::BinaryFormat::Macho).is_err());
+    let mut native = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    native.declare_with("main", Decl::function().global(), vec![0xc3]).expect("can declare and define main");
+    assert!(native.emit_as(target_lexicon::BinaryFormat::Wasm).is_err());
+
+    let path = std::env::temp_dir().join(format!("faerie-{}-wasm.o", std::process::id()));
+    std::fs::write(&path, &bytes).expect("can write object");
+    let output = std::process::Command::new("obj2yaml").arg(&path).output();
+    let _ = std::fs::remove_file(&path);
+    let output = match output {
+        Ok(output) => output,
+        Err(_) => return eprintln!("obj2yaml is not available, skipping"),
+    };
+    assert!(output.status.success(), "obj2yaml failed: {}", String::from_utf8_lossy(&output.stderr));
+    let yaml = String::from_utf8(output.stdout).expect("yaml is utf8");
+    let yaml = yaml.lines().map(str::trim).collect::<Vec<_>>();
+    let has = |lines: &[&str]| yaml.windows(lines.len()).any(|window| window == lines);
+    // the import is the first symbol, followed by the local definitions and then the global ones; main's body
+    // follows the count, and the sizes and bodies of init and add
+    assert!(has(&["- Type:            R_WASM_MEMORY_ADDR_SLEB", "Index:           6", "Offset:          0x11"]), "{:#?}", yaml);
+    assert!(has(&["- Type:            R_WASM_FUNCTION_INDEX_LEB", "Index:           2", "Offset:          0x1A"]), "{:#?}", yaml);
+    assert!(has(&["- Type:            R_WASM_FUNCTION_INDEX_LEB", "Index:           0", "Offset:          0x20"]), "{:#?}", yaml);
+    // the pointers of callbacks follow the count and the header of its segment
+    assert!(has(&["- Type:            R_WASM_TABLE_INDEX_I32", "Index:           2", "Offset:          0x6"]), "{:#?}", yaml);
+    assert!(has(&["- Type:            R_WASM_MEMORY_ADDR_I32", "Index:           5", "Offset:          0xA"]), "{:#?}", yaml);
+    assert!(has(&["Name:            .rodata.str.message", "Alignment:       0", "Flags:           [ STRINGS ]"]), "{:#?}", yaml);
+    assert!(has(&["Name:            .data.counter", "Alignment:       3"]), "{:#?}", yaml);
+    assert!(has(&["- Priority:        65535", "Symbol:          1"]), "{:#?}", yaml);
+    assert!(has(&["Name:            __indirect_function_table", "Flags:           [ UNDEFINED, NO_STRIP ]"]), "{:#?}", yaml);
+}